@@ -12,7 +12,7 @@ fn real_main() -> i32 {
         return 1;
     }
     let fname = std::path::Path::new(&*args[1]);
-    let file = fs::File::open(&fname).unwrap();
+    let file = fs::File::open(fname).unwrap();
     let reader = BufReader::new(file);
 
     let mut archive = zip::ZipArchive::new(reader).unwrap();
@@ -34,7 +34,7 @@ fn real_main() -> i32 {
             }
         }
 
-        if (&*file.name()).ends_with('/') {
+        if file.name().ends_with('/') {
             println!(
                 "Entry {} is a directory with name \"{}\"",
                 i,
@@ -49,5 +49,5 @@ fn real_main() -> i32 {
             );
         }
     }
-    return 0;
+    0
 }
@@ -30,5 +30,5 @@ fn real_main() -> i32 {
             }
         }
     }
-    return 0;
+    0
 }
@@ -0,0 +1,82 @@
+//! Cache of decompressed file contents
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// Bounded least-recently-used cache of decompressed files, keyed by file index
+#[derive(Clone, Debug, Default)]
+pub(crate) struct EntryCache {
+    capacity: usize,
+    used: usize,
+    // Least recently used entry first
+    entries: VecDeque<(usize, Arc<[u8]>)>,
+}
+
+impl EntryCache {
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change the maximum number of bytes held, evicting entries if needed.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict(0);
+    }
+
+    pub fn get(&mut self, index: usize) -> Option<Arc<[u8]>> {
+        let position = self.entries.iter().position(|(i, _)| *i == index)?;
+        let entry = self.entries.remove(position)?;
+        let data = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(data)
+    }
+
+    /// Store the contents of a file, unless it doesn't fit in the cache at all.
+    pub fn insert(&mut self, index: usize, data: Arc<[u8]>) {
+        if data.len() > self.capacity {
+            return;
+        }
+        if let Some(position) = self.entries.iter().position(|(i, _)| *i == index) {
+            let (_, old) = self.entries.remove(position).unwrap();
+            self.used -= old.len();
+        }
+        self.evict(data.len());
+        self.used += data.len();
+        self.entries.push_back((index, data));
+    }
+
+    /// Drop least recently used entries until `additional` more bytes fit.
+    fn evict(&mut self, additional: usize) {
+        while self.used + additional > self.capacity {
+            match self.entries.pop_front() {
+                Some((_, data)) => self.used -= data.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::EntryCache;
+    use std::sync::Arc;
+
+    #[test]
+    fn least_recently_used_is_evicted() {
+        let mut cache = EntryCache::default();
+        cache.set_capacity(8);
+        cache.insert(0, Arc::from(&b"abc"[..]));
+        cache.insert(1, Arc::from(&b"def"[..]));
+        assert!(cache.get(0).is_some());
+        cache.insert(2, Arc::from(&b"ghi"[..]));
+        assert!(cache.get(1).is_none());
+        assert_eq!(&*cache.get(0).unwrap(), b"abc");
+        assert_eq!(&*cache.get(2).unwrap(), b"ghi");
+
+        cache.insert(3, Arc::from(&b"too large"[..]));
+        assert!(cache.get(3).is_none());
+
+        cache.set_capacity(0);
+        assert!(cache.get(0).is_none());
+    }
+}
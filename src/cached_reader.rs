@@ -0,0 +1,223 @@
+//! Block cache for slow readers
+//!
+//! Opening a [`ZipArchive`](crate::read::ZipArchive) does many small reads: the end of central
+//! directory record is searched for from the back, then the central directory is parsed header
+//! by header, and every opened file starts with a seek to its local header. Over a network,
+//! each of these would be a round trip. A [`CachedReader`] reads whole blocks instead, and keeps
+//! the most recently used ones.
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::prelude::*;
+
+/// `Read + Seek` adapter that reads `inner` in blocks, and caches the most recently used ones,
+/// evicting the least recently used one when it is full
+///
+/// Reads of at least a block that aren't cached go to `inner` directly, so that streaming the
+/// contents of large files doesn't evict the blocks holding the central directory.
+///
+/// ```no_run
+/// use std::fs::File;
+/// use zip::read::CachedReader;
+///
+/// # fn main() -> zip::result::ZipResult<()> {
+/// // 64 KiB blocks, at most 1 MiB cached
+/// let reader = CachedReader::new(File::open("remote.zip")?, 64 * 1024, 16);
+/// let archive = zip::ZipArchive::new(reader)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CachedReader<R> {
+    inner: R,
+    block_size: usize,
+    capacity: usize,
+    // Least recently used block first, by block number
+    blocks: VecDeque<(u64, Vec<u8>)>,
+    position: u64,
+    len: Option<u64>,
+}
+
+impl<R: Read + Seek> CachedReader<R> {
+    /// Read from `inner` in blocks of `block_size` bytes, keeping at most `capacity` of them
+    pub fn new(inner: R, block_size: usize, capacity: usize) -> CachedReader<R> {
+        CachedReader {
+            inner,
+            block_size: std::cmp::max(block_size, 1),
+            capacity,
+            blocks: VecDeque::new(),
+            position: 0,
+            len: None,
+        }
+    }
+
+    /// Unwrap and return the source
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Get a block, reading it if it isn't cached
+    fn block(&mut self, number: u64) -> io::Result<&[u8]> {
+        match self.blocks.iter().position(|(n, _)| *n == number) {
+            Some(position) => {
+                let block = self.blocks.remove(position).unwrap();
+                self.blocks.push_back(block);
+            }
+            None => {
+                let mut data = Vec::with_capacity(self.block_size);
+                self.inner
+                    .seek(io::SeekFrom::Start(number * self.block_size as u64))?;
+                (&mut self.inner)
+                    .take(self.block_size as u64)
+                    .read_to_end(&mut data)?;
+                if self.blocks.len() >= self.capacity {
+                    self.blocks.pop_front();
+                }
+                self.blocks.push_back((number, data));
+            }
+        }
+        Ok(&self.blocks.back().unwrap().1)
+    }
+
+    fn is_cached(&self, number: u64) -> bool {
+        self.blocks.iter().any(|(n, _)| *n == number)
+    }
+}
+
+impl<R: Read + Seek> Read for CachedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let number = self.position / self.block_size as u64;
+        let count =
+            if self.capacity == 0 || (buf.len() >= self.block_size && !self.is_cached(number)) {
+                self.inner.seek(io::SeekFrom::Start(self.position))?;
+                self.inner.read(buf)?
+            } else {
+                let offset = (self.position % self.block_size as u64) as usize;
+                let block = self.block(number)?;
+                if offset >= block.len() {
+                    return Ok(0);
+                }
+                let count = std::cmp::min(buf.len(), block.len() - offset);
+                buf[..count].copy_from_slice(&block[offset..offset + count]);
+                count
+            };
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<R: Read + Seek> Seek for CachedReader<R> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            io::SeekFrom::Start(offset) => {
+                self.position = offset;
+                return Ok(offset);
+            }
+            io::SeekFrom::End(offset) => {
+                let len = match self.len {
+                    Some(len) => len,
+                    None => {
+                        let len = self.inner.seek(io::SeekFrom::End(0))?;
+                        self.len = Some(len);
+                        len
+                    }
+                };
+                (len, offset)
+            }
+            io::SeekFrom::Current(offset) => (self.position, offset),
+        };
+        let position = if offset >= 0 {
+            base.checked_add(offset as u64)
+        } else {
+            base.checked_sub(offset.wrapping_neg() as u64)
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CachedReader;
+    use std::io::{self, Read, Seek, SeekFrom};
+
+    /// Counts the reads that reach the underlying data
+    struct CountingReader {
+        inner: io::Cursor<Vec<u8>>,
+        reads: usize,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            self.inner.read(buf)
+        }
+    }
+
+    impl Seek for CountingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn cached_blocks() {
+        let data: Vec<u8> = (0..100).collect();
+        let inner = CountingReader {
+            inner: io::Cursor::new(data),
+            reads: 0,
+        };
+        let mut reader = CachedReader::new(inner, 16, 2);
+        let mut buf = [0; 4];
+
+        reader.seek(SeekFrom::End(-4)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [96, 97, 98, 99]);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        reader.seek(SeekFrom::Start(14)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [14, 15, 16, 17]);
+        let reads = reader.inner.reads;
+
+        // Both blocks are cached now
+        reader.seek(SeekFrom::Start(12)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [16, 17, 18, 19]);
+        assert_eq!(reader.inner.reads, reads);
+
+        // Large reads bypass the cache
+        let mut large = [0; 32];
+        reader.seek(SeekFrom::Start(50)).unwrap();
+        reader.read_exact(&mut large).unwrap();
+        assert_eq!(large[0], 50);
+        reader.seek(SeekFrom::Start(12)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(reader.inner.reads, reads + 1);
+    }
+
+    #[test]
+    fn cached_archive() {
+        use crate::write::{FileOptions, ZipWriter};
+        use crate::ZipArchive;
+        use std::io::Write;
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.start_file("a.txt", FileOptions::default()).unwrap();
+        writer.write_all(b"cached").unwrap();
+        let v = writer.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(CachedReader::new(io::Cursor::new(v), 64, 8)).unwrap();
+        let mut contents = String::new();
+        zip.by_name("a.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "cached");
+    }
+}
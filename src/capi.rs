@@ -0,0 +1,369 @@
+//! C interface for reading and writing archives
+//!
+//! Enabled with the `capi` feature. The functions work on opaque handles to archives on disk,
+//! and return `ZIP_OK` or one of the negative `ZIP_ERR_*` codes. Panics are caught at the
+//! boundary and reported as `ZIP_ERR_PANIC`. Paths and names are NUL-terminated UTF-8.
+//!
+//! To build a library that C code can link to, build this crate as a `cdylib` or `staticlib`,
+//! for example with `cargo rustc --release --features capi --crate-type cdylib`. The matching
+//! declarations are:
+//!
+//! ```c
+//! typedef struct ZipReader ZipReader;
+//! typedef struct ZipFileWriter ZipFileWriter;
+//!
+//! int zip_reader_open(const char *path, ZipReader **reader);
+//! int zip_reader_len(const ZipReader *reader, size_t *len);
+//! int zip_reader_name(const ZipReader *reader, size_t index, char *buf, size_t buf_len, size_t *name_len);
+//! int zip_reader_extract(ZipReader *reader, size_t index, const char *path);
+//! void zip_reader_close(ZipReader *reader);
+//!
+//! int zip_writer_create(const char *path, ZipFileWriter **writer);
+//! int zip_writer_write_entry(ZipFileWriter *writer, const char *name, const uint8_t *data, size_t len);
+//! int zip_writer_close(ZipFileWriter *writer);
+//! ```
+
+use std::ffi::CStr;
+use std::fs;
+use std::io::{self, Write};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+use std::slice;
+
+use crate::read::ZipArchive;
+use crate::result::ZipError;
+use crate::write::{FileOptions, ZipWriter};
+
+/// The call succeeded
+pub const ZIP_OK: c_int = 0;
+/// Reading or writing a file failed
+pub const ZIP_ERR_IO: c_int = -1;
+/// The file is not a valid zip archive
+pub const ZIP_ERR_INVALID_ARCHIVE: c_int = -2;
+/// The archive uses a feature or compression method that is not supported
+pub const ZIP_ERR_UNSUPPORTED: c_int = -3;
+/// There is no entry with the given index
+pub const ZIP_ERR_NOT_FOUND: c_int = -4;
+/// A pointer was null, or a string was not valid UTF-8
+pub const ZIP_ERR_INVALID_ARGUMENT: c_int = -5;
+/// The buffer is too small for the result
+pub const ZIP_ERR_BUFFER_TOO_SMALL: c_int = -6;
+/// The library panicked, which is a bug
+pub const ZIP_ERR_PANIC: c_int = -7;
+
+/// Handle to an archive opened for reading
+pub struct ZipReader(ZipArchive<fs::File>);
+
+/// Handle to an archive being written
+pub struct ZipFileWriter(ZipWriter<fs::File>);
+
+enum Error {
+    Zip(ZipError),
+    Code(c_int),
+}
+
+impl From<ZipError> for Error {
+    fn from(err: ZipError) -> Error {
+        Error::Zip(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Zip(ZipError::Io(err))
+    }
+}
+
+fn error_code(err: &ZipError) -> c_int {
+    match *err {
+        ZipError::Io(_) => ZIP_ERR_IO,
+        ZipError::InvalidArchive { .. } | ZipError::LimitExceeded(_) => ZIP_ERR_INVALID_ARCHIVE,
+        ZipError::UnsupportedArchive(_) | ZipError::UnsupportedCompression(_) => {
+            ZIP_ERR_UNSUPPORTED
+        }
+        ZipError::FileNotFound => ZIP_ERR_NOT_FOUND,
+    }
+}
+
+/// Run `f`, and turn its result or panic into a return code
+fn guard<F: FnOnce() -> Result<(), Error>>(f: F) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => ZIP_OK,
+        Ok(Err(Error::Zip(err))) => error_code(&err),
+        Ok(Err(Error::Code(code))) => code,
+        Err(_) => ZIP_ERR_PANIC,
+    }
+}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> Result<&'a str, Error> {
+    if s.is_null() {
+        return Err(Error::Code(ZIP_ERR_INVALID_ARGUMENT));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| Error::Code(ZIP_ERR_INVALID_ARGUMENT))
+}
+
+unsafe fn ref_arg<'a, T>(p: *const T) -> Result<&'a T, Error> {
+    p.as_ref().ok_or(Error::Code(ZIP_ERR_INVALID_ARGUMENT))
+}
+
+unsafe fn mut_arg<'a, T>(p: *mut T) -> Result<&'a mut T, Error> {
+    p.as_mut().ok_or(Error::Code(ZIP_ERR_INVALID_ARGUMENT))
+}
+
+/// Open the archive at `path` for reading, and store its handle in `*reader`
+///
+/// The handle must be released with [`zip_reader_close`].
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string, and `reader` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn zip_reader_open(
+    path: *const c_char,
+    reader: *mut *mut ZipReader,
+) -> c_int {
+    guard(|| {
+        let path = str_arg(path)?;
+        let out = mut_arg(reader)?;
+        let archive = ZipArchive::new(fs::File::open(path)?)?;
+        *out = Box::into_raw(Box::new(ZipReader(archive)));
+        Ok(())
+    })
+}
+
+/// Store the number of entries in the archive in `*len`
+///
+/// # Safety
+///
+/// `reader` must be a handle from [`zip_reader_open`], and `len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn zip_reader_len(reader: *const ZipReader, len: *mut usize) -> c_int {
+    guard(|| {
+        *mut_arg(len)? = ref_arg(reader)?.0.len();
+        Ok(())
+    })
+}
+
+/// Copy the name of entry `index` into `buf`, followed by a NUL byte
+///
+/// The length of the name without the NUL byte is stored in `*name_len`, also when `buf` is too
+/// small and `ZIP_ERR_BUFFER_TOO_SMALL` is returned. `buf` may be null if `buf_len` is 0, to
+/// only get the length.
+///
+/// # Safety
+///
+/// `reader` must be a handle from [`zip_reader_open`], `buf` must be valid for `buf_len` bytes
+/// of writes, and `name_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn zip_reader_name(
+    reader: *const ZipReader,
+    index: usize,
+    buf: *mut c_char,
+    buf_len: usize,
+    name_len: *mut usize,
+) -> c_int {
+    guard(|| {
+        let archive = &ref_arg(reader)?.0;
+        let name = archive
+            .name_for_index(index)
+            .ok_or(Error::Code(ZIP_ERR_NOT_FOUND))?;
+        *mut_arg(name_len)? = name.len();
+        if name.len() >= buf_len {
+            return Err(Error::Code(ZIP_ERR_BUFFER_TOO_SMALL));
+        }
+        if buf.is_null() {
+            return Err(Error::Code(ZIP_ERR_INVALID_ARGUMENT));
+        }
+        ptr::copy_nonoverlapping(name.as_ptr() as *const c_char, buf, name.len());
+        *buf.add(name.len()) = 0;
+        Ok(())
+    })
+}
+
+/// Extract entry `index` to the file at `path`, or create a directory there if the entry is one
+///
+/// # Safety
+///
+/// `reader` must be a handle from [`zip_reader_open`], and `path` must be a NUL-terminated
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn zip_reader_extract(
+    reader: *mut ZipReader,
+    index: usize,
+    path: *const c_char,
+) -> c_int {
+    guard(|| {
+        let archive = &mut mut_arg(reader)?.0;
+        let path = Path::new(str_arg(path)?);
+        if index >= archive.len() {
+            return Err(Error::Code(ZIP_ERR_NOT_FOUND));
+        }
+        let mut file = archive.by_index(index)?;
+        if file.is_dir() {
+            fs::create_dir_all(path)?;
+        } else {
+            file.copy_to(&mut fs::File::create(path)?)?;
+        }
+        Ok(())
+    })
+}
+
+/// Close an archive opened with [`zip_reader_open`]. Does nothing if `reader` is null.
+///
+/// # Safety
+///
+/// `reader` must be null or a handle from [`zip_reader_open`] that wasn't closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn zip_reader_close(reader: *mut ZipReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
+
+/// Create a new archive at `path`, and store its handle in `*writer`
+///
+/// The archive is only complete after [`zip_writer_close`] was called.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string, and `writer` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn zip_writer_create(
+    path: *const c_char,
+    writer: *mut *mut ZipFileWriter,
+) -> c_int {
+    guard(|| {
+        let path = str_arg(path)?;
+        let out = mut_arg(writer)?;
+        let file = fs::File::create(path)?;
+        *out = Box::into_raw(Box::new(ZipFileWriter(ZipWriter::new(file))));
+        Ok(())
+    })
+}
+
+/// Add a file called `name` with the `len` bytes at `data` as its contents
+///
+/// The file is compressed with the default method. `data` may be null if `len` is 0.
+///
+/// # Safety
+///
+/// `writer` must be a handle from [`zip_writer_create`], `name` must be a NUL-terminated string,
+/// and `data` must be valid for `len` bytes of reads.
+#[no_mangle]
+pub unsafe extern "C" fn zip_writer_write_entry(
+    writer: *mut ZipFileWriter,
+    name: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    guard(|| {
+        let zip = &mut mut_arg(writer)?.0;
+        let name = str_arg(name)?;
+        let data = if len == 0 {
+            &[][..]
+        } else if data.is_null() {
+            return Err(Error::Code(ZIP_ERR_INVALID_ARGUMENT));
+        } else {
+            slice::from_raw_parts(data, len)
+        };
+        zip.start_file(name, FileOptions::default())?;
+        zip.write_all(data)?;
+        Ok(())
+    })
+}
+
+/// Write the central directory and close an archive created with [`zip_writer_create`]
+///
+/// The handle is released even if writing fails. Does nothing if `writer` is null.
+///
+/// # Safety
+///
+/// `writer` must be null or a handle from [`zip_writer_create`] that wasn't closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn zip_writer_close(writer: *mut ZipFileWriter) -> c_int {
+    if writer.is_null() {
+        return ZIP_OK;
+    }
+    let mut writer = Box::from_raw(writer);
+    guard(move || {
+        writer.0.finish()?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn capi_round_trip() {
+        let directory = std::env::temp_dir().join(format!("zip-capi-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let archive = CString::new(directory.join("test.zip").to_str().unwrap()).unwrap();
+        let extracted = directory.join("a.txt");
+        let extracted_c = CString::new(extracted.to_str().unwrap()).unwrap();
+
+        unsafe {
+            let mut writer = ptr::null_mut();
+            assert_eq!(zip_writer_create(archive.as_ptr(), &mut writer), ZIP_OK);
+            let name = CString::new("a.txt").unwrap();
+            let data = b"from C";
+            assert_eq!(
+                zip_writer_write_entry(writer, name.as_ptr(), data.as_ptr(), data.len()),
+                ZIP_OK
+            );
+            assert_eq!(
+                zip_writer_write_entry(writer, ptr::null(), data.as_ptr(), data.len()),
+                ZIP_ERR_INVALID_ARGUMENT
+            );
+            assert_eq!(zip_writer_close(writer), ZIP_OK);
+
+            let mut reader = ptr::null_mut();
+            assert_eq!(zip_reader_open(archive.as_ptr(), &mut reader), ZIP_OK);
+            let mut len = 0;
+            assert_eq!(zip_reader_len(reader, &mut len), ZIP_OK);
+            assert_eq!(len, 1);
+
+            let mut name_len = 0;
+            let mut buf = [0 as c_char; 4];
+            assert_eq!(
+                zip_reader_name(reader, 0, buf.as_mut_ptr(), buf.len(), &mut name_len),
+                ZIP_ERR_BUFFER_TOO_SMALL
+            );
+            assert_eq!(name_len, 5);
+            let mut buf = [0 as c_char; 6];
+            assert_eq!(
+                zip_reader_name(reader, 0, buf.as_mut_ptr(), buf.len(), &mut name_len),
+                ZIP_OK
+            );
+            assert_eq!(CStr::from_ptr(buf.as_ptr()).to_str().unwrap(), "a.txt");
+            assert_eq!(
+                zip_reader_name(reader, 1, buf.as_mut_ptr(), buf.len(), &mut name_len),
+                ZIP_ERR_NOT_FOUND
+            );
+
+            assert_eq!(zip_reader_extract(reader, 0, extracted_c.as_ptr()), ZIP_OK);
+            zip_reader_close(reader);
+        }
+
+        assert_eq!(fs::read_to_string(&extracted).unwrap(), "from C");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn capi_invalid_archive() {
+        let path = std::env::temp_dir().join(format!("zip-capi-invalid-{}", std::process::id()));
+        fs::write(&path, b"not a zip file").unwrap();
+        let path_c = CString::new(path.to_str().unwrap()).unwrap();
+        let mut reader = ptr::null_mut();
+        let code = unsafe { zip_reader_open(path_c.as_ptr(), &mut reader) };
+        fs::remove_file(&path).unwrap();
+        assert_eq!(code, ZIP_ERR_INVALID_ARCHIVE);
+        assert!(reader.is_null());
+    }
+}
@@ -0,0 +1,505 @@
+//! Checkpoints for seeking within deflated files
+//!
+//! A deflate stream can't be decompressed from the middle, since every block may refer back to
+//! the 32 KiB of data before it. Where a block starts, that data is all that is needed to carry
+//! on though. So while a file is decompressed, [`Checkpoints`] looks for the blocks in the
+//! compressed data, and keeps the position and window of one of them every megabyte. Seeking
+//! can then start over from the nearest checkpoint instead of from the start of the file.
+#![cfg_attr(
+    not(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    )),
+    allow(dead_code)
+)]
+
+use std::collections::VecDeque;
+
+/// Deflate can only refer back 32 KiB
+pub const WINDOW_SIZE: usize = 32 * 1024;
+
+/// Decompressed bytes between checkpoints, each of which keeps a window
+const SPACING: u64 = 1024 * 1024;
+
+/// A place to start decompressing from
+pub struct Checkpoint {
+    /// Position of a block in the compressed data, in bits
+    pub input_bits: u64,
+    /// Position of the block in the decompressed data
+    pub output: u64,
+    /// The decompressed data before the block that it may refer to
+    pub window: Vec<u8>,
+}
+
+/// The checkpoints of a deflate stream that were passed while decompressing it
+pub struct Checkpoints {
+    spacing: u64,
+    list: Vec<Checkpoint>,
+    // Only set while decompressing from the start of a block
+    scanner: Option<BlockScanner>,
+    // Blocks to keep a checkpoint of, whose start wasn't decompressed yet
+    pending: VecDeque<Boundary>,
+    // The last decompressed data, ending at `output`
+    history: Vec<u8>,
+    // Whether `history` goes back to the start of the stream
+    history_complete: bool,
+    output: u64,
+}
+
+impl Checkpoints {
+    pub fn new() -> Checkpoints {
+        Checkpoints::with_spacing(SPACING)
+    }
+
+    fn with_spacing(spacing: u64) -> Checkpoints {
+        Checkpoints {
+            spacing,
+            list: Vec::new(),
+            scanner: None,
+            pending: VecDeque::new(),
+            history: Vec::new(),
+            history_complete: false,
+            output: 0,
+        }
+    }
+
+    /// Start looking for blocks, from one at the given positions that `window` was
+    /// decompressed right before
+    pub fn start(&mut self, input_bits: u64, output: u64, window: &[u8]) {
+        self.scanner = Some(BlockScanner::new(Boundary { input_bits, output }));
+        self.pending.clear();
+        self.history.clear();
+        self.history.extend_from_slice(window);
+        // A short window only happens at the start of the stream
+        self.history_complete = window.len() < WINDOW_SIZE;
+        self.output = output;
+    }
+
+    /// Take in the compressed data that was consumed, and the data it was decompressed to
+    pub fn update(&mut self, input: &[u8], output: &[u8]) {
+        let scanner = match &mut self.scanner {
+            Some(scanner) => scanner,
+            None => return,
+        };
+        let mut found = Vec::new();
+        scanner.update(input, &mut found);
+        let next = scanner.next.output;
+        for boundary in found {
+            let last = match (self.pending.back(), self.list.last()) {
+                (Some(pending), _) => pending.output,
+                (None, Some(checkpoint)) => checkpoint.output,
+                (None, None) => 0,
+            };
+            if boundary.output >= last + self.spacing {
+                self.pending.push_back(boundary);
+            }
+        }
+
+        self.history.extend_from_slice(output);
+        self.output += output.len() as u64;
+        while let Some(&boundary) = self.pending.front() {
+            if boundary.output > self.output {
+                break;
+            }
+            self.pending.pop_front();
+            let before = (self.output - boundary.output) as usize;
+            let end = match self.history.len().checked_sub(before) {
+                Some(end) => end,
+                None => continue,
+            };
+            if end >= WINDOW_SIZE || self.history_complete {
+                self.list.push(Checkpoint {
+                    input_bits: boundary.input_bits,
+                    output: boundary.output,
+                    window: self.history[end.saturating_sub(WINDOW_SIZE)..end].to_vec(),
+                });
+            }
+        }
+
+        // Keep the data that the windows of the blocks still to be found may need
+        let first = self
+            .pending
+            .front()
+            .map_or(next, |boundary| boundary.output);
+        let keep = (self.output + WINDOW_SIZE as u64).saturating_sub(first) as usize;
+        if self.history.len() > keep {
+            self.history.drain(..self.history.len() - keep);
+            self.history_complete = false;
+        }
+    }
+
+    /// The position of the last checkpoint at or before `output`, in the decompressed data
+    pub fn before(&self, output: u64) -> Option<u64> {
+        let index = match self.list.binary_search_by_key(&output, |c| c.output) {
+            Ok(index) => index,
+            Err(index) => index.checked_sub(1)?,
+        };
+        Some(self.list[index].output)
+    }
+
+    /// The checkpoint at `output` in the decompressed data
+    pub fn get(&self, output: u64) -> Option<&Checkpoint> {
+        self.list
+            .binary_search_by_key(&output, |c| c.output)
+            .ok()
+            .map(|index| &self.list[index])
+    }
+}
+
+/// Where a block starts
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Boundary {
+    input_bits: u64,
+    output: u64,
+}
+
+/// Finds the blocks of a deflate stream without decompressing it, by only decoding how long
+/// its literals and matches are
+struct BlockScanner {
+    // Compressed data that wasn't scanned yet, starting with the byte that holds the next block
+    data: Vec<u8>,
+    // Bit of the first byte at which the next block starts
+    bit: usize,
+    next: Boundary,
+    // Scan again once there is this much data, so that a long block isn't scanned over and
+    // over while it comes in
+    retry_at: usize,
+    done: bool,
+}
+
+impl BlockScanner {
+    fn new(next: Boundary) -> BlockScanner {
+        BlockScanner {
+            data: Vec::new(),
+            bit: 0,
+            next,
+            retry_at: 0,
+            done: false,
+        }
+    }
+
+    /// Add compressed data, adding the blocks that were found to start in it to `found`
+    fn update(&mut self, input: &[u8], found: &mut Vec<Boundary>) {
+        if self.done {
+            return;
+        }
+        self.data.extend_from_slice(input);
+        if self.data.len() < self.retry_at {
+            return;
+        }
+
+        let mut bits = Bits {
+            data: &self.data,
+            position: self.bit,
+        };
+        loop {
+            let start = bits.position;
+            match scan_block(&mut bits) {
+                Ok((output, last)) => {
+                    self.next.input_bits += (bits.position - start) as u64;
+                    self.next.output += output;
+                    if last {
+                        self.done = true;
+                        break;
+                    }
+                    found.push(self.next);
+                }
+                Err(ScanError::Incomplete) => {
+                    bits.position = start;
+                    break;
+                }
+                // The inflater reports this when it gets there
+                Err(ScanError::Invalid) => {
+                    self.done = true;
+                    break;
+                }
+            }
+        }
+
+        let position = bits.position;
+        if self.done {
+            self.data = Vec::new();
+            return;
+        }
+        self.data.drain(..position / 8);
+        self.bit = position % 8;
+        self.retry_at = self.data.len() * 2;
+    }
+}
+
+enum ScanError {
+    Incomplete,
+    Invalid,
+}
+
+/// Reads a deflate stream bit by bit, least significant bit first
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Bits<'a> {
+    fn skip(&mut self, count: usize) -> Result<(), ScanError> {
+        if self.position + count > self.data.len() * 8 {
+            return Err(ScanError::Incomplete);
+        }
+        self.position += count;
+        Ok(())
+    }
+
+    fn take(&mut self, count: usize) -> Result<u32, ScanError> {
+        let start = self.position;
+        self.skip(count)?;
+        let mut value = 0;
+        for i in 0..count {
+            let position = start + i;
+            let bit = (self.data[position / 8] >> (position % 8)) & 1;
+            value |= u32::from(bit) << i;
+        }
+        Ok(value)
+    }
+}
+
+/// A canonical Huffman code, as the number of codes of each length and the symbols in the
+/// order of their codes
+struct Huffman {
+    count: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, ScanError> {
+        let mut count = [0u16; 16];
+        for &length in lengths {
+            count[length as usize] += 1;
+        }
+        let mut left = 1i32;
+        for &count in &count[1..] {
+            left = (left << 1) - i32::from(count);
+            if left < 0 {
+                return Err(ScanError::Invalid);
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + count[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Ok(Huffman { count, symbols })
+    }
+
+    fn decode(&self, bits: &mut Bits<'_>) -> Result<u16, ScanError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.count[1..] {
+            code |= bits.take(1)? as i32;
+            let count = i32::from(count);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(ScanError::Invalid)
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// The order in which the lengths of the code length code are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Skip over a block, returning how much data it decompresses to and whether it's the last one
+fn scan_block(bits: &mut Bits<'_>) -> Result<(u64, bool), ScanError> {
+    let last = bits.take(1)? == 1;
+    let output = match bits.take(2)? {
+        0 => {
+            bits.skip((8 - bits.position % 8) % 8)?;
+            let length = bits.take(16)?;
+            if bits.take(16)? != !length & 0xffff {
+                return Err(ScanError::Invalid);
+            }
+            bits.skip(length as usize * 8)?;
+            u64::from(length)
+        }
+        1 => {
+            let mut lengths = [8u8; 288];
+            for length in &mut lengths[144..256] {
+                *length = 9;
+            }
+            for length in &mut lengths[256..280] {
+                *length = 7;
+            }
+            scan_codes(bits, &Huffman::new(&lengths)?, &Huffman::new(&[5; 30])?)?
+        }
+        2 => {
+            let literals = bits.take(5)? as usize + 257;
+            let distances = bits.take(5)? as usize + 1;
+            let code_lengths = bits.take(4)? as usize + 4;
+            if literals > 286 || distances > 30 {
+                return Err(ScanError::Invalid);
+            }
+            let mut lengths = [0u8; 316];
+            for &symbol in &CODE_LENGTH_ORDER[..code_lengths] {
+                lengths[symbol] = bits.take(3)? as u8;
+            }
+            let code = Huffman::new(&lengths[..19])?;
+
+            let total = literals + distances;
+            let mut index = 0;
+            while index < total {
+                let symbol = code.decode(bits)?;
+                if symbol < 16 {
+                    lengths[index] = symbol as u8;
+                    index += 1;
+                    continue;
+                }
+                let (length, repeat) = match symbol {
+                    16 if index > 0 => (lengths[index - 1], 3 + bits.take(2)? as usize),
+                    16 => return Err(ScanError::Invalid),
+                    17 => (0, 3 + bits.take(3)? as usize),
+                    _ => (0, 11 + bits.take(7)? as usize),
+                };
+                if index + repeat > total {
+                    return Err(ScanError::Invalid);
+                }
+                for entry in &mut lengths[index..index + repeat] {
+                    *entry = length;
+                }
+                index += repeat;
+            }
+            if lengths[256] == 0 {
+                return Err(ScanError::Invalid);
+            }
+            scan_codes(
+                bits,
+                &Huffman::new(&lengths[..literals])?,
+                &Huffman::new(&lengths[literals..total])?,
+            )?
+        }
+        _ => return Err(ScanError::Invalid),
+    };
+    Ok((output, last))
+}
+
+/// Skip over the literals and matches of a compressed block, returning how much data they
+/// decompress to
+fn scan_codes(
+    bits: &mut Bits<'_>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<u64, ScanError> {
+    let mut output = 0;
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        if symbol < 256 {
+            output += 1;
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(output);
+        }
+        let symbol = symbol - 257;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(ScanError::Invalid);
+        }
+        output += u64::from(LENGTH_BASE[symbol]);
+        output += u64::from(bits.take(LENGTH_EXTRA[symbol] as usize)?);
+        let symbol = distances.decode(bits)? as usize;
+        if symbol >= DISTANCE_EXTRA.len() {
+            return Err(ScanError::Invalid);
+        }
+        bits.skip(DISTANCE_EXTRA[symbol] as usize)?;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    ))]
+    #[test]
+    fn checkpoints_at_blocks() {
+        use super::{Checkpoints, WINDOW_SIZE};
+        use flate2::{Compression, Decompress, FlushDecompress};
+        use std::io::Write;
+
+        let mut state = 1u32;
+        let contents: Vec<u8> = (0..300_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                b"abcdefgh "[(state >> 24) as usize % 9]
+            })
+            .collect();
+        let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&contents).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        // Fed in small pieces, as they come from a reader
+        let mut checkpoints = Checkpoints::with_spacing(50_000);
+        checkpoints.start(0, 0, &[]);
+        let mut decompress = Decompress::new(false);
+        let mut output = vec![0; 1000];
+        let mut input = &compressed[..];
+        while decompress.total_out() < contents.len() as u64 {
+            let (before_in, before_out) = (decompress.total_in(), decompress.total_out());
+            let piece = &input[..std::cmp::min(700, input.len())];
+            decompress
+                .decompress(piece, &mut output, FlushDecompress::None)
+                .unwrap();
+            let consumed = (decompress.total_in() - before_in) as usize;
+            let produced = (decompress.total_out() - before_out) as usize;
+            checkpoints.update(&input[..consumed], &output[..produced]);
+            input = &input[consumed..];
+        }
+        assert!(checkpoints.list.len() >= 3);
+
+        // Decompressing from a checkpoint, as the inflater does it
+        for checkpoint in &checkpoints.list {
+            let start = checkpoint.output as usize;
+            assert_eq!(checkpoint.window.len(), WINDOW_SIZE);
+            assert_eq!(checkpoint.window, &contents[start - WINDOW_SIZE..start]);
+
+            let shift = checkpoint.input_bits % 8;
+            let rest = &compressed[(checkpoint.input_bits / 8) as usize..];
+            let mut stream = vec![0];
+            stream.extend_from_slice(&(WINDOW_SIZE as u16).to_le_bytes());
+            stream.extend_from_slice(&(!(WINDOW_SIZE as u16)).to_le_bytes());
+            stream.extend_from_slice(&checkpoint.window);
+            for (i, &byte) in rest.iter().enumerate() {
+                let next = rest.get(i + 1).map_or(0, |&next| u16::from(next));
+                stream.push(((u16::from(byte) | next << 8) >> shift) as u8);
+            }
+            let mut decompress = Decompress::new(false);
+            let mut output = vec![0; WINDOW_SIZE + contents.len() - start];
+            decompress
+                .decompress(&stream, &mut output, FlushDecompress::Finish)
+                .unwrap();
+            assert_eq!(&output[WINDOW_SIZE..], &contents[start..]);
+        }
+        assert_eq!(checkpoints.before(49_999), None);
+        let second = checkpoints.list[1].output;
+        assert_eq!(checkpoints.before(second + 1), Some(second));
+    }
+}
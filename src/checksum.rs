@@ -0,0 +1,297 @@
+//! Helper module to compute checksums of data as it is read
+
+use std::io;
+use std::io::prelude::*;
+
+/// A hash computed over the contents of a file as they are decompressed, see
+/// [`ZipFile::hash_with`](crate::read::ZipFile::hash_with)
+///
+/// With the `digest` feature, this is implemented for every hash function that implements the
+/// [digest](https://docs.rs/digest) traits, like SHA-256. Other hashes, like xxh3, can be used
+/// through a wrapper type.
+pub trait Hasher {
+    /// Add `data` to the hash
+    fn update(&mut self, data: &[u8]);
+}
+
+#[cfg(feature = "digest")]
+impl<D: digest::Input> Hasher for D {
+    fn update(&mut self, data: &[u8]) {
+        digest::Input::input(self, data)
+    }
+}
+
+/// A checksum that a [`ChecksumReader`] checks the data against
+pub(crate) trait Checksum {
+    type Output: PartialEq;
+
+    fn update(&mut self, data: &[u8]);
+
+    fn value(&self) -> Self::Output;
+}
+
+impl Checksum for crc32fast::Hasher {
+    type Output = u32;
+
+    fn update(&mut self, data: &[u8]) {
+        crc32fast::Hasher::update(self, data)
+    }
+
+    fn value(&self) -> u32 {
+        self.clone().finalize()
+    }
+}
+
+/// Reader that validates a checksum when it reaches the EOF, and can feed the data to another
+/// hasher on the way.
+pub(crate) struct ChecksumReader<'h, R, H: Checksum> {
+    inner: R,
+    hasher: H,
+    check: Option<H::Output>,
+    extra: Option<&'h mut dyn Hasher>,
+}
+
+/// Reader that validates the CRC32 when it reaches the EOF.
+pub(crate) type Crc32Reader<'h, R> = ChecksumReader<'h, R, crc32fast::Hasher>;
+
+impl<'h, R, H: Checksum + Default> ChecksumReader<'h, R, H> {
+    /// Get a new reader which checks the inner reader against checksum.
+    pub fn new(inner: R, checksum: H::Output) -> ChecksumReader<'h, R, H> {
+        ChecksumReader {
+            inner,
+            hasher: H::default(),
+            check: Some(checksum),
+            extra: None,
+        }
+    }
+
+    /// Get a new reader which passes data through without computing a checksum.
+    pub fn new_unchecked(inner: R) -> ChecksumReader<'h, R, H> {
+        ChecksumReader {
+            inner,
+            hasher: H::default(),
+            check: None,
+            extra: None,
+        }
+    }
+}
+
+impl<'h, R, H: Checksum> ChecksumReader<'h, R, H> {
+    fn check_matches(&self) -> bool {
+        match self.check {
+            Some(ref check) => *check == self.hasher.value(),
+            None => true,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        if self.check.is_some() {
+            self.hasher.update(data);
+        }
+        if let Some(extra) = self.extra.as_mut() {
+            extra.update(data);
+        }
+    }
+
+    /// Feed all data that is read from now on to `hasher` as well.
+    pub fn set_extra_hasher(&mut self, hasher: &'h mut dyn Hasher) {
+        self.extra = Some(hasher);
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Stop checking the checksum, for when not all of the data will pass through this reader.
+    pub fn disable_check(&mut self) {
+        self.check = None;
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<'h, R: Read, H: Checksum> Read for ChecksumReader<'h, R, H> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = match self.inner.read(buf) {
+            Ok(0) if !buf.is_empty() && !self.check_matches() => {
+                return Err(io::Error::new(io::ErrorKind::Other, "Invalid checksum"))
+            }
+            Ok(n) => n,
+            Err(e) => return Err(e),
+        };
+        self.update(&buf[0..count]);
+        Ok(count)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        let count = match self.inner.read_vectored(bufs) {
+            Ok(0) if bufs.iter().any(|buf| !buf.is_empty()) && !self.check_matches() => {
+                return Err(io::Error::new(io::ErrorKind::Other, "Invalid checksum"))
+            }
+            Ok(n) => n,
+            Err(e) => return Err(e),
+        };
+        let mut remaining = count;
+        for buf in bufs.iter() {
+            let len = std::cmp::min(remaining, buf.len());
+            self.update(&buf[..len]);
+            remaining -= len;
+        }
+        Ok(count)
+    }
+}
+
+/// The data is hashed as it is consumed, so what was read ahead but not consumed yet isn't part
+/// of the checksum.
+impl<'h, R: BufRead, H: Checksum> BufRead for ChecksumReader<'h, R, H> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let matches = self.check_matches();
+        let data = self.inner.fill_buf()?;
+        if data.is_empty() && !matches {
+            return Err(io::Error::new(io::ErrorKind::Other, "Invalid checksum"));
+        }
+        Ok(data)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // Gets the buffered data back without reading
+        if let Ok(data) = self.inner.fill_buf() {
+            let data = &data[..std::cmp::min(amt, data.len())];
+            if self.check.is_some() {
+                self.hasher.update(data);
+            }
+            if let Some(extra) = self.extra.as_mut() {
+                extra.update(data);
+            }
+        }
+        self.inner.consume(amt);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_empty_reader() {
+        let data: &[u8] = b"";
+        let mut buf = [0; 1];
+
+        let mut reader = Crc32Reader::new(data, 0);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+        let mut reader = Crc32Reader::new(data, 1);
+        assert!(reader
+            .read(&mut buf)
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid checksum"));
+    }
+
+    #[test]
+    fn test_byte_by_byte() {
+        let data: &[u8] = b"1234";
+        let mut buf = [0; 1];
+
+        let mut reader = Crc32Reader::new(data, 0x9be3e0a3);
+        assert_eq!(reader.read(&mut buf).unwrap(), 1);
+        assert_eq!(reader.read(&mut buf).unwrap(), 1);
+        assert_eq!(reader.read(&mut buf).unwrap(), 1);
+        assert_eq!(reader.read(&mut buf).unwrap(), 1);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        // Can keep reading 0 bytes after the end
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_unchecked() {
+        let data: &[u8] = b"1234";
+        let mut buf = [0; 5];
+
+        let mut reader = Crc32Reader::new_unchecked(data);
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_zero_read() {
+        let data: &[u8] = b"1234";
+        let mut buf = [0; 5];
+
+        let mut reader = Crc32Reader::new(data, 0x9be3e0a3);
+        assert_eq!(reader.read(&mut buf[..0]).unwrap(), 0);
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_buf_read() {
+        let data: &[u8] = b"1234";
+
+        let mut reader = Crc32Reader::new(data, 0x9be3e0a3);
+        assert_eq!(reader.fill_buf().unwrap(), b"1234");
+        reader.consume(1);
+        assert_eq!(reader.fill_buf().unwrap(), b"234");
+        reader.consume(3);
+        assert_eq!(reader.fill_buf().unwrap(), b"");
+
+        let mut reader = Crc32Reader::new(data, 0x9be3e0a3);
+        reader.consume(3);
+        assert!(reader.fill_buf().is_ok());
+        reader.consume(0);
+        let mut rest = Vec::new();
+        assert!(reader.read_to_end(&mut rest).is_ok());
+        assert_eq!(rest, b"4");
+
+        let mut reader = Crc32Reader::new(data, 1);
+        reader.consume(4);
+        assert!(reader.fill_buf().is_err());
+    }
+
+    #[test]
+    fn test_extra_hasher() {
+        struct Collect(Vec<u8>);
+
+        impl Hasher for Collect {
+            fn update(&mut self, data: &[u8]) {
+                self.0.extend_from_slice(data);
+            }
+        }
+
+        let data: &[u8] = b"1234";
+        let mut buf = [0; 2];
+        let mut collect = Collect(Vec::new());
+
+        let mut reader = Crc32Reader::new(data, 0x9be3e0a3);
+        assert_eq!(reader.read(&mut buf).unwrap(), 2);
+        reader.set_extra_hasher(&mut collect);
+        assert_eq!(reader.read(&mut buf).unwrap(), 2);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        assert_eq!(collect.0, b"34");
+    }
+
+    #[test]
+    fn test_vectored() {
+        let data: &[u8] = b"1234";
+        let (mut first, mut second) = ([0; 3], [0; 3]);
+
+        let mut reader = Crc32Reader::new(data, 0x9be3e0a3);
+        let mut bufs = [
+            io::IoSliceMut::new(&mut first),
+            io::IoSliceMut::new(&mut second),
+        ];
+        assert_eq!(reader.read_vectored(&mut bufs).unwrap(), 4);
+        assert_eq!(reader.read_vectored(&mut bufs).unwrap(), 0);
+        assert_eq!((&first, &second[..1]), (b"123", &b"4"[..]));
+
+        let mut reader = Crc32Reader::new(data, 1);
+        let mut bufs = [
+            io::IoSliceMut::new(&mut first),
+            io::IoSliceMut::new(&mut second),
+        ];
+        assert_eq!(reader.read_vectored(&mut bufs).unwrap(), 4);
+        assert!(reader.read_vectored(&mut bufs).is_err());
+    }
+}
@@ -10,7 +10,7 @@ use std::fmt;
 ///
 /// When creating ZIP files, you may choose the method to use with
 /// [`zip::write::FileOptions::compression_method`]
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum CompressionMethod {
     /// Store the file as is
     Stored,
@@ -112,6 +112,30 @@ impl CompressionMethod {
     }
 }
 
+/// Name of a compression method defined for the ZIP format, by its numeric id
+pub(crate) fn method_name(id: u16) -> Option<&'static str> {
+    Some(match id {
+        0 => "Stored",
+        1 => "Shrink",
+        2..=5 => "Reduce",
+        6 => "Implode",
+        8 => "Deflate",
+        9 => "Deflate64",
+        10 => "PKWARE Implode",
+        12 => "BZIP2",
+        14 => "LZMA",
+        16 => "IBM z/OS CMPSC",
+        18 => "IBM TERSE",
+        20 | 93 => "Zstandard",
+        94 => "MP3",
+        95 => "XZ",
+        96 => "JPEG",
+        97 => "WavPack",
+        98 => "PPMd",
+        _ => return None,
+    })
+}
+
 impl fmt::Display for CompressionMethod {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // Just duplicate what the Debug format looks like, i.e, the enum key:
@@ -134,6 +158,7 @@ mod test {
         }
     }
 
+    #[allow(clippy::vec_init_then_push)]
     fn methods() -> Vec<CompressionMethod> {
         let mut methods = Vec::new();
         methods.push(CompressionMethod::Stored);
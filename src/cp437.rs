@@ -1,6 +1,7 @@
 //! Convert a string in IBM codepage 437 to UTF-8
 
 /// Trait to convert IBM codepage 437 to the target type
+#[allow(clippy::wrong_self_convention)]
 pub trait FromCp437 {
     /// Target type
     type Target;
@@ -188,9 +189,9 @@ mod test {
     #[test]
     fn example_slice() {
         use super::FromCp437;
-        let data = b"Cura\x87ao";
-        assert!(::std::str::from_utf8(data).is_err());
-        assert_eq!(data.from_cp437(), "Curaçao");
+        let data = b"Cura\x87ao".to_vec();
+        assert!(::std::str::from_utf8(&data).is_err());
+        assert_eq!(data.as_slice().from_cp437(), "Curaçao");
     }
 
     #[test]
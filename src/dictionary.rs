@@ -0,0 +1,53 @@
+//! Preset dictionaries for deflated files
+//!
+//! A file compressed with a preset dictionary can refer back to the dictionary as if it came
+//! right before the file, which helps a lot for small files that look alike. The zip format has
+//! no place for this, so such files get an extra field holding the CRC32 of the dictionary, and
+//! can only be decompressed by a reader that is given the same dictionary.
+#![cfg_attr(
+    not(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    )),
+    allow(dead_code)
+)]
+
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Header id of the extra field, "zd"
+pub const EXTRA_FIELD_ID: u16 = 0x647a;
+
+/// Deflate can only refer back 32 KiB, anything before that is of no use.
+pub fn window(dictionary: &[u8]) -> &[u8] {
+    const WINDOW_SIZE: usize = 32 * 1024;
+    &dictionary[dictionary.len().saturating_sub(WINDOW_SIZE)..]
+}
+
+/// The id stored in the extra field, for a dictionary that was cut down with [`window`]
+pub fn id(dictionary: &[u8]) -> u32 {
+    crc32fast::hash(dictionary)
+}
+
+/// Extra field marking a file that was compressed with `dictionary`
+pub fn extra_field(dictionary: &[u8]) -> Vec<u8> {
+    let mut field = vec![0; 8];
+    LittleEndian::write_u16(&mut field[0..], EXTRA_FIELD_ID);
+    LittleEndian::write_u16(&mut field[2..], 4);
+    LittleEndian::write_u32(&mut field[4..], id(dictionary));
+    field
+}
+
+/// Get the id of the dictionary a file was compressed with, if any
+pub fn find_id(mut extra_field: &[u8]) -> Option<u32> {
+    while extra_field.len() >= 4 {
+        let kind = LittleEndian::read_u16(&extra_field[0..]);
+        let len = LittleEndian::read_u16(&extra_field[2..]) as usize;
+        let data = extra_field.get(4..4 + len)?;
+        if kind == EXTRA_FIELD_ID && len == 4 {
+            return Some(LittleEndian::read_u32(data));
+        }
+        extra_field = &extra_field[4 + len..];
+    }
+    None
+}
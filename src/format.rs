@@ -0,0 +1,538 @@
+//! The records an archive is made of
+//!
+//! Every record is stored as it is on disk, without interpretation: sizes that don't fit are
+//! `0xFFFFFFFF` with the real value in a ZIP64 extra field, names are raw bytes, and times are
+//! in MS-DOS format. `read_from` expects the reader at the signature of the record, and
+//! `write_to` writes the signature as well. This is meant for tools that need to patch or
+//! inspect an archive below what [`ZipArchive`](crate::read::ZipArchive) and
+//! [`ZipWriter`](crate::write::ZipWriter) offer; nothing is checked for consistency, except that
+//! `write_to` fails for names, extra fields and comments too long for their 16-bit lengths.
+
+use crate::result::{InvalidArchiveKind, ZipError, ZipResult};
+use crate::spec::{
+    CENTRAL_DIRECTORY_END_SIGNATURE, CENTRAL_DIRECTORY_HEADER_SIGNATURE,
+    LOCAL_FILE_HEADER_SIGNATURE, ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIGNATURE,
+    ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE,
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io;
+use std::io::prelude::*;
+
+fn read_bytes<T: Read>(reader: &mut T, len: usize) -> ZipResult<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Get the length of a variable-length field, which has to fit in 16 bits
+fn field_length(field: &[u8], error: &'static str) -> ZipResult<u16> {
+    if field.len() > 0xFFFF {
+        return Err(ZipError::Io(io::Error::new(io::ErrorKind::Other, error)));
+    }
+    Ok(field.len() as u16)
+}
+
+/// The header in front of the data of every file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LocalFileHeader {
+    /// Version of the specification needed to extract the file
+    pub version_needed: u16,
+    /// General purpose bit flags
+    pub flags: u16,
+    /// Numeric id of the compression method
+    pub compression_method: u16,
+    /// Last modification time, in MS-DOS format
+    pub last_mod_time: u16,
+    /// Last modification date, in MS-DOS format
+    pub last_mod_date: u16,
+    /// CRC32 of the uncompressed data
+    pub crc32: u32,
+    /// Size of the compressed data
+    pub compressed_size: u32,
+    /// Size of the uncompressed data
+    pub uncompressed_size: u32,
+    /// Name of the file
+    pub file_name: Vec<u8>,
+    /// Extra field
+    pub extra_field: Vec<u8>,
+}
+
+impl LocalFileHeader {
+    /// Read a local file header, up to the start of the file data
+    pub fn read_from<T: Read>(reader: &mut T) -> ZipResult<LocalFileHeader> {
+        let signature = reader.read_u32::<LittleEndian>()?;
+        if signature != LOCAL_FILE_HEADER_SIGNATURE {
+            return Err(ZipError::invalid(
+                InvalidArchiveKind::InvalidLocalFileHeader,
+            ));
+        }
+        let version_needed = reader.read_u16::<LittleEndian>()?;
+        let flags = reader.read_u16::<LittleEndian>()?;
+        let compression_method = reader.read_u16::<LittleEndian>()?;
+        let last_mod_time = reader.read_u16::<LittleEndian>()?;
+        let last_mod_date = reader.read_u16::<LittleEndian>()?;
+        let crc32 = reader.read_u32::<LittleEndian>()?;
+        let compressed_size = reader.read_u32::<LittleEndian>()?;
+        let uncompressed_size = reader.read_u32::<LittleEndian>()?;
+        let file_name_length = reader.read_u16::<LittleEndian>()? as usize;
+        let extra_field_length = reader.read_u16::<LittleEndian>()? as usize;
+        Ok(LocalFileHeader {
+            version_needed,
+            flags,
+            compression_method,
+            last_mod_time,
+            last_mod_date,
+            crc32,
+            compressed_size,
+            uncompressed_size,
+            file_name: read_bytes(reader, file_name_length)?,
+            extra_field: read_bytes(reader, extra_field_length)?,
+        })
+    }
+
+    /// Write the local file header
+    pub fn write_to<T: Write>(&self, writer: &mut T) -> ZipResult<()> {
+        let file_name_length = field_length(&self.file_name, "File name is too long")?;
+        let extra_field_length = field_length(&self.extra_field, "Extra field is too long")?;
+        writer.write_u32::<LittleEndian>(LOCAL_FILE_HEADER_SIGNATURE)?;
+        writer.write_u16::<LittleEndian>(self.version_needed)?;
+        writer.write_u16::<LittleEndian>(self.flags)?;
+        writer.write_u16::<LittleEndian>(self.compression_method)?;
+        writer.write_u16::<LittleEndian>(self.last_mod_time)?;
+        writer.write_u16::<LittleEndian>(self.last_mod_date)?;
+        writer.write_u32::<LittleEndian>(self.crc32)?;
+        writer.write_u32::<LittleEndian>(self.compressed_size)?;
+        writer.write_u32::<LittleEndian>(self.uncompressed_size)?;
+        writer.write_u16::<LittleEndian>(file_name_length)?;
+        writer.write_u16::<LittleEndian>(extra_field_length)?;
+        writer.write_all(&self.file_name)?;
+        writer.write_all(&self.extra_field)?;
+        Ok(())
+    }
+}
+
+/// The entry for a file in the central directory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CentralDirectoryHeader {
+    /// System and version of the specification the file was written with
+    pub version_made_by: u16,
+    /// Version of the specification needed to extract the file
+    pub version_needed: u16,
+    /// General purpose bit flags
+    pub flags: u16,
+    /// Numeric id of the compression method
+    pub compression_method: u16,
+    /// Last modification time, in MS-DOS format
+    pub last_mod_time: u16,
+    /// Last modification date, in MS-DOS format
+    pub last_mod_date: u16,
+    /// CRC32 of the uncompressed data
+    pub crc32: u32,
+    /// Size of the compressed data
+    pub compressed_size: u32,
+    /// Size of the uncompressed data
+    pub uncompressed_size: u32,
+    /// Number of the disk the local header is on
+    pub disk_number: u16,
+    /// Internal file attributes
+    pub internal_attributes: u16,
+    /// External file attributes
+    pub external_attributes: u32,
+    /// Offset of the local header, relative to the start of its disk
+    pub local_header_offset: u32,
+    /// Name of the file
+    pub file_name: Vec<u8>,
+    /// Extra field
+    pub extra_field: Vec<u8>,
+    /// File comment
+    pub file_comment: Vec<u8>,
+}
+
+impl CentralDirectoryHeader {
+    /// Read a central directory header
+    pub fn read_from<T: Read>(reader: &mut T) -> ZipResult<CentralDirectoryHeader> {
+        let signature = reader.read_u32::<LittleEndian>()?;
+        if signature != CENTRAL_DIRECTORY_HEADER_SIGNATURE {
+            return Err(ZipError::invalid(
+                InvalidArchiveKind::InvalidCentralDirectoryHeader,
+            ));
+        }
+        let version_made_by = reader.read_u16::<LittleEndian>()?;
+        let version_needed = reader.read_u16::<LittleEndian>()?;
+        let flags = reader.read_u16::<LittleEndian>()?;
+        let compression_method = reader.read_u16::<LittleEndian>()?;
+        let last_mod_time = reader.read_u16::<LittleEndian>()?;
+        let last_mod_date = reader.read_u16::<LittleEndian>()?;
+        let crc32 = reader.read_u32::<LittleEndian>()?;
+        let compressed_size = reader.read_u32::<LittleEndian>()?;
+        let uncompressed_size = reader.read_u32::<LittleEndian>()?;
+        let file_name_length = reader.read_u16::<LittleEndian>()? as usize;
+        let extra_field_length = reader.read_u16::<LittleEndian>()? as usize;
+        let file_comment_length = reader.read_u16::<LittleEndian>()? as usize;
+        let disk_number = reader.read_u16::<LittleEndian>()?;
+        let internal_attributes = reader.read_u16::<LittleEndian>()?;
+        let external_attributes = reader.read_u32::<LittleEndian>()?;
+        let local_header_offset = reader.read_u32::<LittleEndian>()?;
+        Ok(CentralDirectoryHeader {
+            version_made_by,
+            version_needed,
+            flags,
+            compression_method,
+            last_mod_time,
+            last_mod_date,
+            crc32,
+            compressed_size,
+            uncompressed_size,
+            disk_number,
+            internal_attributes,
+            external_attributes,
+            local_header_offset,
+            file_name: read_bytes(reader, file_name_length)?,
+            extra_field: read_bytes(reader, extra_field_length)?,
+            file_comment: read_bytes(reader, file_comment_length)?,
+        })
+    }
+
+    /// Write the central directory header
+    pub fn write_to<T: Write>(&self, writer: &mut T) -> ZipResult<()> {
+        let file_name_length = field_length(&self.file_name, "File name is too long")?;
+        let extra_field_length = field_length(&self.extra_field, "Extra field is too long")?;
+        let file_comment_length = field_length(&self.file_comment, "File comment is too long")?;
+        writer.write_u32::<LittleEndian>(CENTRAL_DIRECTORY_HEADER_SIGNATURE)?;
+        writer.write_u16::<LittleEndian>(self.version_made_by)?;
+        writer.write_u16::<LittleEndian>(self.version_needed)?;
+        writer.write_u16::<LittleEndian>(self.flags)?;
+        writer.write_u16::<LittleEndian>(self.compression_method)?;
+        writer.write_u16::<LittleEndian>(self.last_mod_time)?;
+        writer.write_u16::<LittleEndian>(self.last_mod_date)?;
+        writer.write_u32::<LittleEndian>(self.crc32)?;
+        writer.write_u32::<LittleEndian>(self.compressed_size)?;
+        writer.write_u32::<LittleEndian>(self.uncompressed_size)?;
+        writer.write_u16::<LittleEndian>(file_name_length)?;
+        writer.write_u16::<LittleEndian>(extra_field_length)?;
+        writer.write_u16::<LittleEndian>(file_comment_length)?;
+        writer.write_u16::<LittleEndian>(self.disk_number)?;
+        writer.write_u16::<LittleEndian>(self.internal_attributes)?;
+        writer.write_u32::<LittleEndian>(self.external_attributes)?;
+        writer.write_u32::<LittleEndian>(self.local_header_offset)?;
+        writer.write_all(&self.file_name)?;
+        writer.write_all(&self.extra_field)?;
+        writer.write_all(&self.file_comment)?;
+        Ok(())
+    }
+}
+
+/// The end of central directory record, at the end of every archive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CentralDirectoryEnd {
+    /// Number of this disk
+    pub disk_number: u16,
+    /// Number of the disk the central directory starts on
+    pub disk_with_central_directory: u16,
+    /// Number of central directory headers on this disk
+    pub number_of_files_on_this_disk: u16,
+    /// Number of central directory headers in total
+    pub number_of_files: u16,
+    /// Size of the central directory
+    pub central_directory_size: u32,
+    /// Offset of the central directory, relative to the start of its disk
+    pub central_directory_offset: u32,
+    /// Comment of the archive
+    pub zip_file_comment: Vec<u8>,
+}
+
+impl CentralDirectoryEnd {
+    /// Read an end of central directory record
+    pub fn read_from<T: Read>(reader: &mut T) -> ZipResult<CentralDirectoryEnd> {
+        let magic = reader.read_u32::<LittleEndian>()?;
+        if magic != CENTRAL_DIRECTORY_END_SIGNATURE {
+            return Err(ZipError::invalid(
+                InvalidArchiveKind::InvalidCentralDirectoryEnd,
+            ));
+        }
+        let disk_number = reader.read_u16::<LittleEndian>()?;
+        let disk_with_central_directory = reader.read_u16::<LittleEndian>()?;
+        let number_of_files_on_this_disk = reader.read_u16::<LittleEndian>()?;
+        let number_of_files = reader.read_u16::<LittleEndian>()?;
+        let central_directory_size = reader.read_u32::<LittleEndian>()?;
+        let central_directory_offset = reader.read_u32::<LittleEndian>()?;
+        let zip_file_comment_length = reader.read_u16::<LittleEndian>()? as usize;
+        let mut zip_file_comment = vec![0; zip_file_comment_length];
+        reader.read_exact(&mut zip_file_comment)?;
+
+        Ok(CentralDirectoryEnd {
+            disk_number,
+            disk_with_central_directory,
+            number_of_files_on_this_disk,
+            number_of_files,
+            central_directory_size,
+            central_directory_offset,
+            zip_file_comment,
+        })
+    }
+
+    pub(crate) fn find_and_parse<T: Read + io::Seek>(
+        reader: &mut T,
+    ) -> ZipResult<(CentralDirectoryEnd, u64)> {
+        Self::find_and_parse_before(reader, ::std::u64::MAX)
+    }
+
+    /// Search backwards for the last footer that starts before `end`. Candidates that don't
+    /// parse, for example because their comment would run past the end of the file, are
+    /// skipped.
+    pub(crate) fn find_and_parse_before<T: Read + io::Seek>(
+        reader: &mut T,
+        end: u64,
+    ) -> ZipResult<(CentralDirectoryEnd, u64)> {
+        const HEADER_SIZE: u64 = 22;
+        let file_length = reader.seek(io::SeekFrom::End(0))?;
+
+        let search_upper_bound = file_length.saturating_sub(HEADER_SIZE + ::std::u16::MAX as u64);
+
+        if file_length < HEADER_SIZE {
+            return Err(ZipError::invalid(
+                InvalidArchiveKind::CentralDirectoryEndNotFound,
+            ));
+        }
+
+        // Read everything that could hold the footer at once, rather than seeking for every byte
+        let mut tail = Vec::new();
+        reader.seek(io::SeekFrom::Start(search_upper_bound))?;
+        reader
+            .take(file_length - search_upper_bound)
+            .read_to_end(&mut tail)?;
+
+        let last = ::std::cmp::min(file_length - HEADER_SIZE, end.saturating_sub(1));
+        let mut first_error = None;
+        let mut pos = last;
+        while pos >= search_upper_bound && pos < end {
+            let index = (pos - search_upper_bound) as usize;
+            if tail[index..index + 4] == CENTRAL_DIRECTORY_END_SIGNATURE.to_le_bytes() {
+                match CentralDirectoryEnd::read_from(&mut &tail[index..]) {
+                    Ok(cde) => return Ok((cde, pos)),
+                    Err(e) => {
+                        first_error.get_or_insert(e.at_offset(pos));
+                    }
+                }
+            }
+            pos = match pos.checked_sub(1) {
+                Some(p) => p,
+                None => break,
+            };
+        }
+        Err(first_error
+            .unwrap_or_else(|| ZipError::invalid(InvalidArchiveKind::CentralDirectoryEndNotFound)))
+    }
+
+    /// Write the end of central directory record
+    pub fn write_to<T: Write>(&self, writer: &mut T) -> ZipResult<()> {
+        let comment_length = field_length(&self.zip_file_comment, "Archive comment is too long")?;
+        writer.write_u32::<LittleEndian>(CENTRAL_DIRECTORY_END_SIGNATURE)?;
+        writer.write_u16::<LittleEndian>(self.disk_number)?;
+        writer.write_u16::<LittleEndian>(self.disk_with_central_directory)?;
+        writer.write_u16::<LittleEndian>(self.number_of_files_on_this_disk)?;
+        writer.write_u16::<LittleEndian>(self.number_of_files)?;
+        writer.write_u32::<LittleEndian>(self.central_directory_size)?;
+        writer.write_u32::<LittleEndian>(self.central_directory_offset)?;
+        writer.write_u16::<LittleEndian>(comment_length)?;
+        writer.write_all(&self.zip_file_comment)?;
+        Ok(())
+    }
+}
+
+/// The record pointing at the [`Zip64CentralDirectoryEnd`], right before the
+/// [`CentralDirectoryEnd`] of ZIP64 archives
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Zip64CentralDirectoryEndLocator {
+    /// Number of the disk the ZIP64 end of central directory record is on
+    pub disk_with_central_directory: u32,
+    /// Offset of the ZIP64 end of central directory record, relative to the start of its disk
+    pub end_of_central_directory_offset: u64,
+    /// Number of disks in total
+    pub number_of_disks: u32,
+}
+
+impl Zip64CentralDirectoryEndLocator {
+    /// Read a ZIP64 end of central directory locator
+    pub fn read_from<T: Read>(reader: &mut T) -> ZipResult<Zip64CentralDirectoryEndLocator> {
+        let magic = reader.read_u32::<LittleEndian>()?;
+        if magic != ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIGNATURE {
+            return Err(ZipError::invalid(InvalidArchiveKind::InvalidZip64Locator));
+        }
+        let disk_with_central_directory = reader.read_u32::<LittleEndian>()?;
+        let end_of_central_directory_offset = reader.read_u64::<LittleEndian>()?;
+        let number_of_disks = reader.read_u32::<LittleEndian>()?;
+
+        Ok(Zip64CentralDirectoryEndLocator {
+            disk_with_central_directory,
+            end_of_central_directory_offset,
+            number_of_disks,
+        })
+    }
+
+    /// Write the ZIP64 end of central directory locator
+    pub fn write_to<T: Write>(&self, writer: &mut T) -> ZipResult<()> {
+        writer.write_u32::<LittleEndian>(ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIGNATURE)?;
+        writer.write_u32::<LittleEndian>(self.disk_with_central_directory)?;
+        writer.write_u64::<LittleEndian>(self.end_of_central_directory_offset)?;
+        writer.write_u32::<LittleEndian>(self.number_of_disks)?;
+        Ok(())
+    }
+}
+
+/// The end of central directory record of ZIP64 archives, holding the values that don't fit in
+/// the [`CentralDirectoryEnd`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Zip64CentralDirectoryEnd {
+    /// System and version of the specification the archive was written with
+    pub version_made_by: u16,
+    /// Version of the specification needed to extract the archive
+    pub version_needed_to_extract: u16,
+    /// Number of this disk
+    pub disk_number: u32,
+    /// Number of the disk the central directory starts on
+    pub disk_with_central_directory: u32,
+    /// Number of central directory headers on this disk
+    pub number_of_files_on_this_disk: u64,
+    /// Number of central directory headers in total
+    pub number_of_files: u64,
+    /// Size of the central directory
+    pub central_directory_size: u64,
+    /// Offset of the central directory, relative to the start of its disk
+    pub central_directory_offset: u64,
+    /// Extensible data sector, which is empty in most archives
+    pub extensible_data: Vec<u8>,
+}
+
+impl Zip64CentralDirectoryEnd {
+    /// Read a ZIP64 end of central directory record
+    pub fn read_from<T: Read>(reader: &mut T) -> ZipResult<Zip64CentralDirectoryEnd> {
+        let magic = reader.read_u32::<LittleEndian>()?;
+        if magic != ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE {
+            return Err(ZipError::invalid(
+                InvalidArchiveKind::Zip64CentralDirectoryEndNotFound,
+            ));
+        }
+        // The size of the record, not counting the signature and this field
+        let record_size = reader.read_u64::<LittleEndian>()?;
+        let version_made_by = reader.read_u16::<LittleEndian>()?;
+        let version_needed_to_extract = reader.read_u16::<LittleEndian>()?;
+        let disk_number = reader.read_u32::<LittleEndian>()?;
+        let disk_with_central_directory = reader.read_u32::<LittleEndian>()?;
+        let number_of_files_on_this_disk = reader.read_u64::<LittleEndian>()?;
+        let number_of_files = reader.read_u64::<LittleEndian>()?;
+        let central_directory_size = reader.read_u64::<LittleEndian>()?;
+        let central_directory_offset = reader.read_u64::<LittleEndian>()?;
+        let mut extensible_data = Vec::new();
+        reader
+            .take(record_size.saturating_sub(44))
+            .read_to_end(&mut extensible_data)?;
+
+        Ok(Zip64CentralDirectoryEnd {
+            version_made_by,
+            version_needed_to_extract,
+            disk_number,
+            disk_with_central_directory,
+            number_of_files_on_this_disk,
+            number_of_files,
+            central_directory_size,
+            central_directory_offset,
+            extensible_data,
+        })
+    }
+
+    pub(crate) fn find_and_parse<T: Read + io::Seek>(
+        reader: &mut T,
+        nominal_offset: u64,
+        search_upper_bound: u64,
+    ) -> ZipResult<(Zip64CentralDirectoryEnd, u64)> {
+        let mut pos = nominal_offset;
+
+        while pos <= search_upper_bound {
+            reader.seek(io::SeekFrom::Start(pos))?;
+
+            if reader.read_u32::<LittleEndian>()? == ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE {
+                let archive_offset = pos - nominal_offset;
+                reader.seek(io::SeekFrom::Start(pos))?;
+                // The extensible data sector ends at the locator, which follows the search range
+                let locator_start = search_upper_bound + 40;
+                let footer =
+                    Zip64CentralDirectoryEnd::read_from(&mut reader.take(locator_start - pos))?;
+                return Ok((footer, archive_offset));
+            }
+
+            pos += 1;
+        }
+
+        Err(
+            ZipError::invalid(InvalidArchiveKind::Zip64CentralDirectoryEndNotFound)
+                .at_offset(nominal_offset),
+        )
+    }
+
+    /// Write the ZIP64 end of central directory record
+    pub fn write_to<T: Write>(&self, writer: &mut T) -> ZipResult<()> {
+        writer.write_u32::<LittleEndian>(ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE)?;
+        writer.write_u64::<LittleEndian>(44 + self.extensible_data.len() as u64)?; // record size
+        writer.write_u16::<LittleEndian>(self.version_made_by)?;
+        writer.write_u16::<LittleEndian>(self.version_needed_to_extract)?;
+        writer.write_u32::<LittleEndian>(self.disk_number)?;
+        writer.write_u32::<LittleEndian>(self.disk_with_central_directory)?;
+        writer.write_u64::<LittleEndian>(self.number_of_files_on_this_disk)?;
+        writer.write_u64::<LittleEndian>(self.number_of_files)?;
+        writer.write_u64::<LittleEndian>(self.central_directory_size)?;
+        writer.write_u64::<LittleEndian>(self.central_directory_offset)?;
+        writer.write_all(&self.extensible_data)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::write::{FileOptions, ZipWriter};
+
+    #[test]
+    fn records_round_trip() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.set_comment("comment");
+        writer.start_file("a.txt", FileOptions::default()).unwrap();
+        writer.write_all(b"contents").unwrap();
+        let v = writer.finish().unwrap().into_inner();
+
+        let mut reader = &v[..];
+        let local = LocalFileHeader::read_from(&mut reader).unwrap();
+        assert_eq!(local.file_name, b"a.txt");
+        let mut written = Vec::new();
+        local.write_to(&mut written).unwrap();
+        assert_eq!(written, &v[..written.len()]);
+
+        let (footer, footer_start) =
+            CentralDirectoryEnd::find_and_parse(&mut io::Cursor::new(&v)).unwrap();
+        assert_eq!(footer.number_of_files, 1);
+        assert_eq!(footer.zip_file_comment, b"comment");
+        let mut written = Vec::new();
+        footer.write_to(&mut written).unwrap();
+        assert_eq!(written, &v[footer_start as usize..]);
+
+        let central_start = footer.central_directory_offset as usize;
+        let central = CentralDirectoryHeader::read_from(&mut &v[central_start..]).unwrap();
+        assert_eq!(central.file_name, b"a.txt");
+        assert_eq!(central.local_header_offset, 0);
+        assert_eq!(central.crc32, local.crc32);
+        let mut written = Vec::new();
+        central.write_to(&mut written).unwrap();
+        assert_eq!(written, &v[central_start..footer_start as usize]);
+
+        assert!(CentralDirectoryHeader::read_from(&mut &v[..]).is_err());
+
+        // Lengths that don't fit are an error, rather than being cut off
+        let mut long = central;
+        long.file_comment = vec![b'x'; 0x10000];
+        let mut written = Vec::new();
+        assert!(long.write_to(&mut written).is_err());
+        assert!(written.is_empty());
+        long.file_comment.pop();
+        long.write_to(&mut written).unwrap();
+    }
+}
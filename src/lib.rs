@@ -6,16 +6,28 @@
 #![warn(missing_docs)]
 
 pub use crate::compression::CompressionMethod;
-pub use crate::read::ZipArchive;
-pub use crate::types::DateTime;
-pub use crate::write::ZipWriter;
+pub use crate::read::{diff, ZipArchive};
+pub use crate::types::{DateTime, System};
+pub use crate::write::{repack, ZipWriter};
 
+mod cache;
+mod cached_reader;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod checkpoint;
+mod checksum;
 mod compression;
 mod cp437;
-mod crc32;
+mod dictionary;
+pub mod format;
+#[cfg(feature = "digest")]
+pub mod manifest;
+mod pool;
 pub mod read;
+mod read_at;
 pub mod result;
-mod spec;
+pub mod sniff;
+pub mod spec;
 mod types;
 pub mod write;
 mod zipcrypto;
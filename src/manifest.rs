@@ -0,0 +1,354 @@
+//! Manifests of the digests of the files in an archive, for signing archives
+//!
+//! A [`Manifest`] lists the name, size and digest of every file. It is stored in the archive as
+//! a text file named [`MANIFEST_NAME`], with a line `<digest in hex> <size> <name>` per file.
+//! Signing the manifest then covers the contents of the whole archive: an application signs it
+//! with the scheme of its choice, stores the signature in a file named [`SIGNATURE_NAME`], and
+//! checks the signature before calling [`ZipArchive::verify_manifest`].
+//!
+//! ```
+//! use sha2::Sha256;
+//! use std::io::{Cursor, Write};
+//! use zip::write::FileOptions;
+//! use zip::{ZipArchive, ZipWriter};
+//!
+//! # fn main() -> zip::result::ZipResult<()> {
+//! let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+//! writer.start_file("hello.txt", FileOptions::default())?;
+//! writer.write_all(b"Hello, World!")?;
+//! let mut archive = ZipArchive::new(writer.finish()?)?;
+//!
+//! let manifest = archive.digest_manifest::<Sha256>()?;
+//! let mut writer = ZipWriter::new_append(archive.into_inner())?;
+//! writer.add_manifest(&manifest, FileOptions::default())?;
+//! let mut archive = ZipArchive::new(writer.finish()?)?;
+//!
+//! archive.verify_manifest::<Sha256>()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::{self, BufRead, Read, Seek, Write};
+
+use digest::generic_array::GenericArray;
+use digest::Digest;
+
+use crate::read::{ZipArchive, ZipFile};
+use crate::result::{InvalidArchiveKind, ZipError, ZipResult};
+use crate::write::{FileOptions, ZipWriter};
+
+/// Name of the manifest in an archive, which is left out of the manifest itself
+pub const MANIFEST_NAME: &str = "META-INF/DIGESTS";
+
+/// Name of the file that holds the signature of the manifest, which is left out of the manifest
+/// as well
+pub const SIGNATURE_NAME: &str = "META-INF/DIGESTS.sig";
+
+/// A file listed in a [`Manifest`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Name of the file
+    pub name: String,
+    /// Uncompressed size of the file
+    pub size: u64,
+    /// Digest of the uncompressed contents of the file
+    pub digest: Vec<u8>,
+}
+
+/// The digests of the files in an archive, in central directory order
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// The files, without directories
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Parse a manifest as it is stored in an archive.
+    ///
+    /// Fails with [`InvalidArchiveKind::InvalidManifest`] if a line can't be parsed.
+    pub fn parse(data: &[u8]) -> ZipResult<Manifest> {
+        let invalid = || ZipError::invalid(InvalidArchiveKind::InvalidManifest);
+        let text = std::str::from_utf8(data).map_err(|_| invalid())?;
+        let mut entries = Vec::new();
+        for line in text.lines() {
+            let mut parts = line.splitn(3, ' ');
+            let digest = parts.next().and_then(from_hex).ok_or_else(invalid)?;
+            let size = parts
+                .next()
+                .and_then(|size| size.parse().ok())
+                .ok_or_else(invalid)?;
+            let name = parts.next().ok_or_else(invalid)?.to_string();
+            entries.push(ManifestEntry { name, size, digest });
+        }
+        Ok(Manifest { entries })
+    }
+
+    /// Get the manifest as it is stored in an archive.
+    ///
+    /// Fails if a file name contains a line break.
+    pub fn to_bytes(&self) -> ZipResult<Vec<u8>> {
+        let mut data = Vec::new();
+        for entry in self.entries.iter() {
+            if entry.name.contains(|c| c == '\n' || c == '\r') {
+                return Err(ZipError::Io(io::Error::new(
+                    io::ErrorKind::Other,
+                    "File names in a manifest can't contain line breaks",
+                )));
+            }
+            for byte in entry.digest.iter() {
+                write!(data, "{:02x}", byte)?;
+            }
+            writeln!(data, " {} {}", entry.size, entry.name)?;
+        }
+        Ok(data)
+    }
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.is_empty() || hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Decompress the rest of `file` into a digest computed with `D`
+fn digest_contents<D: Digest>(file: &mut ZipFile) -> ZipResult<GenericArray<u8, D::OutputSize>> {
+    let mut digest = D::new();
+    loop {
+        let count = {
+            let buffer = file.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(digest.result());
+            }
+            digest.input(buffer);
+            buffer.len()
+        };
+        file.consume(count);
+    }
+}
+
+impl<R: Read + Seek> ZipArchive<R> {
+    /// Decompress the file at `index` and compute its digest with `D`.
+    ///
+    /// The contents are hashed as they are decompressed, so they are never all in memory at
+    /// once. Directories have the digest of no data.
+    pub fn hash_entry<D: Digest>(
+        &mut self,
+        index: usize,
+    ) -> ZipResult<GenericArray<u8, D::OutputSize>> {
+        digest_contents::<D>(&mut self.by_index(index)?)
+    }
+
+    /// Decompress every file and list their digests, computed with `D`.
+    ///
+    /// Directories without data are left out, as are the files named [`MANIFEST_NAME`] and
+    /// [`SIGNATURE_NAME`]. Since anything in a second file with one of those names wouldn't be
+    /// covered, archives that have one are rejected with [`InvalidArchiveKind::InvalidManifest`].
+    pub fn digest_manifest<D: Digest>(&mut self) -> ZipResult<Manifest> {
+        for &name in [MANIFEST_NAME, SIGNATURE_NAME].iter() {
+            if self.indices_for_name(name).len() > 1 {
+                return Err(ZipError::invalid(InvalidArchiveKind::InvalidManifest).in_entry(name));
+            }
+        }
+        let mut entries = Vec::new();
+        for index in 0..self.len() {
+            let mut file = self.by_index(index)?;
+            let name = file.name();
+            if (file.is_dir() && file.compressed_size() == 0)
+                || name == MANIFEST_NAME
+                || name == SIGNATURE_NAME
+            {
+                continue;
+            }
+            let digest = digest_contents::<D>(&mut file)?;
+            entries.push(ManifestEntry {
+                name: file.name().to_string(),
+                size: file.size(),
+                digest: digest.to_vec(),
+            });
+        }
+        Ok(Manifest { entries })
+    }
+
+    /// Read the manifest stored in the archive, if there is one
+    ///
+    /// Fails with [`InvalidArchiveKind::InvalidManifest`] if there are several.
+    pub fn manifest(&mut self) -> ZipResult<Option<Manifest>> {
+        if self.indices_for_name(MANIFEST_NAME).len() > 1 {
+            return Err(
+                ZipError::invalid(InvalidArchiveKind::InvalidManifest).in_entry(MANIFEST_NAME)
+            );
+        }
+        let mut file = match self.by_name(MANIFEST_NAME) {
+            Ok(file) => file,
+            Err(ZipError::FileNotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        Manifest::parse(&data).map(Some)
+    }
+
+    /// Check the files against the manifest stored in the archive, and return the manifest.
+    ///
+    /// Fails with [`ZipError::FileNotFound`] if there is no manifest, and with
+    /// [`InvalidArchiveKind::ManifestMismatch`] for the first file that was added, removed or
+    /// changed since the manifest was made. This doesn't check a signature of the manifest,
+    /// which should be done before trusting the result.
+    pub fn verify_manifest<D: Digest>(&mut self) -> ZipResult<Manifest> {
+        let manifest = self.manifest()?.ok_or(ZipError::FileNotFound)?;
+        let actual = self.digest_manifest::<D>()?;
+        let mut expected = manifest.entries.iter();
+        for entry in actual.entries.iter() {
+            if expected.next() != Some(entry) {
+                return Err(
+                    ZipError::invalid(InvalidArchiveKind::ManifestMismatch).in_entry(&entry.name)
+                );
+            }
+        }
+        if let Some(entry) = expected.next() {
+            return Err(
+                ZipError::invalid(InvalidArchiveKind::ManifestMismatch).in_entry(&entry.name)
+            );
+        }
+        Ok(manifest)
+    }
+}
+
+impl<W: Write + Seek> ZipWriter<W> {
+    /// Store `manifest` in the archive, as a file named [`MANIFEST_NAME`]
+    pub fn add_manifest(&mut self, manifest: &Manifest, options: FileOptions) -> ZipResult<()> {
+        let data = manifest.to_bytes()?;
+        self.start_file(MANIFEST_NAME, options)?;
+        self.write_all(&data)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sha2::Sha256;
+    use std::io::Cursor;
+
+    fn archive(contents: &[(&str, &str)]) -> Cursor<Vec<u8>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.add_directory("dir", FileOptions::default()).unwrap();
+        for &(name, data) in contents.iter() {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(data.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn signed(contents: &[(&str, &str)]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut archive = ZipArchive::new(archive(contents)).unwrap();
+        let manifest = archive.digest_manifest::<Sha256>().unwrap();
+        let mut writer = ZipWriter::new_append(archive.into_inner()).unwrap();
+        writer
+            .add_manifest(&manifest, FileOptions::default())
+            .unwrap();
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn manifest_round_trip() {
+        let mut archive = signed(&[("dir/a.txt", "first"), ("b with spaces", "second")]);
+        let manifest = archive.verify_manifest::<Sha256>().unwrap();
+        let names: Vec<_> = manifest.entries.iter().map(|e| &e.name[..]).collect();
+        assert_eq!(names, ["dir/a.txt", "b with spaces"]);
+        assert_eq!(manifest.entries[0].size, 5);
+        assert_eq!(
+            Manifest::parse(&manifest.to_bytes().unwrap()).unwrap(),
+            manifest
+        );
+    }
+
+    #[test]
+    fn manifest_mismatch() {
+        let mut archive = signed(&[("a.txt", "first"), ("b.txt", "second")]);
+        let manifest = archive.manifest().unwrap().unwrap();
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("a.txt", FileOptions::default()).unwrap();
+        writer.write_all(b"first").unwrap();
+        writer.start_file("b.txt", FileOptions::default()).unwrap();
+        writer.write_all(b"Second").unwrap();
+        writer
+            .add_manifest(&manifest, FileOptions::default())
+            .unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        match archive.verify_manifest::<Sha256>() {
+            Err(ZipError::InvalidArchive {
+                kind: InvalidArchiveKind::ManifestMismatch,
+                entry: Some(entry),
+                ..
+            }) => assert_eq!(entry, "b.txt"),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn hash_entry() {
+        let data = "some data".repeat(10_000);
+        let mut archive = ZipArchive::new(archive(&[("a.txt", &data)])).unwrap();
+        assert_eq!(
+            archive.hash_entry::<Sha256>(1).unwrap(),
+            Sha256::digest(data.as_bytes())
+        );
+        assert_eq!(
+            archive.hash_entry::<Sha256>(0).unwrap(),
+            Sha256::digest(b"")
+        );
+        assert!(archive.hash_entry::<Sha256>(2).is_err());
+    }
+
+    #[test]
+    fn manifest_covers_similar_names() {
+        let append = |archive: ZipArchive<Cursor<Vec<u8>>>, name: &str| {
+            let mut writer = ZipWriter::new_append(archive.into_inner()).unwrap();
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(b"unsigned").unwrap();
+            ZipArchive::new(writer.finish().unwrap()).unwrap()
+        };
+
+        let archive = signed(&[("a.txt", "first")]);
+        let mut archive = append(archive, SIGNATURE_NAME);
+        archive.verify_manifest::<Sha256>().unwrap();
+
+        let mut archive = append(archive, "META-INF/DIGESTS.evil/payload");
+        match archive.verify_manifest::<Sha256>() {
+            Err(ZipError::InvalidArchive {
+                kind: InvalidArchiveKind::ManifestMismatch,
+                entry: Some(entry),
+                ..
+            }) => assert_eq!(entry, "META-INF/DIGESTS.evil/payload"),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+
+        for &name in [MANIFEST_NAME, SIGNATURE_NAME].iter() {
+            let archive = append(signed(&[("a.txt", "first")]), name);
+            let mut archive = append(archive, SIGNATURE_NAME);
+            match archive.verify_manifest::<Sha256>() {
+                Err(ZipError::InvalidArchive {
+                    kind: InvalidArchiveKind::InvalidManifest,
+                    ..
+                }) => {}
+                other => panic!("unexpected result: {:?}", other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn missing_manifest() {
+        let mut archive = ZipArchive::new(archive(&[("a.txt", "first")])).unwrap();
+        assert!(archive.manifest().unwrap().is_none());
+        match archive.verify_manifest::<Sha256>() {
+            Err(ZipError::FileNotFound) => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+}
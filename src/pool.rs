@@ -0,0 +1,463 @@
+//! Reusable decompression state
+//!
+//! Opening an entry normally allocates a fresh input buffer and, for deflate, a fresh inflate
+//! context. A [`ZipArchive`](crate::read::ZipArchive) keeps one of each around between calls to
+//! `by_index`, so extracting many entries doesn't hit the allocator for every one of them.
+#![cfg_attr(
+    not(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib",
+        feature = "bzip2"
+    )),
+    allow(dead_code)
+)]
+
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate-miniz",
+    feature = "deflate-zlib"
+))]
+use flate2::{Decompress, FlushDecompress, Status};
+
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate-miniz",
+    feature = "deflate-zlib"
+))]
+use crate::checkpoint::Checkpoints;
+
+// Large enough that the decoders rarely have to stop for more input
+const BUFFER_SIZE: usize = 64 * 1024;
+const OUTPUT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Decompression state that is kept between reads of the same archive
+#[derive(Default)]
+pub(crate) struct DecompressorPool {
+    buffer: Option<Vec<u8>>,
+    output: Option<Vec<u8>>,
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    ))]
+    inflater: Option<Decompress>,
+}
+
+impl DecompressorPool {
+    pub fn take_buffer(&mut self) -> Vec<u8> {
+        self.buffer.take().unwrap_or_else(|| vec![0; BUFFER_SIZE])
+    }
+
+    pub fn put_buffer(&mut self, buffer: Vec<u8>) {
+        self.buffer = Some(buffer);
+    }
+
+    /// Buffer for decompressed data
+    pub fn take_output_buffer(pool: Option<&mut Self>) -> Vec<u8> {
+        pool.and_then(|pool| pool.output.take())
+            .unwrap_or_else(|| vec![0; OUTPUT_BUFFER_SIZE])
+    }
+
+    pub fn put_output_buffer(&mut self, buffer: Vec<u8>) {
+        self.output = Some(buffer);
+    }
+
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    ))]
+    pub fn take_inflater(&mut self) -> Decompress {
+        match self.inflater.take() {
+            Some(mut inflater) => {
+                inflater.reset(false);
+                inflater
+            }
+            None => Decompress::new(false),
+        }
+    }
+
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    ))]
+    pub fn put_inflater(&mut self, inflater: Decompress) {
+        self.inflater = Some(inflater);
+    }
+}
+
+// Cloning an archive doesn't need to share scratch space; the clone starts out empty.
+impl Clone for DecompressorPool {
+    fn clone(&self) -> Self {
+        DecompressorPool::default()
+    }
+}
+
+impl fmt::Debug for DecompressorPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DecompressorPool")
+    }
+}
+
+/// A `BufReader` over a buffer that can be handed back to a [`DecompressorPool`]
+///
+/// An empty buffer is only allocated once data has to be buffered.
+pub(crate) struct PooledBufReader<R> {
+    inner: R,
+    buffer: Vec<u8>,
+    pos: usize,
+    cap: usize,
+    // Bits of the data to leave out, see `skip_bits`
+    skip: u32,
+    // Byte read before the buffered data, of which the bits that weren't skipped come next
+    carry: Option<u8>,
+}
+
+impl<R> PooledBufReader<R> {
+    pub fn new(inner: R, buffer: Vec<u8>) -> PooledBufReader<R> {
+        PooledBufReader {
+            inner,
+            buffer,
+            pos: 0,
+            cap: 0,
+            skip: 0,
+            carry: None,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn is_allocated(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
+    /// Use `buffer` for reading ahead, if this reader has no buffer yet
+    pub fn allocate(&mut self, buffer: Vec<u8>) {
+        if self.buffer.is_empty() {
+            self.buffer = buffer;
+        }
+    }
+
+    /// Number of bytes that were read ahead from the underlying reader
+    pub fn buffered(&self) -> usize {
+        self.cap - self.pos
+    }
+
+    /// Forget the buffered data, for when the underlying reader was repositioned.
+    pub fn discard_buffer(&mut self) {
+        self.pos = 0;
+        self.cap = 0;
+        self.skip = 0;
+        self.carry = None;
+    }
+
+    /// Leave out the lowest `count` bits of the next byte, shifting all of the data after it
+    /// down, for a deflate stream that starts in the middle of a byte
+    pub fn skip_bits(&mut self, count: u32) {
+        self.skip = count % 8;
+    }
+
+    /// Consumes this reader, returning the underlying reader and the buffer.
+    ///
+    /// Any data still in the buffer is lost.
+    pub fn into_parts(self) -> (R, Vec<u8>) {
+        (self.inner, self.buffer)
+    }
+}
+
+impl<R: Read> Read for PooledBufReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Reads that are large enough don't need the buffer, which may not be allocated yet
+        if self.pos >= self.cap && self.skip == 0 && buf.len() >= self.buffer.len() {
+            return self.inner.read(buf);
+        }
+        let count = {
+            let mut available = self.fill_buf()?;
+            available.read(buf)?
+        };
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl<R: Read> BufRead for PooledBufReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos >= self.cap {
+            if self.buffer.is_empty() {
+                self.buffer = vec![0; BUFFER_SIZE];
+            }
+            self.cap = self.inner.read(&mut self.buffer)?;
+            self.pos = 0;
+            if self.skip == 0 {
+                break;
+            }
+
+            // Every byte takes its high bits from the one after it, so the last one has to
+            // wait for the next read
+            let mut shifted = 0;
+            for i in 0..self.cap {
+                let byte = self.buffer[i];
+                if let Some(previous) = self.carry {
+                    self.buffer[shifted] = (previous >> self.skip) | (byte << (8 - self.skip));
+                    shifted += 1;
+                }
+                self.carry = Some(byte);
+            }
+            if self.cap == 0 {
+                match self.carry.take() {
+                    Some(previous) => {
+                        self.buffer[0] = previous >> self.skip;
+                        shifted = 1;
+                    }
+                    None => break,
+                }
+            }
+            self.cap = shifted;
+        }
+        Ok(&self.buffer[self.pos..self.cap])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = std::cmp::min(self.pos + amt, self.cap);
+    }
+}
+
+/// Raw deflate decoder driving a (possibly recycled) inflate context
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate-miniz",
+    feature = "deflate-zlib"
+))]
+pub(crate) struct Inflater<R> {
+    inner: R,
+    state: Decompress,
+    // Preset dictionary the window is primed with, empty if there is none
+    dictionary: Vec<u8>,
+    // Position in the decompressed data that the context was last started at, and how much
+    // output priming its window took
+    start: u64,
+    primed: u64,
+    // Whether nothing was read since the context was started
+    fresh: bool,
+    checkpoints: Option<Box<Checkpoints>>,
+}
+
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate-miniz",
+    feature = "deflate-zlib"
+))]
+impl<R> Inflater<R> {
+    pub fn new(inner: R, state: Decompress) -> Inflater<R> {
+        Inflater {
+            inner,
+            state,
+            dictionary: Vec::new(),
+            start: 0,
+            primed: 0,
+            fresh: true,
+            checkpoints: None,
+        }
+    }
+
+    /// Decompress as if `dictionary` came right before the data, see [`crate::dictionary`]
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> io::Result<()> {
+        self.dictionary = crate::dictionary::window(dictionary).to_vec();
+        prime(&mut self.state, &self.dictionary).map_err(|_| {
+            io::Error::new(io::ErrorKind::Other, "failed to load the preset dictionary")
+        })?;
+        self.primed = self.dictionary.len() as u64;
+        Ok(())
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Position in the decompressed data
+    pub fn total_out(&self) -> u64 {
+        self.state.total_out() - self.primed + self.start
+    }
+
+    /// Keep [`Checkpoints`] from here on, to restart from with [`Inflater::restart_at`]
+    ///
+    /// They can only be found from the start of a block, so this takes effect once the context
+    /// is restarted, unless nothing was read yet.
+    pub fn record_checkpoints(&mut self) {
+        if self.checkpoints.is_none() {
+            let mut checkpoints = Box::new(Checkpoints::new());
+            if self.fresh && self.start == 0 {
+                checkpoints.start(0, 0, &self.dictionary);
+            }
+            self.checkpoints = Some(checkpoints);
+        }
+    }
+
+    /// Position of the last checkpoint at or before `output` in the decompressed data, or 0
+    /// for the start of the stream
+    pub fn checkpoint_before(&self, output: u64) -> u64 {
+        self.checkpoints
+            .as_ref()
+            .and_then(|checkpoints| checkpoints.before(output))
+            .unwrap_or(0)
+    }
+
+    /// Start over from the checkpoint at `output` in the decompressed data, or from the start
+    /// of the stream. Returns the position in the compressed data, in bits, that the inner
+    /// reader has to continue from.
+    pub fn restart_at(&mut self, output: u64) -> io::Result<u64> {
+        let checkpoint = self
+            .checkpoints
+            .as_ref()
+            .and_then(|checkpoints| checkpoints.get(output));
+        let (input_bits, output, window) = match checkpoint {
+            Some(checkpoint) => (
+                checkpoint.input_bits,
+                checkpoint.output,
+                checkpoint.window.clone(),
+            ),
+            None => (0, 0, self.dictionary.clone()),
+        };
+
+        self.state.reset(false);
+        prime(&mut self.state, &window)?;
+        self.start = output;
+        self.primed = window.len() as u64;
+        self.fresh = true;
+        if let Some(checkpoints) = &mut self.checkpoints {
+            checkpoints.start(input_bits, output, &window);
+        }
+        Ok(input_bits)
+    }
+
+    pub fn into_parts(self) -> (R, Decompress) {
+        (self.inner, self.state)
+    }
+}
+
+/// Feed `window` to a new inflate context as a stored block that isn't the last one, so that
+/// the data after it can refer back to it
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate-miniz",
+    feature = "deflate-zlib"
+))]
+fn prime(state: &mut Decompress, window: &[u8]) -> io::Result<()> {
+    if window.is_empty() {
+        return Ok(());
+    }
+    let len = window.len() as u16;
+    let mut block = vec![0];
+    block.extend_from_slice(&len.to_le_bytes());
+    block.extend_from_slice(&(!len).to_le_bytes());
+    block.extend_from_slice(window);
+    let mut output = vec![0; window.len()];
+    match state.decompress(&block, &mut output, FlushDecompress::None) {
+        Ok(_) if state.total_out() == len as u64 => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Other,
+            "failed to prime the inflate context",
+        )),
+    }
+}
+
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate-miniz",
+    feature = "deflate-zlib"
+))]
+impl<R: BufRead> Read for Inflater<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let (read, consumed, status, eof);
+            {
+                let input = self.inner.fill_buf()?;
+                eof = input.is_empty();
+                let before_out = self.state.total_out();
+                let before_in = self.state.total_in();
+                let flush = if eof {
+                    FlushDecompress::Finish
+                } else {
+                    FlushDecompress::None
+                };
+                status = self.state.decompress(input, buf, flush);
+                read = (self.state.total_out() - before_out) as usize;
+                consumed = (self.state.total_in() - before_in) as usize;
+                if let Some(checkpoints) = &mut self.checkpoints {
+                    checkpoints.update(&input[..consumed], &buf[..read]);
+                }
+            }
+            self.inner.consume(consumed);
+            self.fresh &= consumed == 0;
+
+            match status {
+                // Zero bytes would be interpreted as EOF, so keep feeding input until we either
+                // produce output or run out of it.
+                Ok(Status::Ok) | Ok(Status::BufError) if read == 0 && !buf.is_empty() => {
+                    if eof {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "incomplete deflate stream",
+                        ));
+                    }
+                }
+                Ok(_) => return Ok(read),
+                Err(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "corrupt deflate stream",
+                    ))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn buffered_read() {
+        let data: &[u8] = b"0123456789";
+        let mut reader = PooledBufReader::new(data, vec![0; 4]);
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+        assert_eq!(reader.into_parts().1.len(), 4);
+    }
+
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    ))]
+    #[test]
+    fn recycled_inflater() {
+        let mut encoder =
+            flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"Hello, World! Hello, World!").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut pool = DecompressorPool::default();
+        for _ in 0..2 {
+            let buffered = PooledBufReader::new(compressed.as_slice(), pool.take_buffer());
+            let mut inflater = Inflater::new(buffered, pool.take_inflater());
+            let mut out = String::new();
+            inflater.read_to_string(&mut out).unwrap();
+            assert_eq!(out, "Hello, World! Hello, World!");
+
+            let (buffered, state) = inflater.into_parts();
+            pool.put_inflater(state);
+            pool.put_buffer(buffered.into_parts().1);
+        }
+    }
+}
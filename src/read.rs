@@ -1,32 +1,56 @@
 //! Types for reading ZIP archives
 
+use crate::cache::EntryCache;
+pub use crate::cached_reader::CachedReader;
+use crate::checksum::Crc32Reader;
+pub use crate::checksum::Hasher;
 use crate::compression::CompressionMethod;
-use crate::crc32::Crc32Reader;
-use crate::result::{InvalidPassword, ZipError, ZipResult};
+use crate::dictionary;
+use crate::format;
+use crate::pool::{DecompressorPool, PooledBufReader};
+pub use crate::read_at::{PositionedReader, ReadAt};
+use crate::result::{InvalidArchiveKind, InvalidPassword, Limit, ZipError, ZipResult};
 use crate::spec;
 use crate::zipcrypto::{ZipCryptoReader, ZipCryptoReaderValid, ZipCryptoValidator};
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt;
 use std::io::{self, prelude::*};
-use std::path::{Component, Path};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use crate::cp437::FromCp437;
 use crate::types::{DateTime, System, ZipFileData};
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
 
 #[cfg(any(
     feature = "deflate",
     feature = "deflate-miniz",
     feature = "deflate-zlib"
 ))]
-use flate2::read::DeflateDecoder;
+use crate::pool::Inflater;
 
 #[cfg(feature = "bzip2")]
-use bzip2::read::BzDecoder;
+use bzip2::bufread::BzDecoder;
 
-mod ffi {
+mod diff;
+pub use self::diff::{diff, EntryChange};
+mod portable;
+pub use self::portable::{PathProblem, PortablePaths};
+mod sanitize;
+pub use self::sanitize::{EnclosedName, MangledName, NameSanitizer, PercentEncodedName};
+mod sink;
+pub use self::sink::ExtractSink;
+use self::sink::FileSystem;
+mod stream;
+pub use self::stream::{ZipStreamFileMetadata, ZipStreamReader, ZipStreamVisitor};
+
+pub(crate) mod ffi {
     pub const S_IFDIR: u32 = 0o0040000;
     pub const S_IFREG: u32 = 0o0100000;
+    pub const S_IFLNK: u32 = 0o0120000;
+    pub const S_IFIFO: u32 = 0o0010000;
 }
 
 /// ZIP archive reader
@@ -45,17 +69,482 @@ mod ffi {
 ///     Ok(())
 /// }
 /// ```
-#[derive(Clone, Debug)]
+///
+/// Cloning an archive is cheap apart from cloning the reader, as the parsed central directory is
+/// shared between the clones. To read files from several threads at once, give each thread its
+/// own clone:
+///
+/// ```no_run
+/// use std::io::prelude::*;
+/// use std::sync::Arc;
+/// fn count_bytes(contents: Arc<[u8]>) -> zip::result::ZipResult<u64> {
+///     let zip = zip::ZipArchive::new(std::io::Cursor::new(contents))?;
+///
+///     let threads: Vec<_> = (0..zip.len())
+///         .map(|i| {
+///             let mut zip = zip.clone();
+///             std::thread::spawn(move || -> zip::result::ZipResult<u64> {
+///                 Ok(std::io::copy(&mut zip.by_index(i)?, &mut std::io::sink())?)
+///             })
+///         })
+///         .collect();
+///     let mut total = 0;
+///     for thread in threads {
+///         total += thread.join().unwrap()?;
+///     }
+///     Ok(total)
+/// }
+/// ```
+#[derive(Clone)]
 pub struct ZipArchive<R> {
     reader: R,
+    shared: Arc<Shared>,
+    pool: DecompressorPool,
+    cache: EntryCache,
+    password: Option<Vec<u8>>,
+    // Preset dictionaries for deflated files, by id
+    dictionaries: HashMap<u32, Vec<u8>>,
+}
+
+// The default password is left out, so that archives can be logged without leaking it
+impl<R: fmt::Debug> fmt::Debug for ZipArchive<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ZipArchive")
+            .field("reader", &self.reader)
+            .field("shared", &self.shared)
+            .field("pool", &self.pool)
+            .field("cache", &self.cache)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("dictionaries", &self.dictionaries)
+            .finish()
+    }
+}
+
+/// The parts of a [`ZipArchive`] that don't change after it is opened, shared between clones
+#[derive(Debug)]
+struct Shared {
     files: Vec<ZipFileData>,
     names_map: HashMap<String, usize>,
+    // Indices of all files sharing a name, for names used more than once
+    duplicates: HashMap<String, Vec<usize>>,
+    offset: u64,
+    comment: Vec<u8>,
+    directory_start: u64,
+    directory_end: u64,
+    claimed_len: usize,
+    apk_signing_block: Option<Range<u64>>,
+    check_local_headers: bool,
+    normalize_names: bool,
+    warnings: Vec<ZipWarning>,
+}
+
+impl Shared {
+    /// Get the key of `name` in `names_map` and `duplicates`
+    fn name_key<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        name_key(name, self.normalize_names)
+    }
+
+    /// Get the index of the last file named `name`
+    fn index_for_name(&self, name: &str) -> Option<usize> {
+        self.names_map.get(&*self.name_key(name)).cloned()
+    }
+}
+
+/// Get the key of `name` in the names map of an archive, its NFC form if `normalize` is set
+#[cfg_attr(not(feature = "unicode-normalization"), allow(unused_variables))]
+fn name_key(name: &str, normalize: bool) -> Cow<'_, str> {
+    #[cfg(feature = "unicode-normalization")]
+    {
+        use unicode_normalization::UnicodeNormalization;
+
+        if normalize && !unicode_normalization::is_nfc(name) {
+            return Cow::Owned(name.nfc().collect());
+        }
+    }
+    Cow::Borrowed(name)
+}
+
+/// What is read from the central directory, before the archive is set up
+struct CentralDirectory {
+    files: Vec<ZipFileData>,
     offset: u64,
     comment: Vec<u8>,
+    // Where the central directory and the end of central directory record start in the reader
+    directory_start: u64,
+    directory_end: u64,
+    // Number of files according to the end of central directory record
+    claimed_len: usize,
+    apk_signing_block: Option<Range<u64>>,
+    warnings: Vec<ZipWarning>,
+}
+
+/// Options for reading a [`ZipArchive`], see [`ZipArchive::new_with_config`]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Config {
+    permissive: bool,
+    reject_duplicate_names: bool,
+    check_local_headers: bool,
+    normalize_names: bool,
+    limits: Limits,
+}
+
+/// Limits on the central directory, see [`Config::max_entries`] and the like
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Limits {
+    entries: usize,
+    file_name_length: usize,
+    comment_length: usize,
+    extra_field_length: usize,
+    // What is left of the budget, as the central directory is read
+    metadata_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            entries: usize::max_value(),
+            file_name_length: usize::max_value(),
+            comment_length: usize::max_value(),
+            extra_field_length: usize::max_value(),
+            metadata_bytes: usize::max_value(),
+        }
+    }
+}
+
+impl Limits {
+    fn check(value: usize, limit: usize, which: Limit) -> ZipResult<()> {
+        if value > limit {
+            return Err(ZipError::LimitExceeded(which));
+        }
+        Ok(())
+    }
+
+    /// Take `bytes` from the memory budget, failing if there isn't enough left
+    fn allocate(&mut self, bytes: usize) -> ZipResult<()> {
+        self.metadata_bytes = self
+            .metadata_bytes
+            .checked_sub(bytes)
+            .ok_or(ZipError::LimitExceeded(Limit::MetadataBytes))?;
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Accept archives that are malformed in common ways, instead of failing with
+    /// [`ZipError::InvalidArchive`].
+    ///
+    /// When enabled:
+    /// - If the central directory isn't where the end of central directory record says it is,
+    ///   it is looked for using only the recorded offset, and using only the recorded size.
+    /// - Broken headers in the central directory are skipped, and reading continues at the
+    ///   next header signature before the end of central directory record. The first header
+    ///   still has to be valid. Skipped headers are reported by [`ZipArchive::warnings`].
+    /// - File names are cut off at the first NULL byte. The full name is still available
+    ///   through [`ZipFile::name_raw`].
+    ///
+    /// This is disabled by default.
+    pub fn permissive(mut self, permissive: bool) -> Config {
+        self.permissive = permissive;
+        self
+    }
+
+    /// Fail with [`InvalidArchiveKind::DuplicateFileName`] if several files have the same name.
+    ///
+    /// Lookups by name only find the last of these files, which can hide the others from
+    /// anything that checks an archive by name. See also [`ZipArchive::duplicate_names`].
+    ///
+    /// This is disabled by default.
+    pub fn reject_duplicate_names(mut self, reject: bool) -> Config {
+        self.reject_duplicate_names = reject;
+        self
+    }
+
+    /// Compare the local header of a file with its central directory entry when it is opened,
+    /// and fail with [`InvalidArchiveKind::LocalHeaderMismatch`] if they disagree.
+    ///
+    /// Files are read using the central directory, while tools that stream an archive use the
+    /// local headers. A crafted archive can show different contents to each, for example to get
+    /// a file past a scanner. The name, compression method and encryption are compared, and the
+    /// CRC32 and sizes unless they are stored after the contents. A file may have the name of
+    /// another file in its local header if both point to it, as files that share their data do.
+    /// [`ZipArchive::validate`] always makes this comparison.
+    ///
+    /// This is disabled by default.
+    pub fn check_local_headers(mut self, check: bool) -> Config {
+        self.check_local_headers = check;
+        self
+    }
+
+    /// Look files up by the Unicode NFC normalization of their names.
+    ///
+    /// Archives made on macOS store names in NFD form, where `é` is an `e` followed by a
+    /// combining accent, while other systems type them in NFC form, with a single `é`. With this
+    /// enabled, lookups like [`ZipArchive::by_name`] find a file by either form, and
+    /// [`ZipArchive::file_names`] and [`ZipArchive::duplicate_names`] return the NFC form.
+    /// [`ZipFile::name`] still returns the name as it is stored in the archive.
+    ///
+    /// This is disabled by default.
+    #[cfg(feature = "unicode-normalization")]
+    pub fn normalize_names(mut self, normalize: bool) -> Config {
+        self.normalize_names = normalize;
+        self
+    }
+
+    /// Fail with [`ZipError::LimitExceeded`] if the archive has more than `max` files.
+    ///
+    /// The number recorded in the end of central directory record is checked before any file is
+    /// read, which protects servers from archives that claim millions of tiny files. There is no
+    /// limit by default.
+    pub fn max_entries(mut self, max: usize) -> Config {
+        self.limits.entries = max;
+        self
+    }
+
+    /// Fail with [`ZipError::LimitExceeded`] if a file name is longer than `max` bytes.
+    ///
+    /// Lengths are checked before the names are read. There is no limit by default, other than
+    /// the 65535 bytes the format allows.
+    pub fn max_file_name_length(mut self, max: usize) -> Config {
+        self.limits.file_name_length = max;
+        self
+    }
+
+    /// Fail with [`ZipError::LimitExceeded`] if a file comment or the archive comment is longer
+    /// than `max` bytes.
+    ///
+    /// There is no limit by default, other than the 65535 bytes the format allows.
+    pub fn max_comment_length(mut self, max: usize) -> Config {
+        self.limits.comment_length = max;
+        self
+    }
+
+    /// Fail with [`ZipError::LimitExceeded`] if the extra field of a file in the central
+    /// directory is longer than `max` bytes.
+    ///
+    /// There is no limit by default, other than the 65535 bytes the format allows.
+    pub fn max_extra_field_length(mut self, max: usize) -> Config {
+        self.limits.extra_field_length = max;
+        self
+    }
+
+    /// Fail with [`ZipError::LimitExceeded`] if the files in the central directory would take
+    /// more than `max` bytes of memory.
+    ///
+    /// This counts the fixed size of every file, along with its name, extra field and comment,
+    /// and the archive comment. Each is counted before it is read, so a small archive that
+    /// claims millions of files fails early instead of running out of memory. The lookup table
+    /// of names comes on top of this, see [`ZipArchive::memory_usage`]. There is no limit by
+    /// default.
+    pub fn max_metadata_bytes(mut self, max: usize) -> Config {
+        self.limits.metadata_bytes = max;
+        self
+    }
+}
+
+/// Options for [`ZipArchive::extract_with_options`]
+#[derive(Copy, Clone, Debug)]
+pub struct ExtractOptions {
+    directory_permissions: bool,
+    unix_owner: bool,
+    strip_components: usize,
+    preallocate: bool,
+    hard_links: bool,
+    special_files: SpecialFiles,
+    portable_paths: PortablePaths,
+}
+
+/// What to do with FIFOs, sockets and devices when extracting, see
+/// [`ExtractOptions::special_files`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SpecialFiles {
+    /// Leave them out
+    Skip,
+    /// Fail with [`ZipError::UnsupportedArchive`]
+    Error,
+    /// Create FIFOs on Unix, and leave out the others
+    Create,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> ExtractOptions {
+        ExtractOptions {
+            directory_permissions: true,
+            unix_owner: false,
+            strip_components: 0,
+            preallocate: false,
+            hard_links: false,
+            special_files: SpecialFiles::Skip,
+            portable_paths: PortablePaths::Keep,
+        }
+    }
+}
+
+impl ExtractOptions {
+    /// Give directories the permissions of their entries in the archive, on Unix.
+    ///
+    /// This is done once all files are written, deepest directories first, so that a read-only
+    /// directory doesn't stop its contents from being extracted. It also covers directories that
+    /// were already created for files that came before their entry. When disabled, directories
+    /// keep the default permissions they were created with.
+    ///
+    /// This is enabled by default.
+    pub fn directory_permissions(mut self, apply: bool) -> ExtractOptions {
+        self.directory_permissions = apply;
+        self
+    }
+
+    /// Give files and directories the user and group of their entries in the archive, on Unix.
+    ///
+    /// The ids are taken from Info-ZIP Unix extra fields, see [`ZipFile::unix_owner`]. Only root
+    /// can give files away, so nothing is changed when running as another user.
+    ///
+    /// This is disabled by default.
+    pub fn unix_owner(mut self, apply: bool) -> ExtractOptions {
+        self.unix_owner = apply;
+        self
+    }
+
+    /// Leave out the first `count` components of every path, like `tar --strip-components`.
+    ///
+    /// This extracts an archive that keeps its contents in a folder, like `project-1.2.3/`,
+    /// right into the target directory. Entries with no more than `count` components are
+    /// skipped, and extraction fails for entries whose path leads out of the directory once
+    /// stripped, like `a/../x`. The default is 0.
+    pub fn strip_components(mut self, count: usize) -> ExtractOptions {
+        self.strip_components = count;
+        self
+    }
+
+    /// Reserve the disk space for every file before writing it, using its size in the archive.
+    ///
+    /// This keeps large files from getting fragmented, and makes extraction fail early when the
+    /// disk is too small. The space is only allocated on Linux, on file systems that support
+    /// it, and the files keep the length of what is written to them, even if the size in the
+    /// archive is wrong. Elsewhere this does nothing.
+    ///
+    /// This is disabled by default.
+    pub fn preallocate(mut self, preallocate: bool) -> ExtractOptions {
+        self.preallocate = preallocate;
+        self
+    }
+
+    /// Extract hard links, see [`ZipFile::hard_link_target`], as hard links to the file they
+    /// point to.
+    ///
+    /// When disabled, or when the file they point to isn't extracted, hard links get a copy of
+    /// its contents instead.
+    ///
+    /// This is disabled by default.
+    pub fn hard_links(mut self, create: bool) -> ExtractOptions {
+        self.hard_links = create;
+        self
+    }
+
+    /// Set what to do with entries whose unix mode makes them a FIFO, socket or device.
+    ///
+    /// Their contents are never extracted. Sockets and devices can't be created from an
+    /// archive, so they are always left out unless this is [`SpecialFiles::Error`].
+    ///
+    /// The default is [`SpecialFiles::Skip`].
+    pub fn special_files(mut self, policy: SpecialFiles) -> ExtractOptions {
+        self.special_files = policy;
+        self
+    }
+
+    /// Set what to do with paths that can't be extracted as they are on every system, see
+    /// [`ZipArchive::path_problems`].
+    ///
+    /// [`PortablePaths::Reject`] fails with [`InvalidArchiveKind::InvalidFilePath`] or
+    /// [`InvalidArchiveKind::PathCollision`] before anything is extracted.
+    /// [`PortablePaths::Rename`] replaces characters that Windows doesn't allow with `_`, drops
+    /// trailing dots and spaces, adds `_` to reserved names like `CON_.txt`, and adds `~2` to
+    /// the names of files that differ only in case from an earlier one, like `README~2.md`.
+    ///
+    /// The default is [`PortablePaths::Keep`].
+    pub fn portable_paths(mut self, policy: PortablePaths) -> ExtractOptions {
+        self.portable_paths = policy;
+        self
+    }
+}
+
+/// A `Read + Seek` trait object that can still be used as a plain reader
+trait ReadSeek: Read + Seek {
+    fn as_read(&mut self) -> &mut dyn Read;
+}
+
+impl<T: Read + Seek> ReadSeek for T {
+    fn as_read(&mut self) -> &mut dyn Read {
+        self
+    }
+}
+
+/// Like `io::Take`, but the contents can also be seeked within
+struct SeekableTake<'a> {
+    inner: &'a mut dyn ReadSeek,
+    start: u64,
+    size: u64,
+    position: u64,
+}
+
+impl<'a> SeekableTake<'a> {
+    /// Takes `size` bytes from the current position of `inner`.
+    fn new(inner: &'a mut dyn ReadSeek, size: u64) -> io::Result<SeekableTake<'a>> {
+        let start = inner.seek(io::SeekFrom::Current(0))?;
+        Ok(SeekableTake {
+            inner,
+            start,
+            size,
+            position: 0,
+        })
+    }
+
+    fn into_take(self) -> io::Take<&'a mut dyn Read> {
+        let remaining = self.size.saturating_sub(self.position);
+        self.inner.as_read().take(remaining)
+    }
+}
+
+impl<'a> Read for SeekableTake<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.size.saturating_sub(self.position);
+        let len = ::std::cmp::min(buf.len() as u64, remaining) as usize;
+        let count = self.inner.read(&mut buf[..len])?;
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<'a> Seek for SeekableTake<'a> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            io::SeekFrom::Start(offset) => Some(offset),
+            io::SeekFrom::End(offset) => offset_position(self.size, offset),
+            io::SeekFrom::Current(offset) => offset_position(self.position, offset),
+        };
+        let position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        self.inner
+            .seek(io::SeekFrom::Start(self.start + position))?;
+        self.position = position;
+        Ok(position)
+    }
+}
+
+fn offset_position(base: u64, offset: i64) -> Option<u64> {
+    if offset >= 0 {
+        base.checked_add(offset as u64)
+    } else {
+        base.checked_sub(offset.wrapping_neg() as u64)
+    }
 }
 
 enum CryptoReader<'a> {
     Plaintext(io::Take<&'a mut dyn Read>),
+    Seekable(SeekableTake<'a>),
     ZipCrypto(ZipCryptoReaderValid<io::Take<&'a mut dyn Read>>),
 }
 
@@ -63,9 +552,38 @@ impl<'a> Read for CryptoReader<'a> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match self {
             CryptoReader::Plaintext(r) => r.read(buf),
+            CryptoReader::Seekable(r) => r.read(buf),
             CryptoReader::ZipCrypto(r) => r.read(buf),
         }
     }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        read_in_turn(self, bufs)
+    }
+}
+
+/// Fill `bufs` one after the other, for readers that can fill only one buffer at a time.
+///
+/// Stops at the first buffer that isn't filled completely, so no read is made that might have
+/// to wait for more data. An error after some data was read is left for the next call.
+fn read_in_turn<R: Read + ?Sized>(
+    reader: &mut R,
+    bufs: &mut [io::IoSliceMut<'_>],
+) -> io::Result<usize> {
+    let mut total = 0;
+    for buf in bufs.iter_mut().filter(|buf| !buf.is_empty()) {
+        match reader.read(buf) {
+            Ok(count) => {
+                total += count;
+                if count < buf.len() {
+                    break;
+                }
+            }
+            Err(_) if total > 0 => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(total)
 }
 
 impl<'a> CryptoReader<'a> {
@@ -73,6 +591,7 @@ impl<'a> CryptoReader<'a> {
     pub fn into_inner(self) -> io::Take<&'a mut dyn Read> {
         match self {
             CryptoReader::Plaintext(r) => r,
+            CryptoReader::Seekable(r) => r.into_take(),
             CryptoReader::ZipCrypto(r) => r.into_inner(),
         }
     }
@@ -81,21 +600,21 @@ impl<'a> CryptoReader<'a> {
 enum ZipFileReader<'a> {
     NoReader,
     Raw(io::Take<&'a mut dyn io::Read>),
-    Stored(Crc32Reader<CryptoReader<'a>>),
+    Stored(Crc32Reader<'a, PooledBufReader<CryptoReader<'a>>>),
     #[cfg(any(
         feature = "deflate",
         feature = "deflate-miniz",
         feature = "deflate-zlib"
     ))]
-    Deflated(Crc32Reader<flate2::read::DeflateDecoder<CryptoReader<'a>>>),
+    Deflated(Crc32Reader<'a, Inflater<PooledBufReader<CryptoReader<'a>>>>),
     #[cfg(feature = "bzip2")]
-    Bzip2(Crc32Reader<BzDecoder<CryptoReader<'a>>>),
+    Bzip2(Crc32Reader<'a, BzDecoder<PooledBufReader<CryptoReader<'a>>>>),
 }
 
 impl<'a> Read for ZipFileReader<'a> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match self {
-            ZipFileReader::NoReader => panic!("ZipFileReader was in an invalid state"),
+            ZipFileReader::NoReader => Err(invalid_state()),
             ZipFileReader::Raw(r) => r.read(buf),
             ZipFileReader::Stored(r) => r.read(buf),
             #[cfg(any(
@@ -108,23 +627,83 @@ impl<'a> Read for ZipFileReader<'a> {
             ZipFileReader::Bzip2(r) => r.read(buf),
         }
     }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        match self {
+            ZipFileReader::NoReader => Err(invalid_state()),
+            ZipFileReader::Raw(r) => read_in_turn(r, bufs),
+            ZipFileReader::Stored(r) => r.read_vectored(bufs),
+            // The decoders produce one buffer at a time
+            #[cfg(any(
+                feature = "deflate",
+                feature = "deflate-miniz",
+                feature = "deflate-zlib"
+            ))]
+            ZipFileReader::Deflated(r) => read_in_turn(r, bufs),
+            #[cfg(feature = "bzip2")]
+            ZipFileReader::Bzip2(r) => read_in_turn(r, bufs),
+        }
+    }
+}
+
+fn invalid_state() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Other,
+        "ZipFileReader was in an invalid state",
+    )
 }
 
 impl<'a> ZipFileReader<'a> {
     /// Consumes this decoder, returning the underlying reader.
-    pub fn into_inner(self) -> io::Take<&'a mut dyn Read> {
-        match self {
-            ZipFileReader::NoReader => panic!("ZipFileReader was in an invalid state"),
+    ///
+    /// Returns `None` if no reader was set up.
+    pub fn into_inner(self) -> Option<io::Take<&'a mut dyn Read>> {
+        Some(match self {
+            ZipFileReader::NoReader => return None,
             ZipFileReader::Raw(r) => r,
-            ZipFileReader::Stored(r) => r.into_inner().into_inner(),
+            ZipFileReader::Stored(r) => r.into_inner().into_parts().0.into_inner(),
+            #[cfg(any(
+                feature = "deflate",
+                feature = "deflate-miniz",
+                feature = "deflate-zlib"
+            ))]
+            ZipFileReader::Deflated(r) => r.into_inner().into_parts().0.into_parts().0.into_inner(),
+            #[cfg(feature = "bzip2")]
+            ZipFileReader::Bzip2(r) => r.into_inner().into_inner().into_parts().0.into_inner(),
+        })
+    }
+
+    /// Hands any reusable decompression state back to the pool.
+    #[cfg_attr(
+        not(any(
+            feature = "deflate",
+            feature = "deflate-miniz",
+            feature = "deflate-zlib",
+            feature = "bzip2"
+        )),
+        allow(unused_variables)
+    )]
+    fn recycle(self, pool: &mut DecompressorPool) {
+        match self {
+            ZipFileReader::Stored(r) => {
+                let buffer = r.into_inner().into_parts().1;
+                if !buffer.is_empty() {
+                    pool.put_buffer(buffer);
+                }
+            }
             #[cfg(any(
                 feature = "deflate",
                 feature = "deflate-miniz",
                 feature = "deflate-zlib"
             ))]
-            ZipFileReader::Deflated(r) => r.into_inner().into_inner().into_inner(),
+            ZipFileReader::Deflated(r) => {
+                let (buffered, state) = r.into_inner().into_parts();
+                pool.put_inflater(state);
+                pool.put_buffer(buffered.into_parts().1);
+            }
             #[cfg(feature = "bzip2")]
-            ZipFileReader::Bzip2(r) => r.into_inner().into_inner().into_inner(),
+            ZipFileReader::Bzip2(r) => pool.put_buffer(r.into_inner().into_inner().into_parts().1),
+            _ => (),
         }
     }
 }
@@ -134,27 +713,131 @@ pub struct ZipFile<'a> {
     data: Cow<'a, ZipFileData>,
     crypto_reader: Option<CryptoReader<'a>>,
     reader: ZipFileReader<'a>,
+    // Not stored in `data`, which may be shared between threads
+    data_start: u64,
+    pool: Option<&'a mut DecompressorPool>,
+    check_crc32: bool,
+    buffer: OutputBuffer,
+    // Extra field of the local header, if it is not the one in `data`
+    local_extra: Option<Vec<u8>>,
+    // Preset dictionary the file was compressed with, if it was given to the archive
+    dictionary: Option<&'a [u8]>,
+}
+
+/// Decompressed data that was read ahead for the `BufRead` implementation of [`ZipFile`], stored
+/// data is buffered by its reader instead
+#[derive(Default)]
+struct OutputBuffer {
+    data: Vec<u8>,
+    pos: usize,
+    cap: usize,
+}
+
+impl OutputBuffer {
+    fn available(&self) -> &[u8] {
+        &self.data[self.pos..self.cap]
+    }
+
+    fn discard(&mut self) {
+        self.pos = 0;
+        self.cap = 0;
+    }
 }
 
+/// Find the contents of a file through its local header, returning them along with the extra
+/// field of the local header
 fn find_content<'a>(
-    data: &mut ZipFileData,
+    data: &ZipFileData,
     reader: &'a mut (impl Read + Seek),
-) -> ZipResult<io::Take<&'a mut dyn Read>> {
+) -> ZipResult<(SeekableTake<'a>, Vec<u8>)> {
     // Parse local header
     reader.seek(io::SeekFrom::Start(data.header_start))?;
     let signature = reader.read_u32::<LittleEndian>()?;
     if signature != spec::LOCAL_FILE_HEADER_SIGNATURE {
-        return Err(ZipError::InvalidArchive("Invalid local file header"));
+        return Err(
+            ZipError::invalid(InvalidArchiveKind::InvalidLocalFileHeader)
+                .at_offset(data.header_start)
+                .in_entry(&data.file_name),
+        );
     }
 
     reader.seek(io::SeekFrom::Current(22))?;
     let file_name_length = reader.read_u16::<LittleEndian>()? as u64;
-    let extra_field_length = reader.read_u16::<LittleEndian>()? as u64;
-    let magic_and_header = 4 + 22 + 2 + 2;
-    data.data_start = data.header_start + magic_and_header + file_name_length + extra_field_length;
+    let extra_field_length = reader.read_u16::<LittleEndian>()? as usize;
+
+    reader.seek(io::SeekFrom::Current(file_name_length as i64))?;
+    let mut local_extra = vec![0; extra_field_length];
+    reader.read_exact(&mut local_extra)?;
+    Ok((
+        SeekableTake::new(reader, data.compressed_size)?,
+        local_extra,
+    ))
+}
+
+/// Fails with [`InvalidArchiveKind::LocalHeaderMismatch`] if the local header of a file disagrees
+/// with its central directory entry
+///
+/// Another name is accepted if it is the one of another file in `files` with the same local
+/// header, as files that share their data are written, see
+/// [`Deduplicate::Share`](crate::write::Deduplicate::Share).
+fn check_local_header<R: Read + Seek>(
+    data: &ZipFileData,
+    files: &[ZipFileData],
+    reader: &mut R,
+) -> ZipResult<()> {
+    let error = || {
+        ZipError::invalid(InvalidArchiveKind::LocalHeaderMismatch)
+            .at_offset(data.header_start)
+            .in_entry(&data.file_name)
+    };
+    reader.seek(io::SeekFrom::Start(data.header_start))?;
+    if reader.read_u32::<LittleEndian>()? != spec::LOCAL_FILE_HEADER_SIGNATURE {
+        return Err(
+            ZipError::invalid(InvalidArchiveKind::InvalidLocalFileHeader)
+                .at_offset(data.header_start)
+                .in_entry(&data.file_name),
+        );
+    }
+    let local = parse_local_header(reader).map_err(|e| e.in_entry(&data.file_name))?;
+    let shares_header = |file: &ZipFileData| {
+        file.header_start == data.header_start && file.name_raw() == local.name_raw()
+    };
+    if (local.name_raw() != data.name_raw() && !files.iter().any(shares_header))
+        || local.compression_method != data.compression_method
+        || local.encrypted != data.encrypted
+    {
+        return Err(error());
+    }
+    // These are zero in the local header if they are stored in a data descriptor
+    if !local.using_data_descriptor
+        && (local.crc32 != data.crc32
+            || local.compressed_size != data.compressed_size
+            || local.uncompressed_size != data.uncompressed_size)
+    {
+        return Err(error());
+    }
+    Ok(())
+}
+
+/// Fails for compression methods that are unknown or that were compiled out by feature flags
+fn check_supported(compression_method: CompressionMethod) -> ZipResult<()> {
+    #[allow(deprecated)]
+    match compression_method {
+        CompressionMethod::Unsupported(id) => Err(ZipError::UnsupportedCompression(id)),
+        _ => Ok(()),
+    }
+}
 
-    reader.seek(io::SeekFrom::Start(data.data_start))?;
-    Ok((reader as &mut dyn Read).take(data.compressed_size))
+fn crypto_validator(
+    crc32: u32,
+    last_modified_time: DateTime,
+    using_data_descriptor: bool,
+) -> ZipCryptoValidator {
+    if using_data_descriptor {
+        ZipCryptoValidator::InfoZipMsdosTime(last_modified_time.timepart())
+    } else {
+        ZipCryptoValidator::PkzipCrc32(crc32)
+    }
 }
 
 fn make_crypto_reader<'a>(
@@ -162,25 +845,16 @@ fn make_crypto_reader<'a>(
     crc32: u32,
     last_modified_time: DateTime,
     using_data_descriptor: bool,
-    reader: io::Take<&'a mut dyn io::Read>,
+    reader: CryptoReader<'a>,
     password: Option<&[u8]>,
 ) -> ZipResult<Result<CryptoReader<'a>, InvalidPassword>> {
-    #[allow(deprecated)]
-    {
-        if let CompressionMethod::Unsupported(_) = compression_method {
-            return unsupported_zip_error("Compression method not supported");
-        }
-    }
+    check_supported(compression_method)?;
 
     let reader = match password {
-        None => CryptoReader::Plaintext(reader),
+        None => reader,
         Some(password) => {
-            let validator = if using_data_descriptor {
-                ZipCryptoValidator::InfoZipMsdosTime(last_modified_time.timepart())
-            } else {
-                ZipCryptoValidator::PkzipCrc32(crc32)
-            };
-            match ZipCryptoReader::new(reader, password).validate(validator)? {
+            let validator = crypto_validator(crc32, last_modified_time, using_data_descriptor);
+            match ZipCryptoReader::new(reader.into_inner(), password).validate(validator)? {
                 None => return Ok(Err(InvalidPassword)),
                 Some(r) => CryptoReader::ZipCrypto(r),
             }
@@ -189,29 +863,75 @@ fn make_crypto_reader<'a>(
     Ok(Ok(reader))
 }
 
+/// Get the preset dictionary a file needs, out of the one that was found for it
+fn dictionary_for<'d>(
+    data: &ZipFileData,
+    dictionary: Option<&'d [u8]>,
+) -> ZipResult<Option<&'d [u8]>> {
+    if data.compression_method != CompressionMethod::DEFLATE
+        || dictionary::find_id(&data.extra_field).is_none()
+    {
+        return Ok(None);
+    }
+    match dictionary {
+        Some(dictionary) => Ok(Some(dictionary)),
+        None => Err(ZipError::UnsupportedArchive(ZipError::DICTIONARY_REQUIRED)),
+    }
+}
+
+fn crc32_reader<'a, R>(reader: R, crc32: Option<u32>) -> Crc32Reader<'a, R> {
+    match crc32 {
+        Some(crc32) => Crc32Reader::new(reader, crc32),
+        None => Crc32Reader::new_unchecked(reader),
+    }
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib",
+        feature = "bzip2"
+    )),
+    allow(unused_variables)
+)]
 fn make_reader<'a>(
     compression_method: CompressionMethod,
-    crc32: u32,
+    crc32: Option<u32>,
     reader: CryptoReader<'a>,
-) -> ZipFileReader<'a> {
-    match compression_method {
-        CompressionMethod::Stored => ZipFileReader::Stored(Crc32Reader::new(reader, crc32)),
+    pool: Option<&mut DecompressorPool>,
+    dictionary: Option<&[u8]>,
+) -> ZipResult<ZipFileReader<'a>> {
+    let mut fresh_pool = DecompressorPool::default();
+    let pool = pool.unwrap_or(&mut fresh_pool);
+    Ok(match compression_method {
+        // Reads go around the buffer until `ZipFile::fill_buf` hands it one
+        CompressionMethod::Stored => ZipFileReader::Stored(crc32_reader(
+            PooledBufReader::new(reader, Vec::new()),
+            crc32,
+        )),
         #[cfg(any(
             feature = "deflate",
             feature = "deflate-miniz",
             feature = "deflate-zlib"
         ))]
         CompressionMethod::Deflated => {
-            let deflate_reader = DeflateDecoder::new(reader);
-            ZipFileReader::Deflated(Crc32Reader::new(deflate_reader, crc32))
+            let buffered = PooledBufReader::new(reader, pool.take_buffer());
+            let mut deflate_reader = Inflater::new(buffered, pool.take_inflater());
+            if let Some(dictionary) = dictionary {
+                deflate_reader.set_dictionary(dictionary)?;
+            }
+            ZipFileReader::Deflated(crc32_reader(deflate_reader, crc32))
         }
         #[cfg(feature = "bzip2")]
         CompressionMethod::Bzip2 => {
-            let bzip2_reader = BzDecoder::new(reader);
-            ZipFileReader::Bzip2(Crc32Reader::new(bzip2_reader, crc32))
+            let buffered = PooledBufReader::new(reader, pool.take_buffer());
+            let bzip2_reader = BzDecoder::new(buffered);
+            ZipFileReader::Bzip2(crc32_reader(bzip2_reader, crc32))
         }
-        _ => panic!("Compression method not supported"),
-    }
+        #[allow(deprecated)]
+        CompressionMethod::Unsupported(id) => return Err(ZipError::UnsupportedCompression(id)),
+    })
 }
 
 impl<R: Read + io::Seek> ZipArchive<R> {
@@ -219,22 +939,16 @@ impl<R: Read + io::Seek> ZipArchive<R> {
     /// separate function to ease the control flow design.
     pub(crate) fn get_directory_counts(
         reader: &mut R,
-        footer: &spec::CentralDirectoryEnd,
+        footer: &format::CentralDirectoryEnd,
         cde_start_pos: u64,
     ) -> ZipResult<(u64, u64, usize)> {
         // See if there's a ZIP64 footer. The ZIP64 locator if present will
-        // have its signature 20 bytes in front of the standard footer. The
-        // standard footer, in turn, is 22+N bytes large, where N is the
-        // comment length. Therefore:
-        let zip64locator = if reader
-            .seek(io::SeekFrom::End(
-                -(20 + 22 + footer.zip_file_comment.len() as i64),
-            ))
-            .is_ok()
-        {
-            match spec::Zip64CentralDirectoryEndLocator::parse(reader) {
+        // have its signature 20 bytes in front of the standard footer.
+        let zip64locator = if let Some(locator_start) = cde_start_pos.checked_sub(20) {
+            reader.seek(io::SeekFrom::Start(locator_start))?;
+            match format::Zip64CentralDirectoryEndLocator::read_from(reader) {
                 Ok(loc) => Some(loc),
-                Err(ZipError::InvalidArchive(_)) => {
+                Err(ZipError::InvalidArchive { .. }) => {
                     // No ZIP64 header; that's actually fine. We're done here.
                     None
                 }
@@ -258,8 +972,8 @@ impl<R: Read + io::Seek> ZipArchive<R> {
                 let archive_offset = cde_start_pos
                     .checked_sub(footer.central_directory_size as u64)
                     .and_then(|x| x.checked_sub(footer.central_directory_offset as u64))
-                    .ok_or(ZipError::InvalidArchive(
-                        "Invalid central directory size or offset",
+                    .ok_or(ZipError::invalid(
+                        InvalidArchiveKind::InvalidCentralDirectoryOffset,
                     ))?;
 
                 let directory_start = footer.central_directory_offset as u64 + archive_offset;
@@ -285,10 +999,10 @@ impl<R: Read + io::Seek> ZipArchive<R> {
 
                 let search_upper_bound = cde_start_pos
                     .checked_sub(60) // minimum size of Zip64CentralDirectoryEnd + Zip64CentralDirectoryEndLocator
-                    .ok_or(ZipError::InvalidArchive(
-                        "File cannot contain ZIP64 central directory end",
+                    .ok_or(ZipError::invalid(
+                        InvalidArchiveKind::Zip64CentralDirectoryEndNotFound,
                     ))?;
-                let (footer, archive_offset) = spec::Zip64CentralDirectoryEnd::find_and_parse(
+                let (footer, archive_offset) = format::Zip64CentralDirectoryEnd::find_and_parse(
                     reader,
                     locator64.end_of_central_directory_offset,
                     search_upper_bound,
@@ -303,9 +1017,9 @@ impl<R: Read + io::Seek> ZipArchive<R> {
                 let directory_start = footer
                     .central_directory_offset
                     .checked_add(archive_offset)
-                    .ok_or_else(|| {
-                        ZipError::InvalidArchive("Invalid central directory size or offset")
-                    })?;
+                    .ok_or(ZipError::invalid(
+                        InvalidArchiveKind::InvalidCentralDirectoryOffset,
+                    ))?;
 
                 Ok((
                     archive_offset,
@@ -319,81 +1033,501 @@ impl<R: Read + io::Seek> ZipArchive<R> {
     /// Read a ZIP archive, collecting the files it contains
     ///
     /// This uses the central directory record of the ZIP file, and ignores local file headers
-    pub fn new(mut reader: R) -> ZipResult<ZipArchive<R>> {
-        let (footer, cde_start_pos) = spec::CentralDirectoryEnd::find_and_parse(&mut reader)?;
-
-        if footer.disk_number != footer.disk_with_central_directory {
-            return unsupported_zip_error("Support for multi-disk files is not implemented");
-        }
-
-        let (archive_offset, directory_start, number_of_files) =
-            Self::get_directory_counts(&mut reader, &footer, cde_start_pos)?;
-
-        let mut files = Vec::new();
-        let mut names_map = HashMap::new();
+    pub fn new(reader: R) -> ZipResult<ZipArchive<R>> {
+        Self::new_with_config(reader, Config::default())
+    }
 
-        if let Err(_) = reader.seek(io::SeekFrom::Start(directory_start)) {
-            return Err(ZipError::InvalidArchive(
-                "Could not seek to start of central directory",
-            ));
+    /// Read a ZIP archive with the given options, collecting the files it contains
+    pub fn new_with_config(mut reader: R, config: Config) -> ZipResult<ZipArchive<R>> {
+        let directory = Self::read_central_directory(&mut reader, &config, &[])?;
+        let archive = Self::from_files(reader, directory, &config);
+        if config.reject_duplicate_names {
+            // The first file whose name was used before, in central directory order
+            let first = archive
+                .shared
+                .duplicates
+                .values()
+                .map(|indices| indices[1])
+                .min();
+            if let Some(index) = first {
+                let name = &archive.shared.files[index].file_name;
+                return Err(ZipError::invalid(InvalidArchiveKind::DuplicateFileName).in_entry(name));
+            }
         }
+        Ok(archive)
+    }
 
-        for _ in 0..number_of_files {
-            let file = central_header_to_zip_file(&mut reader, archive_offset)?;
-            names_map.insert(file.file_name.clone(), files.len());
-            files.push(file);
+    /// Read a ZIP archive, salvaging what is left of it if the central directory is damaged
+    ///
+    /// This reads the central directory like [`ZipArchive::new`]. If that fails, the whole
+    /// reader is scanned for local file headers instead, and every file whose header and data
+    /// are complete is collected; [`ZipFile::is_recovered`] returns true for these. File
+    /// comments, permissions and the archive comment are only stored in the central directory,
+    /// so they are lost. The original error is returned if no file could be recovered.
+    pub fn new_with_recovery(mut reader: R) -> ZipResult<ZipArchive<R>> {
+        let config = Config::default();
+        let error = match Self::read_central_directory(&mut reader, &config, &[]) {
+            Ok(directory) => return Ok(Self::from_files(reader, directory, &config)),
+            Err(e) => e,
+        };
+        let files = recover_files(&mut reader)?;
+        if files.is_empty() {
+            return Err(error);
         }
-
-        Ok(ZipArchive {
-            reader,
+        // There is no central directory, new files would be written at the end
+        let end = reader.seek(io::SeekFrom::End(0))?;
+        let directory = CentralDirectory {
             files,
-            names_map,
-            offset: archive_offset,
-            comment: footer.zip_file_comment,
-        })
+            offset: 0,
+            comment: Vec::new(),
+            directory_start: end,
+            directory_end: end,
+            claimed_len: 0,
+            apk_signing_block: None,
+            warnings: Vec::new(),
+        };
+        Ok(Self::from_files(reader, directory, &config))
+    }
+
+    /// Read the central directory. `disk_starts` holds the offset of every disk in `reader` if
+    /// the archive is split, and is empty otherwise.
+    fn read_central_directory(
+        reader: &mut R,
+        config: &Config,
+        disk_starts: &[u64],
+    ) -> ZipResult<CentralDirectory> {
+        // The signature of the footer can also turn up in the archive comment or in junk after
+        // the archive, so a footer is only accepted if its central directory can be read. If
+        // none can, the error of the last footer in the file is reported.
+        let mut search_end = ::std::u64::MAX;
+        let mut first_error = None;
+        loop {
+            let (footer, cde_start_pos) =
+                match format::CentralDirectoryEnd::find_and_parse_before(reader, search_end) {
+                    Ok(found) => found,
+                    Err(e) => return Err(first_error.unwrap_or(e)),
+                };
+            match Self::read_central_directory_at(
+                reader,
+                config,
+                disk_starts,
+                footer,
+                cde_start_pos,
+            ) {
+                Ok(directory) => return Ok(directory),
+                // Looking further would only read another directory that the archive hides
+                Err(e @ ZipError::LimitExceeded(_)) => return Err(e),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                    search_end = cde_start_pos;
+                }
+            }
+        }
+    }
+
+    /// Read the central directory described by the footer found at `cde_start_pos`
+    fn read_central_directory_at(
+        reader: &mut R,
+        config: &Config,
+        disk_starts: &[u64],
+        footer: format::CentralDirectoryEnd,
+        cde_start_pos: u64,
+    ) -> ZipResult<CentralDirectory> {
+        let (archive_offset, directory_start, number_of_files) = if disk_starts.is_empty() {
+            if footer.disk_number != footer.disk_with_central_directory {
+                return unsupported_zip_error("Support for multi-disk files is not implemented");
+            }
+
+            let mut counts = Self::get_directory_counts(reader, &footer, cde_start_pos);
+            if config.permissive {
+                counts = Self::locate_central_directory(reader, &footer, cde_start_pos, counts);
+            }
+            counts?
+        } else {
+            if footer.number_of_files == 0xFFFF || footer.central_directory_offset == 0xFFFFFFFF {
+                return unsupported_zip_error("Split ZIP64 archives are not supported");
+            }
+            let directory_start =
+                disk_start(disk_starts, footer.disk_with_central_directory as u32)?
+                    + footer.central_directory_offset as u64;
+            (0, directory_start, footer.number_of_files as usize)
+        };
+
+        Limits::check(number_of_files, config.limits.entries, Limit::Entries)?;
+        Limits::check(
+            footer.zip_file_comment.len(),
+            config.limits.comment_length,
+            Limit::CommentLength,
+        )?;
+        let mut limits = config.limits;
+        limits.allocate(footer.zip_file_comment.len())?;
+        let mut files = Vec::new();
+        let mut warnings = Vec::new();
+
+        if reader.seek(io::SeekFrom::Start(directory_start)).is_err() {
+            return Err(
+                ZipError::invalid(InvalidArchiveKind::InvalidCentralDirectoryOffset)
+                    .at_offset(directory_start),
+            );
+        }
+
+        // Some writers store the number of files modulo 65536 instead of switching to ZIP64, so
+        // headers that follow the claimed number of files are read as well
+        while files.len() < number_of_files
+            || (disk_starts.is_empty() && central_header_follows(reader, cde_start_pos)?)
+        {
+            Limits::check(files.len() + 1, config.limits.entries, Limit::Entries)?;
+            let header_start = reader.seek(io::SeekFrom::Current(0))?;
+            let mut file = match central_header_to_zip_file(reader, archive_offset, &mut limits) {
+                Ok(file) => file,
+                Err(e @ ZipError::LimitExceeded(_)) => return Err(e),
+                // A footer that points at no valid header at all is not recovered from
+                Err(_) if config.permissive && !files.is_empty() => {
+                    if !disk_starts.is_empty() {
+                        break;
+                    }
+                    // Without another header, the central directory just ended early
+                    match find_central_header(reader, header_start + 1, cde_start_pos)? {
+                        Some(next) => {
+                            warnings.push(ZipWarning::InvalidCentralHeader {
+                                offset: header_start,
+                            });
+                            reader.seek(io::SeekFrom::Start(next))?;
+                            continue;
+                        }
+                        None => break,
+                    }
+                }
+                Err(e) => return Err(e),
+            };
+            if config.permissive {
+                if let Some(index) = file.file_name.find('\0') {
+                    if file.file_name_raw.is_empty() {
+                        file.file_name_raw = file.file_name.as_bytes().to_vec();
+                    }
+                    file.file_name.truncate(index);
+                }
+            }
+            if !disk_starts.is_empty() {
+                file.header_start += disk_start(disk_starts, file.disk_number)?;
+            }
+            files.push(file);
+        }
+        if files.len() != number_of_files {
+            warnings.push(ZipWarning::FileCountMismatch {
+                claimed: number_of_files,
+                found: files.len(),
+            });
+        }
+        let apk_signing_block = if disk_starts.is_empty() {
+            find_apk_signing_block(reader, directory_start)?
+        } else {
+            None
+        };
+
+        Ok(CentralDirectory {
+            files,
+            offset: archive_offset,
+            comment: footer.zip_file_comment,
+            directory_start,
+            directory_end: cde_start_pos,
+            claimed_len: number_of_files,
+            apk_signing_block,
+            warnings,
+        })
+    }
+
+    /// Check the central directory location found by [`ZipArchive::get_directory_counts`], and
+    /// look for it elsewhere if the offset or size recorded in the footer is wrong.
+    pub(crate) fn locate_central_directory(
+        reader: &mut R,
+        footer: &format::CentralDirectoryEnd,
+        cde_start_pos: u64,
+        counts: ZipResult<(u64, u64, usize)>,
+    ) -> ZipResult<(u64, u64, usize)> {
+        if let Ok((_, directory_start, number_of_files)) = counts {
+            if number_of_files == 0 || is_central_header(reader, directory_start)? {
+                return counts;
+            }
+        }
+
+        let offset = footer.central_directory_offset as u64;
+        let from_size = cde_start_pos.checked_sub(footer.central_directory_size as u64);
+        for &directory_start in [Some(offset), from_size].iter().flatten() {
+            if is_central_header(reader, directory_start)? {
+                let number_of_files = footer.number_of_files_on_this_disk as usize;
+                return Ok((0, directory_start, number_of_files));
+            }
+        }
+        counts
+    }
+
+    fn from_files(reader: R, directory: CentralDirectory, config: &Config) -> ZipArchive<R> {
+        let mut files = directory.files;
+        // The list is kept for as long as the archive, don't leave room to grow
+        files.shrink_to_fit();
+        let mut names_map = HashMap::new();
+        let mut duplicates = HashMap::new();
+        let mut warnings = directory.warnings;
+        for (index, file) in files.iter().enumerate() {
+            let key = name_key(&file.file_name, config.normalize_names).into_owned();
+            if let Some(previous) = names_map.insert(key.clone(), index) {
+                duplicates
+                    .entry(key)
+                    .or_insert_with(|| vec![previous])
+                    .push(index);
+                warnings.push(ZipWarning::DuplicateName { index });
+            }
+            if !extra_field_is_valid(&file.extra_field) {
+                warnings.push(ZipWarning::InvalidExtraField { index });
+            }
+            if is_cp437_name(file) {
+                warnings.push(ZipWarning::Cp437Name { index });
+            }
+            if !file.last_modified_time.is_valid() {
+                warnings.push(ZipWarning::InvalidTimestamp { index });
+            }
+        }
+        ZipArchive {
+            reader,
+            shared: Arc::new(Shared {
+                files,
+                names_map,
+                duplicates,
+                offset: directory.offset,
+                comment: directory.comment,
+                directory_start: directory.directory_start,
+                directory_end: directory.directory_end,
+                claimed_len: directory.claimed_len,
+                apk_signing_block: directory.apk_signing_block,
+                check_local_headers: config.check_local_headers,
+                normalize_names: config.normalize_names,
+                warnings,
+            }),
+            pool: DecompressorPool::default(),
+            cache: EntryCache::default(),
+            password: None,
+            dictionaries: HashMap::new(),
+        }
     }
+
     /// Extract a Zip archive into a directory, overwriting files if they
     /// already exist. Paths are sanitized with [`ZipFile::enclosed_name`].
     ///
+    /// Permissions are restored on Unix, and the read-only, hidden and system attributes on
+    /// Windows.
+    ///
     /// Extraction is not atomic; If an error is encountered, some of the files
     /// may be left on disk.
     pub fn extract<P: AsRef<Path>>(&mut self, directory: P) -> ZipResult<()> {
-        use std::fs;
-
-        for i in 0..self.len() {
-            let mut file = self.by_index(i)?;
-            let filepath = file
-                .enclosed_name()
-                .ok_or(ZipError::InvalidArchive("Invalid file path"))?;
+        self.extract_with_options(directory, ExtractOptions::default())
+    }
 
-            let outpath = directory.as_ref().join(filepath);
+    /// Extract a Zip archive into a directory like [`ZipArchive::extract`], with the given
+    /// options.
+    pub fn extract_with_options<P: AsRef<Path>>(
+        &mut self,
+        directory: P,
+        options: ExtractOptions,
+    ) -> ZipResult<()> {
+        self.extract_with_sanitizer(directory, options, &EnclosedName)
+    }
 
-            if file.name().ends_with('/') {
-                fs::create_dir_all(&outpath)?;
-            } else {
-                if let Some(p) = outpath.parent() {
-                    if !p.exists() {
-                        fs::create_dir_all(&p)?;
+    /// Extract a Zip archive into a directory like [`ZipArchive::extract_with_options`], turning
+    /// the names of the files into paths with `sanitizer` instead of
+    /// [`ZipFile::enclosed_name`].
+    ///
+    /// Paths are still checked to stay in the directory, see [`NameSanitizer`].
+    pub fn extract_with_sanitizer<P, N>(
+        &mut self,
+        directory: P,
+        options: ExtractOptions,
+        sanitizer: &N,
+    ) -> ZipResult<()>
+    where
+        P: AsRef<Path>,
+        N: NameSanitizer + ?Sized,
+    {
+        let strip =
+            |path: &Path| -> PathBuf { path.components().skip(options.strip_components).collect() };
+        let renames = match options.portable_paths {
+            PortablePaths::Keep => HashMap::new(),
+            policy => {
+                let (problems, renames) =
+                    portable::check(self.shared.files.iter().enumerate().filter_map(
+                        |(index, file)| {
+                            let path = strip(&sanitizer.sanitize(&file.file_name)?);
+                            Some((index, file.is_dir(), path))
+                        },
+                    ));
+                if policy == PortablePaths::Reject {
+                    if let Some(problem) = problems.first() {
+                        let (kind, index) = match *problem {
+                            PathProblem::Reserved(index) => {
+                                (InvalidArchiveKind::InvalidFilePath, index)
+                            }
+                            PathProblem::CaseCollision(_, index) => {
+                                (InvalidArchiveKind::PathCollision, index)
+                            }
+                        };
+                        return Err(
+                            ZipError::invalid(kind).in_entry(&self.shared.files[index].file_name)
+                        );
                     }
                 }
-                let mut outfile = fs::File::create(&outpath)?;
-                io::copy(&mut file, &mut outfile)?;
+                renames
             }
-            // Get and Set permissions
-            #[cfg(unix)]
+        };
+        let mut sink = FileSystem::new(directory.as_ref(), options);
+        let indices = 0..self.len();
+        self.extract_selected(&mut sink, indices, sanitizer, |path| {
+            let path = strip(path);
+            Some(renames.get(&path).cloned().unwrap_or(path))
+        })
+    }
+
+    /// Find the paths that can't be extracted as they are on every system: names that Windows
+    /// doesn't allow, and files whose paths only differ in case.
+    ///
+    /// Paths are sanitized with [`ZipFile::enclosed_name`] first, and those that can't be are
+    /// left out. See [`ExtractOptions::portable_paths`] to deal with the problems when
+    /// extracting.
+    pub fn path_problems(&self) -> Vec<PathProblem> {
+        let entries = self
+            .shared
+            .files
+            .iter()
+            .enumerate()
+            .filter_map(|(index, file)| {
+                Some((index, file.is_dir(), file.enclosed_name()?.to_path_buf()))
+            });
+        portable::check(entries).0
+    }
+
+    /// Extract the entries below the directory `prefix` into a directory, leaving out the
+    /// prefix itself, like [`ZipArchive::extract`] does for the whole archive.
+    ///
+    /// This is meant for archives that keep their contents in a folder, where `prefix` is
+    /// the name of the folder. The entries are found like [`ZipArchive::entries_under`] finds
+    /// them, and no other entries are read. Extraction fails for entries whose path leads out
+    /// of the directory once the prefix is left out, like `prefix/../x`.
+    pub fn extract_subtree<P: AsRef<Path>>(&mut self, prefix: &str, directory: P) -> ZipResult<()> {
+        let indices = self.entries_under(prefix);
+        let prefix = Path::new(prefix.trim_end_matches('/'));
+        let mut sink = FileSystem::new(directory.as_ref(), ExtractOptions::default());
+        self.extract_selected(&mut sink, indices, &EnclosedName, |path| {
+            path.strip_prefix(prefix).ok().map(Path::to_path_buf)
+        })
+    }
+
+    /// Extract a Zip archive into `sink`, such as an in-memory file system or an archive of
+    /// another kind.
+    ///
+    /// The entries are passed to the sink in order, with paths sanitized by
+    /// [`ZipFile::enclosed_name`]. The metadata of directories is set after all files are
+    /// written, deepest directories first, like [`ZipArchive::extract`] does.
+    pub fn extract_to_sink<S: ExtractSink>(&mut self, sink: &mut S) -> ZipResult<()> {
+        let indices = 0..self.len();
+        self.extract_selected(sink, indices, &EnclosedName, |path| {
+            Some(path.to_path_buf())
+        })
+    }
+
+    /// Extract the entries with the given indices for which `select` maps the path from
+    /// `sanitizer` to a path in `sink`, skipping the others and those mapped to an empty path
+    fn extract_selected<S, I, N, F>(
+        &mut self,
+        sink: &mut S,
+        indices: I,
+        sanitizer: &N,
+        mut select: F,
+    ) -> ZipResult<()>
+    where
+        S: ExtractSink,
+        I: IntoIterator<Item = usize>,
+        N: NameSanitizer + ?Sized,
+        F: FnMut(&Path) -> Option<PathBuf>,
+    {
+        let mut directories = Vec::new();
+        // Where the files that hard links can point at were extracted to, by index
+        let mut extracted = HashMap::new();
+        for i in indices {
+            let mut file = self.by_index(i)?;
+            let path = sanitized_path(sanitizer, file.name())?;
+            let path = match selected_path(&mut select, &path, file.name())? {
+                Some(path) => path,
+                None => continue,
+            };
+
+            if file.is_dir() {
+                sink.create_dir(&path)?;
+                directories.push((i, path));
+            } else if file.is_special() {
+                sink.special_file(&path, &file)?;
+            } else if file
+                .unix_mode()
+                .map_or(false, |mode| mode & 0o170000 == ffi::S_IFLNK)
             {
-                use std::os::unix::fs::PermissionsExt;
-                if let Some(mode) = file.unix_mode() {
-                    fs::set_permissions(&outpath, fs::Permissions::from_mode(mode))?;
-                }
+                let mut target = Vec::new();
+                file.read_to_end(&mut target)?;
+                let target = String::from_utf8_lossy(&target).into_owned();
+                sink.symlink(Path::new(&target), &path)?;
+            } else if let Some(target) = file.hard_link_target() {
+                drop(file);
+                self.extract_hard_link(sink, &extracted, &target, &path)?;
+                extracted.insert(i, path);
+            } else {
+                let mut writer = sink.create_file(&path)?;
+                sink.reserve(&mut writer, file.size())?;
+                file.copy_to(&mut writer)?;
+                drop(writer);
+                sink.set_metadata(&path, &file)?;
+                extracted.insert(i, path);
             }
         }
+
+        directories.sort_by_key(|(_, path)| std::cmp::Reverse(path.components().count()));
+        for (i, path) in directories {
+            let file = self.by_index_raw(i)?;
+            sink.set_metadata(&path, &file)?;
+        }
         Ok(())
     }
 
+    /// Extract a hard link to the file named `target` at `path`, or a copy of the file if the
+    /// sink doesn't link it. It is only linked if the file is among the `extracted` ones, so
+    /// links to files that come later or aren't extracted are copies.
+    fn extract_hard_link<S: ExtractSink>(
+        &mut self,
+        sink: &mut S,
+        extracted: &HashMap<usize, PathBuf>,
+        target: &str,
+        path: &Path,
+    ) -> ZipResult<()> {
+        let index = match self.shared.index_for_name(target) {
+            Some(index) if !self.shared.files[index].is_dir() => index,
+            _ => {
+                return Err(
+                    ZipError::invalid(InvalidArchiveKind::InvalidLinkTarget).in_entry(target)
+                )
+            }
+        };
+        if let Some(target_path) = extracted.get(&index) {
+            if sink.hard_link(target_path, path)? {
+                return Ok(());
+            }
+        }
+        let mut file = self.by_index(index)?;
+        let mut writer = sink.create_file(path)?;
+        sink.reserve(&mut writer, file.size())?;
+        file.copy_to(&mut writer)?;
+        drop(writer);
+        sink.set_metadata(path, &file)
+    }
+
     /// Number of files contained in this zip.
     pub fn len(&self) -> usize {
-        self.files.len()
+        self.shared.files.len()
     }
 
     /// Whether this zip archive contains no files
@@ -406,17 +1540,281 @@ impl<R: Read + io::Seek> ZipArchive<R> {
     /// Normally this value is zero, but if the zip has arbitrary data prepended to it, then this value will be the size
     /// of that prepended data.
     pub fn offset(&self) -> u64 {
-        self.offset
+        self.shared.offset
+    }
+
+    /// Get the position of the central directory in the underlying reader, in bytes.
+    ///
+    /// For an archive opened with [`ZipArchive::new_with_recovery`] that has no readable
+    /// central directory, this is the end of the reader.
+    pub fn central_directory_start(&self) -> u64 {
+        self.shared.directory_start
+    }
+
+    /// Get the position of the end of central directory record in the underlying reader, in
+    /// bytes.
+    ///
+    /// This is where the central directory ends, unless the archive uses ZIP64, which puts its
+    /// own records in between.
+    pub fn central_directory_end(&self) -> u64 {
+        self.shared.directory_end
+    }
+
+    /// Get the number of files that the end of central directory record claims the archive has.
+    ///
+    /// This is the same as [`ZipArchive::len`] unless the count is wrong, which is reported by
+    /// [`ZipArchive::warnings`].
+    pub fn claimed_len(&self) -> usize {
+        self.shared.claimed_len
+    }
+
+    /// Get the position of the APK Signing Block in the underlying reader, if there is one.
+    ///
+    /// Android packages store their signatures in a block between the last file and the central
+    /// directory, which belongs to neither. [`ZipWriter::new_append`](crate::ZipWriter::new_append),
+    /// [`repack`](crate::repack) and [`ZipEditor`](crate::write::ZipEditor) keep the block right
+    /// before the central directory, although the signatures in it are only still valid if the
+    /// archive didn't change.
+    pub fn apk_signing_block(&self) -> Option<Range<u64>> {
+        self.shared.apk_signing_block.clone()
+    }
+
+    /// Read the APK Signing Block, see [`ZipArchive::apk_signing_block`]
+    ///
+    /// The block is returned whole, including the size fields and the magic at its end.
+    pub fn read_apk_signing_block(&mut self) -> ZipResult<Option<Vec<u8>>> {
+        let span = match self.apk_signing_block() {
+            Some(span) => span,
+            None => return Ok(None),
+        };
+        let mut block = vec![0; (span.end - span.start) as usize];
+        self.reader.seek(io::SeekFrom::Start(span.start))?;
+        self.reader.read_exact(&mut block)?;
+        Ok(Some(block))
+    }
+
+    /// Get the problems that were found while opening the archive, but didn't stop it from
+    /// being read
+    pub fn warnings(&self) -> &[ZipWarning] {
+        &self.shared.warnings
+    }
+
+    /// Get the data in front of the first file, such as the stub of a self-extracting archive.
+    ///
+    /// Unlike [`ZipArchive::offset`], this also finds prepended data that the offsets in the
+    /// archive already account for.
+    pub fn prepended_data(&mut self) -> ZipResult<impl Read + '_> {
+        let len = self.prepended_data_len();
+        self.reader.seek(io::SeekFrom::Start(0))?;
+        Ok((&mut self.reader).take(len))
+    }
+
+    /// Copy the archive to `writer` without its prepended data, adjusting the offsets in the
+    /// central directory so the copy is a plain ZIP archive.
+    pub fn copy_without_preamble<W: Write>(&mut self, mut writer: W) -> ZipResult<()> {
+        if self.shared.files.iter().any(|file| file.recovered) {
+            return unsupported_zip_error("Recovered archives have no central directory to copy");
+        }
+        let preamble = self.prepended_data_len();
+        let directory_start = self
+            .shared
+            .files
+            .iter()
+            .map(|file| file.central_header_start)
+            .min()
+            .unwrap_or(preamble);
+        // Stored offsets are relative to `self.shared.offset`, and need to become relative to the end of
+        // the preamble.
+        let delta = preamble - self.shared.offset;
+
+        self.reader.seek(io::SeekFrom::Start(preamble))?;
+        io::copy(
+            &mut (&mut self.reader).take(directory_start - preamble),
+            &mut writer,
+        )?;
+
+        let mut directory = Vec::new();
+        self.reader.read_to_end(&mut directory)?;
+        if delta != 0 {
+            for file in self.shared.files.iter() {
+                let header =
+                    &mut directory[(file.central_header_start - directory_start) as usize..];
+                shift_central_header(header, delta)?;
+            }
+            shift_directory_end(
+                &mut directory,
+                directory_start,
+                self.shared.directory_end,
+                self.shared.offset,
+                delta,
+            )?;
+        }
+        writer.write_all(&directory)?;
+        Ok(())
+    }
+
+    fn prepended_data_len(&self) -> u64 {
+        self.shared
+            .files
+            .iter()
+            .map(|file| file.header_start)
+            .min()
+            .unwrap_or(self.shared.offset)
+    }
+
+    /// Estimate the memory used by the parsed central directory, in bytes.
+    ///
+    /// This covers the information on every file and the indices for looking up files by name.
+    /// It doesn't include the reader, decompression buffers or the cache set up with
+    /// [`ZipArchive::set_cache_size`]. Clones of an archive share the central directory.
+    pub fn memory_usage(&self) -> usize {
+        use std::mem::size_of;
+
+        let shared = &*self.shared;
+        let files = shared.files.capacity() * size_of::<ZipFileData>()
+            + shared
+                .files
+                .iter()
+                .map(ZipFileData::heap_size)
+                .sum::<usize>();
+        // A hash map entry takes one control byte besides the key and value
+        let names = shared.names_map.capacity() * (size_of::<(String, usize)>() + 1)
+            + shared.names_map.keys().map(String::capacity).sum::<usize>();
+        let duplicates = shared.duplicates.capacity() * (size_of::<(String, Vec<usize>)>() + 1)
+            + shared
+                .duplicates
+                .iter()
+                .map(|(name, indices)| name.capacity() + indices.capacity() * size_of::<usize>())
+                .sum::<usize>();
+        size_of::<Shared>() + files + names + duplicates + shared.comment.capacity()
     }
 
     /// Get the comment of the zip archive.
     pub fn comment(&self) -> &[u8] {
-        &self.comment
+        &self.shared.comment
+    }
+
+    /// Returns whether the file with this index is encrypted, without opening it
+    pub fn is_encrypted(&self, file_number: usize) -> ZipResult<bool> {
+        self.shared
+            .files
+            .get(file_number)
+            .map(|file| file.encrypted)
+            .ok_or(ZipError::FileNotFound)
+    }
+
+    /// Returns whether any file in the archive is encrypted, and so needs a password to be read
+    pub fn has_encrypted_entries(&self) -> bool {
+        self.shared.files.iter().any(|file| file.encrypted)
     }
 
     /// Returns an iterator over all the file and directory names in this archive.
     pub fn file_names(&self) -> impl Iterator<Item = &str> {
-        self.names_map.keys().map(|s| s.as_str())
+        self.shared.names_map.keys().map(|s| s.as_str())
+    }
+
+    /// Get the name of the file at `index`, without reading its local header
+    #[cfg(feature = "capi")]
+    pub(crate) fn name_for_index(&self, index: usize) -> Option<&str> {
+        self.shared
+            .files
+            .get(index)
+            .map(|file| file.file_name.as_str())
+    }
+
+    /// Returns the names used by more than one file in this archive, with the indices of these
+    /// files in central directory order.
+    ///
+    /// Lookups by name, like [`ZipArchive::by_name`], only find the last of these files.
+    pub fn duplicate_names(&self) -> impl Iterator<Item = (&str, &[usize])> {
+        self.shared
+            .duplicates
+            .iter()
+            .map(|(name, indices)| (name.as_str(), indices.as_slice()))
+    }
+
+    /// Returns the indices of all files with the given name, in central directory order.
+    pub fn indices_for_name(&self, name: &str) -> &[usize] {
+        let key = self.shared.name_key(name);
+        if let Some(indices) = self.shared.duplicates.get(&*key) {
+            return indices;
+        }
+        match self.shared.names_map.get(&*key) {
+            Some(index) => std::slice::from_ref(index),
+            None => &[],
+        }
+    }
+
+    /// Returns the indices of the files whose name matches any of `globs`, in central directory
+    /// order.
+    ///
+    /// Patterns use the syntax of the [`glob`](https://docs.rs/glob) crate and match whole
+    /// names. `*` doesn't match a `/`, while `**` matches any number of directories, so
+    /// `**/*.dll` finds DLLs at any depth. Fails if a pattern is invalid.
+    #[cfg(feature = "glob")]
+    pub fn find(&self, globs: &[&str]) -> ZipResult<Vec<usize>> {
+        let patterns = globs
+            .iter()
+            .map(|glob| glob::Pattern::new(glob))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ZipError::Io(io::Error::new(io::ErrorKind::InvalidInput, e)))?;
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..glob::MatchOptions::new()
+        };
+        Ok(self
+            .shared
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(_, file)| {
+                patterns
+                    .iter()
+                    .any(|pattern| pattern.matches_with(&file.file_name, options))
+            })
+            .map(|(index, _)| index)
+            .collect())
+    }
+
+    /// Returns the indices of the files and directories below the directory `prefix`, in
+    /// central directory order.
+    ///
+    /// A slash is added to `prefix` if it doesn't end in one, so `"docs"` doesn't match
+    /// `docs.txt`. The entry of the directory itself isn't included.
+    pub fn entries_under(&self, prefix: &str) -> Vec<usize> {
+        let prefix = match prefix.trim_end_matches('/') {
+            "" => String::new(),
+            prefix => format!("{}/", prefix),
+        };
+        self.shared
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(_, file)| file.file_name.starts_with(&prefix) && file.file_name != prefix)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Returns a lending iterator over the files in this archive, in central directory order.
+    ///
+    /// Because every [`ZipFile`] borrows the archive, this can't be an [`Iterator`]; use
+    /// [`ZipEntries::next`] in a `while let` loop instead:
+    ///
+    /// ```no_run
+    /// # fn main() -> zip::result::ZipResult<()> {
+    /// # let mut zip = zip::ZipArchive::new(std::io::Cursor::new(Vec::new()))?;
+    /// let mut entries = zip.entries();
+    /// while let Some(file) = entries.next() {
+    ///     println!("Filename: {}", file?.name());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn entries(&mut self) -> ZipEntries<'_, R> {
+        ZipEntries {
+            archive: self,
+            index: 0,
+        }
     }
 
     /// Search for a file entry by name, decrypt with given password
@@ -430,7 +1828,52 @@ impl<R: Read + io::Seek> ZipArchive<R> {
 
     /// Search for a file entry by name
     pub fn by_name<'a>(&'a mut self, name: &str) -> ZipResult<ZipFile<'a>> {
-        Ok(self.by_name_with_optional_password(name, None)?.unwrap())
+        self.by_name_with_optional_password(name, None)?
+            .map_err(|_| ZipError::UnsupportedArchive(ZipError::INVALID_PASSWORD))
+    }
+
+    /// Decrypt files with `password` when they are opened without one.
+    ///
+    /// This applies to [`ZipArchive::by_index`], [`ZipArchive::by_name`] and everything built on
+    /// them, like [`ZipArchive::extract`]. If the password is wrong for a file, opening it fails
+    /// with [`ZipError::INVALID_PASSWORD`] instead of [`ZipError::PASSWORD_REQUIRED`].
+    pub fn set_default_password(&mut self, password: &[u8]) {
+        self.password = Some(password.to_vec());
+    }
+
+    /// Add a preset dictionary for files that were deflated with one.
+    ///
+    /// Such files are written by [`ZipWriter::set_deflate_dictionary`](crate::write::ZipWriter::set_deflate_dictionary),
+    /// and are marked with the CRC32 of their dictionary, so several dictionaries can be added
+    /// and each file uses its own. Opening such a file without its dictionary fails with
+    /// [`ZipError::DICTIONARY_REQUIRED`] once it is read.
+    pub fn add_deflate_dictionary(&mut self, dictionary: &[u8]) {
+        let dictionary = crate::dictionary::window(dictionary);
+        self.dictionaries
+            .insert(crate::dictionary::id(dictionary), dictionary.to_vec());
+    }
+
+    /// Search for a file entry by path, regardless of the path separators used.
+    ///
+    /// `/` and `\` are treated as the same separator, and a trailing separator is ignored,
+    /// so `Path::new("dir\\file.txt")` finds an entry named `dir/file.txt`, and `"dir"` finds
+    /// `dir/`.
+    pub fn by_path<'a, P: AsRef<Path>>(&'a mut self, path: P) -> ZipResult<ZipFile<'a>> {
+        let wanted = normalize_separators(&path.as_ref().to_string_lossy());
+        let index = match self.shared.index_for_name(&wanted) {
+            Some(index) => index,
+            None => {
+                let wanted = self.shared.name_key(&wanted);
+                self.shared
+                    .files
+                    .iter()
+                    .position(|file| {
+                        self.shared.name_key(&normalize_separators(&file.file_name)) == wanted
+                    })
+                    .ok_or(ZipError::FileNotFound)?
+            }
+        };
+        self.by_index(index)
     }
 
     fn by_name_with_optional_password<'a>(
@@ -438,8 +1881,8 @@ impl<R: Read + io::Seek> ZipArchive<R> {
         name: &str,
         password: Option<&[u8]>,
     ) -> ZipResult<Result<ZipFile<'a>, InvalidPassword>> {
-        let index = match self.names_map.get(name) {
-            Some(index) => *index,
+        let index = match self.shared.index_for_name(name) {
+            Some(index) => index,
             None => {
                 return Err(ZipError::FileNotFound);
             }
@@ -456,44 +1899,269 @@ impl<R: Read + io::Seek> ZipArchive<R> {
         self.by_index_with_optional_password(file_number, Some(password))
     }
 
+    /// Check a password for a file by index, without decrypting the file
+    ///
+    /// Only the 12 byte encryption header in front of the contents is read. The header only
+    /// contains a one byte check value, so about one in 256 wrong passwords is still accepted
+    /// here and only found out through the CRC32 when the file is read. Files that are not
+    /// encrypted accept any password.
+    pub fn verify_password(&mut self, file_number: usize, password: &[u8]) -> ZipResult<bool> {
+        let data = self
+            .shared
+            .files
+            .get(file_number)
+            .ok_or(ZipError::FileNotFound)?;
+        if !data.encrypted {
+            return Ok(true);
+        }
+        let validator = crypto_validator(
+            data.crc32,
+            data.last_modified_time,
+            data.using_data_descriptor,
+        );
+        let (content, _) = find_content(data, &mut self.reader)?;
+        Ok(ZipCryptoReader::new(content, password)
+            .validate(validator)?
+            .is_some())
+    }
+
     /// Get a contained file by index
     pub fn by_index<'a>(&'a mut self, file_number: usize) -> ZipResult<ZipFile<'a>> {
-        Ok(self
-            .by_index_with_optional_password(file_number, None)?
-            .unwrap())
+        self.by_index_with_optional_password(file_number, None)?
+            .map_err(|_| ZipError::UnsupportedArchive(ZipError::INVALID_PASSWORD))
+    }
+
+    /// Get a contained file by index, without verifying its CRC32
+    ///
+    /// This is for callers that check the integrity of the contents in another way, for example
+    /// with a signature, and saves the time spent computing the checksum.
+    pub fn by_index_unchecked_crc<'a>(&'a mut self, file_number: usize) -> ZipResult<ZipFile<'a>> {
+        let mut file = self.by_index(file_number)?;
+        file.check_crc32 = false;
+        Ok(file)
+    }
+
+    /// Read a whole file, selected by index or by name, into a vector.
+    ///
+    /// The CRC32 of the contents is checked.
+    ///
+    /// ```no_run
+    /// # fn main() -> zip::result::ZipResult<()> {
+    /// let mut archive = zip::ZipArchive::new(std::fs::File::open("archive.zip")?)?;
+    /// let manifest = archive.read_entry_to_vec("META-INF/MANIFEST.MF")?;
+    /// let first = archive.read_entry_to_vec(0)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_entry_to_vec<S: FileSelector>(&mut self, file: S) -> ZipResult<Vec<u8>> {
+        let index = file.index_in(self)?;
+        let mut contents = Vec::new();
+        self.by_index(index)?.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    /// Read a whole file, selected by index or by name, into a string.
+    ///
+    /// The CRC32 of the contents is checked. Fails with an [`io::ErrorKind::InvalidData`] error
+    /// if the contents are not valid UTF-8.
+    pub fn read_entry_to_string<S: FileSelector>(&mut self, file: S) -> ZipResult<String> {
+        let index = file.index_in(self)?;
+        let mut contents = String::new();
+        self.by_index(index)?.read_to_string(&mut contents)?;
+        Ok(contents)
+    }
+
+    /// Decompress at most `n_bytes` from the start of a file, and return them.
+    ///
+    /// Decompression stops as soon as enough data has been produced, which makes this cheap
+    /// for previews or content sniffing of large files. The result is shorter than `n_bytes`
+    /// only if the file itself is. Since the file is not read to the end, its CRC32 is not
+    /// checked.
+    pub fn read_prefix(&mut self, file_number: usize, n_bytes: usize) -> ZipResult<Vec<u8>> {
+        let file = self.by_index(file_number)?;
+        let capacity = std::cmp::min(n_bytes as u64, file.size()) as usize;
+        let mut prefix = Vec::with_capacity(capacity);
+        file.take(n_bytes as u64).read_to_end(&mut prefix)?;
+        Ok(prefix)
+    }
+
+    /// Keep up to `max_bytes` of recently used file contents in memory for
+    /// [`ZipArchive::by_index_cached`].
+    ///
+    /// The cache is disabled by default. Files larger than `max_bytes` are never cached.
+    /// Shrinking the cache evicts the least recently used files.
+    pub fn set_cache_size(&mut self, max_bytes: usize) {
+        self.cache.set_capacity(max_bytes);
+    }
+
+    /// Get the decompressed contents of a file by index, going through the cache.
+    ///
+    /// If the file was read recently it is returned without touching the reader again;
+    /// otherwise it is read in full, its CRC32 is checked and it is added to the cache
+    /// (see [`ZipArchive::set_cache_size`]).
+    pub fn by_index_cached(&mut self, file_number: usize) -> ZipResult<Arc<[u8]>> {
+        if let Some(data) = self.cache.get(file_number) {
+            return Ok(data);
+        }
+        let mut buffer = Vec::new();
+        self.by_index(file_number)?.read_to_end(&mut buffer)?;
+        let data: Arc<[u8]> = Arc::from(buffer);
+        if self.cache.capacity() > 0 {
+            self.cache.insert(file_number, data.clone());
+        }
+        Ok(data)
+    }
+
+    /// Decompress every file in the archive and report all problems found.
+    ///
+    /// Unlike reading the files one by one, this doesn't stop at the first broken file, so it
+    /// can be used to implement an equivalent of `zip -T`. An empty result means the archive
+    /// is intact.
+    pub fn validate(&mut self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        for i in 0..self.len() {
+            if let Ok(entry_issues) = self.validate_entry(i) {
+                issues.extend(entry_issues);
+            }
+        }
+        issues
+    }
+
+    /// Decompress a single file by index, checking its CRC32 and size against the central
+    /// directory.
+    ///
+    /// Problems with the file are returned as [`ValidationIssue`]s; an error is only returned
+    /// if there is no file with this index.
+    pub fn validate_entry(&mut self, file_number: usize) -> ZipResult<Vec<ValidationIssue>> {
+        let data = self
+            .shared
+            .files
+            .get(file_number)
+            .ok_or(ZipError::FileNotFound)?;
+        let name = data.file_name.clone();
+        let expected_crc32 = data.crc32;
+        let expected_size = data.uncompressed_size;
+        let issue = |kind| ValidationIssue {
+            index: file_number,
+            name: name.clone(),
+            kind,
+        };
+
+        // The contents described by the central directory can't be trusted if the local header
+        // says otherwise
+        match check_local_header(data, &self.shared.files, &mut self.reader) {
+            Ok(()) => {}
+            Err(ZipError::InvalidArchive {
+                kind: InvalidArchiveKind::LocalHeaderMismatch,
+                ..
+            }) => return Ok(vec![issue(ValidationIssueKind::LocalHeaderMismatch)]),
+            Err(e) => return Ok(vec![issue(ValidationIssueKind::Unreadable(e))]),
+        }
+
+        // The CRC32 is compared below, so a mismatch can be reported alongside a wrong size.
+        let mut file = match self.by_index_unchecked_crc(file_number) {
+            Ok(file) => file,
+            Err(e) => return Ok(vec![issue(ValidationIssueKind::Unreadable(e))]),
+        };
+
+        let mut hasher = crc32fast::Hasher::new();
+        let mut size = 0;
+        let mut buffer = [0; 8192];
+        loop {
+            match file.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(count) => {
+                    hasher.update(&buffer[..count]);
+                    size += count as u64;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Ok(vec![issue(ValidationIssueKind::Unreadable(e.into()))]),
+            }
+        }
+
+        let mut issues = Vec::new();
+        if size != expected_size {
+            issues.push(issue(ValidationIssueKind::SizeMismatch {
+                expected: expected_size,
+                actual: size,
+            }));
+        }
+        let crc32 = hasher.finalize();
+        if crc32 != expected_crc32 {
+            issues.push(issue(ValidationIssueKind::CrcMismatch {
+                expected: expected_crc32,
+                actual: crc32,
+            }));
+        }
+        Ok(issues)
+    }
+
+    /// Search for a file entry by name, without decompressing it
+    ///
+    /// See [`ZipArchive::by_index_raw`].
+    pub fn by_name_raw<'a>(&'a mut self, name: &str) -> ZipResult<ZipFile<'a>> {
+        let index = match self.shared.index_for_name(name) {
+            Some(index) => index,
+            None => return Err(ZipError::FileNotFound),
+        };
+        self.by_index_raw(index)
     }
 
     /// Get a contained file by index without decompressing it
+    ///
+    /// Reading the file gives the data as it is stored, which is [`ZipFile::compressed_size`]
+    /// bytes compressed with [`ZipFile::compression`]. Encrypted files are not decrypted either.
     pub fn by_index_raw<'a>(&'a mut self, file_number: usize) -> ZipResult<ZipFile<'a>> {
-        let reader = &mut self.reader;
-        self.files
-            .get_mut(file_number)
-            .ok_or(ZipError::FileNotFound)
-            .and_then(move |data| {
-                Ok(ZipFile {
-                    crypto_reader: None,
-                    reader: ZipFileReader::Raw(find_content(data, reader)?),
-                    data: Cow::Borrowed(data),
-                })
-            })
+        let data = self
+            .shared
+            .files
+            .get(file_number)
+            .ok_or(ZipError::FileNotFound)?;
+        if self.shared.check_local_headers {
+            check_local_header(data, &self.shared.files, &mut self.reader)?;
+        }
+        let (content, local_extra) = find_content(data, &mut self.reader)?;
+        Ok(ZipFile {
+            crypto_reader: None,
+            data_start: content.start,
+            reader: ZipFileReader::Raw(content.into_take()),
+            data: Cow::Borrowed(data),
+            pool: None,
+            check_crc32: true,
+            buffer: OutputBuffer::default(),
+            local_extra: Some(local_extra),
+            dictionary: None,
+        })
     }
 
     fn by_index_with_optional_password<'a>(
         &'a mut self,
         file_number: usize,
-        mut password: Option<&[u8]>,
+        password: Option<&[u8]>,
     ) -> ZipResult<Result<ZipFile<'a>, InvalidPassword>> {
-        if file_number >= self.files.len() {
+        if file_number >= self.shared.files.len() {
             return Err(ZipError::FileNotFound);
         }
-        let data = &mut self.files[file_number];
+        let data = &self.shared.files[file_number];
 
-        match (password, data.encrypted) {
-            (None, true) => return Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)),
-            (Some(_), false) => password = None, //Password supplied, but none needed! Discard.
-            _ => {}
+        let password = match (password, data.encrypted, &self.password) {
+            (None, true, Some(default)) => Some(&default[..]),
+            (None, true, None) => {
+                return Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED))
+            }
+            (Some(_), false, _) => None, //Password supplied, but none needed! Discard.
+            (password, _, _) => password,
+        };
+        if self.shared.check_local_headers {
+            check_local_header(data, &self.shared.files, &mut self.reader)?;
         }
-        let limit_reader = find_content(data, &mut self.reader)?;
+        let (content, local_extra) = find_content(data, &mut self.reader)?;
+        let data_start = content.start;
+        let limit_reader = CryptoReader::Seekable(content);
+        let dictionaries = &self.dictionaries;
+        let dictionary = dictionary::find_id(&data.extra_field)
+            .and_then(|id| dictionaries.get(&id))
+            .map(|dictionary| &dictionary[..]);
 
         match make_crypto_reader(
             data.compression_method,
@@ -505,8 +2173,14 @@ impl<R: Read + io::Seek> ZipArchive<R> {
         ) {
             Ok(Ok(crypto_reader)) => Ok(Ok(ZipFile {
                 crypto_reader: Some(crypto_reader),
+                data_start,
                 reader: ZipFileReader::NoReader,
                 data: Cow::Borrowed(data),
+                pool: Some(&mut self.pool),
+                check_crc32: true,
+                buffer: OutputBuffer::default(),
+                local_extra: Some(local_extra),
+                dictionary,
             })),
             Err(e) => Err(e),
             Ok(Err(e)) => Ok(Err(e)),
@@ -521,24 +2195,616 @@ impl<R: Read + io::Seek> ZipArchive<R> {
     }
 }
 
-fn unsupported_zip_error<T>(detail: &'static str) -> ZipResult<T> {
-    Err(ZipError::UnsupportedArchive(detail))
+impl<R: Read + io::Seek> ZipArchive<SplitArchiveReader<R>> {
+    /// Read a ZIP archive that is split over several files, collecting the files it contains
+    ///
+    /// Split archives that need ZIP64 are not supported.
+    pub fn new_split(mut reader: SplitArchiveReader<R>) -> ZipResult<Self> {
+        let disk_starts = reader.starts[..reader.segments.len()].to_vec();
+        let config = Config::default();
+        let directory = Self::read_central_directory(&mut reader, &config, &disk_starts)?;
+        Ok(Self::from_files(reader, directory, &config))
+    }
 }
 
-/// Parse a central directory entry to collect the information for the file.
-pub(crate) fn central_header_to_zip_file<R: Read + io::Seek>(
-    reader: &mut R,
+impl<R: Read + Write + io::Seek> ZipArchive<R> {
+    /// Edit the metadata of the files in place, see [`ZipEditor`](crate::write::ZipEditor).
+    ///
+    /// Split archives and archives with files after the central directory can't be edited.
+    pub fn into_editor(mut self) -> ZipResult<crate::write::ZipEditor<R>> {
+        let apk_signing_block = self.read_apk_signing_block()?;
+        let files_end = match self.shared.apk_signing_block {
+            Some(ref span) => span.start,
+            None => self.shared.directory_start,
+        };
+        let shared = &self.shared;
+        if shared.files.iter().any(|file| file.disk_number != 0) {
+            return Err(ZipError::UnsupportedArchive(
+                "Split archives can't be edited",
+            ));
+        }
+        if shared
+            .files
+            .iter()
+            .any(|file| file.header_start >= files_end)
+        {
+            return Err(ZipError::UnsupportedArchive(
+                "Archives with files after the central directory can't be edited",
+            ));
+        }
+        Ok(crate::write::ZipEditor::new(
+            self.reader,
+            shared.files.clone(),
+            shared.comment.clone(),
+            files_end,
+            apk_signing_block,
+        ))
+    }
+}
+
+/// Archives held in memory, including memory-mapped files
+impl<T: AsRef<[u8]>> ZipArchive<io::Cursor<T>> {
+    /// Get the contents of a file without copying them.
+    ///
+    /// Only files stored without compression or encryption can be accessed this way, for other
+    /// files this returns `Ok(None)`. The CRC32 of the contents is not checked.
+    pub fn data_slice(&mut self, file_number: usize) -> ZipResult<Option<&[u8]>> {
+        let data = self
+            .shared
+            .files
+            .get(file_number)
+            .ok_or(ZipError::FileNotFound)?;
+        if data.encrypted || data.compression_method != CompressionMethod::Stored {
+            return Ok(None);
+        }
+        let start = find_content(data, &mut self.reader)?.0.start;
+
+        let buffer = self.reader.get_ref().as_ref();
+        let end = start.saturating_add(data.compressed_size);
+        if end > buffer.len() as u64 {
+            return Err(ZipError::Io(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "file contents extend past the end of the archive",
+            )));
+        }
+        Ok(Some(&buffer[start as usize..end as usize]))
+    }
+}
+
+/// Reader presenting the segments of a split archive (`.z01`, `.z02`, ..., `.zip`) as one stream
+///
+/// Use it with [`ZipArchive::new_split`]:
+///
+/// ```no_run
+/// # fn main() -> zip::result::ZipResult<()> {
+/// let reader = zip::read::SplitArchiveReader::open("archive.zip")?;
+/// let mut zip = zip::ZipArchive::new_split(reader)?;
+/// for i in 0..zip.len() {
+///     println!("Filename: {}", zip.by_index(i)?.name());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SplitArchiveReader<R> {
+    segments: Vec<R>,
+    // Offset of every segment in the combined stream, followed by the total length
+    starts: Vec<u64>,
+    position: u64,
+}
+
+impl<R: Read + io::Seek> SplitArchiveReader<R> {
+    /// Combine the segments of a split archive, ordered from the first disk to the last.
+    pub fn new(mut segments: Vec<R>) -> io::Result<SplitArchiveReader<R>> {
+        let mut starts = vec![0];
+        let mut length = 0;
+        for segment in &mut segments {
+            length += segment.seek(io::SeekFrom::End(0))?;
+            starts.push(length);
+        }
+        Ok(SplitArchiveReader {
+            segments,
+            starts,
+            position: 0,
+        })
+    }
+
+    /// Unwrap and return the segment readers
+    pub fn into_inner(self) -> Vec<R> {
+        self.segments
+    }
+}
+
+impl SplitArchiveReader<std::fs::File> {
+    /// Open a split archive, given the path of its last segment
+    ///
+    /// The other segments are expected in the same directory, with the same name and the
+    /// extensions `.z01`, `.z02`, and so on.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<SplitArchiveReader<std::fs::File>> {
+        let path = path.as_ref();
+        let mut segments = Vec::new();
+        for number in 1.. {
+            match std::fs::File::open(path.with_extension(format!("z{:02}", number))) {
+                Ok(segment) => segments.push(segment),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => break,
+                Err(e) => return Err(e),
+            }
+        }
+        segments.push(std::fs::File::open(path)?);
+        SplitArchiveReader::new(segments)
+    }
+}
+
+impl<R: Read + io::Seek> Read for SplitArchiveReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let position = self.position;
+        let index = match (0..self.segments.len()).find(|&i| position < self.starts[i + 1]) {
+            Some(index) => index,
+            None => return Ok(0),
+        };
+        // Don't read past the end of the segment, in case it is longer than it was when opened
+        let available = self.starts[index + 1] - position;
+        let len = std::cmp::min(buf.len() as u64, available) as usize;
+
+        let segment = &mut self.segments[index];
+        segment.seek(io::SeekFrom::Start(position - self.starts[index]))?;
+        let count = segment.read(&mut buf[..len])?;
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<R: Read + io::Seek> io::Seek for SplitArchiveReader<R> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            io::SeekFrom::Start(offset) => {
+                self.position = offset;
+                return Ok(offset);
+            }
+            io::SeekFrom::End(offset) => (self.starts[self.segments.len()], offset),
+            io::SeekFrom::Current(offset) => (self.position, offset),
+        };
+        let position = base as i128 + offset as i128;
+        if position < 0 || position > ::std::u64::MAX as i128 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            ));
+        }
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
+
+/// Lending iterator over the files of a [`ZipArchive`], created by [`ZipArchive::entries`]
+pub struct ZipEntries<'a, R> {
+    archive: &'a mut ZipArchive<R>,
+    index: usize,
+}
+
+impl<'a, R: Read + io::Seek> ZipEntries<'a, R> {
+    /// Open the next file in the archive, or return `None` once all files have been visited.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<ZipResult<ZipFile<'_>>> {
+        if self.index >= self.archive.len() {
+            return None;
+        }
+        let index = self.index;
+        self.index += 1;
+        Some(self.archive.by_index(index))
+    }
+
+    /// Number of files that have not been visited yet
+    pub fn remaining(&self) -> usize {
+        self.archive.len() - self.index
+    }
+}
+
+/// A problem with a file found by [`ZipArchive::validate`]
+#[derive(Debug)]
+pub struct ValidationIssue {
+    /// Index of the file in the archive
+    pub index: usize,
+    /// Name of the file, as stored in the central directory
+    pub name: String,
+    /// What is wrong with the file
+    pub kind: ValidationIssueKind,
+}
+
+/// A problem found while opening an archive, see [`ZipArchive::warnings`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ZipWarning {
+    /// The end of central directory record claims a different number of files than the
+    /// central directory holds
+    FileCountMismatch {
+        /// Number of files according to the end of central directory record
+        claimed: usize,
+        /// Number of files that were read
+        found: usize,
+    },
+    /// A broken central directory header was skipped, with [`Config::permissive`]
+    InvalidCentralHeader {
+        /// Offset of the header in the reader
+        offset: u64,
+    },
+    /// A file has the same name as a file before it, which lookups by name won't find
+    DuplicateName {
+        /// Index of the later file
+        index: usize,
+    },
+    /// The blocks in the extra field of a file don't add up to its length
+    InvalidExtraField {
+        /// Index of the file
+        index: usize,
+    },
+    /// A file name with characters outside of ASCII isn't marked as UTF-8, and was decoded as
+    /// code page 437, which may not be what the archive was made with
+    Cp437Name {
+        /// Index of the file
+        index: usize,
+    },
+    /// The modification time of a file is not a valid date and time
+    InvalidTimestamp {
+        /// Index of the file
+        index: usize,
+    },
+}
+
+/// A way to pick a file from a [`ZipArchive`]: its index, or its name like with
+/// [`ZipArchive::by_name`]
+pub trait FileSelector {
+    /// Get the index of the selected file
+    fn index_in<R>(&self, archive: &ZipArchive<R>) -> ZipResult<usize>;
+}
+
+impl FileSelector for usize {
+    fn index_in<R>(&self, archive: &ZipArchive<R>) -> ZipResult<usize> {
+        if *self < archive.shared.files.len() {
+            Ok(*self)
+        } else {
+            Err(ZipError::FileNotFound)
+        }
+    }
+}
+
+impl FileSelector for str {
+    fn index_in<R>(&self, archive: &ZipArchive<R>) -> ZipResult<usize> {
+        archive
+            .shared
+            .index_for_name(self)
+            .ok_or(ZipError::FileNotFound)
+    }
+}
+
+impl FileSelector for String {
+    fn index_in<R>(&self, archive: &ZipArchive<R>) -> ZipResult<usize> {
+        self.as_str().index_in(archive)
+    }
+}
+
+impl<S: FileSelector + ?Sized> FileSelector for &S {
+    fn index_in<R>(&self, archive: &ZipArchive<R>) -> ZipResult<usize> {
+        (**self).index_in(archive)
+    }
+}
+
+/// The kinds of problems [`ZipArchive::validate`] reports
+#[derive(Debug)]
+pub enum ValidationIssueKind {
+    /// The file could not be opened or decompressed
+    Unreadable(ZipError),
+    /// The decompressed data doesn't match the CRC32 from the central directory
+    CrcMismatch {
+        /// CRC32 stored in the central directory
+        expected: u32,
+        /// CRC32 of the decompressed data
+        actual: u32,
+    },
+    /// The decompressed data doesn't have the size from the central directory
+    SizeMismatch {
+        /// Uncompressed size stored in the central directory
+        expected: u64,
+        /// Number of bytes produced by decompression
+        actual: u64,
+    },
+    /// The local header disagrees with the central directory, see
+    /// [`Config::check_local_headers`]
+    LocalHeaderMismatch,
+}
+
+/// Subtract `delta` from the local header offset of the central directory header at the start
+/// of `header`.
+fn shift_central_header(header: &mut [u8], delta: u64) -> ZipResult<()> {
+    let invalid = || ZipError::invalid(InvalidArchiveKind::InvalidCentralDirectoryHeader);
+    if header.len() < 46 {
+        return Err(invalid());
+    }
+    let offset = LittleEndian::read_u32(&header[42..]);
+    if offset != 0xFFFFFFFF {
+        let offset = shift_offset(offset as u64, delta)?;
+        LittleEndian::write_u32(&mut header[42..], offset as u32);
+        return Ok(());
+    }
+
+    // The offset is in the ZIP64 extra field, after the sizes that didn't fit either
+    let mut position = 46 + LittleEndian::read_u16(&header[28..]) as usize;
+    let extra_end = position + LittleEndian::read_u16(&header[30..]) as usize;
+    while position + 4 <= extra_end {
+        let kind = LittleEndian::read_u16(&header[position..]);
+        let len = LittleEndian::read_u16(&header[position + 2..]) as usize;
+        if kind == 0x0001 {
+            let mut field = position + 4;
+            if LittleEndian::read_u32(&header[24..]) == 0xFFFFFFFF {
+                field += 8;
+            }
+            if LittleEndian::read_u32(&header[20..]) == 0xFFFFFFFF {
+                field += 8;
+            }
+            let offset = header.get(field..field + 8).ok_or_else(invalid)?;
+            let offset = shift_offset(LittleEndian::read_u64(offset), delta)?;
+            LittleEndian::write_u64(&mut header[field..], offset);
+            return Ok(());
+        }
+        position += 4 + len;
+    }
+    Err(invalid())
+}
+
+/// Subtract `delta` from the central directory offsets in the end of central directory records
+/// of `directory`, which starts at `start` in the reader. `end` is where the end of central
+/// directory record starts in the reader, and `offset` is what the offsets in the archive are
+/// relative to.
+fn shift_directory_end(
+    directory: &mut [u8],
+    start: u64,
+    end: u64,
+    offset: u64,
+    delta: u64,
+) -> ZipResult<()> {
+    let record_at = |position: u64, len: usize, signature: u32, kind: InvalidArchiveKind| {
+        position
+            .checked_sub(start)
+            .map(|position| position as usize)
+            .filter(|&position| {
+                directory.len() >= len
+                    && position <= directory.len() - len
+                    && LittleEndian::read_u32(&directory[position..]) == signature
+            })
+            .ok_or_else(|| ZipError::invalid(kind))
+    };
+    let end = record_at(
+        end,
+        22,
+        spec::CENTRAL_DIRECTORY_END_SIGNATURE,
+        InvalidArchiveKind::CentralDirectoryEndNotFound,
+    )?;
+    let zip64 = match end.checked_sub(20) {
+        Some(locator)
+            if LittleEndian::read_u32(&directory[locator..])
+                == spec::ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIGNATURE =>
+        {
+            let zip64_end = LittleEndian::read_u64(&directory[locator + 8..]);
+            let record = record_at(
+                zip64_end.saturating_add(offset),
+                56,
+                spec::ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE,
+                InvalidArchiveKind::Zip64CentralDirectoryEndNotFound,
+            )?;
+            Some((locator, record))
+        }
+        _ => None,
+    };
+
+    let central_offset = LittleEndian::read_u32(&directory[end + 16..]);
+    if central_offset != 0xFFFFFFFF {
+        let central_offset = shift_offset(central_offset as u64, delta)?;
+        LittleEndian::write_u32(&mut directory[end + 16..], central_offset as u32);
+    }
+    if let Some((locator, record)) = zip64 {
+        let zip64_end = LittleEndian::read_u64(&directory[locator + 8..]);
+        LittleEndian::write_u64(
+            &mut directory[locator + 8..],
+            shift_offset(zip64_end, delta)?,
+        );
+        let central_offset = LittleEndian::read_u64(&directory[record + 48..]);
+        LittleEndian::write_u64(
+            &mut directory[record + 48..],
+            shift_offset(central_offset, delta)?,
+        );
+    }
+    Ok(())
+}
+
+fn shift_offset(offset: u64, delta: u64) -> ZipResult<u64> {
+    offset.checked_sub(delta).ok_or(ZipError::invalid(
+        InvalidArchiveKind::InvalidCentralDirectoryOffset,
+    ))
+}
+
+/// Offset of the start of a disk of a split archive.
+fn disk_start(disk_starts: &[u64], disk_number: u32) -> ZipResult<u64> {
+    disk_starts
+        .get(disk_number as usize)
+        .cloned()
+        .ok_or(ZipError::invalid(InvalidArchiveKind::MissingSegment))
+}
+
+/// Whether a central directory header starts at `position`.
+fn is_central_header<R: Read + io::Seek>(reader: &mut R, position: u64) -> ZipResult<bool> {
+    reader.seek(io::SeekFrom::Start(position))?;
+    match reader.read_u32::<LittleEndian>() {
+        Ok(signature) => Ok(signature == spec::CENTRAL_DIRECTORY_HEADER_SIGNATURE),
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Find the APK Signing Block that ends at `directory_start`, if there is one
+///
+/// The block starts and ends with its size, not counting the first size field, and ends with a
+/// 16 byte magic.
+pub(crate) fn find_apk_signing_block<R: Read + io::Seek>(
+    reader: &mut R,
+    directory_start: u64,
+) -> ZipResult<Option<Range<u64>>> {
+    let footer_start = match directory_start.checked_sub(24) {
+        Some(start) => start,
+        None => return Ok(None),
+    };
+    let mut footer = [0; 24];
+    reader.seek(io::SeekFrom::Start(footer_start))?;
+    reader.read_exact(&mut footer)?;
+    if &footer[8..] != spec::APK_SIGNING_BLOCK_MAGIC {
+        return Ok(None);
+    }
+    let size = LittleEndian::read_u64(&footer);
+    let start = match size
+        .checked_add(8)
+        .and_then(|len| directory_start.checked_sub(len))
+    {
+        Some(start) => start,
+        None => return Ok(None),
+    };
+    reader.seek(io::SeekFrom::Start(start))?;
+    if reader.read_u64::<LittleEndian>()? != size {
+        return Ok(None);
+    }
+    Ok(Some(start..directory_start))
+}
+
+/// Find the first central directory header signature in `start..end`
+pub(crate) fn find_central_header<R: Read + io::Seek>(
+    reader: &mut R,
+    start: u64,
+    end: u64,
+) -> ZipResult<Option<u64>> {
+    let signature = spec::CENTRAL_DIRECTORY_HEADER_SIGNATURE.to_le_bytes();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut position = start;
+    while position + 4 <= end {
+        let len = ::std::cmp::min(buffer.len() as u64, end - position) as usize;
+        reader.seek(io::SeekFrom::Start(position))?;
+        reader.read_exact(&mut buffer[..len])?;
+        if let Some(index) = buffer[..len].windows(4).position(|w| w == signature) {
+            return Ok(Some(position + index as u64));
+        }
+        // The signature may continue in the next chunk
+        position += len as u64 - 3;
+    }
+    Ok(None)
+}
+
+/// Whether another central directory header starts at the current position, before `end`
+pub(crate) fn central_header_follows<R: Read + io::Seek>(
+    reader: &mut R,
+    end: u64,
+) -> ZipResult<bool> {
+    let position = reader.seek(io::SeekFrom::Current(0))?;
+    let follows = position < end && is_central_header(reader, position)?;
+    reader.seek(io::SeekFrom::Start(position))?;
+    Ok(follows)
+}
+
+/// Use `/` as the only separator and strip trailing separators from a file name.
+fn normalize_separators(name: &str) -> String {
+    name.replace('\\', "/").trim_end_matches('/').to_owned()
+}
+
+fn unsupported_zip_error<T>(detail: &'static str) -> ZipResult<T> {
+    Err(ZipError::UnsupportedArchive(detail))
+}
+
+/// Turn the name of a file into a path with `sanitizer`, failing if the path isn't safe
+fn sanitized_path<N: NameSanitizer + ?Sized>(sanitizer: &N, name: &str) -> ZipResult<PathBuf> {
+    sanitizer
+        .sanitize(name)
+        .filter(|path| !path.to_string_lossy().contains('\0') && crate::types::is_enclosed(path))
+        .ok_or_else(|| ZipError::invalid(InvalidArchiveKind::InvalidFilePath).in_entry(name))
+}
+
+/// Map the sanitized `path` of the file named `name` with `select`, for
+/// [`ZipArchive::extract_selected`]. Returns `None` for files that are skipped, and fails for
+/// paths that don't stay in the directory anymore, whatever `select` did to them.
+fn selected_path<F>(select: &mut F, path: &Path, name: &str) -> ZipResult<Option<PathBuf>>
+where
+    F: FnMut(&Path) -> Option<PathBuf>,
+{
+    match select(path) {
+        Some(ref path) if path.as_os_str().is_empty() => Ok(None),
+        Some(ref path) if !crate::types::is_enclosed(path) => {
+            Err(ZipError::invalid(InvalidArchiveKind::InvalidFilePath).in_entry(name))
+        }
+        path => Ok(path),
+    }
+}
+
+/// Whether the blocks in an extra field fill it exactly
+fn extra_field_is_valid(mut extra_field: &[u8]) -> bool {
+    while !extra_field.is_empty() {
+        if extra_field.len() < 4 {
+            return false;
+        }
+        let len = 4 + LittleEndian::read_u16(&extra_field[2..4]) as usize;
+        if extra_field.len() < len {
+            return false;
+        }
+        extra_field = &extra_field[len..];
+    }
+    true
+}
+
+/// Whether the name of a file was decoded as code page 437 and has non-ASCII characters
+fn is_cp437_name(file: &ZipFileData) -> bool {
+    // UTF-8 names are only stored separately when they are invalid, and then they are decoded
+    // with replacement characters, which code page 437 doesn't produce
+    !file.file_name_raw.is_empty()
+        && !file.file_name_raw.is_ascii()
+        && file.file_name == (&file.file_name_raw[..]).from_cp437()
+}
+
+/// Decode a file name, returning it along with the raw name if that differs from the decoded one.
+///
+/// Most names are the same either way, and not keeping a second copy of them saves memory for
+/// archives with many files.
+fn decode_file_name(raw: Vec<u8>, is_utf8: bool) -> (String, Vec<u8>) {
+    let name = match is_utf8 {
+        true => String::from_utf8_lossy(&raw).into_owned(),
+        false => (&raw[..]).from_cp437().into_owned(),
+    };
+    if name.as_bytes() == &raw[..] {
+        (name, Vec::new())
+    } else {
+        (name, raw)
+    }
+}
+
+/// Parse a central directory entry to collect the information for the file.
+pub(crate) fn central_header_to_zip_file<R: Read + io::Seek>(
+    reader: &mut R,
     archive_offset: u64,
+    limits: &mut Limits,
 ) -> ZipResult<ZipFileData> {
     let central_header_start = reader.seek(io::SeekFrom::Current(0))?;
     // Parse central header
     let signature = reader.read_u32::<LittleEndian>()?;
     if signature != spec::CENTRAL_DIRECTORY_HEADER_SIGNATURE {
-        return Err(ZipError::InvalidArchive("Invalid Central Directory header"));
+        return Err(
+            ZipError::invalid(InvalidArchiveKind::InvalidCentralDirectoryHeader)
+                .at_offset(central_header_start),
+        );
     }
+    parse_central_header(reader, archive_offset, central_header_start, limits)
+}
 
+/// Parse a central directory entry, starting right after its signature.
+fn parse_central_header<R: Read>(
+    reader: &mut R,
+    archive_offset: u64,
+    central_header_start: u64,
+    limits: &mut Limits,
+) -> ZipResult<ZipFileData> {
     let version_made_by = reader.read_u16::<LittleEndian>()?;
-    let _version_to_extract = reader.read_u16::<LittleEndian>()?;
+    let version_to_extract = reader.read_u16::<LittleEndian>()?;
     let flags = reader.read_u16::<LittleEndian>()?;
     let encrypted = flags & 1 == 1;
     let is_utf8 = flags & (1 << 11) != 0;
@@ -552,10 +2818,31 @@ pub(crate) fn central_header_to_zip_file<R: Read + io::Seek>(
     let file_name_length = reader.read_u16::<LittleEndian>()? as usize;
     let extra_field_length = reader.read_u16::<LittleEndian>()? as usize;
     let file_comment_length = reader.read_u16::<LittleEndian>()? as usize;
-    let _disk_number = reader.read_u16::<LittleEndian>()?;
-    let _internal_file_attributes = reader.read_u16::<LittleEndian>()?;
+    let disk_number = reader.read_u16::<LittleEndian>()?;
+    let internal_file_attributes = reader.read_u16::<LittleEndian>()?;
     let external_file_attributes = reader.read_u32::<LittleEndian>()?;
     let offset = reader.read_u32::<LittleEndian>()? as u64;
+    Limits::check(
+        file_name_length,
+        limits.file_name_length,
+        Limit::FileNameLength,
+    )?;
+    Limits::check(
+        extra_field_length,
+        limits.extra_field_length,
+        Limit::ExtraFieldLength,
+    )?;
+    Limits::check(
+        file_comment_length,
+        limits.comment_length,
+        Limit::CommentLength,
+    )?;
+    limits.allocate(
+        std::mem::size_of::<ZipFileData>()
+            + file_name_length
+            + extra_field_length
+            + file_comment_length,
+    )?;
     let mut file_name_raw = vec![0; file_name_length];
     reader.read_exact(&mut file_name_raw)?;
     let mut extra_field = vec![0; extra_field_length];
@@ -563,12 +2850,9 @@ pub(crate) fn central_header_to_zip_file<R: Read + io::Seek>(
     let mut file_comment_raw = vec![0; file_comment_length];
     reader.read_exact(&mut file_comment_raw)?;
 
-    let file_name = match is_utf8 {
-        true => String::from_utf8_lossy(&*file_name_raw).into_owned(),
-        false => file_name_raw.clone().from_cp437(),
-    };
+    let (file_name, file_name_raw) = decode_file_name(file_name_raw, is_utf8);
     let file_comment = match is_utf8 {
-        true => String::from_utf8_lossy(&*file_comment_raw).into_owned(),
+        true => String::from_utf8_lossy(&file_comment_raw).into_owned(),
         false => file_comment_raw.from_cp437(),
     };
 
@@ -576,6 +2860,7 @@ pub(crate) fn central_header_to_zip_file<R: Read + io::Seek>(
     let mut result = ZipFileData {
         system: System::from_u8((version_made_by >> 8) as u8),
         version_made_by: version_made_by as u8,
+        version_to_extract,
         encrypted,
         using_data_descriptor,
         compression_method: {
@@ -591,10 +2876,14 @@ pub(crate) fn central_header_to_zip_file<R: Read + io::Seek>(
         extra_field,
         file_comment,
         header_start: offset,
+        disk_number: disk_number as u32,
         central_header_start,
         data_start: 0,
+        internal_attributes: internal_file_attributes,
         external_attributes: external_file_attributes,
         large_file: false,
+        recovered: false,
+        flags,
     };
 
     match parse_extra_field(&mut result) {
@@ -643,54 +2932,361 @@ fn parse_extra_field(file: &mut ZipFileData) -> ZipResult<()> {
     Ok(())
 }
 
-/// Methods for retrieving information on zip files
-impl<'a> ZipFile<'a> {
-    fn get_reader(&mut self) -> &mut ZipFileReader<'a> {
-        if let ZipFileReader::NoReader = self.reader {
-            let data = &self.data;
-            let crypto_reader = self.crypto_reader.take().expect("Invalid reader state");
-            self.reader = make_reader(data.compression_method, data.crc32, crypto_reader)
-        }
-        &mut self.reader
+/// Parse a local file header, starting right after its signature.
+///
+/// The fields that are only stored in the central directory are left empty.
+fn parse_local_header<R: io::Read>(reader: &mut R) -> ZipResult<ZipFileData> {
+    // The local header only stores the version needed to extract, it stands in for the version
+    // the file was made by
+    let version_made_by = reader.read_u16::<LittleEndian>()?;
+    let flags = reader.read_u16::<LittleEndian>()?;
+    let encrypted = flags & 1 == 1;
+    let is_utf8 = flags & (1 << 11) != 0;
+    let using_data_descriptor = flags & (1 << 3) != 0;
+    #[allow(deprecated)]
+    let compression_method = CompressionMethod::from_u16(reader.read_u16::<LittleEndian>()?);
+    let last_mod_time = reader.read_u16::<LittleEndian>()?;
+    let last_mod_date = reader.read_u16::<LittleEndian>()?;
+    let crc32 = reader.read_u32::<LittleEndian>()?;
+    let compressed_size = reader.read_u32::<LittleEndian>()?;
+    let uncompressed_size = reader.read_u32::<LittleEndian>()?;
+    let file_name_length = reader.read_u16::<LittleEndian>()? as usize;
+    let extra_field_length = reader.read_u16::<LittleEndian>()? as usize;
+
+    let mut file_name_raw = vec![0; file_name_length];
+    reader.read_exact(&mut file_name_raw)?;
+    let mut extra_field = vec![0; extra_field_length];
+    reader.read_exact(&mut extra_field)?;
+
+    let (file_name, file_name_raw) = decode_file_name(file_name_raw, is_utf8);
+
+    let mut result = ZipFileData {
+        system: System::from_u8((version_made_by >> 8) as u8),
+        version_made_by: version_made_by as u8,
+        version_to_extract: version_made_by,
+        encrypted,
+        using_data_descriptor,
+        compression_method,
+        last_modified_time: DateTime::from_msdos(last_mod_date, last_mod_time),
+        crc32,
+        compressed_size: compressed_size as u64,
+        uncompressed_size: uncompressed_size as u64,
+        file_name,
+        file_name_raw,
+        extra_field,
+        file_comment: String::new(), // file comment is only available in the central directory
+        // header_start and data_start are not known here; callers that can seek fill them in.
+        header_start: 0,
+        disk_number: 0,
+        data_start: 0,
+        central_header_start: 0,
+        // The file attributes are only available in the central directory.
+        // We set these to zero, which should be valid as the docs state 'If input came
+        // from standard input, this field is set to zero.'
+        internal_attributes: 0,
+        external_attributes: 0,
+        large_file: false,
+        recovered: false,
+        flags,
+    };
+
+    match parse_extra_field(&mut result) {
+        Ok(..) | Err(ZipError::Io(..)) => {}
+        Err(e) => return Err(e),
     }
 
-    pub(crate) fn get_raw_reader(&mut self) -> &mut dyn Read {
-        if let ZipFileReader::NoReader = self.reader {
-            let crypto_reader = self.crypto_reader.take().expect("Invalid reader state");
-            self.reader = ZipFileReader::Raw(crypto_reader.into_inner())
+    Ok(result)
+}
+
+/// Scan a damaged archive for local file headers, collecting every file with a complete header
+/// and data.
+fn recover_files<R: Read + io::Seek>(reader: &mut R) -> ZipResult<Vec<ZipFileData>> {
+    let signatures = find_signatures(reader)?;
+    let reader_len = reader.seek(io::SeekFrom::End(0))?;
+    let mut files = Vec::new();
+    // Headers inside the data of a recovered file belong to an embedded archive
+    let mut next_free = 0;
+
+    for &(position, signature) in &signatures {
+        if signature != spec::LOCAL_FILE_HEADER_SIGNATURE || position < next_free {
+            continue;
+        }
+        reader.seek(io::SeekFrom::Start(position + 4))?;
+        let mut file = match parse_local_header(reader) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        file.header_start = position;
+        file.data_start = reader.seek(io::SeekFrom::Current(0))?;
+        file.recovered = true;
+        if file.using_data_descriptor && !read_data_descriptor(reader, &mut file, &signatures)? {
+            continue;
         }
-        &mut self.reader
-    }
 
-    /// Get the version of the file
-    pub fn version_made_by(&self) -> (u8, u8) {
-        (
-            self.data.version_made_by / 10,
-            self.data.version_made_by % 10,
-        )
+        let data_end = match file.data_start.checked_add(file.compressed_size) {
+            Some(end) if end <= reader_len => end,
+            _ => continue,
+        };
+        next_free = data_end;
+        files.push(file);
     }
+    Ok(files)
+}
 
-    /// Get the name of the file
-    ///
-    /// # Warnings
-    ///
-    /// It is dangerous to use this name directly when extracting an archive.
-    /// It may contain an absolute path (`/etc/shadow`), or break out of the
-    /// current directory (`../runtime`). Carelessly writing to these paths
-    /// allows an attacker to craft a ZIP archive that will overwrite critical
-    /// files.
-    ///
-    /// You can use the [`ZipFile::enclosed_name`] method to validate the name
-    /// as a safe path.
-    pub fn name(&self) -> &str {
-        &self.data.file_name
+/// Find the positions of all local file header and data descriptor signatures.
+fn find_signatures<R: Read + io::Seek>(reader: &mut R) -> ZipResult<Vec<(u64, u32)>> {
+    reader.seek(io::SeekFrom::Start(0))?;
+    let mut signatures = Vec::new();
+    let mut buffer = vec![0; 64 * 1024];
+    // Offset in the reader of the start of the buffer
+    let mut buffer_start = 0;
+    // Bytes at the start of the buffer that are left over from the previous read
+    let mut kept = 0;
+
+    loop {
+        let count = match reader.read(&mut buffer[kept..]) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let end = kept + count;
+        for (i, window) in buffer[..end].windows(4).enumerate() {
+            let signature = LittleEndian::read_u32(window);
+            if signature == spec::LOCAL_FILE_HEADER_SIGNATURE
+                || signature == spec::DATA_DESCRIPTOR_SIGNATURE
+            {
+                signatures.push((buffer_start + i as u64, signature));
+            }
+        }
+
+        // Keep the last three bytes, which may be the start of a signature
+        let keep = std::cmp::min(3, end);
+        for i in 0..keep {
+            buffer[i] = buffer[end - keep + i];
+        }
+        buffer_start += (end - keep) as u64;
+        kept = keep;
+    }
+    Ok(signatures)
+}
+
+/// Fill in the CRC32 and sizes of a file from the data descriptor following its data.
+///
+/// Returns false if no data descriptor matching the file could be found.
+fn read_data_descriptor<R: Read + io::Seek>(
+    reader: &mut R,
+    file: &mut ZipFileData,
+    signatures: &[(u64, u32)],
+) -> ZipResult<bool> {
+    let position = match signatures.iter().find(|&&(position, signature)| {
+        signature == spec::DATA_DESCRIPTOR_SIGNATURE && position >= file.data_start
+    }) {
+        Some(&(position, _)) => position,
+        None => return Ok(false),
+    };
+    let compressed_size = position - file.data_start;
+
+    reader.seek(io::SeekFrom::Start(position + 4))?;
+    let crc32 = reader.read_u32::<LittleEndian>()?;
+    let mut sizes = Vec::new();
+    reader.take(16).read_to_end(&mut sizes)?;
+    // Plain data descriptors store 32-bit sizes, ZIP64 ones 64-bit sizes
+    let uncompressed_size =
+        if sizes.len() >= 8 && LittleEndian::read_u32(&sizes) as u64 == compressed_size {
+            LittleEndian::read_u32(&sizes[4..]) as u64
+        } else if sizes.len() >= 16 && LittleEndian::read_u64(&sizes) == compressed_size {
+            LittleEndian::read_u64(&sizes[8..])
+        } else {
+            return Ok(false);
+        };
+
+    file.crc32 = crc32;
+    file.compressed_size = compressed_size;
+    file.uncompressed_size = uncompressed_size;
+    Ok(true)
+}
+
+/// Methods for retrieving information on zip files
+impl<'a> ZipFile<'a> {
+    /// Skip over the remaining contents of the file.
+    fn drain(&mut self) -> io::Result<()> {
+        // self.data is Owned, this reader is constructed by a streaming reader.
+        // In this case, we want to exhaust the reader so that the next file is accessible.
+        if let Cow::Owned(_) = self.data {
+            let mut buffer = [0; 1 << 16];
+
+            // Get the inner `Take` reader so all decryption, decompression and CRC calculation is skipped.
+            let innerreader = ::std::mem::replace(&mut self.reader, ZipFileReader::NoReader);
+            let mut reader: std::io::Take<&mut dyn std::io::Read> = match innerreader
+                .into_inner()
+                .or_else(|| self.crypto_reader.take().map(CryptoReader::into_inner))
+            {
+                Some(reader) => reader,
+                None => return Ok(()),
+            };
+
+            while reader.read(&mut buffer)? != 0 {}
+        }
+        Ok(())
+    }
+
+    /// Decompress the rest of the file into `writer`, returning the number of bytes written.
+    ///
+    /// This goes through a larger buffer than `io::copy` uses, which files from a [`ZipArchive`]
+    /// share with each other, so extracting many files doesn't allocate one for each of them.
+    pub fn copy_to<W: Write + ?Sized>(&mut self, writer: &mut W) -> ZipResult<u64> {
+        let mut written = 0;
+        loop {
+            let count = {
+                let buffer = self.fill_buf()?;
+                if buffer.is_empty() {
+                    return Ok(written);
+                }
+                writer.write_all(buffer)?;
+                buffer.len()
+            };
+            self.consume(count);
+            written += count as u64;
+        }
+    }
+
+    /// Finish reading this file, skipping whatever has not been read yet.
+    ///
+    /// When reading with [`read_zipfile_from_stream`], the stream has to be positioned after
+    /// this file before the next one can be read. Dropping a `ZipFile` does this as well, but
+    /// ignores I/O errors; call this method to find out whether it succeeded.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.drain()
+    }
+
+    /// Feed the contents to `hasher` as well while they are decompressed, to compute another hash
+    /// without reading the file twice, like one to check against a manifest kept elsewhere.
+    ///
+    /// Only what is read after this call is hashed, and like the CRC32, the hash only covers the
+    /// contents if they are read in order without seeking. Whatever isn't read before the file is
+    /// dropped isn't hashed either.
+    ///
+    /// ```
+    /// use std::io::{Cursor, Read, Write};
+    /// use zip::read::Hasher;
+    ///
+    /// /// Sum of all bytes, standing in for a real hash function
+    /// struct Sum(u64);
+    ///
+    /// impl Hasher for Sum {
+    ///     fn update(&mut self, data: &[u8]) {
+    ///         self.0 += data.iter().map(|&byte| byte as u64).sum::<u64>();
+    ///     }
+    /// }
+    ///
+    /// # fn main() -> zip::result::ZipResult<()> {
+    /// let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    /// writer.start_file("numbers", Default::default())?;
+    /// writer.write_all(&[1, 2, 3])?;
+    /// let mut archive = zip::ZipArchive::new(writer.finish()?)?;
+    ///
+    /// let mut sum = Sum(0);
+    /// let mut file = archive.by_name("numbers")?;
+    /// file.hash_with(&mut sum)?;
+    /// std::io::copy(&mut file, &mut std::io::sink())?;
+    /// drop(file);
+    /// assert_eq!(sum.0, 6);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn hash_with(&mut self, hasher: &'a mut dyn Hasher) -> ZipResult<()> {
+        if let ZipFileReader::Raw(_) = self.get_reader()? {
+            return Err(invalid_state().into());
+        }
+        // What `fill_buf` read ahead was decompressed before, but hasn't been read yet
+        hasher.update(self.buffer.available());
+        match &mut self.reader {
+            ZipFileReader::Stored(reader) => reader.set_extra_hasher(hasher),
+            #[cfg(any(
+                feature = "deflate",
+                feature = "deflate-miniz",
+                feature = "deflate-zlib"
+            ))]
+            ZipFileReader::Deflated(reader) => reader.set_extra_hasher(hasher),
+            #[cfg(feature = "bzip2")]
+            ZipFileReader::Bzip2(reader) => reader.set_extra_hasher(hasher),
+            ZipFileReader::NoReader | ZipFileReader::Raw(_) => return Err(invalid_state().into()),
+        }
+        Ok(())
+    }
+
+    fn get_reader(&mut self) -> ZipResult<&mut ZipFileReader<'a>> {
+        if let ZipFileReader::NoReader = self.reader {
+            let data = &self.data;
+            // Check this before giving up the crypto reader, so the file stays in a usable state
+            check_supported(data.compression_method)?;
+            let crypto_reader = self.crypto_reader.take().ok_or_else(invalid_state)?;
+            let pool = self.pool.as_mut().map(|pool| &mut **pool);
+            let crc32 = if self.check_crc32 {
+                Some(data.crc32)
+            } else {
+                None
+            };
+            let dictionary = dictionary_for(data, self.dictionary)?;
+            self.reader = make_reader(
+                data.compression_method,
+                crc32,
+                crypto_reader,
+                pool,
+                dictionary,
+            )?;
+        }
+        Ok(&mut self.reader)
+    }
+
+    pub(crate) fn get_raw_reader(&mut self) -> &mut dyn Read {
+        if let ZipFileReader::NoReader = self.reader {
+            if let Some(crypto_reader) = self.crypto_reader.take() {
+                self.reader = ZipFileReader::Raw(crypto_reader.into_inner())
+            }
+        }
+        &mut self.reader
+    }
+
+    /// Get the version of the file
+    pub fn version_made_by(&self) -> (u8, u8) {
+        (
+            self.data.version_made_by / 10,
+            self.data.version_made_by % 10,
+        )
+    }
+
+    /// Get the version of the specification needed to extract the file, as (major, minor)
+    ///
+    /// This is 2.0 for deflated files and directories, 4.5 for files that use ZIP64 and 4.6 for
+    /// BZIP2 compressed files, for example.
+    pub fn version_needed_to_extract(&self) -> (u8, u8) {
+        let version = self.data.version_to_extract as u8;
+        (version / 10, version % 10)
+    }
+
+    /// Get the name of the file
+    ///
+    /// # Warnings
+    ///
+    /// It is dangerous to use this name directly when extracting an archive.
+    /// It may contain an absolute path (`/etc/shadow`), or break out of the
+    /// current directory (`../runtime`). Carelessly writing to these paths
+    /// allows an attacker to craft a ZIP archive that will overwrite critical
+    /// files.
+    ///
+    /// You can use the [`ZipFile::enclosed_name`] method to validate the name
+    /// as a safe path.
+    pub fn name(&self) -> &str {
+        &self.data.file_name
     }
 
     /// Get the name of the file, in the raw (internal) byte representation.
     ///
     /// The encoding of this data is currently undefined.
     pub fn name_raw(&self) -> &[u8] {
-        &self.data.file_name_raw
+        self.data.name_raw()
     }
 
     /// Get the name of the file in a sanitized form. It truncates the name to the first NULL byte,
@@ -731,20 +3327,7 @@ impl<'a> ZipFile<'a> {
     /// to path-based exploits. It is recommended over
     /// [`ZipFile::mangled_name`].
     pub fn enclosed_name(&self) -> Option<&Path> {
-        if self.data.file_name.contains('\0') {
-            return None;
-        }
-        let path = Path::new(&self.data.file_name);
-        let mut depth = 0usize;
-        for component in path.components() {
-            match component {
-                Component::Prefix(_) | Component::RootDir => return None,
-                Component::ParentDir => depth = depth.checked_sub(1)?,
-                Component::Normal(_) => depth += 1,
-                Component::CurDir => (),
-            }
-        }
-        Some(path)
+        self.data.enclosed_name()
     }
 
     /// Get the comment of the file
@@ -772,12 +3355,12 @@ impl<'a> ZipFile<'a> {
         self.data.last_modified_time
     }
     /// Returns whether the file is actually a directory
+    ///
+    /// Directories normally have a name ending in a slash, but some tools only mark them as
+    /// directories in the external attributes, so the MS-DOS directory attribute and the unix
+    /// file type count as well for files without data.
     pub fn is_dir(&self) -> bool {
-        self.name()
-            .chars()
-            .rev()
-            .next()
-            .map_or(false, |c| c == '/' || c == '\\')
+        self.data.is_dir()
     }
 
     /// Returns whether the file is a regular file
@@ -785,29 +3368,97 @@ impl<'a> ZipFile<'a> {
         !self.is_dir()
     }
 
+    /// Returns whether the file is marked as text in its internal file attributes
+    ///
+    /// Some tools use this to convert line endings or character sets when extracting. Most
+    /// archivers don't set the flag, so it being false doesn't mean that the file is binary.
+    pub fn is_text(&self) -> bool {
+        self.data.internal_attributes & 1 != 0
+    }
+
+    /// Returns whether the file has the hidden attribute in its MS-DOS attributes
+    pub fn is_hidden(&self) -> bool {
+        self.data.external_attributes & crate::types::DOS_HIDDEN != 0
+    }
+
+    /// Returns whether the file has the system attribute in its MS-DOS attributes
+    pub fn is_system_file(&self) -> bool {
+        self.data.external_attributes & crate::types::DOS_SYSTEM != 0
+    }
+
+    /// Returns whether the file is encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.data.encrypted
+    }
+
+    /// Get the options to write a copy of the file with, see [`ZipWriter::start_file`].
+    ///
+    /// The compression method, modification time, permissions, creator system, text flag, MS-DOS
+    /// attributes and `large_file` are taken from the file. Comments and extra fields are not
+    /// part of [`FileOptions`], get them with [`ZipFile::comment`] and [`ZipFile::extra_data`].
+    /// [`ZipWriter::raw_copy_file`] keeps them along with the compressed data.
+    ///
+    /// [`ZipWriter::start_file`]: crate::write::ZipWriter::start_file
+    /// [`ZipWriter::raw_copy_file`]: crate::write::ZipWriter::raw_copy_file
+    /// [`FileOptions`]: crate::write::FileOptions
+    pub fn options(&self) -> crate::write::FileOptions {
+        crate::write::FileOptions::from_entry(&self.data)
+    }
+
+    /// Get the general purpose bit flags of the file, as stored in the central directory
+    ///
+    /// The flags that matter for reading are also available as [`ZipFile::is_encrypted`] and
+    /// [`ZipFile::has_data_descriptor`]. For a file opened from a stream, these are the flags of
+    /// its local header.
+    pub fn flags(&self) -> u16 {
+        self.data.flags
+    }
+
+    /// Returns whether the CRC32 and sizes of the file are stored in a data descriptor after its
+    /// contents, instead of in its local header
+    pub fn has_data_descriptor(&self) -> bool {
+        self.data.using_data_descriptor
+    }
+
     /// Get unix mode for the file
     pub fn unix_mode(&self) -> Option<u32> {
-        if self.data.external_attributes == 0 {
-            return None;
+        self.data.unix_mode()
+    }
+
+    /// Get the unix user and group ids of the file, from an Info-ZIP Unix extra field ("ux")
+    ///
+    /// The field is looked for in the central directory, and then in the local header.
+    pub fn unix_owner(&self) -> Option<(u32, u32)> {
+        self.data
+            .unix_owner()
+            .or_else(|| crate::types::find_unix_owner(self.local_extra_data()))
+    }
+
+    /// Returns whether the unix mode of the file makes it a FIFO, socket or device
+    pub fn is_special(&self) -> bool {
+        match self.unix_mode().map(|mode| mode & 0o170000) {
+            None | Some(0) | Some(ffi::S_IFREG) | Some(ffi::S_IFDIR) | Some(ffi::S_IFLNK) => false,
+            Some(_) => !self.is_dir(),
         }
+    }
 
-        match self.data.system {
-            System::Unix => Some(self.data.external_attributes >> 16),
-            System::Dos => {
-                // Interpret MSDOS directory bit
-                let mut mode = if 0x10 == (self.data.external_attributes & 0x10) {
-                    ffi::S_IFDIR | 0o0775
-                } else {
-                    ffi::S_IFREG | 0o0664
-                };
-                if 0x01 == (self.data.external_attributes & 0x01) {
-                    // Read-only bit; strip write permissions
-                    mode &= 0o0555;
-                }
-                Some(mode)
-            }
-            _ => None,
+    /// Get the name of the file this entry is a hard link to, if it is one
+    ///
+    /// Hard links are regular files without contents, whose PKWARE Unix extra field holds the
+    /// name of the file they link to, as written by [`ZipWriter::add_hard_link`]. The field is
+    /// looked for in the central directory, and then in the local header.
+    ///
+    /// [`ZipWriter::add_hard_link`]: crate::write::ZipWriter::add_hard_link
+    pub fn hard_link_target(&self) -> Option<String> {
+        let regular = self
+            .unix_mode()
+            .map_or(true, |mode| mode & 0o170000 == ffi::S_IFREG);
+        if !regular || self.is_dir() || self.size() != 0 {
+            return None;
         }
+        crate::types::find_link_target(self.extra_data())
+            .or_else(|| crate::types::find_link_target(self.local_extra_data()))
+            .map(|target| String::from_utf8_lossy(target).into_owned())
     }
 
     /// Get the CRC32 hash of the original file
@@ -820,9 +3471,18 @@ impl<'a> ZipFile<'a> {
         &self.data.extra_field
     }
 
+    /// Get the extra data of the local header for this file
+    ///
+    /// This can differ from [`ZipFile::extra_data`], which comes from the central directory.
+    /// Some fields, like alignment padding or the access and creation times of the extended
+    /// timestamp field, are often only stored in the local header.
+    pub fn local_extra_data(&self) -> &[u8] {
+        self.local_extra.as_ref().unwrap_or(&self.data.extra_field)
+    }
+
     /// Get the starting offset of the data of the compressed file
     pub fn data_start(&self) -> u64 {
-        self.data.data_start
+        self.data_start
     }
 
     /// Get the starting offset of the zip header for this file
@@ -833,44 +3493,216 @@ impl<'a> ZipFile<'a> {
     pub fn central_header_start(&self) -> u64 {
         self.data.central_header_start
     }
+
+    /// Returns whether the file was recovered by [`ZipArchive::new_with_recovery`] from a
+    /// damaged archive, rather than read from the central directory
+    pub fn is_recovered(&self) -> bool {
+        self.data.recovered
+    }
 }
 
 impl<'a> Read for ZipFile<'a> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.get_reader().read(buf)
+        if self.buffer.pos >= self.buffer.cap {
+            return self.get_reader()?.read(buf);
+        }
+        let count = self.buffer.available().read(buf)?;
+        self.consume(count);
+        Ok(count)
     }
-}
 
-impl<'a> Drop for ZipFile<'a> {
-    fn drop(&mut self) {
-        // self.data is Owned, this reader is constructed by a streaming reader.
-        // In this case, we want to exhaust the reader so that the next file is accessible.
-        if let Cow::Owned(_) = self.data {
-            let mut buffer = [0; 1 << 16];
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        if self.buffer.pos >= self.buffer.cap {
+            return self.get_reader()?.read_vectored(bufs);
+        }
+        let count = self.buffer.available().read_vectored(bufs)?;
+        self.consume(count);
+        Ok(count)
+    }
+}
 
-            // Get the inner `Take` reader so all decryption, decompression and CRC calculation is skipped.
-            let mut reader: std::io::Take<&mut dyn std::io::Read> = match &mut self.reader {
-                ZipFileReader::NoReader => {
-                    let innerreader = ::std::mem::replace(&mut self.crypto_reader, None);
-                    innerreader.expect("Invalid reader state").into_inner()
+/// Reads ahead into a buffer that is only allocated once `fill_buf` is first called, so there is
+/// no need to wrap a `ZipFile` in a `BufReader`.
+impl<'a> BufRead for ZipFile<'a> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.get_reader()?;
+        if self.buffer.pos >= self.buffer.cap {
+            match &mut self.reader {
+                // Stored data is served straight from the buffer it's read into
+                ZipFileReader::Stored(reader) => {
+                    let buffered = reader.get_mut();
+                    if !buffered.is_allocated() {
+                        if let Some(pool) = self.pool.as_mut() {
+                            buffered.allocate(pool.take_buffer());
+                        }
+                    }
+                    return reader.fill_buf();
                 }
                 reader => {
-                    let innerreader = ::std::mem::replace(reader, ZipFileReader::NoReader);
-                    innerreader.into_inner()
+                    if self.buffer.data.is_empty() {
+                        let pool = self.pool.as_mut().map(|pool| &mut **pool);
+                        self.buffer.data = DecompressorPool::take_output_buffer(pool);
+                    }
+                    self.buffer.cap = reader.read(&mut self.buffer.data)?;
+                    self.buffer.pos = 0;
                 }
-            };
+            }
+        }
+        Ok(self.buffer.available())
+    }
 
-            loop {
-                match reader.read(&mut buffer) {
-                    Ok(0) => break,
-                    Ok(_) => (),
-                    Err(e) => panic!(
-                        "Could not consume all of the output of the current ZipFile: {:?}",
-                        e
-                    ),
+    fn consume(&mut self, amt: usize) {
+        match &mut self.reader {
+            ZipFileReader::Stored(reader) => reader.consume(amt),
+            _ => self.buffer.pos = ::std::cmp::min(self.buffer.pos + amt, self.buffer.cap),
+        }
+    }
+}
+
+fn not_seekable() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Other,
+        "seeking is only supported for unencrypted stored or deflated files in a ZipArchive",
+    )
+}
+
+/// Seek within a deflated file by decompressing up to the new position.
+///
+/// Decompressing starts over from the nearest checkpoint before the new position when seeking
+/// backward, or when seeking forward past one. Otherwise the data in between is skipped.
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate-miniz",
+    feature = "deflate-zlib"
+))]
+fn seek_deflated(
+    reader: &mut Crc32Reader<'_, Inflater<PooledBufReader<CryptoReader<'_>>>>,
+    size: u64,
+    pos: io::SeekFrom,
+) -> io::Result<u64> {
+    let current = reader.get_mut().total_out();
+    let target = match pos {
+        io::SeekFrom::Start(offset) => Some(offset),
+        io::SeekFrom::End(offset) => offset_position(size, offset),
+        io::SeekFrom::Current(offset) => offset_position(current, offset),
+    }
+    .ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        )
+    })?;
+    if target == current {
+        return Ok(current);
+    }
+    reader.disable_check();
+
+    let inflater = reader.get_mut();
+    let seekable = match inflater.get_mut().get_mut() {
+        CryptoReader::Seekable(_) => true,
+        _ => false,
+    };
+    if seekable {
+        inflater.record_checkpoints();
+    } else if target < current {
+        return Err(not_seekable());
+    }
+    let checkpoint = inflater.checkpoint_before(target);
+    if target < current || checkpoint > current {
+        let input_bits = inflater.restart_at(checkpoint)?;
+        let buffered = inflater.get_mut();
+        if let CryptoReader::Seekable(inner) = buffered.get_mut() {
+            inner.seek(io::SeekFrom::Start(input_bits / 8))?;
+        }
+        buffered.discard_buffer();
+        buffered.skip_bits((input_bits % 8) as u32);
+    }
+
+    let skip = target - reader.get_mut().total_out();
+    io::copy(&mut reader.take(skip), &mut io::sink())?;
+    Ok(reader.get_mut().total_out())
+}
+
+/// Seeking is supported for unencrypted files that are stored without compression or deflated,
+/// when they are read from a [`ZipArchive`]. Positions are relative to the start of the file
+/// contents.
+///
+/// Stored files are seeked directly in the archive. Deflated files have to be decompressed up
+/// to the new position, so seeking within them is slow. Once a deflated file was seeked, a
+/// checkpoint is kept about every megabyte of the data that is decompressed, which takes 32 KiB
+/// each. Seeking backward starts over from the nearest checkpoint before the new position, or
+/// from the beginning of the file if there is none. Seeking past the end of a deflated file
+/// stops at the end.
+///
+/// Since the CRC32 can't be checked unless all of the contents are read in order, it isn't
+/// checked anymore once the position is changed.
+impl<'a> Seek for ZipFile<'a> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        // The reader is ahead of the current position by whatever is still buffered
+        let pos = match pos {
+            io::SeekFrom::Current(offset) => {
+                io::SeekFrom::Current(offset - self.buffer.available().len() as i64)
+            }
+            pos => pos,
+        };
+        let position = self.seek_reader(pos)?;
+        self.buffer.discard();
+        Ok(position)
+    }
+}
+
+impl<'a> ZipFile<'a> {
+    fn seek_reader(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        #[cfg(any(
+            feature = "deflate",
+            feature = "deflate-miniz",
+            feature = "deflate-zlib"
+        ))]
+        let size = self.data.uncompressed_size;
+        match self.get_reader()? {
+            ZipFileReader::Stored(reader) => {
+                let buffered = reader.get_mut();
+                // The reader is ahead of the current position by whatever is still buffered
+                let ahead = buffered.buffered() as u64;
+                if let CryptoReader::Seekable(inner) = buffered.get_mut() {
+                    let old_position = inner.position - ahead;
+                    let pos = match pos {
+                        io::SeekFrom::Current(offset) => {
+                            io::SeekFrom::Current(offset - ahead as i64)
+                        }
+                        pos => pos,
+                    };
+                    let position = inner.seek(pos)?;
+                    buffered.discard_buffer();
+                    if position != old_position {
+                        reader.disable_check();
+                    }
+                    return Ok(position);
                 }
+                Err(not_seekable())
+            }
+            #[cfg(any(
+                feature = "deflate",
+                feature = "deflate-miniz",
+                feature = "deflate-zlib"
+            ))]
+            ZipFileReader::Deflated(reader) => seek_deflated(reader, size, pos),
+            _ => Err(not_seekable()),
+        }
+    }
+}
+
+impl<'a> Drop for ZipFile<'a> {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            ::std::mem::replace(&mut self.reader, ZipFileReader::NoReader).recycle(pool);
+            if !self.buffer.data.is_empty() {
+                pool.put_output_buffer(::std::mem::replace(&mut self.buffer.data, Vec::new()));
             }
         }
+
+        // Errors can't be reported from here, use `finish` to observe them.
+        let _ = self.drain();
     }
 }
 
@@ -884,89 +3716,49 @@ impl<'a> Drop for ZipFile<'a> {
 /// is encountered. No more files should be read after this.
 ///
 /// The Drop implementation of ZipFile ensures that the reader will be correctly positioned after
-/// the structure is done.
+/// the structure is done. Use [`ZipFile::finish`] to be notified of errors while doing so.
 ///
 /// Missing fields are:
 /// * `comment`: set to an empty string
 /// * `data_start`: set to 0
 /// * `external_attributes`: `unix_mode()`: will return None
+/// * `internal_attributes`: `is_text()`: will return false
 pub fn read_zipfile_from_stream<'a, R: io::Read>(
     reader: &'a mut R,
-) -> ZipResult<Option<ZipFile<'_>>> {
+) -> ZipResult<Option<ZipFile<'a>>> {
     let signature = reader.read_u32::<LittleEndian>()?;
 
     match signature {
         spec::LOCAL_FILE_HEADER_SIGNATURE => (),
         spec::CENTRAL_DIRECTORY_HEADER_SIGNATURE => return Ok(None),
-        _ => return Err(ZipError::InvalidArchive("Invalid local file header")),
+        _ => {
+            return Err(ZipError::invalid(
+                InvalidArchiveKind::InvalidLocalFileHeader,
+            ))
+        }
     }
 
-    let version_made_by = reader.read_u16::<LittleEndian>()?;
-    let flags = reader.read_u16::<LittleEndian>()?;
-    let encrypted = flags & 1 == 1;
-    let is_utf8 = flags & (1 << 11) != 0;
-    let using_data_descriptor = flags & (1 << 3) != 0;
-    #[allow(deprecated)]
-    let compression_method = CompressionMethod::from_u16(reader.read_u16::<LittleEndian>()?);
-    let last_mod_time = reader.read_u16::<LittleEndian>()?;
-    let last_mod_date = reader.read_u16::<LittleEndian>()?;
-    let crc32 = reader.read_u32::<LittleEndian>()?;
-    let compressed_size = reader.read_u32::<LittleEndian>()?;
-    let uncompressed_size = reader.read_u32::<LittleEndian>()?;
-    let file_name_length = reader.read_u16::<LittleEndian>()? as usize;
-    let extra_field_length = reader.read_u16::<LittleEndian>()? as usize;
-
-    let mut file_name_raw = vec![0; file_name_length];
-    reader.read_exact(&mut file_name_raw)?;
-    let mut extra_field = vec![0; extra_field_length];
-    reader.read_exact(&mut extra_field)?;
-
-    let file_name = match is_utf8 {
-        true => String::from_utf8_lossy(&*file_name_raw).into_owned(),
-        false => file_name_raw.clone().from_cp437(),
-    };
-
-    let mut result = ZipFileData {
-        system: System::from_u8((version_made_by >> 8) as u8),
-        version_made_by: version_made_by as u8,
-        encrypted,
-        using_data_descriptor,
-        compression_method,
-        last_modified_time: DateTime::from_msdos(last_mod_date, last_mod_time),
-        crc32,
-        compressed_size: compressed_size as u64,
-        uncompressed_size: uncompressed_size as u64,
-        file_name,
-        file_name_raw,
-        extra_field,
-        file_comment: String::new(), // file comment is only available in the central directory
-        // header_start and data start are not available, but also don't matter, since seeking is
-        // not available.
-        header_start: 0,
-        data_start: 0,
-        central_header_start: 0,
-        // The external_attributes field is only available in the central directory.
-        // We set this to zero, which should be valid as the docs state 'If input came
-        // from standard input, this field is set to zero.'
-        external_attributes: 0,
-        large_file: false,
-    };
+    read_local_file(reader).map(Some)
+}
 
-    match parse_extra_field(&mut result) {
-        Ok(..) | Err(ZipError::Io(..)) => {}
-        Err(e) => return Err(e),
-    }
+/// Read a file from a stream, starting right after the signature of its local header.
+fn read_local_file<'a, R: io::Read>(reader: &'a mut R) -> ZipResult<ZipFile<'a>> {
+    let result = parse_local_header(reader)?;
 
-    if encrypted {
+    if result.encrypted {
         return unsupported_zip_error("Encrypted files are not supported");
     }
-    if using_data_descriptor {
+    if result.using_data_descriptor {
         return unsupported_zip_error("The file length is not available in the local header");
     }
 
-    let limit_reader = (reader as &'a mut dyn io::Read).take(result.compressed_size as u64);
+    let limit_reader = CryptoReader::Plaintext(
+        (reader as &'a mut dyn io::Read).take(result.compressed_size as u64),
+    );
 
     let result_crc32 = result.crc32;
+    // Streams have no way to be given a dictionary
+    let dictionary = dictionary_for(&result, None)?;
     let result_compression_method = result.compression_method;
     let crypto_reader = make_crypto_reader(
         result_compression_method,
@@ -978,11 +3770,23 @@ pub fn read_zipfile_from_stream<'a, R: io::Read>(
     )?
     .unwrap();
 
-    Ok(Some(ZipFile {
+    Ok(ZipFile {
+        data_start: result.data_start,
         data: Cow::Owned(result),
         crypto_reader: None,
-        reader: make_reader(result_compression_method, result_crc32, crypto_reader),
-    }))
+        reader: make_reader(
+            result_compression_method,
+            Some(result_crc32),
+            crypto_reader,
+            None,
+            dictionary,
+        )?,
+        pool: None,
+        check_crc32: true,
+        buffer: OutputBuffer::default(),
+        local_extra: None,
+        dictionary: None,
+    })
 }
 
 #[cfg(test)]
@@ -1030,6 +3834,8 @@ mod test {
         let mut reader = ZipArchive::new(io::Cursor::new(v)).unwrap();
         assert!(reader.comment() == b"");
         assert_eq!(reader.by_index(0).unwrap().central_header_start(), 77);
+        assert!(!reader.has_encrypted_entries());
+        assert!(!reader.by_index(0).unwrap().is_encrypted());
     }
 
     #[test]
@@ -1040,12 +3846,7 @@ mod test {
         let mut v = Vec::new();
         v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
         let mut reader = io::Cursor::new(v);
-        loop {
-            match read_zipfile_from_stream(&mut reader).unwrap() {
-                None => break,
-                _ => (),
-            }
-        }
+        while read_zipfile_from_stream(&mut reader).unwrap().is_some() {}
     }
 
     #[test]
@@ -1079,16 +3880,1032 @@ mod test {
         let mut buf3 = [0; 5];
         let mut buf4 = [0; 5];
 
-        file1.read(&mut buf1).unwrap();
-        file2.read(&mut buf2).unwrap();
-        file1.read(&mut buf3).unwrap();
-        file2.read(&mut buf4).unwrap();
+        file1.read_exact(&mut buf1).unwrap();
+        file2.read_exact(&mut buf2).unwrap();
+        file1.read_exact(&mut buf3).unwrap();
+        file2.read_exact(&mut buf4).unwrap();
 
         assert_eq!(buf1, buf2);
         assert_eq!(buf3, buf4);
         assert!(buf1 != buf3);
     }
 
+    #[test]
+    fn zip_read_prefix() {
+        use super::ZipArchive;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
+        let mut reader = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        assert_eq!(reader.read_prefix(0, 11).unwrap(), b"application");
+        assert_eq!(
+            reader.read_prefix(0, 1024).unwrap(),
+            b"application/vnd.oasis.opendocument.text".as_ref()
+        );
+        assert!(reader.read_prefix(1, 11).is_err());
+    }
+
+    #[test]
+    fn zip_cached_read() {
+        use super::ZipArchive;
+        use std::io;
+        use std::sync::Arc;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
+        let mut reader = ZipArchive::new(io::Cursor::new(v)).unwrap();
+
+        let uncached = reader.by_index_cached(0).unwrap();
+        assert!(!Arc::ptr_eq(&uncached, &reader.by_index_cached(0).unwrap()));
+
+        reader.set_cache_size(1024);
+        let first = reader.by_index_cached(0).unwrap();
+        assert_eq!(&*first, b"application/vnd.oasis.opendocument.text".as_ref());
+        assert!(Arc::ptr_eq(&first, &reader.by_index_cached(0).unwrap()));
+    }
+
+    #[test]
+    fn zip_by_path() {
+        use super::ZipArchive;
+        use std::io;
+        use std::path::Path;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/files_and_dirs.zip"));
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let names: Vec<String> = zip.file_names().map(|s| s.to_owned()).collect();
+
+        for name in names {
+            let windows_name = name.replace('/', "\\");
+            assert_eq!(zip.by_path(Path::new(&windows_name)).unwrap().name(), name);
+            let trimmed = name.trim_end_matches('/');
+            assert_eq!(zip.by_path(trimmed).unwrap().name(), name);
+        }
+        assert!(zip.by_path("does/not/exist").is_err());
+    }
+
+    #[test]
+    fn zip_entries() {
+        use super::ZipArchive;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/files_and_dirs.zip"));
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let expected: Vec<String> = (0..zip.len())
+            .map(|i| zip.by_index(i).unwrap().name().to_owned())
+            .collect();
+
+        let mut names = Vec::new();
+        let mut entries = zip.entries();
+        assert_eq!(entries.remaining(), expected.len());
+        while let Some(file) = entries.next() {
+            names.push(file.unwrap().name().to_owned());
+        }
+        assert_eq!(entries.remaining(), 0);
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn zip_validate() {
+        use super::{ValidationIssueKind, ZipArchive};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Write};
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/files_and_dirs.zip"));
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        assert!(zip.validate().is_empty());
+        assert!(zip.validate_entry(zip.len()).is_err());
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(crate::CompressionMethod::Stored);
+        writer.start_file("good.txt", options).unwrap();
+        writer.write_all(b"good contents").unwrap();
+        writer.start_file("bad.txt", options).unwrap();
+        writer.write_all(b"bad contents").unwrap();
+        let mut v = writer.finish().unwrap().into_inner();
+        let position = v.windows(12).position(|w| w == b"bad contents").unwrap();
+        v[position] = b'B';
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let issues = zip.validate();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].index, 1);
+        assert_eq!(issues[0].name, "bad.txt");
+        match issues[0].kind {
+            ValidationIssueKind::CrcMismatch { expected, actual } => assert_ne!(expected, actual),
+            ref kind => panic!("unexpected issue {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn zip_local_header_mismatch() {
+        use super::{Config, ValidationIssueKind, ZipArchive};
+        use crate::result::{InvalidArchiveKind, ZipError};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer
+            .start_file("safe.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"contents").unwrap();
+        let mut v = writer.finish().unwrap().into_inner();
+        // Rename the file in the local header only
+        let position = v.windows(8).position(|w| w == b"safe.txt").unwrap();
+        v[position..position + 4].copy_from_slice(b"evil");
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v.clone())).unwrap();
+        assert!(zip.by_index(0).is_ok());
+        let issues = zip.validate();
+        assert_eq!(issues.len(), 1);
+        match issues[0].kind {
+            ValidationIssueKind::LocalHeaderMismatch => {}
+            ref kind => panic!("unexpected issue {:?}", kind),
+        }
+
+        let config = Config::default().check_local_headers(true);
+        let mut zip = ZipArchive::new_with_config(io::Cursor::new(v), config).unwrap();
+        match zip.by_index(0) {
+            Err(ZipError::InvalidArchive {
+                kind: InvalidArchiveKind::LocalHeaderMismatch,
+                entry: Some(ref name),
+                ..
+            }) if name == "safe.txt" => {}
+            Err(e) => panic!("unexpected error {:?}", e),
+            Ok(_) => panic!("mismatched local header accepted"),
+        };
+    }
+
+    #[test]
+    fn zip_recovery() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use byteorder::{LittleEndian, WriteBytesExt};
+        use std::io::{self, Read, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer
+            .start_file("first.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"first contents").unwrap();
+        let mut v = writer.finish().unwrap().into_inner();
+        // Drop the central directory and end of central directory record
+        let central_directory = v.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        v.truncate(central_directory);
+
+        // Append a stored file that uses a data descriptor
+        let contents = b"second contents";
+        let crc32 = crc32fast::hash(contents);
+        v.write_u32::<LittleEndian>(0x04034b50).unwrap();
+        v.write_u16::<LittleEndian>(20).unwrap();
+        v.write_u16::<LittleEndian>(1 << 3).unwrap();
+        v.write_u16::<LittleEndian>(0).unwrap();
+        v.write_u32::<LittleEndian>(0).unwrap();
+        v.write_u32::<LittleEndian>(0).unwrap();
+        v.write_u32::<LittleEndian>(0).unwrap();
+        v.write_u32::<LittleEndian>(0).unwrap();
+        v.write_u16::<LittleEndian>(10).unwrap();
+        v.write_u16::<LittleEndian>(0).unwrap();
+        v.extend_from_slice(b"second.txt");
+        v.extend_from_slice(contents);
+        v.write_u32::<LittleEndian>(0x08074b50).unwrap();
+        v.write_u32::<LittleEndian>(crc32).unwrap();
+        v.write_u32::<LittleEndian>(contents.len() as u32).unwrap();
+        v.write_u32::<LittleEndian>(contents.len() as u32).unwrap();
+
+        // A truncated file can't be recovered
+        let truncated = v[..40].to_vec();
+        v.extend_from_slice(&truncated);
+
+        assert!(ZipArchive::new(io::Cursor::new(v.clone())).is_err());
+        let mut zip = ZipArchive::new_with_recovery(io::Cursor::new(v)).unwrap();
+        assert_eq!(zip.len(), 2);
+        for (i, (name, expected)) in [
+            ("first.txt", "first contents"),
+            ("second.txt", "second contents"),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let mut file = zip.by_index(i).unwrap();
+            assert!(file.is_recovered());
+            assert_eq!(file.name(), *name);
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, *expected);
+        }
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/files_and_dirs.zip"));
+        let mut zip = ZipArchive::new_with_recovery(io::Cursor::new(v)).unwrap();
+        assert!(!zip.by_index(0).unwrap().is_recovered());
+    }
+
+    #[test]
+    fn zip_permissive() {
+        use super::{Config, ZipArchive};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Read, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer
+            .start_file("file.txt\0garbage", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"contents").unwrap();
+        let mut v = writer.finish().unwrap().into_inner();
+        // Break the central directory offset in the end of central directory record
+        let offset_position = v.len() - 6;
+        v[offset_position] += 5;
+
+        assert!(ZipArchive::new(io::Cursor::new(v.clone())).is_err());
+        let config = Config::default().permissive(true);
+        let mut zip = ZipArchive::new_with_config(io::Cursor::new(v), config).unwrap();
+        let mut file = zip.by_name("file.txt").unwrap();
+        assert_eq!(file.name_raw(), b"file.txt\0garbage");
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "contents");
+    }
+
+    #[test]
+    fn zip_signature_in_comment() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer
+            .start_file("file.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"contents").unwrap();
+        // A complete footer in the comment, pointing at a central directory that doesn't exist
+        let mut comment = b"PK\x05\x06".to_vec();
+        comment.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0, 46, 0, 0, 0, 7, 0, 0, 0, 0, 0]);
+        writer.set_raw_comment(comment.clone());
+        let mut v = writer.finish().unwrap().into_inner();
+        // Junk after the archive, with another signature in it
+        v.extend_from_slice(b"junk PK\x05\x06 junk");
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        assert_eq!(zip.comment(), &comment[..]);
+        assert_eq!(zip.len(), 1);
+        assert!(zip.by_name("file.txt").is_ok());
+    }
+
+    #[test]
+    fn zip_directory_location() {
+        use super::{Config, ZipArchive, ZipWarning};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for name in &["first.txt", "second.txt"] {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(b"contents").unwrap();
+        }
+        let mut v = writer.finish().unwrap().into_inner();
+
+        let zip = ZipArchive::new(io::Cursor::new(v.clone())).unwrap();
+        let footer_start = v.len() as u64 - 22;
+        assert_eq!(zip.central_directory_end(), footer_start);
+        let first_header = zip.central_directory_start() as usize;
+        assert_eq!(&v[first_header..first_header + 4], b"PK\x01\x02");
+        assert_eq!(zip.claimed_len(), 2);
+
+        // Break the signature of the second central header
+        let second_header = v[first_header + 4..]
+            .windows(4)
+            .position(|w| w == b"PK\x01\x02");
+        v[first_header + 4 + second_header.unwrap()] = 0;
+        let config = Config::default().permissive(true);
+        let zip = ZipArchive::new_with_config(io::Cursor::new(v), config).unwrap();
+        assert_eq!(zip.len(), 1);
+        assert_eq!(zip.claimed_len(), 2);
+        assert_eq!(
+            zip.warnings().last(),
+            Some(&ZipWarning::FileCountMismatch {
+                claimed: 2,
+                found: 1
+            })
+        );
+    }
+
+    #[test]
+    fn zip_skip_broken_header() {
+        use super::{Config, ZipArchive, ZipWarning};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for name in &["first.txt", "second.txt", "third.txt"] {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(b"contents").unwrap();
+        }
+        let mut v = writer.finish().unwrap().into_inner();
+        let zip = ZipArchive::new(io::Cursor::new(v.clone())).unwrap();
+        assert!(zip.warnings().is_empty());
+
+        // Break the signature of the second central header
+        let first_header = zip.central_directory_start() as usize;
+        let second_header = first_header
+            + 4
+            + v[first_header + 4..]
+                .windows(4)
+                .position(|w| w == b"PK\x01\x02")
+                .unwrap();
+        v[second_header] = 0;
+        assert!(ZipArchive::new(io::Cursor::new(v.clone())).is_err());
+
+        let config = Config::default().permissive(true);
+        let mut zip = ZipArchive::new_with_config(io::Cursor::new(v), config).unwrap();
+        assert_eq!(zip.len(), 2);
+        assert!(zip.by_name("third.txt").is_ok());
+        assert_eq!(
+            zip.warnings(),
+            &[
+                ZipWarning::InvalidCentralHeader {
+                    offset: second_header as u64
+                },
+                ZipWarning::FileCountMismatch {
+                    claimed: 3,
+                    found: 2
+                }
+            ][..]
+        );
+    }
+
+    #[test]
+    fn zip_file_warnings() {
+        use super::{ZipArchive, ZipWarning};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for name in &["caf\u{e9}.txt", "plain.txt", "plain.txt"] {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(b"contents").unwrap();
+        }
+        let mut v = writer.finish().unwrap().into_inner();
+
+        // Clear the UTF-8 flag of the first file, and zero the date of the second
+        let first_header = ZipArchive::new(io::Cursor::new(v.clone()))
+            .unwrap()
+            .central_directory_start() as usize;
+        v[first_header + 9] &= !0x08;
+        let second_header = first_header
+            + 4
+            + v[first_header + 4..]
+                .windows(4)
+                .position(|w| w == b"PK\x01\x02")
+                .unwrap();
+        v[second_header + 14..second_header + 16].copy_from_slice(&[0, 0]);
+
+        let zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        assert_eq!(
+            zip.warnings(),
+            &[
+                ZipWarning::Cp437Name { index: 0 },
+                ZipWarning::InvalidTimestamp { index: 1 },
+                ZipWarning::DuplicateName { index: 2 },
+            ][..]
+        );
+    }
+
+    #[test]
+    fn zip_wrapped_file_count() {
+        use super::{ZipArchive, ZipWarning};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for name in &["first.txt", "second.txt", "third.txt"] {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(b"contents").unwrap();
+        }
+        let mut v = writer.finish().unwrap().into_inner();
+
+        // Claim a single file, like a count of 65537 that was stored without ZIP64
+        let footer_start = v.len() - 22;
+        v[footer_start + 8..footer_start + 12].copy_from_slice(&[1, 0, 1, 0]);
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        assert_eq!(zip.claimed_len(), 1);
+        assert_eq!(zip.len(), 3);
+        assert_eq!(
+            zip.warnings(),
+            &[ZipWarning::FileCountMismatch {
+                claimed: 1,
+                found: 3
+            }][..]
+        );
+        assert!(zip.by_name("third.txt").is_ok());
+    }
+
+    #[test]
+    fn split_archive_reader() {
+        use super::SplitArchiveReader;
+        use std::io::{self, Read, Seek};
+
+        let segments = vec![
+            io::Cursor::new(b"abc".to_vec()),
+            io::Cursor::new(Vec::new()),
+            io::Cursor::new(b"defg".to_vec()),
+        ];
+        let mut reader = SplitArchiveReader::new(segments).unwrap();
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "abcdefg");
+
+        reader.seek(io::SeekFrom::End(-5)).unwrap();
+        let mut buffer = [0; 3];
+        reader.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"cde");
+        assert!(reader.seek(io::SeekFrom::Current(-6)).is_err());
+    }
+
+    #[test]
+    fn zip_split() {
+        use super::{SplitArchiveReader, ZipArchive};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Read, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer
+            .start_file("file.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"contents").unwrap();
+        let mut v = writer.finish().unwrap().into_inner();
+
+        // Move the central directory to a second disk
+        let central_directory = v.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        let end = v.len() - 22;
+        v[end + 4] = 1; // number of this disk
+        v[end + 6] = 1; // disk where the central directory starts
+        v[end + 16..end + 20].copy_from_slice(&[0; 4]); // offset of the central directory
+        let second = v.split_off(central_directory);
+
+        let segments = vec![io::Cursor::new(v), io::Cursor::new(second)];
+        let reader = SplitArchiveReader::new(segments).unwrap();
+        let mut zip = ZipArchive::new_split(reader).unwrap();
+        let mut contents = String::new();
+        zip.by_name("file.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "contents");
+    }
+
+    #[test]
+    fn zip_prepended_data() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use crate::DateTime;
+        use std::io::{self, Read, Write};
+
+        let stub = b"#!/bin/sh\nexit 0\n".to_vec();
+        // The comment holds a record whose central directory can't be read, which is skipped
+        let comment = b"PK\x05\x06\0\0\0\0\x01\0\x01\0\x2e\0\0\0\xff\xff\xff\x7f\0\0".to_vec();
+        let options = FileOptions::default().last_modified_time(DateTime::default());
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.set_raw_comment(comment.clone());
+        writer.start_file("file", options).unwrap();
+        writer.write_all(b"contents").unwrap();
+        let plain = writer.finish().unwrap().into_inner();
+
+        // The stub is either simply concatenated, or accounted for in the offsets
+        let mut writer = ZipWriter::new_with_prepended_data(io::Cursor::new(stub.clone())).unwrap();
+        writer.set_raw_comment(comment);
+        writer.start_file("file", options).unwrap();
+        writer.write_all(b"contents").unwrap();
+        let absolute = writer.finish().unwrap().into_inner();
+        let relative = [&stub[..], &plain[..]].concat();
+
+        for v in vec![relative, absolute] {
+            let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+            let mut prepended = Vec::new();
+            zip.prepended_data()
+                .unwrap()
+                .read_to_end(&mut prepended)
+                .unwrap();
+            assert_eq!(prepended, stub);
+
+            let mut copy = Vec::new();
+            zip.copy_without_preamble(&mut copy).unwrap();
+            assert_eq!(copy, plain);
+        }
+    }
+
+    #[test]
+    fn zip_duplicate_names() {
+        use super::{Config, ZipArchive};
+        use crate::result::{InvalidArchiveKind, ZipError};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Read, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for (name, contents) in &[("a", "first"), ("b", "other"), ("a", "second")] {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        let v = writer.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v.clone())).unwrap();
+        assert_eq!(
+            zip.duplicate_names().collect::<Vec<_>>(),
+            vec![("a", &[0, 2][..])]
+        );
+        assert_eq!(zip.indices_for_name("a"), &[0, 2]);
+        assert_eq!(zip.indices_for_name("b"), &[1]);
+        assert!(zip.indices_for_name("c").is_empty());
+        let mut contents = String::new();
+        zip.by_name("a")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "second");
+
+        let config = Config::default().reject_duplicate_names(true);
+        match ZipArchive::new_with_config(io::Cursor::new(v), config) {
+            Err(ZipError::InvalidArchive {
+                kind: InvalidArchiveKind::DuplicateFileName,
+                entry: Some(ref name),
+                ..
+            }) if name == "a" => {}
+            _ => panic!("duplicate names should be rejected"),
+        }
+
+        // The error names the first duplicate in central directory order
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for name in (0..10).chain(0..10).map(|i| format!("file {}", i)) {
+            writer.start_file(name, FileOptions::default()).unwrap();
+        }
+        let v = writer.finish().unwrap().into_inner();
+        match ZipArchive::new_with_config(io::Cursor::new(v), config) {
+            Err(ZipError::InvalidArchive {
+                entry: Some(ref name),
+                ..
+            }) => assert_eq!(name, "file 0"),
+            _ => panic!("duplicate names should be rejected"),
+        }
+    }
+
+    #[test]
+    fn zip_unsupported_compression() {
+        use super::ZipArchive;
+        use crate::result::ZipError;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(crate::CompressionMethod::Stored);
+        writer.start_file("lzma.txt", options).unwrap();
+        writer.write_all(b"not really lzma").unwrap();
+        let mut v = writer.finish().unwrap().into_inner();
+
+        // Mark the entry as LZMA in both the local and the central header
+        let local = v.windows(4).position(|w| w == b"PK\x03\x04").unwrap();
+        v[local + 8] = 14;
+        let central = v.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        v[central + 10] = 14;
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v.clone())).unwrap();
+        match zip.by_index(0) {
+            Err(ZipError::UnsupportedCompression(14)) => {}
+            _ => panic!("LZMA entries should be reported as unsupported"),
+        };
+
+        match super::read_zipfile_from_stream(&mut io::Cursor::new(v)) {
+            Err(ZipError::UnsupportedCompression(14)) => {}
+            _ => panic!("LZMA entries should be reported as unsupported"),
+        };
+    }
+
+    #[test]
+    fn zip_stream_finish_error() {
+        use super::read_zipfile_from_stream;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Read, Write};
+
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::Other, "disconnected"))
+            }
+        }
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(crate::CompressionMethod::Stored);
+        writer.start_file("file.txt", options).unwrap();
+        writer.write_all(&[b'x'; 100]).unwrap();
+        let mut v = writer.finish().unwrap().into_inner();
+
+        // Cut the stream off in the middle of the file contents
+        let data = v.windows(4).position(|w| w == b"xxxx").unwrap();
+        v.truncate(data + 10);
+
+        let mut stream = io::Cursor::new(v.clone()).chain(Failing);
+        let file = read_zipfile_from_stream(&mut stream).unwrap().unwrap();
+        assert!(file.finish().is_err());
+
+        // Dropping the file ignores the error instead of panicking
+        let mut stream = io::Cursor::new(v).chain(Failing);
+        drop(read_zipfile_from_stream(&mut stream).unwrap().unwrap());
+    }
+
+    #[test]
+    fn zip_seek_stored() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Read, Seek, SeekFrom, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(crate::CompressionMethod::Stored);
+        writer.start_file("digits.txt", options).unwrap();
+        writer.write_all(b"0123456789").unwrap();
+        writer.start_file("next.txt", options).unwrap();
+        writer.write_all(b"next").unwrap();
+        let v = writer.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let mut file = zip.by_name("digits.txt").unwrap();
+        let mut buf = [0; 3];
+        assert_eq!(file.seek(SeekFrom::Start(5)).unwrap(), 5);
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"567");
+        assert_eq!(file.seek(SeekFrom::End(-3)).unwrap(), 7);
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"789");
+        assert_eq!(file.seek(SeekFrom::Current(-8)).unwrap(), 2);
+        assert!(file.seek(SeekFrom::Current(-3)).is_err());
+
+        // Reads stop at the end of the file, even after seeking past it
+        let mut rest = Vec::new();
+        file.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"23456789");
+        file.seek(SeekFrom::Start(20)).unwrap();
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+    }
+
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    ))]
+    #[test]
+    fn zip_seek_deflated() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Read, Seek, SeekFrom, Write};
+
+        let contents: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(crate::CompressionMethod::Deflated);
+        writer.start_file("data.bin", options).unwrap();
+        writer.write_all(&contents).unwrap();
+        let v = writer.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let mut file = zip.by_index(0).unwrap();
+        let mut buf = [0; 4];
+        for &position in &[70_000u64, 12_345, 12_345, 99_996] {
+            assert_eq!(file.seek(SeekFrom::Start(position)).unwrap(), position);
+            file.read_exact(&mut buf).unwrap();
+            let expected = &contents[position as usize..position as usize + 4];
+            assert_eq!(&buf, expected);
+        }
+        assert_eq!(file.seek(SeekFrom::Current(-8)).unwrap(), 99_992);
+        assert_eq!(file.seek(SeekFrom::End(10)).unwrap(), 100_000);
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+        drop(file);
+
+        let mut raw = zip.by_index_raw(0).unwrap();
+        assert!(raw.seek(SeekFrom::Start(1)).is_err());
+    }
+
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    ))]
+    #[test]
+    fn zip_seek_deflated_checkpoints() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Read, Seek, SeekFrom, Write};
+
+        // Many blocks, with checkpoints every megabyte
+        let mut state = 1u32;
+        let contents: Vec<u8> = (0..3_500_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                b"abcdefgh "[(state >> 24) as usize % 9]
+            })
+            .collect();
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(crate::CompressionMethod::Deflated);
+        writer.start_file("data.bin", options).unwrap();
+        writer.write_all(&contents).unwrap();
+        let v = writer.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let mut file = zip.by_index(0).unwrap();
+        let mut buf = [0; 1000];
+        let positions = [
+            3_400_000u64,
+            1_500_000,
+            2_900_000,
+            2_100_000,
+            100,
+            3_000_000,
+            1_048_576,
+        ];
+        for &position in &positions {
+            assert_eq!(file.seek(SeekFrom::Start(position)).unwrap(), position);
+            file.read_exact(&mut buf).unwrap();
+            let expected = &contents[position as usize..position as usize + buf.len()];
+            assert_eq!(&buf[..], expected);
+        }
+        let mut rest = Vec::new();
+        file.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &contents[1_049_576..]);
+    }
+
+    #[test]
+    fn zip_buf_read() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer
+            .start_file("lines.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"first\nsecond\nthird\n").unwrap();
+        let stored = FileOptions::default().compression_method(crate::CompressionMethod::Stored);
+        writer.start_file("stored.txt", stored).unwrap();
+        writer.write_all(b"first\nsecond\nthird\n").unwrap();
+        let mut v = writer.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v.clone())).unwrap();
+        for &name in &["lines.txt", "stored.txt"] {
+            let mut file = zip.by_name(name).unwrap();
+            let mut line = String::new();
+            file.read_line(&mut line).unwrap();
+            assert_eq!(line, "first\n");
+
+            // Reads and seeks take the buffered data into account
+            let mut buf = [0; 3];
+            file.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"sec");
+            assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 9);
+            let lines: Vec<String> = file.lines().map(Result::unwrap).collect();
+            assert_eq!(lines, vec!["ond", "third"]);
+        }
+
+        // Stored data that is consumed from the buffer is checked against the CRC32
+        let position = v.windows(5).rposition(|w| w == b"third").unwrap();
+        v[position] = b'T';
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let mut file = zip.by_name("stored.txt").unwrap();
+        assert!(file.copy_to(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn zip_data_slice() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let stored = FileOptions::default().compression_method(crate::CompressionMethod::Stored);
+        writer.start_file("stored.txt", stored).unwrap();
+        writer.write_all(b"stored contents").unwrap();
+        #[cfg(any(
+            feature = "deflate",
+            feature = "deflate-miniz",
+            feature = "deflate-zlib"
+        ))]
+        {
+            let deflated =
+                FileOptions::default().compression_method(crate::CompressionMethod::Deflated);
+            writer.start_file("deflated.txt", deflated).unwrap();
+            writer.write_all(b"deflated contents").unwrap();
+        }
+        let v = writer.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(io::Cursor::new(&v[..])).unwrap();
+        assert_eq!(zip.data_slice(0).unwrap(), Some(&b"stored contents"[..]));
+        #[cfg(any(
+            feature = "deflate",
+            feature = "deflate-miniz",
+            feature = "deflate-zlib"
+        ))]
+        assert_eq!(zip.data_slice(1).unwrap(), None);
+        assert!(zip.data_slice(2).is_err());
+    }
+
+    #[test]
+    fn zip_read_from_threads() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Read, Write};
+        use std::sync::Arc;
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for i in 0..4 {
+            writer
+                .start_file(format!("{}.txt", i), FileOptions::default())
+                .unwrap();
+            write!(writer, "contents of {}", i).unwrap();
+        }
+        let v: Arc<[u8]> = writer.finish().unwrap().into_inner().into();
+
+        let zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let threads: Vec<_> = (0..zip.len())
+            .map(|i| {
+                let mut zip = zip.clone();
+                std::thread::spawn(move || {
+                    let mut contents = String::new();
+                    zip.by_index(i)
+                        .unwrap()
+                        .read_to_string(&mut contents)
+                        .unwrap();
+                    contents
+                })
+            })
+            .collect();
+        for (i, thread) in threads.into_iter().enumerate() {
+            assert_eq!(thread.join().unwrap(), format!("contents of {}", i));
+        }
+    }
+
+    #[test]
+    fn zip_raw_names() {
+        use super::ZipArchive;
+        use std::io;
+
+        let v = include_bytes!("../tests/data/files_and_dirs.zip");
+        let mut zip = ZipArchive::new(io::Cursor::new(v.to_vec())).unwrap();
+        assert!(zip.shared.files.iter().all(|f| f.file_name_raw.is_empty()));
+        assert!(zip.memory_usage() > zip.len() * std::mem::size_of::<crate::types::ZipFileData>());
+        for i in 0..zip.len() {
+            let file = zip.by_index(i).unwrap();
+            assert_eq!(file.name_raw(), file.name().as_bytes());
+        }
+
+        // Names that are decoded differently keep their raw bytes
+        let (name, raw) = super::decode_file_name(vec![b'a', 0x81], false);
+        assert_eq!(name, "a\u{fc}");
+        assert_eq!(raw, vec![b'a', 0x81]);
+    }
+
+    #[test]
+    fn zip_unchecked_crc() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Read, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(crate::CompressionMethod::Stored);
+        writer.start_file("file.txt", options).unwrap();
+        writer.write_all(b"original").unwrap();
+        let mut v = writer.finish().unwrap().into_inner();
+        let data = v.windows(8).position(|w| w == b"original").unwrap();
+        v[data] = b'O';
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let mut contents = String::new();
+        assert!(zip
+            .by_index(0)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .is_err());
+        contents.clear();
+        zip.by_index_unchecked_crc(0)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "Original");
+    }
+
+    #[test]
+    fn zip_copy_to() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Read, Write};
+
+        let contents: Vec<u8> = (0..200_000u32).map(|i| (i % 253) as u8).collect();
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for name in &["first", "second"] {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(&contents).unwrap();
+        }
+        let v = writer.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        for i in 0..2 {
+            let mut file = zip.by_index(i).unwrap();
+            let mut start = [0; 10];
+            file.read_exact(&mut start).unwrap();
+            let mut rest = Vec::new();
+            assert_eq!(file.copy_to(&mut rest).unwrap(), 199_990);
+            assert_eq!(&start[..], &contents[..10]);
+            assert_eq!(&rest[..], &contents[10..]);
+        }
+    }
+
+    #[test]
+    fn zip_local_extra_data() {
+        use super::{read_zipfile_from_stream, ZipArchive};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer
+            .start_file_with_extra_data("file.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(&[0xef, 0xbe, 5, 0]).unwrap();
+        writer.write_all(b"local").unwrap();
+        writer.end_local_start_central_extra_data().unwrap();
+        writer.write_all(&[0xef, 0xbe, 7, 0]).unwrap();
+        writer.write_all(b"central").unwrap();
+        writer.end_extra_data().unwrap();
+        writer.write_all(b"contents").unwrap();
+        let v = writer.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v.clone())).unwrap();
+        let file = zip.by_index(0).unwrap();
+        assert_eq!(&file.extra_data()[4..], b"central");
+        assert_eq!(&file.local_extra_data()[4..], b"local");
+        drop(file);
+        assert_eq!(
+            &zip.by_index_raw(0).unwrap().local_extra_data()[4..],
+            b"local"
+        );
+
+        let mut reader = io::Cursor::new(v);
+        let file = read_zipfile_from_stream(&mut reader).unwrap().unwrap();
+        assert_eq!(&file.local_extra_data()[4..], b"local");
+    }
+
+    #[test]
+    fn zip_read_entry_helpers() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer
+            .start_file("text.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"some text").unwrap();
+        writer
+            .start_file("binary.bin", FileOptions::default())
+            .unwrap();
+        writer.write_all(&[0xff, 0xfe]).unwrap();
+        let v = writer.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        assert_eq!(zip.read_entry_to_string("text.txt").unwrap(), "some text");
+        assert_eq!(zip.read_entry_to_vec(1).unwrap(), vec![0xff, 0xfe]);
+        assert_eq!(
+            zip.read_entry_to_vec(String::from("text.txt")).unwrap(),
+            b"some text"
+        );
+        assert!(zip.read_entry_to_string("binary.bin").is_err());
+        assert!(zip.read_entry_to_vec("missing").is_err());
+        assert!(zip.read_entry_to_vec(2).is_err());
+    }
+
+    #[test]
+    fn zip_raw_by_name() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Read, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer
+            .start_file("text.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(&[b'a'; 1000]).unwrap();
+        let stats = writer.finish_file().unwrap().unwrap();
+        let v = writer.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let method = zip.by_index(0).unwrap().compression();
+        let mut file = zip.by_name_raw("text.txt").unwrap();
+        assert_eq!(file.compression(), method);
+        assert_eq!(file.size(), 1000);
+        let mut raw = Vec::new();
+        file.read_to_end(&mut raw).unwrap();
+        assert_eq!(raw.len() as u64, file.compressed_size());
+        assert_eq!(raw.len() as u64, stats.compressed_size());
+        drop(file);
+        assert!(zip.by_name_raw("missing.txt").is_err());
+    }
+
     #[test]
     fn file_and_dir_predicates() {
         use super::ZipArchive;
@@ -1108,4 +4925,758 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn general_purpose_flags() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::Cursor;
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("ascii.txt", FileOptions::default())
+            .unwrap();
+        writer
+            .start_file("ünicode.txt", FileOptions::default())
+            .unwrap();
+        let mut archive = writer.finish().unwrap().into_inner();
+        // Mark the first file as having a data descriptor in the central directory
+        let central = archive
+            .windows(4)
+            .position(|window| window == b"PK\x01\x02")
+            .unwrap();
+        archive[central + 8] |= 1 << 3;
+
+        let mut archive = ZipArchive::new(Cursor::new(archive)).unwrap();
+        let file = archive.by_index(0).unwrap();
+        assert_eq!(file.flags(), 1 << 3);
+        assert!(file.has_data_descriptor());
+        drop(file);
+        let file = archive.by_index(1).unwrap();
+        assert_eq!(file.flags(), 1 << 11);
+        assert!(!file.has_data_descriptor());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extract_directory_permissions() {
+        use super::{ExtractOptions, ZipArchive};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::fs;
+        use std::io::{Cursor, Write};
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("locked/nested/file.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"contents").unwrap();
+        let options = FileOptions::default().unix_permissions(0o555);
+        writer.add_directory("locked/nested", options).unwrap();
+        writer.add_directory("locked", options).unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let directory = std::env::temp_dir().join(format!("zip-extract-{}", std::process::id()));
+        let mode = |path: &str| {
+            fs::metadata(directory.join(path))
+                .unwrap()
+                .permissions()
+                .mode()
+        };
+        let unlock = || {
+            for path in &["locked", "locked/nested"] {
+                let _ =
+                    fs::set_permissions(directory.join(path), fs::Permissions::from_mode(0o755));
+            }
+        };
+
+        archive.extract(&directory).unwrap();
+        assert_eq!(mode("locked") & 0o777, 0o555);
+        assert_eq!(mode("locked/nested") & 0o777, 0o555);
+        let contents = fs::read_to_string(directory.join("locked/nested/file.txt")).unwrap();
+        assert_eq!(contents, "contents");
+        unlock();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let options = ExtractOptions::default().directory_permissions(false);
+        archive.extract_with_options(&directory, options).unwrap();
+        assert_ne!(mode("locked") & 0o777, 0o555);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extract_unix_owner() {
+        use super::{ExtractOptions, ZipArchive};
+        use crate::write::{FileOptions, ZipWriter};
+        use byteorder::{LittleEndian, WriteBytesExt};
+        use std::fs;
+        use std::io::{Cursor, Write};
+        use std::os::unix::fs::MetadataExt;
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file_with_extra_data("owned.txt", FileOptions::default())
+            .unwrap();
+        writer.write_u16::<LittleEndian>(0x7875).unwrap();
+        writer.write_u16::<LittleEndian>(11).unwrap();
+        writer.write_all(&[1, 4]).unwrap();
+        writer.write_u32::<LittleEndian>(1234).unwrap();
+        writer.write_u8(4).unwrap();
+        writer.write_u32::<LittleEndian>(5678).unwrap();
+        writer.end_extra_data().unwrap();
+        writer.write_all(b"contents").unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        assert_eq!(
+            archive.by_index(0).unwrap().unix_owner(),
+            Some((1234, 5678))
+        );
+
+        let directory = std::env::temp_dir().join(format!("zip-owner-{}", std::process::id()));
+        let options = ExtractOptions::default().unix_owner(true);
+        archive.extract_with_options(&directory, options).unwrap();
+        let metadata = fs::metadata(directory.join("owned.txt")).unwrap();
+        // Only root can change owners, anyone else just gets the files
+        if unsafe { libc::geteuid() } == 0 {
+            assert_eq!((metadata.uid(), metadata.gid()), (1234, 5678));
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn apk_signing_block() {
+        use super::ZipArchive;
+        use crate::write::{repack, FileOptions, RepackOptions, ZipWriter};
+        use byteorder::{LittleEndian, WriteBytesExt};
+        use std::io::{Cursor, Write};
+
+        let mut block = Vec::new();
+        block.write_u64::<LittleEndian>(40).unwrap();
+        block.write_all(&[0xAB; 16]).unwrap();
+        block.write_u64::<LittleEndian>(40).unwrap();
+        block.write_all(b"APK Sig Block 42").unwrap();
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("classes.dex", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"dex").unwrap();
+        writer.set_apk_signing_block(block.clone()).unwrap();
+        let archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        let span = archive.apk_signing_block().unwrap();
+        assert_eq!(span.end, archive.central_directory_start());
+        assert_eq!(span.end - span.start, block.len() as u64);
+
+        // Appending puts new files before the block
+        let mut writer = ZipWriter::new_append(archive.into_inner()).unwrap();
+        writer
+            .start_file("resources.arsc", FileOptions::default())
+            .unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        assert_eq!(archive.len(), 2);
+        assert_eq!(
+            archive.read_apk_signing_block().unwrap(),
+            Some(block.clone())
+        );
+
+        let copy = repack(
+            &mut archive,
+            Cursor::new(Vec::new()),
+            RepackOptions::default(),
+        )
+        .unwrap();
+        let mut copy = ZipArchive::new(copy).unwrap();
+        assert_eq!(copy.read_apk_signing_block().unwrap(), Some(block));
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        assert!(writer.set_apk_signing_block(vec![0; 32]).is_err());
+    }
+
+    #[test]
+    fn extract_strip_components() {
+        use super::{ExtractOptions, ZipArchive};
+        use crate::result::{InvalidArchiveKind, ZipError};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::fs;
+        use std::io::{Cursor, Write};
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default();
+        writer.add_directory("project-1.2.3", options).unwrap();
+        writer
+            .start_file("project-1.2.3/src/lib.rs", options)
+            .unwrap();
+        writer.write_all(b"lib").unwrap();
+        writer.start_file("top-level.txt", options).unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let directory = std::env::temp_dir().join(format!("zip-strip-{}", std::process::id()));
+        let options = ExtractOptions::default().strip_components(1);
+        archive.extract_with_options(&directory, options).unwrap();
+        assert_eq!(
+            fs::read_to_string(directory.join("src").join("lib.rs")).unwrap(),
+            "lib"
+        );
+        assert!(!directory.join("project-1.2.3").exists());
+        assert!(!directory.join("top-level.txt").exists());
+        fs::remove_dir_all(&directory).unwrap();
+
+        // Enclosed as it is, but not once `a` is stripped
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("a/../evil", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"evil").unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        match archive.extract_with_options(directory.join("inner"), options) {
+            Err(ZipError::InvalidArchive {
+                kind: InvalidArchiveKind::InvalidFilePath,
+                ..
+            }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(!directory.join("evil").exists());
+        fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn extract_preallocate() {
+        use super::{ExtractOptions, ZipArchive};
+        use crate::compression::CompressionMethod;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::fs;
+        use std::io::{Cursor, Write};
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("big.bin", FileOptions::default())
+            .unwrap();
+        writer.write_all(&[7; 100_000]).unwrap();
+        writer
+            .start_file("empty.bin", FileOptions::default())
+            .unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let directory = std::env::temp_dir().join(format!("zip-prealloc-{}", std::process::id()));
+        let options = ExtractOptions::default().preallocate(true);
+        archive.extract_with_options(&directory, options).unwrap();
+        assert_eq!(
+            fs::read(directory.join("big.bin")).unwrap(),
+            vec![7; 100_000]
+        );
+        assert_eq!(fs::metadata(directory.join("empty.bin")).unwrap().len(), 0);
+        fs::remove_dir_all(&directory).unwrap();
+
+        // Files only get the length of the data that was extracted
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        writer
+            .start_file_raw("short.bin", stored, 5, 100_000, 0)
+            .unwrap();
+        writer.write_all(b"short").unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        assert!(archive.extract_with_options(&directory, options).is_err());
+        let len = fs::metadata(directory.join("short.bin")).unwrap().len();
+        assert!(len <= 5);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn extract_hard_links() {
+        use super::{ExtractOptions, ZipArchive};
+        use crate::format::{CentralDirectoryEnd, CentralDirectoryHeader};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::fs;
+        use std::io::{Cursor, Write};
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default();
+        writer.start_file("bin/tool", options).unwrap();
+        writer.write_all(b"#!/bin/sh").unwrap();
+        writer
+            .add_hard_link("bin/alias", "bin/tool", options)
+            .unwrap();
+        writer.add_hard_link("other", "bin/alias", options).unwrap();
+        assert!(writer.add_hard_link("broken", "missing", options).is_err());
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().hard_link_target(), None);
+        assert_eq!(
+            archive
+                .by_name("other")
+                .unwrap()
+                .hard_link_target()
+                .as_ref()
+                .map(|target| target.as_str()),
+            Some("bin/tool")
+        );
+
+        let directory = std::env::temp_dir().join(format!("zip-links-{}", std::process::id()));
+        for &hard_links in [false, true].iter() {
+            let options = ExtractOptions::default().hard_links(hard_links);
+            archive.extract_with_options(&directory, options).unwrap();
+            for name in ["bin/tool", "bin/alias", "other"].iter() {
+                assert_eq!(fs::read(directory.join(name)).unwrap(), b"#!/bin/sh");
+            }
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                let links = fs::metadata(directory.join("bin/tool")).unwrap().nlink();
+                assert_eq!(links, if hard_links { 3 } else { 1 });
+            }
+            fs::remove_dir_all(&directory).unwrap();
+        }
+
+        // A link that comes before its target in the central directory gets a copy
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("tool", options).unwrap();
+        writer.write_all(b"#!/bin/sh").unwrap();
+        writer.add_hard_link("alias", "tool", options).unwrap();
+        let mut bytes = writer.finish().unwrap().into_inner();
+        let (footer, _) = CentralDirectoryEnd::find_and_parse(&mut Cursor::new(&bytes)).unwrap();
+        let start = footer.central_directory_offset as usize;
+        let mut reader = &bytes[start..];
+        let tool = CentralDirectoryHeader::read_from(&mut reader).unwrap();
+        let alias = CentralDirectoryHeader::read_from(&mut reader).unwrap();
+        let mut central = Vec::new();
+        alias.write_to(&mut central).unwrap();
+        tool.write_to(&mut central).unwrap();
+        bytes[start..start + central.len()].copy_from_slice(&central);
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "alias");
+        let options = ExtractOptions::default().hard_links(true);
+        archive.extract_with_options(&directory, options).unwrap();
+        for name in ["tool", "alias"].iter() {
+            assert_eq!(fs::read(directory.join(name)).unwrap(), b"#!/bin/sh");
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn extract_special_files() {
+        use super::{ExtractOptions, SpecialFiles, ZipArchive};
+        use crate::result::ZipError;
+        use crate::write::{FileOptions, ZipWriter};
+        use byteorder::{ByteOrder, LittleEndian};
+        use std::fs;
+        use std::io::{Cursor, Write};
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("pipe", FileOptions::default()).unwrap();
+        writer.write_all(b"not extracted").unwrap();
+        writer.start_file("file", FileOptions::default()).unwrap();
+        let mut bytes = writer.finish().unwrap().into_inner();
+        // Make the first file a FIFO
+        let central = bytes.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        LittleEndian::write_u32(&mut bytes[central + 38..], 0o010644 << 16);
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert!(archive.by_index(0).unwrap().is_special());
+        assert!(!archive.by_index(1).unwrap().is_special());
+
+        let directory = std::env::temp_dir().join(format!("zip-special-{}", std::process::id()));
+        archive.extract(&directory).unwrap();
+        assert!(fs::symlink_metadata(directory.join("pipe")).is_err());
+        assert!(directory.join("file").is_file());
+        fs::remove_dir_all(&directory).unwrap();
+
+        let options = ExtractOptions::default().special_files(SpecialFiles::Error);
+        match archive.extract_with_options(&directory, options) {
+            Err(ZipError::UnsupportedArchive(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        fs::remove_dir_all(&directory).ok();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+
+            let options = ExtractOptions::default().special_files(SpecialFiles::Create);
+            archive.extract_with_options(&directory, options).unwrap();
+            let metadata = fs::symlink_metadata(directory.join("pipe")).unwrap();
+            assert!(metadata.file_type().is_fifo());
+            fs::remove_dir_all(&directory).unwrap();
+        }
+    }
+
+    #[test]
+    fn directories_without_slash() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use byteorder::{ByteOrder, LittleEndian};
+        use std::fs;
+        use std::io::{Cursor, Write};
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for &name in ["unix", "dos", "file", "data"].iter() {
+            writer.start_file(name, FileOptions::default()).unwrap();
+        }
+        writer.write_all(b"not a directory").unwrap();
+        let mut bytes = writer.finish().unwrap().into_inner();
+        let central: Vec<_> = bytes
+            .windows(4)
+            .enumerate()
+            .filter(|&(_, w)| w == b"PK\x01\x02")
+            .map(|(i, _)| i)
+            .collect();
+        LittleEndian::write_u32(&mut bytes[central[0] + 38..], 0o040755 << 16);
+        // Made by MS-DOS, with the directory attribute
+        bytes[central[1] + 5] = 0;
+        LittleEndian::write_u32(&mut bytes[central[1] + 38..], 0x10);
+        LittleEndian::write_u32(&mut bytes[central[3] + 38..], 0o040755 << 16);
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let dirs: Vec<_> = (0..4)
+            .map(|i| archive.by_index(i).unwrap().is_dir())
+            .collect();
+        assert_eq!(dirs, [true, true, false, false]);
+
+        let directory = std::env::temp_dir().join(format!("zip-dirs-{}", std::process::id()));
+        archive.extract(&directory).unwrap();
+        assert!(directory.join("unix").is_dir());
+        assert!(directory.join("dos").is_dir());
+        assert!(directory.join("file").is_file());
+        assert_eq!(
+            fs::read(directory.join("data")).unwrap(),
+            b"not a directory"
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn extract_portable_paths() {
+        use super::{ExtractOptions, PathProblem, PortablePaths, ZipArchive};
+        use crate::result::{InvalidArchiveKind, ZipError};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::fs;
+        use std::io::{Cursor, Write};
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for &name in ["Readme", "README", "CON.txt"].iter() {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(name.as_bytes()).unwrap();
+        }
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        assert_eq!(
+            archive.path_problems(),
+            vec![PathProblem::CaseCollision(0, 1), PathProblem::Reserved(2)]
+        );
+
+        let directory = std::env::temp_dir().join(format!("zip-portable-{}", std::process::id()));
+        let options = ExtractOptions::default().portable_paths(PortablePaths::Reject);
+        match archive.extract_with_options(&directory, options) {
+            Err(ZipError::InvalidArchive {
+                kind: InvalidArchiveKind::PathCollision,
+                entry: Some(entry),
+                ..
+            }) => assert_eq!(entry, "README"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(!directory.exists());
+
+        let options = ExtractOptions::default().portable_paths(PortablePaths::Rename);
+        archive.extract_with_options(&directory, options).unwrap();
+        for &(path, contents) in [
+            ("Readme", "Readme"),
+            ("README~2", "README"),
+            ("CON_.txt", "CON.txt"),
+        ]
+        .iter()
+        {
+            assert_eq!(fs::read_to_string(directory.join(path)).unwrap(), contents);
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn extract_with_sanitizer() {
+        use super::{ExtractOptions, NameSanitizer, PercentEncodedName, ZipArchive};
+        use crate::result::{InvalidArchiveKind, ZipError};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::fs;
+        use std::io::{Cursor, Write};
+        use std::path::PathBuf;
+
+        struct Escape;
+
+        impl NameSanitizer for Escape {
+            fn sanitize(&self, name: &str) -> Option<PathBuf> {
+                Some(PathBuf::from("..").join(name))
+            }
+        }
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("../up.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"up").unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let directory = std::env::temp_dir().join(format!("zip-sanitizer-{}", std::process::id()));
+        let options = ExtractOptions::default();
+        match archive.extract(&directory) {
+            Err(ZipError::InvalidArchive {
+                kind: InvalidArchiveKind::InvalidFilePath,
+                ..
+            }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(archive
+            .extract_with_sanitizer(&directory, options, &Escape)
+            .is_err());
+        archive
+            .extract_with_sanitizer(&directory, options, &PercentEncodedName)
+            .unwrap();
+        assert_eq!(
+            fs::read(directory.join("%2E%2E").join("up.txt")).unwrap(),
+            b"up"
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn normalize_names() {
+        use super::{Config, ZipArchive};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::Cursor;
+
+        // "café" as macOS stores it, with a combining accent
+        let decomposed = "cafe\u{301}/menu.txt";
+        let composed = "caf\u{e9}/menu.txt";
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file(decomposed, FileOptions::default())
+            .unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let archive = ZipArchive::new(bytes.clone()).unwrap();
+        assert!(archive.indices_for_name(composed).is_empty());
+
+        let config = Config::default().normalize_names(true);
+        let mut archive = ZipArchive::new_with_config(bytes, config).unwrap();
+        assert_eq!(archive.indices_for_name(composed), &[0]);
+        assert_eq!(archive.indices_for_name(decomposed), &[0]);
+        assert_eq!(archive.file_names().collect::<Vec<_>>(), [composed]);
+        assert_eq!(archive.by_name(composed).unwrap().name(), decomposed);
+    }
+
+    #[test]
+    fn parse_limits() {
+        use super::{Config, ZipArchive};
+        use crate::result::{Limit, ZipError};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::Cursor;
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.set_comment("archive comment");
+        for &name in ["a", "b", "a longer name"].iter() {
+            writer.start_file(name, FileOptions::default()).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+
+        let limited = |config: Config| match ZipArchive::new_with_config(bytes.clone(), config) {
+            Err(ZipError::LimitExceeded(limit)) => Some(limit),
+            Ok(_) => None,
+            Err(e) => panic!("unexpected error: {:?}", e),
+        };
+        assert_eq!(
+            limited(Config::default().max_entries(2)),
+            Some(Limit::Entries)
+        );
+        assert_eq!(
+            limited(Config::default().max_file_name_length(8)),
+            Some(Limit::FileNameLength)
+        );
+        assert_eq!(
+            limited(Config::default().max_comment_length(8)),
+            Some(Limit::CommentLength)
+        );
+        assert_eq!(limited(Config::default().max_extra_field_length(0)), None);
+        assert_eq!(
+            limited(Config::default().max_metadata_bytes(100)),
+            Some(Limit::MetadataBytes)
+        );
+        let config = Config::default()
+            .max_entries(3)
+            .max_file_name_length(13)
+            .max_comment_length(15)
+            .max_metadata_bytes(64 * 1024);
+        assert_eq!(limited(config), None);
+    }
+
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    ))]
+    #[test]
+    fn read_vectored() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use crate::CompressionMethod;
+        use std::io::{Cursor, IoSliceMut, Read, Write};
+
+        let contents: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for &method in [CompressionMethod::Stored, CompressionMethod::Deflated].iter() {
+            let options = FileOptions::default().compression_method(method);
+            writer.start_file(format!("{:?}", method), options).unwrap();
+            writer.write_all(&contents).unwrap();
+        }
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).unwrap();
+            let mut read = Vec::new();
+            let (mut first, mut second) = (vec![0; 1000], vec![0; 30_000]);
+            loop {
+                let mut bufs = [IoSliceMut::new(&mut first), IoSliceMut::new(&mut second)];
+                let count = file.read_vectored(&mut bufs).unwrap();
+                if count == 0 {
+                    break;
+                }
+                read.extend_from_slice(&first[..count.min(first.len())]);
+                read.extend_from_slice(&second[..count.saturating_sub(first.len())]);
+            }
+            assert!(read == contents, "{}", file.name());
+        }
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn hash_with() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use sha2::{Digest, Sha256};
+        use std::io::{self, BufRead, Cursor, Read, Write};
+
+        let contents: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("data", FileOptions::default()).unwrap();
+        writer.write_all(&contents).unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let mut hasher = Sha256::new();
+        let mut file = archive.by_index(0).unwrap();
+        file.hash_with(&mut hasher).unwrap();
+        let mut start = [0; 1000];
+        file.read_exact(&mut start).unwrap();
+        io::copy(&mut file, &mut io::sink()).unwrap();
+        drop(file);
+        assert_eq!(hasher.result(), Sha256::digest(&contents));
+
+        let mut file = archive.by_index(0).unwrap();
+        file.read_exact(&mut start).unwrap();
+        let mut hasher = Sha256::new();
+        file.hash_with(&mut hasher).unwrap();
+        file.copy_to(&mut io::sink()).unwrap();
+        drop(file);
+        assert_eq!(hasher.result(), Sha256::digest(&contents[1000..]));
+
+        // Data that was read ahead, but not consumed yet, is hashed too
+        let mut file = archive.by_index(0).unwrap();
+        file.read_exact(&mut start).unwrap();
+        assert!(!file.fill_buf().unwrap().is_empty());
+        let mut hasher = Sha256::new();
+        file.hash_with(&mut hasher).unwrap();
+        file.copy_to(&mut io::sink()).unwrap();
+        drop(file);
+        assert_eq!(hasher.result(), Sha256::digest(&contents[1000..]));
+    }
+
+    #[test]
+    fn extract_selected_stays_enclosed() {
+        use super::{EnclosedName, ExtractOptions, FileSystem, ZipArchive};
+        use crate::result::{InvalidArchiveKind, ZipError};
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{Cursor, Write};
+        use std::path::PathBuf;
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("a.txt", FileOptions::default()).unwrap();
+        writer.write_all(b"a").unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let directory = std::env::temp_dir().join(format!("zip-selected-{}", std::process::id()));
+        let mut sink = FileSystem::new(&directory, ExtractOptions::default());
+        match archive.extract_selected(&mut sink, 0..1, &EnclosedName, |path| {
+            Some(PathBuf::from("..").join(path))
+        }) {
+            Err(ZipError::InvalidArchive {
+                kind: InvalidArchiveKind::InvalidFilePath,
+                ..
+            }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(!directory.join("..").join("a.txt").exists());
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn extract_subtree() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::fs;
+        use std::io::{Cursor, Write};
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default();
+        writer.add_directory("project-1.0", options).unwrap();
+        writer
+            .start_file("project-1.0/src/lib.rs", options)
+            .unwrap();
+        writer.write_all(b"lib").unwrap();
+        writer.start_file("project-1.0/README", options).unwrap();
+        writer.start_file("project-1.0.txt", options).unwrap();
+        writer.start_file("other.txt", options).unwrap();
+        let mut bytes = writer.finish().unwrap().into_inner();
+        // Entries outside the subtree aren't read, so one that can't be doesn't matter
+        let central = bytes.windows(4).rposition(|w| w == b"PK\x01\x02").unwrap();
+        bytes[central + 8] |= 1;
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert!(archive.by_name("other.txt").is_err());
+        assert_eq!(archive.entries_under("project-1.0"), [1, 2]);
+        assert_eq!(archive.entries_under("project-1.0/src/"), [1]);
+        assert_eq!(archive.entries_under("").len(), 5);
+
+        let directory = std::env::temp_dir().join(format!("zip-subtree-{}", std::process::id()));
+        archive.extract_subtree("project-1.0/", &directory).unwrap();
+        assert_eq!(
+            fs::read_to_string(directory.join("src").join("lib.rs")).unwrap(),
+            "lib"
+        );
+        assert!(directory.join("README").is_file());
+        assert!(!directory.join("other.txt").exists());
+        assert!(!directory.join("project-1.0.txt").exists());
+        fs::remove_dir_all(&directory).unwrap();
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("pkg/../evil", options).unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        assert!(archive
+            .extract_subtree("pkg", directory.join("inner"))
+            .is_err());
+        assert!(!directory.join("evil").exists());
+        fs::remove_dir_all(&directory).ok();
+    }
+
+    #[cfg(feature = "glob")]
+    #[test]
+    fn find_glob() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::Cursor;
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for name in &[
+            "setup.dll",
+            "bin/app.exe",
+            "bin/x64/native.dll",
+            "readme.txt",
+        ] {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+        }
+        let archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        assert_eq!(archive.find(&["**/*.dll"]).unwrap(), [0, 2]);
+        assert_eq!(archive.find(&["bin/*", "*.txt"]).unwrap(), [1, 3]);
+        assert!(archive.find(&["[oops"]).is_err());
+    }
 }
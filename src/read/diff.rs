@@ -0,0 +1,156 @@
+//! Comparing the central directories of two archives
+
+use super::ZipArchive;
+use crate::types::ZipFileData;
+
+/// A difference between two archives, found by [`diff`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryChange<'a> {
+    /// The file is only in the second archive
+    Added {
+        /// Name of the file
+        name: &'a str,
+        /// Index of the file in the second archive
+        index: usize,
+    },
+    /// The file is only in the first archive
+    Removed {
+        /// Name of the file
+        name: &'a str,
+        /// Index of the file in the first archive
+        index: usize,
+    },
+    /// The file is in both archives, with different contents
+    Changed {
+        /// Name of the file
+        name: &'a str,
+        /// Index of the file in the first archive
+        old_index: usize,
+        /// Index of the file in the second archive
+        new_index: usize,
+    },
+}
+
+impl<'a> EntryChange<'a> {
+    /// Get the name of the file that changed
+    pub fn name(&self) -> &'a str {
+        match *self {
+            EntryChange::Added { name, .. }
+            | EntryChange::Removed { name, .. }
+            | EntryChange::Changed { name, .. } => name,
+        }
+    }
+}
+
+/// Compare two archives by their central directories, without reading any file contents.
+///
+/// Files are matched by name, and are changed if their CRC32 or uncompressed size differ, so
+/// recompressing a file doesn't count as a change. Like [`ZipArchive::by_name`], only the last
+/// of several files with the same name is compared. Removed and changed files come first, in
+/// the order of `old`, followed by the added files in the order of `new`.
+pub fn diff<'a, A, B>(old: &'a ZipArchive<A>, new: &'a ZipArchive<B>) -> Vec<EntryChange<'a>> {
+    let mut changes = Vec::new();
+    for (index, file) in old.shared.files.iter().enumerate() {
+        let name = file.file_name.as_str();
+        if old.shared.index_for_name(name) != Some(index) {
+            continue;
+        }
+        match new.shared.index_for_name(name) {
+            None => changes.push(EntryChange::Removed { name, index }),
+            Some(new_index) => {
+                if !same_contents(file, &new.shared.files[new_index]) {
+                    changes.push(EntryChange::Changed {
+                        name,
+                        old_index: index,
+                        new_index,
+                    });
+                }
+            }
+        }
+    }
+    for (index, file) in new.shared.files.iter().enumerate() {
+        let name = file.file_name.as_str();
+        if new.shared.index_for_name(name) == Some(index)
+            && old.shared.index_for_name(name).is_none()
+        {
+            changes.push(EntryChange::Added { name, index });
+        }
+    }
+    changes
+}
+
+fn same_contents(old: &ZipFileData, new: &ZipFileData) -> bool {
+    old.crc32 == new.crc32 && old.uncompressed_size == new.uncompressed_size
+}
+
+#[cfg(test)]
+mod test {
+    use super::{diff, EntryChange};
+    use crate::compression::CompressionMethod;
+    use crate::write::{FileOptions, ZipWriter};
+    use crate::ZipArchive;
+    use std::io::{Cursor, Write};
+
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    ))]
+    const RECOMPRESSED: CompressionMethod = CompressionMethod::Deflated;
+    #[cfg(not(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    )))]
+    const RECOMPRESSED: CompressionMethod = CompressionMethod::Stored;
+
+    fn archive(files: &[(&str, &str)], method: CompressionMethod) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(method);
+        for (name, contents) in files {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn diff_archives() {
+        let old = archive(
+            &[
+                ("same.txt", "same"),
+                ("gone.txt", "gone"),
+                ("edit.txt", "old"),
+            ],
+            CompressionMethod::Stored,
+        );
+        let new = archive(
+            &[
+                ("edit.txt", "new"),
+                ("new.txt", "new"),
+                ("same.txt", "same"),
+            ],
+            RECOMPRESSED,
+        );
+
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                EntryChange::Removed {
+                    name: "gone.txt",
+                    index: 1
+                },
+                EntryChange::Changed {
+                    name: "edit.txt",
+                    old_index: 2,
+                    new_index: 0
+                },
+                EntryChange::Added {
+                    name: "new.txt",
+                    index: 1
+                },
+            ]
+        );
+        assert!(diff(&old, &old).is_empty());
+    }
+}
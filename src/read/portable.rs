@@ -0,0 +1,164 @@
+//! Finding paths that can't be extracted as they are on every system
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+/// What to do with paths that can't be extracted as they are on every system, see
+/// [`ExtractOptions::portable_paths`](super::ExtractOptions::portable_paths)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PortablePaths {
+    /// Extract them as they are
+    Keep,
+    /// Fail before anything is extracted
+    Reject,
+    /// Change the names so that they work everywhere, and no file replaces another
+    Rename,
+}
+
+/// A path that can't be extracted as it is on every system, see
+/// [`ZipArchive::path_problems`](super::ZipArchive::path_problems)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PathProblem {
+    /// The path of the file with this index has a name that Windows reserves, like `CON` or
+    /// `NUL`, a character it doesn't allow, or a name ending in a dot or space, which it drops
+    Reserved(usize),
+    /// The files with these indices have paths that only differ in case, so that the second
+    /// replaces the first on case-insensitive file systems, like the defaults of Windows and
+    /// macOS
+    CaseCollision(usize, usize),
+}
+
+/// Find the problems with the paths of `entries`, given as their index, whether they are a
+/// directory and their path. Returns them along with the new paths of the entries that need
+/// to be renamed to avoid them.
+pub(crate) fn check<I>(entries: I) -> (Vec<PathProblem>, HashMap<PathBuf, PathBuf>)
+where
+    I: IntoIterator<Item = (usize, bool, PathBuf)>,
+{
+    let mut problems = Vec::new();
+    let mut renames = HashMap::new();
+    // The files so far by their lowercase path, with their index and original path
+    let mut files: HashMap<String, (usize, PathBuf)> = HashMap::new();
+    for (index, is_dir, path) in entries {
+        let mut portable: PathBuf = path
+            .components()
+            .map(|component| match component {
+                Component::Normal(name) => portable_name(&name.to_string_lossy()).into(),
+                other => other.as_os_str().to_os_string(),
+            })
+            .collect();
+        if portable != path {
+            problems.push(PathProblem::Reserved(index));
+        }
+        // Directories with the same name merge, which is harmless
+        if !is_dir {
+            let key = portable.to_string_lossy().to_lowercase();
+            let collision = match files.get(&key) {
+                Some(&(first, ref original)) if *original != path => Some(first),
+                _ => None,
+            };
+            if let Some(first) = collision {
+                problems.push(PathProblem::CaseCollision(first, index));
+                portable = (2..)
+                    .map(|n| with_suffix(&portable, n))
+                    .find(|path| !files.contains_key(&path.to_string_lossy().to_lowercase()))
+                    .unwrap();
+            }
+            files.insert(
+                portable.to_string_lossy().to_lowercase(),
+                (index, path.clone()),
+            );
+        }
+        if portable != path {
+            renames.insert(path, portable);
+        }
+    }
+    (problems, renames)
+}
+
+/// Change a file name so that Windows accepts it
+fn portable_name(name: &str) -> String {
+    const RESERVED: &[&str] = &[
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+
+    let mut name: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' | '\\' | '\0'..='\x1f' => '_',
+            c => c,
+        })
+        .collect();
+    let trimmed = name.trim_end_matches(|c| c == '.' || c == ' ').len();
+    if trimmed == 0 {
+        return "_".to_string();
+    }
+    name.truncate(trimmed);
+    // Reserved names are reserved with any extension
+    let stem = name.find('.').unwrap_or(name.len());
+    if RESERVED
+        .iter()
+        .any(|reserved| name[..stem].eq_ignore_ascii_case(reserved))
+    {
+        name.insert(stem, '_');
+    }
+    name
+}
+
+/// Add `~n` to the file name of `path`, before its extension
+fn with_suffix(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}~{}.{}", stem, n, extension.to_string_lossy()),
+        None => format!("{}~{}", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn portable_names() {
+        assert_eq!(portable_name("readme.txt"), "readme.txt");
+        assert_eq!(portable_name("con"), "con_");
+        assert_eq!(portable_name("NUL.tar.gz"), "NUL_.tar.gz");
+        assert_eq!(portable_name("COM10"), "COM10");
+        assert_eq!(portable_name("what?.txt"), "what_.txt");
+        assert_eq!(portable_name("trailing. ."), "trailing");
+        assert_eq!(portable_name(".."), "_");
+    }
+
+    #[test]
+    fn case_collisions() {
+        let entries = vec![
+            (0, true, PathBuf::from("Docs")),
+            (1, true, PathBuf::from("docs")),
+            (2, false, PathBuf::from("docs/Readme.md")),
+            (3, false, PathBuf::from("Docs/README.md")),
+            (4, false, PathBuf::from("docs/readme~2.md")),
+            (5, false, PathBuf::from("docs/aux.c")),
+        ];
+        let (problems, renames) = check(entries);
+        assert_eq!(
+            problems,
+            vec![
+                PathProblem::CaseCollision(2, 3),
+                PathProblem::CaseCollision(3, 4),
+                PathProblem::Reserved(5)
+            ]
+        );
+        assert_eq!(
+            renames[Path::new("Docs/README.md")],
+            Path::new("Docs/README~2.md")
+        );
+        assert_eq!(
+            renames[Path::new("docs/readme~2.md")],
+            Path::new("docs/readme~2~2.md")
+        );
+        assert_eq!(renames[Path::new("docs/aux.c")], Path::new("docs/aux_.c"));
+        assert_eq!(renames.len(), 3);
+    }
+}
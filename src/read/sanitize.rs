@@ -0,0 +1,123 @@
+//! Turning the names of files into paths to extract them to
+
+use std::path::PathBuf;
+
+use crate::types::{is_enclosed, mangle_name};
+
+/// Policy for turning the names of files into paths, for
+/// [`ZipArchive::extract_with_sanitizer`](super::ZipArchive::extract_with_sanitizer)
+///
+/// Whatever path is returned, extraction fails if it isn't relative or leads out of the
+/// directory being extracted to, so a sanitizer can't make extraction unsafe. Entries mapped to
+/// an empty path are skipped.
+///
+/// ```
+/// use std::path::PathBuf;
+/// use zip::read::NameSanitizer;
+///
+/// /// Extract every file into one directory, dropping the directories they were in
+/// struct Flatten;
+///
+/// impl NameSanitizer for Flatten {
+///     fn sanitize(&self, name: &str) -> Option<PathBuf> {
+///         match name.rsplit('/').next() {
+///             Some("..") | Some(".") => None,
+///             Some(name) => Some(PathBuf::from(name)),
+///             None => None,
+///         }
+///     }
+/// }
+/// ```
+pub trait NameSanitizer {
+    /// Get the path to extract the file named `name` to, or `None` to fail with
+    /// [`InvalidArchiveKind::InvalidFilePath`](crate::result::InvalidArchiveKind::InvalidFilePath)
+    fn sanitize(&self, name: &str) -> Option<PathBuf>;
+}
+
+/// Reject names that aren't safe to use as they are, see
+/// [`ZipFile::enclosed_name`](super::ZipFile::enclosed_name)
+///
+/// This is what [`ZipArchive::extract`](super::ZipArchive::extract) does.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct EnclosedName;
+
+impl NameSanitizer for EnclosedName {
+    fn sanitize(&self, name: &str) -> Option<PathBuf> {
+        let path = PathBuf::from(name);
+        if name.contains('\0') || !is_enclosed(&path) {
+            return None;
+        }
+        Some(path)
+    }
+}
+
+/// Drop the parts of names that aren't safe, see
+/// [`ZipFile::mangled_name`](super::ZipFile::mangled_name)
+///
+/// Every name is accepted, but `foo/../bar` for example becomes `foo/bar`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MangledName;
+
+impl NameSanitizer for MangledName {
+    fn sanitize(&self, name: &str) -> Option<PathBuf> {
+        Some(mangle_name(name))
+    }
+}
+
+/// Percent-encode the characters of names that aren't safe
+///
+/// Names are split at `/`, leaving out empty parts and `.`, so absolute paths become relative.
+/// In each part, `%`, control characters, `\` and the characters Windows doesn't allow in file
+/// names are encoded, as are the dots of `..`. Every name is accepted.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PercentEncodedName;
+
+impl NameSanitizer for PercentEncodedName {
+    fn sanitize(&self, name: &str) -> Option<PathBuf> {
+        Some(
+            name.split('/')
+                .filter(|part| !part.is_empty() && *part != ".")
+                .map(|part| {
+                    if part == ".." {
+                        return "%2E%2E".to_string();
+                    }
+                    let mut encoded = String::with_capacity(part.len());
+                    for c in part.chars() {
+                        match c {
+                            '%' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*' => {
+                                encoded.push_str(&format!("%{:02X}", c as u32))
+                            }
+                            c if c.is_control() => {
+                                let mut bytes = [0; 4];
+                                for byte in c.encode_utf8(&mut bytes).bytes() {
+                                    encoded.push_str(&format!("%{:02X}", byte));
+                                }
+                            }
+                            c => encoded.push(c),
+                        }
+                    }
+                    encoded
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn sanitizers() {
+        assert_eq!(
+            EnclosedName.sanitize("a/../b"),
+            Some(PathBuf::from("a/../b"))
+        );
+        assert_eq!(EnclosedName.sanitize("../b"), None);
+        assert_eq!(MangledName.sanitize("/a/../b"), Some(PathBuf::from("a/b")));
+        let encoded = PercentEncodedName.sanitize("/a:b//../100%\u{7}").unwrap();
+        assert_eq!(encoded, Path::new("a%3Ab/%2E%2E/100%25%07"));
+        assert!(is_enclosed(&encoded));
+    }
+}
@@ -0,0 +1,365 @@
+//! Extracting archives into something other than the file system
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::{ExtractOptions, SpecialFiles, ZipFile};
+use crate::result::{ZipError, ZipResult};
+
+/// Destination for [`ZipArchive::extract_to_sink`](super::ZipArchive::extract_to_sink)
+///
+/// Paths are relative to the root of the extraction, and are already sanitized. The parents of a
+/// path are not always created before it, because archives don't need directory entries.
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use std::path::{Path, PathBuf};
+/// use zip::read::ExtractSink;
+/// use zip::result::ZipResult;
+///
+/// #[derive(Default)]
+/// struct Sizes(BTreeMap<PathBuf, usize>);
+///
+/// impl ExtractSink for Sizes {
+///     type File = Vec<u8>;
+///
+///     fn create_dir(&mut self, _path: &Path) -> ZipResult<()> {
+///         Ok(())
+///     }
+///
+///     fn create_file(&mut self, _path: &Path) -> ZipResult<Vec<u8>> {
+///         Ok(Vec::new())
+///     }
+///
+///     fn set_metadata(&mut self, path: &Path, file: &zip::read::ZipFile<'_>) -> ZipResult<()> {
+///         self.0.insert(path.to_path_buf(), file.size() as usize);
+///         Ok(())
+///     }
+/// }
+///
+/// # fn main() -> ZipResult<()> {
+/// # let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+/// # zip.start_file("file.txt", Default::default())?;
+/// # let mut archive = zip::ZipArchive::new(zip.finish()?)?;
+/// let mut sizes = Sizes::default();
+/// archive.extract_to_sink(&mut sizes)?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait ExtractSink {
+    /// Writer for the contents of a file
+    type File: Write;
+
+    /// Create a directory, if it doesn't exist yet
+    fn create_dir(&mut self, path: &Path) -> ZipResult<()>;
+
+    /// Create a file, replacing it if it exists, and return a writer for its contents
+    fn create_file(&mut self, path: &Path) -> ZipResult<Self::File>;
+
+    /// Make room for `size` bytes in a file that was just created, before its contents are
+    /// written. Does nothing by default.
+    fn reserve(&mut self, file: &mut Self::File, size: u64) -> ZipResult<()> {
+        let _ = (file, size);
+        Ok(())
+    }
+
+    /// Apply the metadata of `file`, like its permissions, to the file or directory at `path`.
+    ///
+    /// This is called for a file once its writer is dropped, and for a directory once all files
+    /// are written. Does nothing by default.
+    fn set_metadata(&mut self, path: &Path, file: &ZipFile<'_>) -> ZipResult<()> {
+        let _ = (path, file);
+        Ok(())
+    }
+
+    /// Create a symbolic link at `path` that points to `target`.
+    ///
+    /// Files with the unix file type of a symbolic link end up here, with their contents as the
+    /// target. The target isn't checked, so it may point outside of the extraction. By default,
+    /// a file holding the target is created instead, which is also what
+    /// [`ZipArchive::extract`](super::ZipArchive::extract) does.
+    fn symlink(&mut self, target: &Path, path: &Path) -> ZipResult<()> {
+        let mut file = self.create_file(path)?;
+        file.write_all(target.to_string_lossy().as_bytes())?;
+        Ok(())
+    }
+
+    /// Create the FIFO, socket or device `file` at `path`, see [`ZipFile::is_special`].
+    ///
+    /// Their contents aren't passed on. By default, they are skipped.
+    fn special_file(&mut self, path: &Path, file: &ZipFile<'_>) -> ZipResult<()> {
+        let _ = (path, file);
+        Ok(())
+    }
+
+    /// Create a hard link at `path` to the file at `target`, which was extracted before.
+    ///
+    /// Returns whether the link was created. If not, a copy of the file is created at `path`
+    /// instead, which is what happens by default.
+    fn hard_link(&mut self, target: &Path, path: &Path) -> ZipResult<bool> {
+        let _ = (target, path);
+        Ok(false)
+    }
+}
+
+/// The file system below a directory, used by [`ZipArchive::extract`](super::ZipArchive::extract)
+pub(crate) struct FileSystem {
+    root: PathBuf,
+    preallocate: bool,
+    hard_links: bool,
+    special_files: SpecialFiles,
+    #[cfg(unix)]
+    directory_permissions: bool,
+    #[cfg(unix)]
+    set_owner: bool,
+}
+
+impl FileSystem {
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    pub fn new(root: &Path, options: ExtractOptions) -> FileSystem {
+        FileSystem {
+            root: root.to_path_buf(),
+            preallocate: options.preallocate,
+            hard_links: options.hard_links,
+            special_files: options.special_files,
+            #[cfg(unix)]
+            directory_permissions: options.directory_permissions,
+            // Only root can give files away
+            #[cfg(unix)]
+            set_owner: options.unix_owner && unsafe { libc::geteuid() } == 0,
+        }
+    }
+}
+
+impl ExtractSink for FileSystem {
+    type File = fs::File;
+
+    fn create_dir(&mut self, path: &Path) -> ZipResult<()> {
+        fs::create_dir_all(self.root.join(path))?;
+        Ok(())
+    }
+
+    fn create_file(&mut self, path: &Path) -> ZipResult<fs::File> {
+        let outpath = self.root.join(path);
+        if let Some(p) = outpath.parent() {
+            if !p.exists() {
+                fs::create_dir_all(p)?;
+            }
+        }
+        Ok(fs::File::create(&outpath)?)
+    }
+
+    fn reserve(&mut self, file: &mut fs::File, size: u64) -> ZipResult<()> {
+        if self.preallocate && size > 0 {
+            preallocate(file, size)?;
+        }
+        Ok(())
+    }
+
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    fn special_file(&mut self, path: &Path, file: &ZipFile<'_>) -> ZipResult<()> {
+        match self.special_files {
+            SpecialFiles::Skip => Ok(()),
+            SpecialFiles::Error => Err(ZipError::UnsupportedArchive(
+                "Special files can't be extracted",
+            )),
+            SpecialFiles::Create => {
+                #[cfg(unix)]
+                {
+                    let is_fifo = file
+                        .unix_mode()
+                        .map_or(false, |mode| mode & 0o170000 == super::ffi::S_IFIFO);
+                    if is_fifo {
+                        let outpath = self.root.join(path);
+                        if let Some(p) = outpath.parent() {
+                            if !p.exists() {
+                                fs::create_dir_all(p)?;
+                            }
+                        }
+                        if fs::symlink_metadata(&outpath).is_ok() {
+                            fs::remove_file(&outpath)?;
+                        }
+                        mkfifo(&outpath)?;
+                        self.set_metadata(path, file)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn hard_link(&mut self, target: &Path, path: &Path) -> ZipResult<bool> {
+        if !self.hard_links {
+            return Ok(false);
+        }
+        let outpath = self.root.join(path);
+        if let Some(p) = outpath.parent() {
+            if !p.exists() {
+                fs::create_dir_all(p)?;
+            }
+        }
+        // Like files, links replace what was there
+        if fs::symlink_metadata(&outpath).is_ok() {
+            fs::remove_file(&outpath)?;
+        }
+        fs::hard_link(self.root.join(target), &outpath)?;
+        Ok(true)
+    }
+
+    #[cfg_attr(not(any(unix, windows)), allow(unused_variables))]
+    fn set_metadata(&mut self, path: &Path, file: &ZipFile<'_>) -> ZipResult<()> {
+        let outpath = self.root.join(path);
+        #[cfg(windows)]
+        set_windows_attributes(&outpath, file)?;
+        // Get and Set permissions, after the owner since changing it can clear setuid bits
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if let (true, Some(owner)) = (self.set_owner, file.unix_owner()) {
+                set_unix_owner(&outpath, owner)?;
+            }
+            let apply_mode = !file.is_dir() || self.directory_permissions;
+            if let (true, Some(mode)) = (apply_mode, file.unix_mode()) {
+                fs::set_permissions(&outpath, fs::Permissions::from_mode(mode))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reserve `size` bytes of disk space for `file`, failing if the disk is full.
+///
+/// The length of the file doesn't change, so that it never ends in zeros that weren't extracted.
+#[cfg(target_os = "linux")]
+fn preallocate(file: &fs::File, size: u64) -> std::io::Result<()> {
+    use libc::{fallocate, off_t, ENOSPC, FALLOC_FL_KEEP_SIZE};
+    use std::os::unix::io::AsRawFd;
+
+    if size > off_t::max_value() as u64 {
+        return Err(std::io::Error::from_raw_os_error(ENOSPC));
+    }
+    if unsafe { fallocate(file.as_raw_fd(), FALLOC_FL_KEEP_SIZE, 0, size as off_t) } == 0 {
+        return Ok(());
+    }
+    let error = std::io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(ENOSPC) => Err(error),
+        // The file system doesn't support it
+        _ => Ok(()),
+    }
+}
+
+/// Space can't be reserved without `fallocate`
+#[cfg(not(target_os = "linux"))]
+fn preallocate(_file: &fs::File, _size: u64) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Give `path` the read-only, hidden and system attributes of `file`, if it has any
+#[cfg(windows)]
+fn set_windows_attributes(path: &Path, file: &ZipFile) -> std::io::Result<()> {
+    use crate::types::{DOS_HIDDEN, DOS_READ_ONLY, DOS_SYSTEM};
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::um::fileapi::SetFileAttributesW;
+
+    let mut attributes = file.data.external_attributes & (DOS_READ_ONLY | DOS_HIDDEN | DOS_SYSTEM);
+    if file.unix_mode().map_or(false, |mode| mode & 0o222 == 0) {
+        attributes |= DOS_READ_ONLY;
+    }
+    if attributes == 0 {
+        return Ok(());
+    }
+    let path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    if unsafe { SetFileAttributesW(path.as_ptr(), attributes) } != 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Change the user and group of `path`, which only root can do
+#[cfg(unix)]
+fn set_unix_owner(path: &Path, (uid, gid): (u32, u32)) -> std::io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { libc::chown(path.as_ptr(), uid, gid) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Create a FIFO at `path`, only readable and writable by the owner until its permissions are set
+#[cfg(unix)]
+fn mkfifo(path: &Path) -> std::io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { libc::mkfifo(path.as_ptr(), 0o600) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::write::{FileOptions, ZipWriter};
+    use crate::ZipArchive;
+    use std::io::Cursor;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<(&'static str, PathBuf)>,
+    }
+
+    impl ExtractSink for Recorder {
+        type File = Vec<u8>;
+
+        fn create_dir(&mut self, path: &Path) -> ZipResult<()> {
+            self.events.push(("dir", path.to_path_buf()));
+            Ok(())
+        }
+
+        fn create_file(&mut self, path: &Path) -> ZipResult<Vec<u8>> {
+            self.events.push(("file", path.to_path_buf()));
+            Ok(Vec::new())
+        }
+
+        fn set_metadata(&mut self, path: &Path, _file: &ZipFile<'_>) -> ZipResult<()> {
+            self.events.push(("metadata", path.to_path_buf()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn extract_to_sink() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default();
+        writer.add_directory("a", options).unwrap();
+        writer.add_directory("a/b", options).unwrap();
+        writer.start_file("a/b/c.txt", options).unwrap();
+        writer.write_all(b"c").unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let mut recorder = Recorder::default();
+        archive.extract_to_sink(&mut recorder).unwrap();
+        let expected: Vec<_> = vec![
+            ("dir", "a"),
+            ("dir", "a/b"),
+            ("file", "a/b/c.txt"),
+            ("metadata", "a/b/c.txt"),
+            ("metadata", "a/b"),
+            ("metadata", "a"),
+        ]
+        .into_iter()
+        .map(|(event, path)| (event, PathBuf::from(path)))
+        .collect();
+        assert_eq!(recorder.events, expected);
+    }
+}
@@ -0,0 +1,308 @@
+//! Reading archives from start to end, without seeking
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use super::{parse_central_header, read_local_file, Limits, ZipFile};
+use crate::compression::CompressionMethod;
+use crate::result::{InvalidArchiveKind, ZipError, ZipResult};
+use crate::spec;
+use crate::types::{DateTime, ZipFileData};
+
+/// Reader for an archive that can't be seeked, such as stdin or a network stream
+///
+/// The files are visited in the order of their local headers, while the stream passes by.
+/// Information that is only stored in the central directory, like the permissions of a file,
+/// is passed to the visitor separately once all files have been read.
+///
+/// ```no_run
+/// use zip::read::{ZipStreamFileMetadata, ZipStreamReader, ZipStreamVisitor};
+/// use zip::read::ZipFile;
+/// use zip::result::ZipResult;
+///
+/// struct Lister;
+///
+/// impl ZipStreamVisitor for Lister {
+///     fn visit_file(&mut self, file: &mut ZipFile<'_>) -> ZipResult<()> {
+///         println!("{}: {} bytes", file.name(), file.size());
+///         Ok(())
+///     }
+///
+///     fn visit_additional_metadata(&mut self, metadata: &ZipStreamFileMetadata) -> ZipResult<()> {
+///         println!("{}: mode {:?}", metadata.name(), metadata.unix_mode());
+///         Ok(())
+///     }
+/// }
+///
+/// ZipStreamReader::new(std::io::stdin()).visit(&mut Lister)?;
+/// # Ok::<(), zip::result::ZipError>(())
+/// ```
+#[derive(Debug)]
+pub struct ZipStreamReader<R>(R);
+
+impl<R: Read> ZipStreamReader<R> {
+    /// Read an archive from the start of `reader`
+    pub fn new(reader: R) -> ZipStreamReader<R> {
+        ZipStreamReader(reader)
+    }
+
+    /// Visit every file in the archive, and then every entry of the central directory.
+    ///
+    /// Files that use a data descriptor or are encrypted can't be read from a stream, and
+    /// result in an error.
+    pub fn visit<V: ZipStreamVisitor>(mut self, visitor: &mut V) -> ZipResult<()> {
+        let mut signature = self.0.read_u32::<LittleEndian>()?;
+        while signature == spec::LOCAL_FILE_HEADER_SIGNATURE {
+            let mut file = read_local_file(&mut self.0)?;
+            visitor.visit_file(&mut file)?;
+            file.finish()?;
+            signature = self.0.read_u32::<LittleEndian>()?;
+        }
+
+        while signature == spec::CENTRAL_DIRECTORY_HEADER_SIGNATURE {
+            // Offsets are not known in a stream, the stored ones are unreliable anyway
+            let data = parse_central_header(&mut self.0, 0, 0, &mut Limits::default())?;
+            visitor.visit_additional_metadata(&ZipStreamFileMetadata(data))?;
+            signature = self.0.read_u32::<LittleEndian>()?;
+        }
+
+        // Anything after the central directory, normally the end of central directory record, is
+        // left unread.
+        Ok(())
+    }
+
+    /// Extract the archive into a directory, like [`ZipArchive::extract`](super::ZipArchive::extract)
+    ///
+    /// Permissions are stored in the central directory, so they are only applied after all files
+    /// have been extracted.
+    pub fn extract<P: AsRef<Path>>(self, directory: P) -> ZipResult<()> {
+        self.visit(&mut Extractor(directory.as_ref()))
+    }
+}
+
+/// Callbacks for [`ZipStreamReader::visit`]
+pub trait ZipStreamVisitor {
+    /// Called for every file, in the order in which they are stored.
+    ///
+    /// The contents don't have to be read completely, the rest is skipped after this returns.
+    /// Information that is only stored in the central directory, like the comment and unix mode
+    /// of the file, is not available yet.
+    fn visit_file(&mut self, file: &mut ZipFile<'_>) -> ZipResult<()>;
+
+    /// Called for every entry in the central directory, after all files have been visited.
+    ///
+    /// Does nothing by default.
+    fn visit_additional_metadata(&mut self, metadata: &ZipStreamFileMetadata) -> ZipResult<()> {
+        let _ = metadata;
+        Ok(())
+    }
+}
+
+/// Information on a file from the central directory of a streamed archive
+#[derive(Debug)]
+pub struct ZipStreamFileMetadata(ZipFileData);
+
+impl ZipStreamFileMetadata {
+    /// Get the name of the file
+    ///
+    /// Like [`ZipFile::name`], this is not safe to use as a path directly, see
+    /// [`ZipStreamFileMetadata::enclosed_name`].
+    pub fn name(&self) -> &str {
+        &self.0.file_name
+    }
+
+    /// Get the name of the file, in the raw (internal) byte representation.
+    pub fn name_raw(&self) -> &[u8] {
+        self.0.name_raw()
+    }
+
+    /// Get the name of the file as a path, if it is safe to use. See [`ZipFile::enclosed_name`].
+    pub fn enclosed_name(&self) -> Option<&Path> {
+        self.0.enclosed_name()
+    }
+
+    /// Returns whether the file is actually a directory
+    pub fn is_dir(&self) -> bool {
+        self.0.is_dir()
+    }
+
+    /// Returns whether the file is a regular file
+    pub fn is_file(&self) -> bool {
+        !self.is_dir()
+    }
+
+    /// Get the comment of the file
+    pub fn comment(&self) -> &str {
+        &self.0.file_comment
+    }
+
+    /// Returns whether the file is encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.0.encrypted
+    }
+
+    /// Get unix mode for the file
+    pub fn unix_mode(&self) -> Option<u32> {
+        self.0.unix_mode()
+    }
+
+    /// Get the compression method used to store the file
+    pub fn compression(&self) -> CompressionMethod {
+        self.0.compression_method
+    }
+
+    /// Get the size of the file in the archive
+    pub fn compressed_size(&self) -> u64 {
+        self.0.compressed_size
+    }
+
+    /// Get the size of the file when uncompressed
+    pub fn size(&self) -> u64 {
+        self.0.uncompressed_size
+    }
+
+    /// Get the time the file was last modified
+    pub fn last_modified(&self) -> DateTime {
+        self.0.last_modified_time
+    }
+
+    /// Get the CRC32 hash of the original file
+    pub fn crc32(&self) -> u32 {
+        self.0.crc32
+    }
+}
+
+struct Extractor<'a>(&'a Path);
+
+impl<'a> ZipStreamVisitor for Extractor<'a> {
+    fn visit_file(&mut self, file: &mut ZipFile<'_>) -> ZipResult<()> {
+        let filepath = file.enclosed_name().ok_or_else(|| {
+            ZipError::invalid(InvalidArchiveKind::InvalidFilePath).in_entry(file.name())
+        })?;
+        let outpath = self.0.join(filepath);
+
+        if file.is_dir() {
+            fs::create_dir_all(&outpath)?;
+        } else {
+            if let Some(p) = outpath.parent() {
+                fs::create_dir_all(p)?;
+            }
+            let mut outfile = fs::File::create(&outpath)?;
+            file.copy_to(&mut outfile)?;
+        }
+        Ok(())
+    }
+
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    fn visit_additional_metadata(&mut self, metadata: &ZipStreamFileMetadata) -> ZipResult<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if let (Some(path), Some(mode)) = (metadata.enclosed_name(), metadata.unix_mode()) {
+                let outpath = self.0.join(path);
+                match fs::set_permissions(&outpath, fs::Permissions::from_mode(mode)) {
+                    // Not every entry of the central directory has to be in the stream
+                    Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    result => result?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::write::{FileOptions, ZipWriter};
+    use std::io::{Cursor, Write};
+
+    #[derive(Default)]
+    struct Collector {
+        files: Vec<(String, String)>,
+        modes: Vec<(String, Option<u32>)>,
+    }
+
+    impl ZipStreamVisitor for Collector {
+        fn visit_file(&mut self, file: &mut ZipFile<'_>) -> ZipResult<()> {
+            let mut contents = String::new();
+            file.read_to_string(&mut contents)?;
+            self.files.push((file.name().to_string(), contents));
+            Ok(())
+        }
+
+        fn visit_additional_metadata(&mut self, metadata: &ZipStreamFileMetadata) -> ZipResult<()> {
+            let mode = metadata.unix_mode().map(|mode| mode & 0o777);
+            self.modes.push((metadata.name().to_string(), mode));
+            Ok(())
+        }
+    }
+
+    fn archive() -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .add_directory("dir", FileOptions::default().unix_permissions(0o750))
+            .unwrap();
+        let options = FileOptions::default().unix_permissions(0o640);
+        writer.start_file("dir/file.txt", options).unwrap();
+        writer.write_all(b"in dir").unwrap();
+        writer.start_file("top.txt", options).unwrap();
+        writer.write_all(b"at the top").unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn visit_stream() {
+        let v = archive();
+        let mut collector = Collector::default();
+        ZipStreamReader::new(&v[..]).visit(&mut collector).unwrap();
+
+        let files: Vec<_> = collector
+            .files
+            .iter()
+            .map(|(name, contents)| (name.as_str(), contents.as_str()))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("dir/", ""),
+                ("dir/file.txt", "in dir"),
+                ("top.txt", "at the top")
+            ]
+        );
+        let modes: Vec<_> = collector
+            .modes
+            .iter()
+            .map(|(name, mode)| (name.as_str(), *mode))
+            .collect();
+        assert_eq!(
+            modes,
+            vec![
+                ("dir/", Some(0o750)),
+                ("dir/file.txt", Some(0o640)),
+                ("top.txt", Some(0o640))
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_stream() {
+        let directory = std::env::temp_dir().join(format!("zip-stream-{}", std::process::id()));
+        ZipStreamReader::new(&archive()[..])
+            .extract(&directory)
+            .unwrap();
+
+        let contents = fs::read_to_string(directory.join("dir").join("file.txt")).unwrap();
+        assert_eq!(contents, "in dir");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = fs::metadata(directory.join("top.txt")).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
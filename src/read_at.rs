@@ -0,0 +1,222 @@
+//! Readers that don't share a cursor
+//!
+//! Clones of a [`ZipArchive`](crate::read::ZipArchive) over `&File` would all move the same
+//! file cursor, so reading from several threads at once mixes up their positions. A
+//! [`PositionedReader`] keeps its own position and reads with `pread` (or `seek_read` on
+//! Windows) instead, so clones of it can be used in parallel on a single file.
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::sync::Arc;
+
+/// Data that can be read at any offset without changing a shared position
+pub trait ReadAt {
+    /// Read bytes starting at `offset`, returning how many bytes were read like [`Read::read`].
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+
+    /// Total size of the data in bytes
+    fn size(&self) -> io::Result<u64>;
+}
+
+#[cfg(unix)]
+impl ReadAt for File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(self, buf, offset)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+#[cfg(windows)]
+impl ReadAt for File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+impl ReadAt for [u8] {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        if offset >= self.len() as u64 {
+            return Ok(0);
+        }
+        (&self[offset as usize..]).read(buf)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+/// Data in JavaScript memory, like an uploaded file read into an `ArrayBuffer`
+///
+/// Only the requested parts are copied into WebAssembly memory:
+///
+/// ```no_run
+/// # fn open(buffer: &js_sys::ArrayBuffer) -> zip::result::ZipResult<()> {
+/// use zip::read::PositionedReader;
+///
+/// let data = js_sys::Uint8Array::new(buffer);
+/// let archive = zip::ZipArchive::new(PositionedReader::new(data))?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "wasm")]
+impl ReadAt for js_sys::Uint8Array {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let len = self.length() as u64;
+        if offset >= len {
+            return Ok(0);
+        }
+        let end = std::cmp::min(len, offset + buf.len() as u64);
+        let count = (end - offset) as usize;
+        self.subarray(offset as u32, end as u32)
+            .copy_to(&mut buf[..count]);
+        Ok(count)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.length() as u64)
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        (**self).size()
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Arc<T> {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        (**self).size()
+    }
+}
+
+/// `Read + Seek` adapter for a [`ReadAt`] source, with a position of its own
+///
+/// Cloning it is cheap if the source is, for example with `&File` or `Arc<File>`, and the
+/// clones can be read from different threads:
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::sync::Arc;
+/// use zip::read::PositionedReader;
+/// use zip::result::ZipResult;
+///
+/// fn open_shared(path: &str) -> ZipResult<zip::ZipArchive<PositionedReader<Arc<File>>>> {
+///     let file = Arc::new(File::open(path)?);
+///     // Clone the archive for each thread that reads from it
+///     zip::ZipArchive::new(PositionedReader::new(file))
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct PositionedReader<T> {
+    inner: T,
+    position: u64,
+}
+
+impl<T: ReadAt> PositionedReader<T> {
+    /// Read from `inner`, starting at offset 0
+    pub fn new(inner: T) -> PositionedReader<T> {
+        PositionedReader { inner, position: 0 }
+    }
+
+    /// Unwrap and return the source
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: ReadAt> Read for PositionedReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read_at(buf, self.position)?;
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<T: ReadAt> Seek for PositionedReader<T> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            io::SeekFrom::Start(offset) => {
+                self.position = offset;
+                return Ok(offset);
+            }
+            io::SeekFrom::End(offset) => (self.inner.size()?, offset),
+            io::SeekFrom::Current(offset) => (self.position, offset),
+        };
+        let position = if offset >= 0 {
+            base.checked_add(offset as u64)
+        } else {
+            base.checked_sub(offset.wrapping_neg() as u64)
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PositionedReader;
+    use std::io::{Read, Seek, SeekFrom};
+
+    #[test]
+    fn independent_positions() {
+        let data: &[u8] = b"0123456789";
+        let mut first = PositionedReader::new(data);
+        let mut second = first.clone();
+        let mut buf = [0; 3];
+
+        first.seek(SeekFrom::End(-3)).unwrap();
+        first.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"789");
+        second.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"012");
+
+        assert_eq!(first.read(&mut buf).unwrap(), 0);
+        assert!(second.seek(SeekFrom::Current(-4)).is_err());
+    }
+
+    #[test]
+    fn shared_archive() {
+        use crate::write::{FileOptions, ZipWriter};
+        use crate::ZipArchive;
+        use std::io::{self, Write};
+        use std::sync::Arc;
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.start_file("a.txt", FileOptions::default()).unwrap();
+        writer.write_all(b"first").unwrap();
+        writer.start_file("b.txt", FileOptions::default()).unwrap();
+        writer.write_all(b"second").unwrap();
+        let v: Arc<[u8]> = writer.finish().unwrap().into_inner().into();
+
+        let mut zip = ZipArchive::new(PositionedReader::new(v)).unwrap();
+        let mut other = zip.clone();
+        let mut a = zip.by_name("a.txt").unwrap();
+        let mut b = other.by_name("b.txt").unwrap();
+        let mut contents = String::new();
+        a.read_to_string(&mut contents).unwrap();
+        b.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "firstsecond");
+    }
+}
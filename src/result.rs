@@ -1,5 +1,6 @@
 //! Error types that can be emitted from this library
 
+use std::fmt;
 use std::io;
 
 use thiserror::Error;
@@ -20,16 +21,32 @@ pub enum ZipError {
     Io(#[from] io::Error),
 
     /// This file is probably not a zip archive
-    #[error("invalid Zip archive")]
-    InvalidArchive(&'static str),
+    #[error("invalid Zip archive: {kind}{}", Context(.offset, .entry))]
+    InvalidArchive {
+        /// Offset in the reader of the structure that is broken, if known
+        offset: Option<u64>,
+        /// Name of the file that is broken, if the problem is with a single file
+        entry: Option<String>,
+        /// What is wrong with the archive
+        kind: InvalidArchiveKind,
+    },
 
     /// This archive is not supported
     #[error("unsupported Zip archive")]
     UnsupportedArchive(&'static str),
 
+    /// The archive uses a compression method that is not supported, or that was disabled with a
+    /// feature flag. This holds the numeric id of the method.
+    #[error("unsupported compression method {}", Method(*.0))]
+    UnsupportedCompression(u16),
+
     /// The requested file could not be found in the archive
     #[error("specified file not found in archive")]
     FileNotFound,
+
+    /// The central directory goes over a limit set in [`Config`](crate::read::Config)
+    #[error("{0} exceeds the configured limit")]
+    LimitExceeded(Limit),
 }
 
 impl ZipError {
@@ -45,6 +62,168 @@ impl ZipError {
     /// # ()
     /// ```
     pub const PASSWORD_REQUIRED: &'static str = "Password required to decrypt file";
+
+    /// The text used as an error when the password set with
+    /// [`ZipArchive::set_default_password`](crate::ZipArchive::set_default_password) is wrong
+    pub const INVALID_PASSWORD: &'static str = "Invalid password for file";
+
+    /// The text used as an error when a file was deflated with a preset dictionary that wasn't
+    /// given with [`ZipArchive::add_deflate_dictionary`](crate::ZipArchive::add_deflate_dictionary)
+    pub const DICTIONARY_REQUIRED: &'static str = "Preset dictionary required to decompress file";
+
+    /// An [`ZipError::InvalidArchive`] error without context
+    pub(crate) fn invalid(kind: InvalidArchiveKind) -> ZipError {
+        ZipError::InvalidArchive {
+            offset: None,
+            entry: None,
+            kind,
+        }
+    }
+
+    /// Record the offset of the broken structure, if this is an [`ZipError::InvalidArchive`]
+    /// error that doesn't have one yet
+    pub(crate) fn at_offset(mut self, position: u64) -> ZipError {
+        if let ZipError::InvalidArchive { ref mut offset, .. } = self {
+            offset.get_or_insert(position);
+        }
+        self
+    }
+
+    /// Record the name of the broken file, if this is an [`ZipError::InvalidArchive`] error that
+    /// doesn't have one yet
+    pub(crate) fn in_entry(mut self, name: &str) -> ZipError {
+        if let ZipError::InvalidArchive { ref mut entry, .. } = self {
+            if entry.is_none() {
+                *entry = Some(name.to_owned());
+            }
+        }
+        self
+    }
+}
+
+/// The ways in which a ZIP archive can be invalid, see [`ZipError::InvalidArchive`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InvalidArchiveKind {
+    /// There is no end of central directory record, so this is probably not a ZIP archive
+    CentralDirectoryEndNotFound,
+    /// The end of central directory record is broken
+    InvalidCentralDirectoryEnd,
+    /// The ZIP64 end of central directory locator is broken
+    InvalidZip64Locator,
+    /// The ZIP64 end of central directory record is missing
+    Zip64CentralDirectoryEndNotFound,
+    /// The recorded size or offset of the central directory doesn't fit in the archive
+    InvalidCentralDirectoryOffset,
+    /// A central directory header is broken
+    InvalidCentralDirectoryHeader,
+    /// A local file header is broken
+    InvalidLocalFileHeader,
+    /// A local file header disagrees with the central directory
+    LocalHeaderMismatch,
+    /// A file name is unsafe to extract
+    InvalidFilePath,
+    /// Several files have the same name
+    DuplicateFileName,
+    /// A segment of a split archive is missing
+    MissingSegment,
+    /// The manifest of digests can't be parsed, see the `manifest` module of the `digest` feature
+    InvalidManifest,
+    /// A file was added, removed or changed since the manifest of digests was made
+    ManifestMismatch,
+    /// A hard link points to a file that isn't in the archive
+    InvalidLinkTarget,
+    /// Several files have paths that only differ in case
+    PathCollision,
+}
+
+impl fmt::Display for InvalidArchiveKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InvalidArchiveKind::CentralDirectoryEndNotFound => {
+                "could not find central directory end"
+            }
+            InvalidArchiveKind::InvalidCentralDirectoryEnd => "invalid central directory end",
+            InvalidArchiveKind::InvalidZip64Locator => {
+                "invalid zip64 central directory end locator"
+            }
+            InvalidArchiveKind::Zip64CentralDirectoryEndNotFound => {
+                "could not find zip64 central directory end"
+            }
+            InvalidArchiveKind::InvalidCentralDirectoryOffset => {
+                "invalid central directory size or offset"
+            }
+            InvalidArchiveKind::InvalidCentralDirectoryHeader => "invalid central directory header",
+            InvalidArchiveKind::InvalidLocalFileHeader => "invalid local file header",
+            InvalidArchiveKind::LocalHeaderMismatch => {
+                "local file header doesn't match central directory"
+            }
+            InvalidArchiveKind::InvalidFilePath => "invalid file path",
+            InvalidArchiveKind::DuplicateFileName => "duplicate file name",
+            InvalidArchiveKind::MissingSegment => "missing segment of split archive",
+            InvalidArchiveKind::InvalidManifest => "invalid manifest",
+            InvalidArchiveKind::ManifestMismatch => "file doesn't match the manifest",
+            InvalidArchiveKind::InvalidLinkTarget => "hard link to a missing file",
+            InvalidArchiveKind::PathCollision => "file paths that only differ in case",
+        })
+    }
+}
+
+/// The limits on the central directory, see [`ZipError::LimitExceeded`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Limit {
+    /// The number of files, see [`Config::max_entries`](crate::read::Config::max_entries)
+    Entries,
+    /// The length of a file name, see
+    /// [`Config::max_file_name_length`](crate::read::Config::max_file_name_length)
+    FileNameLength,
+    /// The length of a file comment or the archive comment, see
+    /// [`Config::max_comment_length`](crate::read::Config::max_comment_length)
+    CommentLength,
+    /// The length of an extra field, see
+    /// [`Config::max_extra_field_length`](crate::read::Config::max_extra_field_length)
+    ExtraFieldLength,
+    /// The memory taken by the files, see
+    /// [`Config::max_metadata_bytes`](crate::read::Config::max_metadata_bytes)
+    MetadataBytes,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Limit::Entries => "number of files",
+            Limit::FileNameLength => "file name length",
+            Limit::CommentLength => "comment length",
+            Limit::ExtraFieldLength => "extra field length",
+            Limit::MetadataBytes => "memory for the central directory",
+        })
+    }
+}
+
+/// Formats the id of a compression method, with its name if it is known
+struct Method(u16);
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match crate::compression::method_name(self.0) {
+            Some(name) => write!(f, "{} ({})", self.0, name),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// Formats the location of an [`ZipError::InvalidArchive`] error
+struct Context<'a>(&'a Option<u64>, &'a Option<String>);
+
+impl<'a> fmt::Display for Context<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ref entry) = self.1 {
+            write!(f, " in {:?}", entry)?;
+        }
+        if let Some(offset) = self.0 {
+            write!(f, " at offset {}", offset)?;
+        }
+        Ok(())
+    }
 }
 
 impl From<ZipError> for io::Error {
@@ -52,3 +231,35 @@ impl From<ZipError> for io::Error {
         io::Error::new(io::ErrorKind::Other, err)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{InvalidArchiveKind, ZipError};
+
+    #[test]
+    fn invalid_archive_context() {
+        let error = ZipError::invalid(InvalidArchiveKind::InvalidLocalFileHeader);
+        assert_eq!(
+            error.to_string(),
+            "invalid Zip archive: invalid local file header"
+        );
+
+        let error = error.at_offset(42).in_entry("file.txt").at_offset(0);
+        assert_eq!(
+            error.to_string(),
+            "invalid Zip archive: invalid local file header in \"file.txt\" at offset 42"
+        );
+    }
+
+    #[test]
+    fn unsupported_compression() {
+        assert_eq!(
+            ZipError::UnsupportedCompression(14).to_string(),
+            "unsupported compression method 14 (LZMA)"
+        );
+        assert_eq!(
+            ZipError::UnsupportedCompression(1000).to_string(),
+            "unsupported compression method 1000"
+        );
+    }
+}
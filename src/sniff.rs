@@ -0,0 +1,187 @@
+//! Telling document formats that are ZIP archives apart, without extracting them
+//!
+//! ```
+//! use std::io::{Cursor, Write};
+//! use zip::sniff::{document_kind, DocumentKind};
+//! use zip::write::FileOptions;
+//!
+//! # fn main() -> zip::result::ZipResult<()> {
+//! let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+//! writer.start_file("mimetype", FileOptions::default())?;
+//! writer.write_all(b"application/epub+zip")?;
+//! let mut archive = zip::ZipArchive::new(writer.finish()?)?;
+//!
+//! let kind = document_kind(&mut archive)?;
+//! assert_eq!(kind, DocumentKind::Epub);
+//! assert_eq!(kind.media_type(), "application/epub+zip");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::{Read, Seek};
+
+use crate::read::ZipArchive;
+use crate::result::{ZipError, ZipResult};
+
+/// What kind of document an archive is, see [`document_kind`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DocumentKind {
+    /// An EPUB e-book
+    Epub,
+    /// An OpenDocument file, with its media type, like
+    /// `application/vnd.oasis.opendocument.text`
+    OpenDocument(String),
+    /// An Office Open XML file, as made by Microsoft Office
+    OfficeOpenXml(OfficeDocument),
+    /// An Android package
+    Apk,
+    /// A Java archive
+    Jar,
+    /// Another format that has a `mimetype` file, holding this media type
+    Other(String),
+    /// A plain ZIP archive, or a format that isn't recognized
+    Zip,
+}
+
+/// The application a [`DocumentKind::OfficeOpenXml`] file belongs to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OfficeDocument {
+    /// A word processing document, `.docx`
+    Document,
+    /// A spreadsheet, `.xlsx`
+    Spreadsheet,
+    /// A presentation, `.pptx`
+    Presentation,
+    /// Any other Office Open XML package
+    Other,
+}
+
+impl DocumentKind {
+    /// Get the media type of the document, `application/zip` for plain archives
+    pub fn media_type(&self) -> &str {
+        match self {
+            DocumentKind::Epub => "application/epub+zip",
+            DocumentKind::OpenDocument(media_type) | DocumentKind::Other(media_type) => media_type,
+            DocumentKind::OfficeOpenXml(OfficeDocument::Document) => {
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            }
+            DocumentKind::OfficeOpenXml(OfficeDocument::Spreadsheet) => {
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            }
+            DocumentKind::OfficeOpenXml(OfficeDocument::Presentation) => {
+                "application/vnd.openxmlformats-officedocument.presentationml.presentation"
+            }
+            DocumentKind::OfficeOpenXml(OfficeDocument::Other) => "application/zip",
+            DocumentKind::Apk => "application/vnd.android.package-archive",
+            DocumentKind::Jar => "application/java-archive",
+            DocumentKind::Zip => "application/zip",
+        }
+    }
+}
+
+/// Find out what kind of document an archive is, from the names of its files and the contents
+/// of its `mimetype` file.
+///
+/// Only the `mimetype` file is read, and only its first 256 bytes. The archive isn't checked
+/// any further, so a broken document can still be recognized.
+pub fn document_kind<R: Read + Seek>(archive: &mut ZipArchive<R>) -> ZipResult<DocumentKind> {
+    if let Some(media_type) = mimetype(archive)? {
+        return Ok(if media_type == "application/epub+zip" {
+            DocumentKind::Epub
+        } else if media_type.starts_with("application/vnd.oasis.opendocument.") {
+            DocumentKind::OpenDocument(media_type)
+        } else {
+            DocumentKind::Other(media_type)
+        });
+    }
+
+    let has = |name: &str| !archive.indices_for_name(name).is_empty();
+    Ok(if has("[Content_Types].xml") {
+        DocumentKind::OfficeOpenXml(if has("word/document.xml") {
+            OfficeDocument::Document
+        } else if has("xl/workbook.xml") {
+            OfficeDocument::Spreadsheet
+        } else if has("ppt/presentation.xml") {
+            OfficeDocument::Presentation
+        } else {
+            OfficeDocument::Other
+        })
+    } else if has("AndroidManifest.xml") {
+        // Signed APKs have a JAR manifest as well
+        DocumentKind::Apk
+    } else if has("META-INF/MANIFEST.MF") {
+        DocumentKind::Jar
+    } else {
+        DocumentKind::Zip
+    })
+}
+
+/// Read the `mimetype` file, if there is one and it holds a plausible media type
+fn mimetype<R: Read + Seek>(archive: &mut ZipArchive<R>) -> ZipResult<Option<String>> {
+    let file = match archive.by_name("mimetype") {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut contents = Vec::new();
+    file.take(256).read_to_end(&mut contents)?;
+    let media_type = match String::from_utf8(contents) {
+        Ok(media_type) => media_type.trim().to_string(),
+        Err(_) => return Ok(None),
+    };
+    if media_type.is_empty() || !media_type.contains('/') {
+        return Ok(None);
+    }
+    Ok(Some(media_type))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::write::{FileOptions, ZipWriter};
+    use std::io::{Cursor, Write};
+
+    fn kind(files: &[(&str, &str)]) -> DocumentKind {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for &(name, contents) in files.iter() {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        document_kind(&mut archive).unwrap()
+    }
+
+    #[test]
+    fn document_kinds() {
+        assert_eq!(
+            kind(&[
+                ("mimetype", "application/vnd.oasis.opendocument.text"),
+                ("content.xml", "")
+            ]),
+            DocumentKind::OpenDocument("application/vnd.oasis.opendocument.text".to_string())
+        );
+        assert_eq!(
+            kind(&[("mimetype", "application/x-krita"), ("maindoc.xml", "")]),
+            DocumentKind::Other("application/x-krita".to_string())
+        );
+        assert_eq!(
+            kind(&[("[Content_Types].xml", ""), ("xl/workbook.xml", "")]),
+            DocumentKind::OfficeOpenXml(OfficeDocument::Spreadsheet)
+        );
+        assert_eq!(
+            kind(&[
+                ("AndroidManifest.xml", ""),
+                ("META-INF/MANIFEST.MF", ""),
+                ("classes.dex", "")
+            ]),
+            DocumentKind::Apk
+        );
+        assert_eq!(
+            kind(&[("META-INF/MANIFEST.MF", ""), ("Main.class", "")]),
+            DocumentKind::Jar
+        );
+        // A mimetype file that isn't a media type is just a file
+        assert_eq!(kind(&[("mimetype", "\u{fffd}garbage")]), DocumentKind::Zip);
+        assert_eq!(kind(&[]).media_type(), "application/zip");
+    }
+}
@@ -1,204 +1,223 @@
+//! Low-level parsing of the structures in an archive
+//!
+//! [`ZipArchive`] stops at the first structure that is broken. The
+//! functions in this module keep going, and return what could be read next to what couldn't,
+//! for tools that inspect damaged archives and as a starting point for fuzzing.
+
+use crate::compression::CompressionMethod;
+use crate::format::CentralDirectoryEnd;
+use crate::read::{central_header_to_zip_file, find_central_header, Limits, ZipArchive};
 use crate::result::{ZipError, ZipResult};
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crate::types::{DateTime, ZipFileData};
 use std::io;
 use std::io::prelude::*;
-
-pub const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
-pub const CENTRAL_DIRECTORY_HEADER_SIGNATURE: u32 = 0x02014b50;
-const CENTRAL_DIRECTORY_END_SIGNATURE: u32 = 0x06054b50;
-pub const ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE: u32 = 0x06064b50;
-const ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIGNATURE: u32 = 0x07064b50;
-
-pub struct CentralDirectoryEnd {
-    pub disk_number: u16,
-    pub disk_with_central_directory: u16,
-    pub number_of_files_on_this_disk: u16,
-    pub number_of_files: u16,
-    pub central_directory_size: u32,
-    pub central_directory_offset: u32,
-    pub zip_file_comment: Vec<u8>,
+use std::path::Path;
+use thiserror::Error;
+
+pub(crate) const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
+pub(crate) const CENTRAL_DIRECTORY_HEADER_SIGNATURE: u32 = 0x02014b50;
+pub(crate) const CENTRAL_DIRECTORY_END_SIGNATURE: u32 = 0x06054b50;
+pub(crate) const ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE: u32 = 0x06064b50;
+pub(crate) const ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+pub(crate) const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+pub(crate) const SPLIT_ARCHIVE_SIGNATURE: u32 = 0x08074b50;
+pub(crate) const SINGLE_SEGMENT_SPLIT_ARCHIVE_SIGNATURE: u32 = 0x30304b50;
+pub(crate) const APK_SIGNING_BLOCK_MAGIC: &[u8; 16] = b"APK Sig Block 42";
+
+/// A structure that couldn't be parsed by [`parse_central_directory`]
+#[derive(Debug, Error)]
+#[error("{error}")]
+pub struct ParseError {
+    /// Offset in the reader where the structure starts, if known
+    pub offset: Option<u64>,
+    /// What went wrong
+    #[source]
+    pub error: ZipError,
 }
 
-impl CentralDirectoryEnd {
-    pub fn parse<T: Read>(reader: &mut T) -> ZipResult<CentralDirectoryEnd> {
-        let magic = reader.read_u32::<LittleEndian>()?;
-        if magic != CENTRAL_DIRECTORY_END_SIGNATURE {
-            return Err(ZipError::InvalidArchive("Invalid digital signature header"));
-        }
-        let disk_number = reader.read_u16::<LittleEndian>()?;
-        let disk_with_central_directory = reader.read_u16::<LittleEndian>()?;
-        let number_of_files_on_this_disk = reader.read_u16::<LittleEndian>()?;
-        let number_of_files = reader.read_u16::<LittleEndian>()?;
-        let central_directory_size = reader.read_u32::<LittleEndian>()?;
-        let central_directory_offset = reader.read_u32::<LittleEndian>()?;
-        let zip_file_comment_length = reader.read_u16::<LittleEndian>()? as usize;
-        let mut zip_file_comment = vec![0; zip_file_comment_length];
-        reader.read_exact(&mut zip_file_comment)?;
-
-        Ok(CentralDirectoryEnd {
-            disk_number,
-            disk_with_central_directory,
-            number_of_files_on_this_disk,
-            number_of_files,
-            central_directory_size,
-            central_directory_offset,
-            zip_file_comment,
-        })
-    }
-
-    pub fn find_and_parse<T: Read + io::Seek>(
-        reader: &mut T,
-    ) -> ZipResult<(CentralDirectoryEnd, u64)> {
-        const HEADER_SIZE: u64 = 22;
-        const BYTES_BETWEEN_MAGIC_AND_COMMENT_SIZE: u64 = HEADER_SIZE - 6;
-        let file_length = reader.seek(io::SeekFrom::End(0))?;
-
-        let search_upper_bound = file_length.saturating_sub(HEADER_SIZE + ::std::u16::MAX as u64);
-
-        if file_length < HEADER_SIZE {
-            return Err(ZipError::InvalidArchive("Invalid zip header"));
-        }
+/// A header of the central directory that was parsed by [`parse_central_directory`]
+#[derive(Debug, Clone)]
+pub struct CentralHeader(ZipFileData);
+
+impl CentralHeader {
+    /// Get the name of the file
+    ///
+    /// Like [`ZipFile::name`](crate::read::ZipFile::name), this is not safe to use as a path
+    /// directly, see [`CentralHeader::enclosed_name`].
+    pub fn name(&self) -> &str {
+        &self.0.file_name
+    }
 
-        let mut pos = file_length - HEADER_SIZE;
-        while pos >= search_upper_bound {
-            reader.seek(io::SeekFrom::Start(pos as u64))?;
-            if reader.read_u32::<LittleEndian>()? == CENTRAL_DIRECTORY_END_SIGNATURE {
-                reader.seek(io::SeekFrom::Current(
-                    BYTES_BETWEEN_MAGIC_AND_COMMENT_SIZE as i64,
-                ))?;
-                let cde_start_pos = reader.seek(io::SeekFrom::Start(pos as u64))?;
-                return CentralDirectoryEnd::parse(reader).map(|cde| (cde, cde_start_pos));
-            }
-            pos = match pos.checked_sub(1) {
-                Some(p) => p,
-                None => break,
-            };
-        }
-        Err(ZipError::InvalidArchive(
-            "Could not find central directory end",
-        ))
-    }
-
-    pub fn write<T: Write>(&self, writer: &mut T) -> ZipResult<()> {
-        writer.write_u32::<LittleEndian>(CENTRAL_DIRECTORY_END_SIGNATURE)?;
-        writer.write_u16::<LittleEndian>(self.disk_number)?;
-        writer.write_u16::<LittleEndian>(self.disk_with_central_directory)?;
-        writer.write_u16::<LittleEndian>(self.number_of_files_on_this_disk)?;
-        writer.write_u16::<LittleEndian>(self.number_of_files)?;
-        writer.write_u32::<LittleEndian>(self.central_directory_size)?;
-        writer.write_u32::<LittleEndian>(self.central_directory_offset)?;
-        writer.write_u16::<LittleEndian>(self.zip_file_comment.len() as u16)?;
-        writer.write_all(&self.zip_file_comment)?;
-        Ok(())
+    /// Get the name of the file, in the raw (internal) byte representation.
+    pub fn name_raw(&self) -> &[u8] {
+        self.0.name_raw()
     }
-}
 
-pub struct Zip64CentralDirectoryEndLocator {
-    pub disk_with_central_directory: u32,
-    pub end_of_central_directory_offset: u64,
-    pub number_of_disks: u32,
-}
+    /// Get the name of the file as a path, if it is safe to use. See
+    /// [`ZipFile::enclosed_name`](crate::read::ZipFile::enclosed_name).
+    pub fn enclosed_name(&self) -> Option<&Path> {
+        self.0.enclosed_name()
+    }
 
-impl Zip64CentralDirectoryEndLocator {
-    pub fn parse<T: Read>(reader: &mut T) -> ZipResult<Zip64CentralDirectoryEndLocator> {
-        let magic = reader.read_u32::<LittleEndian>()?;
-        if magic != ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIGNATURE {
-            return Err(ZipError::InvalidArchive(
-                "Invalid zip64 locator digital signature header",
-            ));
-        }
-        let disk_with_central_directory = reader.read_u32::<LittleEndian>()?;
-        let end_of_central_directory_offset = reader.read_u64::<LittleEndian>()?;
-        let number_of_disks = reader.read_u32::<LittleEndian>()?;
+    /// Returns whether the file is actually a directory
+    pub fn is_dir(&self) -> bool {
+        self.0.is_dir()
+    }
+
+    /// Get the comment of the file
+    pub fn comment(&self) -> &str {
+        &self.0.file_comment
+    }
+
+    /// Get the extra field of the central header
+    pub fn extra_data(&self) -> &[u8] {
+        &self.0.extra_field
+    }
+
+    /// Returns whether the file is encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.0.encrypted
+    }
+
+    /// Get unix mode for the file
+    pub fn unix_mode(&self) -> Option<u32> {
+        self.0.unix_mode()
+    }
+
+    /// Get the compression method used to store the file
+    pub fn compression(&self) -> CompressionMethod {
+        self.0.compression_method
+    }
+
+    /// Get the size of the file in the archive
+    pub fn compressed_size(&self) -> u64 {
+        self.0.compressed_size
+    }
+
+    /// Get the size of the file when uncompressed
+    pub fn size(&self) -> u64 {
+        self.0.uncompressed_size
+    }
+
+    /// Get the time the file was last modified
+    pub fn last_modified(&self) -> DateTime {
+        self.0.last_modified_time
+    }
 
-        Ok(Zip64CentralDirectoryEndLocator {
-            disk_with_central_directory,
-            end_of_central_directory_offset,
-            number_of_disks,
-        })
+    /// Get the CRC32 hash of the original file
+    pub fn crc32(&self) -> u32 {
+        self.0.crc32
     }
 
-    pub fn write<T: Write>(&self, writer: &mut T) -> ZipResult<()> {
-        writer.write_u32::<LittleEndian>(ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIGNATURE)?;
-        writer.write_u32::<LittleEndian>(self.disk_with_central_directory)?;
-        writer.write_u64::<LittleEndian>(self.end_of_central_directory_offset)?;
-        writer.write_u32::<LittleEndian>(self.number_of_disks)?;
-        Ok(())
+    /// Get the offset of the local header of the file, including the offset of the archive in
+    /// the reader
+    pub fn header_start(&self) -> u64 {
+        self.0.header_start
+    }
+
+    /// Get the offset of this header in the reader
+    pub fn central_header_start(&self) -> u64 {
+        self.0.central_header_start
     }
 }
 
-pub struct Zip64CentralDirectoryEnd {
-    pub version_made_by: u16,
-    pub version_needed_to_extract: u16,
-    pub disk_number: u32,
-    pub disk_with_central_directory: u32,
-    pub number_of_files_on_this_disk: u64,
-    pub number_of_files: u64,
-    pub central_directory_size: u64,
-    pub central_directory_offset: u64,
-    //pub extensible_data_sector: Vec<u8>, <-- We don't do anything with this at the moment.
+/// Parse every header of the central directory, continuing after headers that are broken.
+///
+/// The central directory is found like [`ZipArchive::new`] does, and its location is checked
+/// like a permissive [`Config`](crate::read::Config) does. After a broken header, parsing
+/// resumes at the next central header signature. Headers are read up to the end of central
+/// directory record, regardless of the number of files it claims. If the central directory
+/// can't be found at all, the result holds just that error.
+///
+/// This doesn't panic on any input.
+pub fn parse_central_directory<R: Read + io::Seek>(
+    reader: &mut R,
+) -> Vec<Result<CentralHeader, ParseError>> {
+    let mut results = Vec::new();
+    if let Err(error) = parse_headers(reader, &mut results) {
+        results.push(Err(ParseError {
+            offset: None,
+            error,
+        }));
+    }
+    results
 }
 
-impl Zip64CentralDirectoryEnd {
-    pub fn find_and_parse<T: Read + io::Seek>(
-        reader: &mut T,
-        nominal_offset: u64,
-        search_upper_bound: u64,
-    ) -> ZipResult<(Zip64CentralDirectoryEnd, u64)> {
-        let mut pos = nominal_offset;
-
-        while pos <= search_upper_bound {
-            reader.seek(io::SeekFrom::Start(pos))?;
-
-            if reader.read_u32::<LittleEndian>()? == ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE {
-                let archive_offset = pos - nominal_offset;
-
-                let _record_size = reader.read_u64::<LittleEndian>()?;
-                // We would use this value if we did anything with the "zip64 extensible data sector".
-
-                let version_made_by = reader.read_u16::<LittleEndian>()?;
-                let version_needed_to_extract = reader.read_u16::<LittleEndian>()?;
-                let disk_number = reader.read_u32::<LittleEndian>()?;
-                let disk_with_central_directory = reader.read_u32::<LittleEndian>()?;
-                let number_of_files_on_this_disk = reader.read_u64::<LittleEndian>()?;
-                let number_of_files = reader.read_u64::<LittleEndian>()?;
-                let central_directory_size = reader.read_u64::<LittleEndian>()?;
-                let central_directory_offset = reader.read_u64::<LittleEndian>()?;
-
-                return Ok((
-                    Zip64CentralDirectoryEnd {
-                        version_made_by,
-                        version_needed_to_extract,
-                        disk_number,
-                        disk_with_central_directory,
-                        number_of_files_on_this_disk,
-                        number_of_files,
-                        central_directory_size,
-                        central_directory_offset,
-                    },
-                    archive_offset,
-                ));
+fn parse_headers<R: Read + io::Seek>(
+    reader: &mut R,
+    results: &mut Vec<Result<CentralHeader, ParseError>>,
+) -> ZipResult<()> {
+    let (footer, cde_start_pos) = CentralDirectoryEnd::find_and_parse(reader)?;
+    let counts = ZipArchive::get_directory_counts(reader, &footer, cde_start_pos);
+    let (archive_offset, directory_start, _) =
+        ZipArchive::locate_central_directory(reader, &footer, cde_start_pos, counts)?;
+
+    let mut position = directory_start;
+    while position < cde_start_pos {
+        reader.seek(io::SeekFrom::Start(position))?;
+        match central_header_to_zip_file(reader, archive_offset, &mut Limits::default()) {
+            Ok(file) => {
+                results.push(Ok(CentralHeader(file)));
+                position = reader.seek(io::SeekFrom::Current(0))?;
+            }
+            Err(error) => {
+                results.push(Err(ParseError {
+                    offset: Some(position),
+                    error: error.at_offset(position),
+                }));
+                match find_central_header(reader, position + 1, cde_start_pos)? {
+                    Some(next) => position = next,
+                    None => break,
+                }
             }
-
-            pos += 1;
         }
+    }
+    Ok(())
+}
 
-        Err(ZipError::InvalidArchive(
-            "Could not find ZIP64 central directory end",
-        ))
-    }
-
-    pub fn write<T: Write>(&self, writer: &mut T) -> ZipResult<()> {
-        writer.write_u32::<LittleEndian>(ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE)?;
-        writer.write_u64::<LittleEndian>(44)?; // record size
-        writer.write_u16::<LittleEndian>(self.version_made_by)?;
-        writer.write_u16::<LittleEndian>(self.version_needed_to_extract)?;
-        writer.write_u32::<LittleEndian>(self.disk_number)?;
-        writer.write_u32::<LittleEndian>(self.disk_with_central_directory)?;
-        writer.write_u64::<LittleEndian>(self.number_of_files_on_this_disk)?;
-        writer.write_u64::<LittleEndian>(self.number_of_files)?;
-        writer.write_u64::<LittleEndian>(self.central_directory_size)?;
-        writer.write_u64::<LittleEndian>(self.central_directory_offset)?;
-        Ok(())
+#[cfg(test)]
+mod test {
+    use super::parse_central_directory;
+    use crate::write::{FileOptions, ZipWriter};
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn parse_broken_central_directory() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for name in &["first.txt", "second.txt", "third.txt"] {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(b"contents").unwrap();
+        }
+        let mut v = writer.finish().unwrap().into_inner();
+
+        // Break the signature of the second central header
+        let headers: Vec<_> = v
+            .windows(4)
+            .enumerate()
+            .filter(|(_, w)| *w == b"PK\x01\x02")
+            .map(|(i, _)| i)
+            .collect();
+        v[headers[1]] = 0;
+
+        let results = parse_central_directory(&mut Cursor::new(&v));
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().name(), "first.txt");
+        assert_eq!(
+            results[1].as_ref().unwrap_err().offset,
+            Some(headers[1] as u64)
+        );
+        assert_eq!(results[2].as_ref().unwrap().name(), "third.txt");
+        assert_eq!(
+            results[2].as_ref().unwrap().central_header_start(),
+            headers[2] as u64
+        );
+
+        // Garbage never panics
+        for len in 0..v.len() {
+            parse_central_directory(&mut Cursor::new(&v[..len]));
+        }
+        let results = parse_central_directory(&mut Cursor::new(b"not a zip file"));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_ref().unwrap_err().offset, None);
     }
 }
@@ -1,13 +1,18 @@
 //! Types that specify what is contained in a ZIP.
 
+/// The system a file was added to an archive on, which determines how its attributes are stored
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum System {
+    /// MS-DOS and Windows, which store attributes like the read-only and directory flags
     Dos = 0,
+    /// Unix, which stores the permissions and file type
     Unix = 3,
+    /// Any other system
     Unknown,
 }
 
 impl System {
+    /// Converts the high byte of the version made by field to a System
     pub fn from_u8(system: u8) -> System {
         use self::System::*;
 
@@ -32,7 +37,9 @@ impl System {
 /// Some utilities use alternative timestamps to improve the accuracy of their
 /// ZIPs, but we don't parse them yet. [We're working on this](https://github.com/zip-rs/zip/issues/156#issuecomment-652981904),
 /// however this API shouldn't be considered complete.
-#[derive(Debug, Clone, Copy)]
+///
+/// Comparing two DateTimes compares them chronologically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct DateTime {
     year: u16,
     month: u8,
@@ -62,12 +69,12 @@ impl DateTime {
         let seconds = (timepart & 0b0000000000011111) << 1;
         let minutes = (timepart & 0b0000011111100000) >> 5;
         let hours = (timepart & 0b1111100000000000) >> 11;
-        let days = (datepart & 0b0000000000011111) >> 0;
+        let days = datepart & 0b0000000000011111;
         let months = (datepart & 0b0000000111100000) >> 5;
         let years = (datepart & 0b1111111000000000) >> 9;
 
         DateTime {
-            year: (years + 1980) as u16,
+            year: years + 1980,
             month: months as u8,
             day: days as u8,
             hour: hours as u8,
@@ -81,10 +88,11 @@ impl DateTime {
     /// The bounds are:
     /// * year: [1980, 2107]
     /// * month: [1, 12]
-    /// * day: [1, 31]
+    /// * day: [1, 31], and no later than the last day of the month
     /// * hour: [0, 23]
     /// * minute: [0, 59]
     /// * second: [0, 60]
+    #[allow(clippy::result_unit_err)]
     pub fn from_date_and_time(
         year: u16,
         month: u8,
@@ -93,33 +101,102 @@ impl DateTime {
         minute: u8,
         second: u8,
     ) -> Result<DateTime, ()> {
-        if year >= 1980
-            && year <= 2107
-            && month >= 1
-            && month <= 12
-            && day >= 1
-            && day <= 31
-            && hour <= 23
-            && minute <= 59
-            && second <= 60
-        {
-            Ok(DateTime {
-                year,
-                month,
-                day,
-                hour,
-                minute,
-                second,
-            })
+        let datetime = DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        };
+        if datetime.is_valid() {
+            Ok(datetime)
         } else {
             Err(())
         }
     }
 
+    /// Returns whether this is an existing date and time that can be stored in a zip file
+    ///
+    /// This is always true for a DateTime constructed from a date and time, but not necessarily
+    /// for one read from a zip file.
+    pub fn is_valid(&self) -> bool {
+        let days_in_month = match self.month {
+            2 if self.year % 4 == 0 && (self.year % 100 != 0 || self.year % 400 == 0) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        };
+        (1980..=2107).contains(&self.year)
+            && (1..=12).contains(&self.month)
+            && (1..=days_in_month).contains(&self.day)
+            && self.hour <= 23
+            && self.minute <= 59
+            && self.second <= 60
+    }
+
+    /// Converts a unix timestamp, the number of seconds since 1970-01-01 00:00:00 UTC, to a
+    /// DateTime in UTC
+    ///
+    /// Returns `Err` when the timestamp is before 1980 or after 2107.
+    #[allow(clippy::result_unit_err)]
+    pub fn from_time_t(timestamp: i64) -> Result<DateTime, ()> {
+        // 1980-01-01 00:00:00
+        if timestamp < 315_532_800 {
+            return Err(());
+        }
+        let timestamp = timestamp as u64;
+        let seconds = timestamp % 86400;
+        let days = timestamp / 86400;
+
+        // Convert the days since 1970-01-01 to a date, using Howard Hinnant's `civil_from_days`
+        let days = days + 719_468;
+        let era = days / 146_097;
+        let day_of_era = days % 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153; // March is 0
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        if year > 2107 {
+            return Err(());
+        }
+
+        DateTime::from_date_and_time(
+            year as u16,
+            month as u8,
+            day as u8,
+            (seconds / 3600) as u8,
+            (seconds / 60 % 60) as u8,
+            (seconds % 60) as u8,
+        )
+    }
+
+    /// Converts the DateTime, taken to be in UTC, to a unix timestamp
+    pub(crate) fn to_time_t(self) -> i64 {
+        // Howard Hinnant's `days_from_civil`, the inverse of the conversion above
+        let month = self.month as i64;
+        let year = self.year as i64 - if month <= 2 { 1 } else { 0 };
+        let era = year / 400;
+        let year_of_era = year - era * 400;
+        let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * shifted_month + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+        days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+
     #[cfg(feature = "time")]
     /// Converts a ::time::Tm object to a DateTime
     ///
     /// Returns `Err` when this object is out of bounds
+    #[allow(clippy::result_unit_err)]
     pub fn from_time(tm: ::time::Tm) -> Result<DateTime, ()> {
         if tm.tm_year >= 80
             && tm.tm_year <= 207
@@ -147,6 +224,13 @@ impl DateTime {
         }
     }
 
+    /// Converts the datetime to an msdos (u16, u16) pair, the inverse of [`DateTime::from_msdos`]
+    ///
+    /// Seconds are rounded down to an even number.
+    pub fn to_msdos(&self) -> (u16, u16) {
+        (self.datepart(), self.timepart())
+    }
+
     /// Gets the time portion of this datetime in the msdos representation
     pub fn timepart(&self) -> u16 {
         ((self.second as u16) >> 1) | ((self.minute as u16) << 5) | ((self.hour as u16) << 11)
@@ -205,8 +289,50 @@ impl DateTime {
     }
 }
 
+#[cfg(feature = "chrono")]
+impl ::std::convert::TryFrom<chrono::NaiveDateTime> for DateTime {
+    type Error = ();
+
+    /// Converts a chrono::NaiveDateTime, failing if it is out of the range of a DateTime
+    fn try_from(value: chrono::NaiveDateTime) -> Result<DateTime, ()> {
+        use chrono::{Datelike, Timelike};
+
+        if !(1980..=2107).contains(&value.year()) {
+            return Err(());
+        }
+        DateTime::from_date_and_time(
+            value.year() as u16,
+            value.month() as u8,
+            value.day() as u8,
+            value.hour() as u8,
+            value.minute() as u8,
+            value.second() as u8,
+        )
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl ::std::convert::TryFrom<DateTime> for chrono::NaiveDateTime {
+    type Error = ();
+
+    /// Converts to a chrono::NaiveDateTime, failing for invalid dates read from an archive
+    fn try_from(value: DateTime) -> Result<chrono::NaiveDateTime, ()> {
+        chrono::NaiveDate::from_ymd_opt(value.year as i32, value.month as u32, value.day as u32)
+            .and_then(|date| {
+                date.and_hms_opt(value.hour as u32, value.minute as u32, value.second as u32)
+            })
+            .ok_or(())
+    }
+}
+
 pub const DEFAULT_VERSION: u8 = 46;
 
+/// MS-DOS attributes, in the low byte of the external attributes of a file
+#[cfg(windows)]
+pub(crate) const DOS_READ_ONLY: u32 = 0x01;
+pub(crate) const DOS_HIDDEN: u32 = 0x02;
+pub(crate) const DOS_SYSTEM: u32 = 0x04;
+
 /// Structure representing a ZIP file.
 #[derive(Debug, Clone)]
 pub struct ZipFileData {
@@ -214,6 +340,8 @@ pub struct ZipFileData {
     pub system: System,
     /// Specification version
     pub version_made_by: u8,
+    /// Version of the specification needed to extract the file, as stored in the archive
+    pub version_to_extract: u16,
     /// True if the file is encrypted.
     pub encrypted: bool,
     /// True if the file uses a data-descriptor section
@@ -231,6 +359,8 @@ pub struct ZipFileData {
     /// Name of the file
     pub file_name: String,
     /// Raw file name. To be used when file_name was incorrectly decoded.
+    ///
+    /// This is empty if the raw name is the same as the UTF-8 encoding of `file_name`.
     pub file_name_raw: Vec<u8>,
     /// Extra field usually used for storage expansion
     pub extra_field: Vec<u8>,
@@ -238,58 +368,137 @@ pub struct ZipFileData {
     pub file_comment: String,
     /// Specifies where the local header of the file starts
     pub header_start: u64,
+    /// Number of the disk the local header is on, for archives split over several files
+    pub disk_number: u32,
     /// Specifies where the central header of the file starts
     ///
     /// Note that when this is not known, it is set to 0
     pub central_header_start: u64,
     /// Specifies where the compressed data of the file starts
     pub data_start: u64,
+    /// Internal file attributes, of which only the text flag is defined
+    pub internal_attributes: u16,
     /// External file attributes
     pub external_attributes: u32,
     /// Reserve local ZIP64 extra field
     pub large_file: bool,
+    /// True if the file was found by scanning for local file headers, rather than through the
+    /// central directory
+    pub recovered: bool,
+    /// General purpose bit flags, as stored in the archive
+    pub flags: u16,
 }
 
 impl ZipFileData {
-    pub fn file_name_sanitized(&self) -> ::std::path::PathBuf {
-        let no_null_filename = match self.file_name.find('\0') {
-            Some(index) => &self.file_name[0..index],
-            None => &self.file_name,
+    pub(crate) fn file_name_sanitized(&self) -> ::std::path::PathBuf {
+        mangle_name(&self.file_name)
+    }
+
+    /// Memory allocated for the variable-length fields, in bytes
+    pub(crate) fn heap_size(&self) -> usize {
+        self.file_name.capacity()
+            + self.file_name_raw.capacity()
+            + self.extra_field.capacity()
+            + self.file_comment.capacity()
+    }
+
+    /// Raw file name, see `file_name_raw`
+    pub fn name_raw(&self) -> &[u8] {
+        if self.file_name_raw.is_empty() {
+            self.file_name.as_bytes()
+        } else {
+            &self.file_name_raw
+        }
+    }
+
+    /// Get the name of the file as a path, if it is safe to use. See
+    /// [`ZipFile::enclosed_name`](crate::read::ZipFile::enclosed_name).
+    pub fn enclosed_name(&self) -> Option<&::std::path::Path> {
+        if self.file_name.contains('\0') {
+            return None;
+        }
+        let path = ::std::path::Path::new(&self.file_name);
+        if is_enclosed(path) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether the file is actually a directory, see
+    /// [`ZipFile::is_dir`](crate::read::ZipFile::is_dir)
+    pub fn is_dir(&self) -> bool {
+        use crate::read::ffi;
+
+        if self
+            .file_name
+            .chars()
+            .next_back()
+            .map_or(false, |c| c == '/' || c == '\\')
+        {
+            return true;
+        }
+        // Files with data aren't directories, whatever their attributes say
+        if self.uncompressed_size != 0 {
+            return false;
+        }
+        match self.system {
+            // Info-ZIP keeps the MS-DOS attributes in the low byte on unix as well
+            System::Unix if self.external_attributes >> 16 == 0 => {
+                self.external_attributes & 0x10 != 0
+            }
+            _ => self
+                .unix_mode()
+                .map_or(false, |mode| mode & 0o170000 == ffi::S_IFDIR),
+        }
+    }
+
+    /// Get unix mode for the file, if the external attributes hold one
+    pub fn unix_mode(&self) -> Option<u32> {
+        use crate::read::ffi;
+
+        if self.external_attributes == 0 {
+            return None;
+        }
+
+        match self.system {
+            System::Unix => Some(self.external_attributes >> 16),
+            System::Dos => {
+                // Interpret MSDOS directory bit
+                let mut mode = if 0x10 == (self.external_attributes & 0x10) {
+                    ffi::S_IFDIR | 0o0775
+                } else {
+                    ffi::S_IFREG | 0o0664
+                };
+                if 0x01 == (self.external_attributes & 0x01) {
+                    // Read-only bit; strip write permissions
+                    mode &= !0o0222;
+                }
+                Some(mode)
+            }
+            _ => None,
         }
-        .to_string();
-
-        // zip files can contain both / and \ as separators regardless of the OS
-        // and as we want to return a sanitized PathBuf that only supports the
-        // OS separator let's convert incompatible separators to compatible ones
-        let separator = ::std::path::MAIN_SEPARATOR;
-        let opposite_separator = match separator {
-            '/' => '\\',
-            _ => '/',
-        };
-        let filename =
-            no_null_filename.replace(&opposite_separator.to_string(), &separator.to_string());
-
-        ::std::path::Path::new(&filename)
-            .components()
-            .filter(|component| match *component {
-                ::std::path::Component::Normal(..) => true,
-                _ => false,
-            })
-            .fold(::std::path::PathBuf::new(), |mut path, ref cur| {
-                path.push(cur.as_os_str());
-                path
-            })
     }
 
-    pub fn zip64_extension(&self) -> bool {
+    /// Get the unix user and group ids of the file, from an Info-ZIP Unix extra field
+    pub fn unix_owner(&self) -> Option<(u32, u32)> {
+        find_unix_owner(&self.extra_field)
+    }
+
+    pub(crate) fn zip64_extension(&self) -> bool {
         self.uncompressed_size > 0xFFFFFFFF
             || self.compressed_size > 0xFFFFFFFF
             || self.header_start > 0xFFFFFFFF
     }
 
-    pub fn version_needed(&self) -> u16 {
+    /// Version of the specification needed to extract the file as it is written
+    pub(crate) fn version_needed(&self) -> u16 {
         // higher versions matched first
-        match (self.zip64_extension(), self.compression_method) {
+        // A reserved ZIP64 extra field needs 4.5 even if the sizes turn out small
+        match (
+            self.zip64_extension() || self.large_file,
+            self.compression_method,
+        ) {
             #[cfg(feature = "bzip2")]
             (_, crate::compression::CompressionMethod::Bzip2) => 46,
             (true, _) => 45,
@@ -298,6 +507,115 @@ impl ZipFileData {
     }
 }
 
+/// Turn a file name into a relative path, see
+/// [`ZipFile::mangled_name`](crate::read::ZipFile::mangled_name)
+pub(crate) fn mangle_name(name: &str) -> ::std::path::PathBuf {
+    let no_null_filename = match name.find('\0') {
+        Some(index) => &name[0..index],
+        None => name,
+    }
+    .to_string();
+
+    // zip files can contain both / and \ as separators regardless of the OS
+    // and as we want to return a sanitized PathBuf that only supports the
+    // OS separator let's convert incompatible separators to compatible ones
+    let separator = ::std::path::MAIN_SEPARATOR;
+    let opposite_separator = match separator {
+        '/' => '\\',
+        _ => '/',
+    };
+    let filename =
+        no_null_filename.replace(&opposite_separator.to_string(), &separator.to_string());
+
+    ::std::path::Path::new(&filename)
+        .components()
+        .filter(|component| match *component {
+            ::std::path::Component::Normal(..) => true,
+            _ => false,
+        })
+        .fold(::std::path::PathBuf::new(), |mut path, ref cur| {
+            path.push(cur.as_os_str());
+            path
+        })
+}
+
+/// Whether `path` is relative and doesn't lead out of the directory it is relative to
+pub(crate) fn is_enclosed(path: &::std::path::Path) -> bool {
+    use std::path::Component;
+
+    let mut depth = 0usize;
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => return false,
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(parent) => depth = parent,
+                None => return false,
+            },
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => (),
+        }
+    }
+    true
+}
+
+/// Find the user and group ids in the Info-ZIP Unix extra field ("ux") of `extra_field`
+pub(crate) fn find_unix_owner(mut extra_field: &[u8]) -> Option<(u32, u32)> {
+    use byteorder::{ByteOrder, LittleEndian};
+
+    const UNIX_EXTRA_FIELD_ID: u16 = 0x7875;
+
+    // Ids are stored little endian, in as many bytes as the size before them says
+    fn read_id(data: &mut &[u8]) -> Option<u32> {
+        let (&size, rest) = data.split_first()?;
+        let bytes = rest.get(..size as usize)?;
+        *data = &rest[size as usize..];
+        let mut id = 0;
+        for (i, &byte) in bytes.iter().enumerate() {
+            if i < 4 {
+                id |= (byte as u32) << (8 * i);
+            } else if byte != 0 {
+                return None;
+            }
+        }
+        Some(id)
+    }
+
+    while extra_field.len() >= 4 {
+        let kind = LittleEndian::read_u16(&extra_field[0..]);
+        let len = LittleEndian::read_u16(&extra_field[2..]) as usize;
+        let mut data = extra_field.get(4..4 + len)?;
+        // Only version 1 of the field exists
+        if kind == UNIX_EXTRA_FIELD_ID && data.first() == Some(&1) {
+            data = &data[1..];
+            let uid = read_id(&mut data)?;
+            let gid = read_id(&mut data)?;
+            return Some((uid, gid));
+        }
+        extra_field = &extra_field[4 + len..];
+    }
+    None
+}
+
+/// ID of the PKWARE Unix extra field, which can hold the target of a link
+pub(crate) const PKWARE_UNIX_EXTRA_FIELD_ID: u16 = 0x000d;
+
+/// Find the target of a link in the PKWARE Unix extra field of `extra_field`
+pub(crate) fn find_link_target(mut extra_field: &[u8]) -> Option<&[u8]> {
+    use byteorder::{ByteOrder, LittleEndian};
+
+    while extra_field.len() >= 4 {
+        let kind = LittleEndian::read_u16(&extra_field[0..]);
+        let len = LittleEndian::read_u16(&extra_field[2..]) as usize;
+        let data = extra_field.get(4..4 + len)?;
+        // The access and modification times, user and group ids come first
+        if kind == PKWARE_UNIX_EXTRA_FIELD_ID && data.len() > 12 {
+            return Some(&data[12..]);
+        }
+        extra_field = &extra_field[4 + len..];
+    }
+    None
+}
+
 #[cfg(test)]
 mod test {
     #[test]
@@ -316,6 +634,7 @@ mod test {
         let data = ZipFileData {
             system: System::Dos,
             version_made_by: 0,
+            version_to_extract: 0,
             encrypted: false,
             using_data_descriptor: false,
             compression_method: crate::compression::CompressionMethod::Stored,
@@ -328,10 +647,14 @@ mod test {
             extra_field: Vec::new(),
             file_comment: String::new(),
             header_start: 0,
+            disk_number: 0,
             data_start: 0,
             central_header_start: 0,
+            internal_attributes: 0,
             external_attributes: 0,
             large_file: false,
+            recovered: false,
+            flags: 0,
         };
         assert_eq!(
             data.file_name_sanitized(),
@@ -340,6 +663,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::unusual_byte_groupings)]
     fn datetime_default() {
         use super::DateTime;
         let dt = DateTime::default();
@@ -348,6 +672,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::unusual_byte_groupings)]
     fn datetime_max() {
         use super::DateTime;
         let dt = DateTime::from_date_and_time(2107, 12, 31, 23, 59, 60).unwrap();
@@ -372,6 +697,11 @@ mod test {
         assert!(DateTime::from_date_and_time(2108, 12, 31, 0, 0, 0).is_err());
         assert!(DateTime::from_date_and_time(2107, 13, 31, 0, 0, 0).is_err());
         assert!(DateTime::from_date_and_time(2107, 12, 32, 0, 0, 0).is_err());
+        assert!(DateTime::from_date_and_time(2000, 2, 29, 0, 0, 0).is_ok());
+        assert!(DateTime::from_date_and_time(2004, 2, 29, 0, 0, 0).is_ok());
+        assert!(DateTime::from_date_and_time(2100, 2, 29, 0, 0, 0).is_err());
+        assert!(DateTime::from_date_and_time(2001, 2, 29, 0, 0, 0).is_err());
+        assert!(DateTime::from_date_and_time(2001, 4, 31, 0, 0, 0).is_err());
     }
 
     #[cfg(feature = "time")]
@@ -438,6 +768,8 @@ mod test {
         assert_eq!(dt.hour(), 10);
         assert_eq!(dt.minute(), 38);
         assert_eq!(dt.second(), 30);
+        assert!(dt.is_valid());
+        assert_eq!(dt.to_msdos(), (0x4D71, 0x54CF));
 
         #[cfg(feature = "time")]
         assert_eq!(
@@ -450,6 +782,7 @@ mod test {
     fn time_out_of_bounds() {
         use super::DateTime;
         let dt = DateTime::from_msdos(0xFFFF, 0xFFFF);
+        assert!(!dt.is_valid());
         assert_eq!(dt.year(), 2107);
         assert_eq!(dt.month(), 15);
         assert_eq!(dt.day(), 31);
@@ -478,6 +811,79 @@ mod test {
         );
     }
 
+    #[test]
+    fn datetime_to_time_t() {
+        use super::DateTime;
+
+        for &timestamp in [315_532_800, 951_782_400, 1_234_567_890, 4_354_819_199].iter() {
+            assert_eq!(
+                DateTime::from_time_t(timestamp).unwrap().to_time_t(),
+                timestamp
+            );
+        }
+    }
+
+    #[test]
+    fn datetime_from_time_t() {
+        use super::DateTime;
+
+        let dt = DateTime::from_time_t(1_542_451_110).unwrap();
+        assert_eq!(
+            dt,
+            DateTime::from_date_and_time(2018, 11, 17, 10, 38, 30).unwrap()
+        );
+        let dt = DateTime::from_time_t(951_825_600).unwrap();
+        assert_eq!(
+            dt,
+            DateTime::from_date_and_time(2000, 2, 29, 12, 0, 0).unwrap()
+        );
+        assert_eq!(DateTime::from_time_t(315_532_800), Ok(DateTime::default()));
+        let dt = DateTime::from_time_t(4_354_819_199).unwrap();
+        assert_eq!(
+            dt,
+            DateTime::from_date_and_time(2107, 12, 31, 23, 59, 59).unwrap()
+        );
+
+        assert!(DateTime::from_time_t(315_532_799).is_err());
+        assert!(DateTime::from_time_t(4_354_819_200).is_err());
+        assert!(DateTime::from_time_t(-1).is_err());
+    }
+
+    #[test]
+    fn datetime_order() {
+        use super::DateTime;
+
+        let earlier = DateTime::from_date_and_time(2018, 11, 17, 10, 38, 30).unwrap();
+        let later = DateTime::from_date_and_time(2018, 12, 1, 0, 0, 0).unwrap();
+        assert!(earlier < later);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono_conversion() {
+        use super::DateTime;
+        use std::convert::TryFrom;
+
+        let naive = chrono::NaiveDate::from_ymd_opt(2018, 11, 17)
+            .unwrap()
+            .and_hms_opt(10, 38, 30)
+            .unwrap();
+        let dt = DateTime::try_from(naive).unwrap();
+        assert_eq!(
+            dt,
+            DateTime::from_date_and_time(2018, 11, 17, 10, 38, 30).unwrap()
+        );
+        assert_eq!(chrono::NaiveDateTime::try_from(dt), Ok(naive));
+
+        let too_early = chrono::NaiveDate::from_ymd_opt(1979, 12, 31)
+            .unwrap()
+            .and_hms_opt(23, 59, 59)
+            .unwrap();
+        assert!(DateTime::try_from(too_early).is_err());
+        let invalid = DateTime::from_msdos(0x0000, 0x0000);
+        assert!(chrono::NaiveDateTime::try_from(invalid).is_err());
+    }
+
     #[cfg(feature = "time")]
     #[test]
     fn time_at_january() {
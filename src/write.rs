@@ -1,11 +1,15 @@
 //! Types for creating ZIP archives
 
 use crate::compression::CompressionMethod;
-use crate::read::{central_header_to_zip_file, ZipArchive, ZipFile};
-use crate::result::{ZipError, ZipResult};
+use crate::format;
+use crate::read::{
+    central_header_follows, central_header_to_zip_file, find_apk_signing_block, Limits, ZipArchive,
+    ZipFile,
+};
+use crate::result::{InvalidArchiveKind, ZipError, ZipResult};
 use crate::spec;
 use crate::types::{DateTime, System, ZipFileData, DEFAULT_VERSION};
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
 use crc32fast::Hasher;
 use std::default::Default;
 use std::io;
@@ -17,10 +21,22 @@ use std::mem;
     feature = "deflate-miniz",
     feature = "deflate-zlib"
 ))]
-use flate2::write::DeflateEncoder;
+use flate2::write::{DeflateDecoder, DeflateEncoder};
 
 #[cfg(feature = "bzip2")]
-use bzip2::write::BzEncoder;
+use bzip2::write::{BzDecoder, BzEncoder};
+
+mod builder;
+pub use self::builder::{WalkOptions, ZipBuilder};
+mod dedup;
+pub use self::dedup::Deduplicate;
+use self::dedup::{ContentHasher, Deduplicator};
+mod editor;
+pub use self::editor::ZipEditor;
+mod repack;
+pub use self::repack::{repack, RepackOptions};
+mod tree;
+pub use self::tree::{SourceTree, TreeEntry};
 
 enum GenericZipWriter<W: Write + io::Seek> {
     Closed,
@@ -30,11 +46,42 @@ enum GenericZipWriter<W: Write + io::Seek> {
         feature = "deflate-miniz",
         feature = "deflate-zlib"
     ))]
-    Deflater(DeflateEncoder<W>),
+    Deflater(DeflateEncoder<Priming<W>>),
     #[cfg(feature = "bzip2")]
     Bzip2(BzEncoder<W>),
 }
 
+/// Writer that drops everything while `discard` is set, so that a compressor can be primed with
+/// a preset dictionary without writing the compressed dictionary
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate-miniz",
+    feature = "deflate-zlib"
+))]
+struct Priming<W> {
+    inner: W,
+    discard: bool,
+}
+
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate-miniz",
+    feature = "deflate-zlib"
+))]
+impl<W: Write> Write for Priming<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.discard {
+            Ok(buf.len())
+        } else {
+            self.inner.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 /// ZIP archive generator
 ///
 /// Handles the bookkeeping involved in building an archive, and provides an
@@ -71,12 +118,114 @@ pub struct ZipWriter<W: Write + io::Seek> {
     writing_to_extra_field: bool,
     writing_to_central_extra_field_only: bool,
     writing_raw: bool,
+    // The ZIP64 extra field of the current file was only reserved, see
+    // `ZipWriter::add_file_from_reader`
+    reserved_zip64: bool,
     comment: Vec<u8>,
+    split_size: Option<u64>,
+    deterministic: bool,
+    central_directory_start: Option<u64>,
+    dictionary: Option<Vec<u8>>,
+    buffered: Option<BufferedFile>,
+    verify_raw_data: bool,
+    raw_verifier: Option<RawVerifier>,
+    apk_signing_block: Option<Vec<u8>>,
+    dedup: Deduplicator,
+}
+
+/// File that is compressed in memory, see [`FileOptions::store_incompressible`]
+struct BufferedFile {
+    data: Vec<u8>,
+    compressed: GenericZipWriter<io::Cursor<Vec<u8>>>,
+}
+
+/// Decompresses raw data as it is written, to check its CRC32 and size, see
+/// [`ZipWriter::set_verify_raw_data`]
+enum RawVerifier {
+    Stored(RawChecksum),
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    ))]
+    Deflated(DeflateDecoder<RawChecksum>),
+    #[cfg(feature = "bzip2")]
+    Bzip2(BzDecoder<RawChecksum>),
+}
+
+#[derive(Default)]
+struct RawChecksum {
+    hasher: Hasher,
+    size: u64,
+}
+
+impl Write for RawChecksum {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.hasher.update(buf);
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl RawVerifier {
+    fn new(method: CompressionMethod) -> ZipResult<RawVerifier> {
+        #[allow(deprecated)]
+        match method {
+            CompressionMethod::Stored => Ok(RawVerifier::Stored(RawChecksum::default())),
+            #[cfg(any(
+                feature = "deflate",
+                feature = "deflate-miniz",
+                feature = "deflate-zlib"
+            ))]
+            CompressionMethod::Deflated => Ok(RawVerifier::Deflated(DeflateDecoder::new(
+                RawChecksum::default(),
+            ))),
+            #[cfg(feature = "bzip2")]
+            CompressionMethod::Bzip2 => {
+                Ok(RawVerifier::Bzip2(BzDecoder::new(RawChecksum::default())))
+            }
+            CompressionMethod::Unsupported(id) => Err(ZipError::UnsupportedCompression(id)),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match *self {
+            RawVerifier::Stored(ref mut w) => w.write_all(buf),
+            #[cfg(any(
+                feature = "deflate",
+                feature = "deflate-miniz",
+                feature = "deflate-zlib"
+            ))]
+            RawVerifier::Deflated(ref mut w) => w.write_all(buf),
+            #[cfg(feature = "bzip2")]
+            RawVerifier::Bzip2(ref mut w) => w.write_all(buf),
+        }
+    }
+
+    fn finish(self) -> io::Result<RawChecksum> {
+        match self {
+            RawVerifier::Stored(w) => Ok(w),
+            #[cfg(any(
+                feature = "deflate",
+                feature = "deflate-miniz",
+                feature = "deflate-zlib"
+            ))]
+            RawVerifier::Deflated(w) => w.finish(),
+            #[cfg(feature = "bzip2")]
+            RawVerifier::Bzip2(mut w) => w.finish(),
+        }
+    }
 }
 
 #[derive(Default)]
 struct ZipWriterStats {
     hasher: Hasher,
+    // Hash of the contents to tell duplicates apart with, if they are looked for
+    content_hasher: Option<Box<dyn ContentHasher>>,
     start: u64,
     bytes_written: u64,
 }
@@ -87,6 +236,82 @@ struct ZipRawValues {
     uncompressed_size: u64,
 }
 
+/// Sizes and checksum of a file that was written, returned by [`ZipWriter::finish_file`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryStats {
+    uncompressed_size: u64,
+    compressed_size: u64,
+    crc32: u32,
+}
+
+impl EntryStats {
+    /// Get the size of the file when uncompressed
+    pub fn size(&self) -> u64 {
+        self.uncompressed_size
+    }
+
+    /// Get the size of the file in the archive
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    /// Get the CRC32 hash of the original file
+    pub fn crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    /// Get the compressed size divided by the uncompressed size, or 1 for an empty file
+    pub fn compression_ratio(&self) -> f64 {
+        compression_ratio(self.compressed_size, self.uncompressed_size)
+    }
+}
+
+/// Totals over all files written so far, returned by [`ZipWriter::stats`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ArchiveStats {
+    files: usize,
+    uncompressed_size: u64,
+    compressed_size: u64,
+    central_directory_offset: Option<u64>,
+}
+
+impl ArchiveStats {
+    /// Get the number of files and directories in the archive
+    pub fn files(&self) -> usize {
+        self.files
+    }
+
+    /// Get the total size of the files when uncompressed
+    pub fn size(&self) -> u64 {
+        self.uncompressed_size
+    }
+
+    /// Get the total size of the files in the archive, without headers
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    /// Get the compressed size divided by the uncompressed size, or 1 for an empty archive
+    pub fn compression_ratio(&self) -> f64 {
+        compression_ratio(self.compressed_size, self.uncompressed_size)
+    }
+
+    /// Get the offset of the central directory in the output, once the archive is finished
+    ///
+    /// For a split archive, this is the offset in all segments put together.
+    pub fn central_directory_offset(&self) -> Option<u64> {
+        self.central_directory_offset
+    }
+}
+
+fn compression_ratio(compressed_size: u64, uncompressed_size: u64) -> f64 {
+    if uncompressed_size == 0 {
+        1.0
+    } else {
+        compressed_size as f64 / uncompressed_size as f64
+    }
+}
+
 /// Metadata for a file to be written
 #[derive(Copy, Clone)]
 pub struct FileOptions {
@@ -94,10 +319,16 @@ pub struct FileOptions {
     last_modified_time: DateTime,
     permissions: Option<u32>,
     large_file: bool,
+    alignment: u16,
+    text_file: bool,
+    system: System,
+    dos_attributes: u32,
+    store_incompressible: bool,
 }
 
 impl FileOptions {
     /// Construct a new FileOptions object
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> FileOptions {
         FileOptions {
             #[cfg(any(
@@ -112,12 +343,14 @@ impl FileOptions {
                 feature = "deflate-zlib"
             )))]
             compression_method: CompressionMethod::Stored,
-            #[cfg(feature = "time")]
-            last_modified_time: DateTime::from_time(time::now()).unwrap_or_default(),
-            #[cfg(not(feature = "time"))]
-            last_modified_time: DateTime::default(),
+            last_modified_time: now(),
             permissions: None,
             large_file: false,
+            alignment: 1,
+            text_file: false,
+            system: System::Unix,
+            dos_attributes: 0,
+            store_incompressible: false,
         }
     }
 
@@ -132,8 +365,8 @@ impl FileOptions {
 
     /// Set the last modified time
     ///
-    /// The default is the current timestamp if the 'time' feature is enabled, and 1980-01-01
-    /// otherwise
+    /// The default is the current timestamp if the 'time' feature is enabled, or the 'wasm'
+    /// feature on `wasm32-unknown-unknown`, and 1980-01-01 otherwise
     pub fn last_modified_time(mut self, mod_time: DateTime) -> FileOptions {
         self.last_modified_time = mod_time;
         self
@@ -158,6 +391,152 @@ impl FileOptions {
         self.large_file = large;
         self
     }
+
+    /// Set the system the new file is recorded as being made on
+    ///
+    /// This determines how the permissions are stored. For [`System::Unix`], the full unix mode
+    /// is kept. For [`System::Dos`], only the read-only and directory flags are, which any
+    /// extractor understands. [`System::Unknown`] is written as Unix. The default is
+    /// [`System::Unix`].
+    pub fn creator_system(mut self, system: System) -> FileOptions {
+        self.system = match system {
+            System::Unknown => System::Unix,
+            system => system,
+        };
+        self
+    }
+
+    /// Mark the new file as text in its internal file attributes, see [`ZipFile::is_text`].
+    ///
+    /// The contents are written as they are. The default is `false`.
+    ///
+    /// [`ZipFile::is_text`]: crate::read::ZipFile::is_text
+    pub fn text_file(mut self, text: bool) -> FileOptions {
+        self.text_file = text;
+        self
+    }
+
+    /// Set the hidden attribute of the new file, which Windows uses to leave it out of listings.
+    ///
+    /// The attribute is stored with the MS-DOS attributes, which are written along with the unix
+    /// permissions too. The default is `false`.
+    pub fn hidden(mut self, hidden: bool) -> FileOptions {
+        self.set_dos_attribute(crate::types::DOS_HIDDEN, hidden);
+        self
+    }
+
+    /// Set the system attribute of the new file, which Windows uses for operating system files.
+    ///
+    /// Like [`FileOptions::hidden`], this is stored with the MS-DOS attributes. The default is
+    /// `false`.
+    pub fn system_file(mut self, system: bool) -> FileOptions {
+        self.set_dos_attribute(crate::types::DOS_SYSTEM, system);
+        self
+    }
+
+    /// The options a file in an archive was written with, as far as they are stored
+    pub(crate) fn from_entry(data: &ZipFileData) -> FileOptions {
+        use crate::types::{DOS_HIDDEN, DOS_SYSTEM};
+
+        let mut options = FileOptions::default()
+            .compression_method(data.compression_method)
+            .last_modified_time(data.last_modified_time)
+            .large_file(data.large_file)
+            .creator_system(data.system)
+            .text_file(data.internal_attributes & 1 != 0);
+        options.permissions = data.unix_mode();
+        options.dos_attributes = data.external_attributes & (DOS_HIDDEN | DOS_SYSTEM);
+        options
+    }
+
+    fn set_dos_attribute(&mut self, attribute: u32, set: bool) {
+        if set {
+            self.dos_attributes |= attribute;
+        } else {
+            self.dos_attributes &= !attribute;
+        }
+    }
+
+    /// Align the start of the file data to a multiple of `alignment` bytes, by padding the extra
+    /// field.
+    ///
+    /// This lets stored files be memory-mapped straight from the archive. Android for example
+    /// requires 4 byte alignment for stored files in an APK, and 4096 bytes for shared
+    /// libraries. Only files started with [`ZipWriter::start_file`] are aligned. The default is
+    /// 1, which adds no padding.
+    pub fn with_alignment(mut self, alignment: u16) -> FileOptions {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Store the new file without compression if compressing it doesn't make it smaller, like
+    /// JPEG or MP4 files.
+    ///
+    /// To be able to go back, the whole file is kept in memory, along with its compressed data,
+    /// until it is finished. Only files started with [`ZipWriter::start_file`] without alignment
+    /// do this. The default is `false`.
+    pub fn store_incompressible(mut self, store: bool) -> FileOptions {
+        self.store_incompressible = store;
+        self
+    }
+}
+
+/// The extra fields of a file that a raw copy of it keeps, leaving out those that the
+/// [`ZipWriter`] writes itself: ZIP64 and alignment padding. The preset dictionary id is kept,
+/// since the compressed data still needs the dictionary.
+fn copied_extra_fields(mut extra_field: &[u8]) -> Vec<u8> {
+    let mut copied = Vec::new();
+    while extra_field.len() >= 4 {
+        let kind = LittleEndian::read_u16(&extra_field[0..]);
+        let end = 4 + LittleEndian::read_u16(&extra_field[2..]) as usize;
+        if end > extra_field.len() {
+            break;
+        }
+        match kind {
+            0x0001 | 0x617a => (),
+            _ => copied.extend_from_slice(&extra_field[..end]),
+        }
+        extra_field = &extra_field[end..];
+    }
+    copied
+}
+
+/// The current local time, or 1980-01-01 if it can't be found
+#[cfg(all(
+    feature = "time",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+fn now() -> DateTime {
+    DateTime::from_time(time::now()).unwrap_or_default()
+}
+
+/// The current local time, or 1980-01-01 if it can't be found
+///
+/// The time crate can't get the time in a browser, JavaScript has to be asked instead.
+#[cfg(all(feature = "wasm", target_arch = "wasm32", target_os = "unknown"))]
+fn now() -> DateTime {
+    let date = js_sys::Date::new_0();
+    DateTime::from_date_and_time(
+        date.get_full_year() as u16,
+        date.get_month() as u8 + 1,
+        date.get_date() as u8,
+        date.get_hours() as u8,
+        date.get_minutes() as u8,
+        date.get_seconds() as u8,
+    )
+    .unwrap_or_default()
+}
+
+/// The current local time, or 1980-01-01 if it can't be found
+#[cfg(not(any(
+    all(
+        feature = "time",
+        not(all(target_arch = "wasm32", target_os = "unknown"))
+    ),
+    all(feature = "wasm", target_arch = "wasm32", target_os = "unknown")
+)))]
+fn now() -> DateTime {
+    DateTime::default()
 }
 
 impl Default for FileOptions {
@@ -179,9 +558,26 @@ impl<W: Write + io::Seek> Write for ZipWriter<W> {
                 if self.writing_to_extra_field {
                     self.files.last_mut().unwrap().extra_field.write(buf)
                 } else {
-                    let write_result = w.write(buf);
+                    let write_result = match self.buffered {
+                        Some(ref mut buffered) => {
+                            buffered.data.extend_from_slice(buf);
+                            buffered.compressed.ref_mut().unwrap().write(buf)
+                        }
+                        None => w.write(buf),
+                    };
                     if let Ok(count) = write_result {
-                        self.stats.update(&buf[0..count]);
+                        if self.writing_raw {
+                            // The CRC32 is given, so only the raw data is checked if at all
+                            self.stats.bytes_written += count as u64;
+                            if let Some(ref mut hasher) = self.stats.content_hasher {
+                                hasher.update(&buf[0..count]);
+                            }
+                            if let Some(ref mut verifier) = self.raw_verifier {
+                                verifier.write_all(&buf[0..count])?;
+                            }
+                        } else {
+                            self.stats.update(&buf[0..count]);
+                        }
                         if self.stats.bytes_written > 0xFFFFFFFF
                             && !self.files.last_mut().unwrap().large_file
                         {
@@ -216,6 +612,9 @@ impl<W: Write + io::Seek> Write for ZipWriter<W> {
 impl ZipWriterStats {
     fn update(&mut self, buf: &[u8]) {
         self.hasher.update(buf);
+        if let Some(ref mut hasher) = self.content_hasher {
+            hasher.update(buf);
+        }
         self.bytes_written += buf.len() as u64;
     }
 }
@@ -223,7 +622,7 @@ impl ZipWriterStats {
 impl<A: Read + Write + io::Seek> ZipWriter<A> {
     /// Initializes the archive from an existing ZIP archive, making it ready for append.
     pub fn new_append(mut readwriter: A) -> ZipResult<ZipWriter<A>> {
-        let (footer, cde_start_pos) = spec::CentralDirectoryEnd::find_and_parse(&mut readwriter)?;
+        let (footer, cde_start_pos) = format::CentralDirectoryEnd::find_and_parse(&mut readwriter)?;
 
         if footer.disk_number != footer.disk_with_central_directory {
             return Err(ZipError::UnsupportedArchive(
@@ -234,17 +633,42 @@ impl<A: Read + Write + io::Seek> ZipWriter<A> {
         let (archive_offset, directory_start, number_of_files) =
             ZipArchive::get_directory_counts(&mut readwriter, &footer, cde_start_pos)?;
 
-        if let Err(_) = readwriter.seek(io::SeekFrom::Start(directory_start)) {
-            return Err(ZipError::InvalidArchive(
-                "Could not seek to start of central directory",
-            ));
+        if readwriter
+            .seek(io::SeekFrom::Start(directory_start))
+            .is_err()
+        {
+            return Err(
+                ZipError::invalid(InvalidArchiveKind::InvalidCentralDirectoryOffset)
+                    .at_offset(directory_start),
+            );
         }
 
-        let files = (0..number_of_files)
-            .map(|_| central_header_to_zip_file(&mut readwriter, archive_offset))
-            .collect::<Result<Vec<_>, _>>()?;
+        let mut files = Vec::new();
+        while files.len() < number_of_files
+            || central_header_follows(&mut readwriter, cde_start_pos)?
+        {
+            let mut file = central_header_to_zip_file(
+                &mut readwriter,
+                archive_offset,
+                &mut Limits::default(),
+            )?;
+            editor::strip_zip64_extra_field(&mut file);
+            files.push(file);
+        }
 
-        let _ = readwriter.seek(io::SeekFrom::Start(directory_start)); // seek directory_start to overwrite it
+        // New files go before the APK Signing Block, which has to stay right before the central
+        // directory
+        let (write_start, apk_signing_block) =
+            match find_apk_signing_block(&mut readwriter, directory_start)? {
+                Some(span) => {
+                    let mut block = vec![0; (span.end - span.start) as usize];
+                    readwriter.seek(io::SeekFrom::Start(span.start))?;
+                    readwriter.read_exact(&mut block)?;
+                    (span.start, Some(block))
+                }
+                None => (directory_start, None),
+            };
+        let _ = readwriter.seek(io::SeekFrom::Start(write_start)); // seek to overwrite the central directory
 
         Ok(ZipWriter {
             inner: GenericZipWriter::Storer(readwriter),
@@ -254,9 +678,65 @@ impl<A: Read + Write + io::Seek> ZipWriter<A> {
             writing_to_extra_field: false,
             writing_to_central_extra_field_only: false,
             comment: footer.zip_file_comment,
-            writing_raw: true, // avoid recomputing the last file's header
+            writing_raw: false,
+            reserved_zip64: false,
+            split_size: None,
+            deterministic: false,
+            central_directory_start: None,
+            dictionary: None,
+            buffered: None,
+            verify_raw_data: false,
+            raw_verifier: None,
+            apk_signing_block,
+            dedup: Deduplicator::default(),
         })
     }
+
+    /// Rename a file that is already in the archive.
+    ///
+    /// Of several files named `old`, the last one is renamed. Only the central directory
+    /// changes, and the name in the local header of the file if the new name has the same
+    /// length. Otherwise, the file is copied to the end of the archive, leaving the space it
+    /// took unused until the archive is [`repack`]ed. Files whose data is shared with duplicates,
+    /// see `ZipWriter::set_deduplicate` of the `digest` feature, only change in the central
+    /// directory.
+    ///
+    /// Fails with [`io::ErrorKind::AlreadyExists`] if another file is named `new`.
+    pub fn rename<S: Into<String>>(&mut self, old: &str, new: S) -> ZipResult<()> {
+        self.finish_file()?;
+        let index = self
+            .files
+            .iter()
+            .rposition(|file| file.file_name == old)
+            .ok_or(ZipError::FileNotFound)?;
+        let new = new.into();
+        if new != old && self.files.iter().any(|file| file.file_name == new) {
+            return Err(ZipError::Io(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "A file with the new name is already in the archive",
+            )));
+        }
+        let header_start = self.files[index].header_start;
+        let shared = self
+            .files
+            .iter()
+            .filter(|file| file.header_start == header_start)
+            .count()
+            > 1;
+        let file = &mut self.files[index];
+        file.file_name = new;
+        file.file_name_raw = Vec::new();
+        // Files that share their data with duplicates keep the local header they have
+        if shared {
+            return Ok(());
+        }
+
+        let writer = self.inner.get_plain();
+        let end = writer.seek(io::SeekFrom::Current(0))?;
+        let end = editor::update_local_header(writer, file, end)?;
+        writer.seek(io::SeekFrom::Start(end))?;
+        Ok(())
+    }
 }
 
 impl<W: Write + io::Seek> ZipWriter<W> {
@@ -272,10 +752,30 @@ impl<W: Write + io::Seek> ZipWriter<W> {
             writing_to_extra_field: false,
             writing_to_central_extra_field_only: false,
             writing_raw: false,
+            reserved_zip64: false,
             comment: Vec::new(),
+            split_size: None,
+            deterministic: false,
+            central_directory_start: None,
+            dictionary: None,
+            buffered: None,
+            verify_raw_data: false,
+            raw_verifier: None,
+            apk_signing_block: None,
+            dedup: Deduplicator::default(),
         }
     }
 
+    /// Initializes an archive after data that is already in `inner`, such as the stub of a
+    /// self-extracting archive.
+    ///
+    /// The archive is written at the end of `inner`. Offsets in the archive are counted from the
+    /// start of `inner`, prepended data included, which is what self-extractors expect.
+    pub fn new_with_prepended_data(mut inner: W) -> ZipResult<ZipWriter<W>> {
+        inner.seek(io::SeekFrom::End(0))?;
+        Ok(ZipWriter::new(inner))
+    }
+
     /// Set ZIP archive comment.
     pub fn set_comment<S>(&mut self, comment: S)
     where
@@ -292,19 +792,141 @@ impl<W: Write + io::Seek> ZipWriter<W> {
         self.comment = comment;
     }
 
+    /// Write the same bytes whenever the same files are added in the same order.
+    ///
+    /// When enabled, the modification time of every file is set to 1980-01-01 00:00:00, the
+    /// earliest time a ZIP archive can hold, and permissions are reduced to `0o755` for
+    /// directories and executable files and `0o644` for other files. Extra data and comments are
+    /// written as given, and files are stored in the order they are added.
+    ///
+    /// This is disabled by default.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    /// Check the data of files added with [`ZipWriter::raw_copy_file`] or
+    /// [`ZipWriter::start_file_raw`] against their CRC32 and size.
+    ///
+    /// The data is written as it is either way, but checking it means decompressing it on the
+    /// side, and finishing the file fails if it doesn't match. Files in compression methods that
+    /// can't be decompressed can't be added at all then. This is disabled by default, in which
+    /// case the CRC32 and size are trusted.
+    pub fn set_verify_raw_data(&mut self, verify: bool) {
+        self.verify_raw_data = verify;
+    }
+
+    /// Store the contents of identical files only once.
+    ///
+    /// When a file is finished, its CRC32, size and the digest of its contents computed with
+    /// `D`, like SHA-256, are compared with those of the files added before it by this writer.
+    /// If they match, the data that was just written is written over again, and the file is
+    /// handled as `mode` says. For files added as raw data, the digest of the compressed data
+    /// is compared instead, so they only match other files added as raw data with the same
+    /// compression method. Empty files, directories and split archives aren't deduplicated.
+    ///
+    /// The files that follow a duplicate are written over its data. Since the underlying writer
+    /// can't be truncated, the data of duplicates at the very end of the archive is left in it,
+    /// unused, until the archive is [`repack`]ed.
+    ///
+    /// This is [`Deduplicate::Off`] by default.
+    ///
+    /// ```
+    /// use sha2::Sha256;
+    /// use std::io::{Cursor, Write};
+    /// use zip::write::{Deduplicate, FileOptions};
+    ///
+    /// # fn main() -> zip::result::ZipResult<()> {
+    /// let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    /// writer.set_deduplicate::<Sha256>(Deduplicate::Share);
+    /// for &name in ["LICENSE", "vendor/LICENSE"].iter() {
+    ///     writer.start_file(name, FileOptions::default())?;
+    ///     writer.write_all(b"Permission is hereby granted")?;
+    /// }
+    /// writer.finish_file()?;
+    /// let duplicate = ("vendor/LICENSE".to_string(), "LICENSE".to_string());
+    /// assert_eq!(writer.duplicates(), &[duplicate]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "digest")]
+    pub fn set_deduplicate<D>(&mut self, mode: Deduplicate)
+    where
+        D: digest::DynDigest + Default + 'static,
+    {
+        fn new_hasher<D: digest::DynDigest + Default + 'static>() -> Box<dyn ContentHasher> {
+            Box::new(D::default())
+        }
+
+        self.dedup.mode = mode;
+        self.dedup.new_hasher = Some(new_hasher::<D>);
+    }
+
+    /// Get the files that were found to duplicate others, as the name of each duplicate along
+    /// with the name of the file it duplicates, see `ZipWriter::set_deduplicate` of the `digest`
+    /// feature
+    pub fn duplicates(&self) -> &[(String, String)] {
+        self.dedup.duplicates()
+    }
+
+    /// Write an APK Signing Block right before the central directory.
+    ///
+    /// `block` is the whole block, including the size fields and the magic at its end, as
+    /// [`ZipArchive::read_apk_signing_block`] returns it. An archive opened with
+    /// [`ZipWriter::new_append`] keeps its block without this.
+    pub fn set_apk_signing_block(&mut self, block: Vec<u8>) -> ZipResult<()> {
+        let len = block.len();
+        let valid = len >= 32
+            && block[len - 16..] == spec::APK_SIGNING_BLOCK_MAGIC[..]
+            && LittleEndian::read_u64(&block) == len as u64 - 8
+            && LittleEndian::read_u64(&block[len - 24..]) == len as u64 - 8;
+        if !valid {
+            return Err(ZipError::Io(io::Error::new(
+                io::ErrorKind::Other,
+                "Invalid APK Signing Block",
+            )));
+        }
+        self.apk_signing_block = Some(block);
+        Ok(())
+    }
+
+    /// Verifier for raw data in `method`, if raw data is verified
+    fn raw_verifier(&self, method: CompressionMethod) -> ZipResult<Option<RawVerifier>> {
+        if self.verify_raw_data {
+            RawVerifier::new(method).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Deflate the files that are started afterwards with a preset dictionary, or stop doing so.
+    ///
+    /// Small files that share a lot with the dictionary, like JSON documents with the same keys,
+    /// compress much better this way. Only the last 32 KiB of the dictionary are used. The zip
+    /// format has no place for a dictionary, so it isn't stored; such files get an extra field
+    /// with its CRC32 instead, and only a reader that is given the same dictionary with
+    /// [`ZipArchive::add_deflate_dictionary`] can decompress them. Other tools can't.
+    ///
+    /// The dictionary is only used for files started with [`ZipWriter::start_file`] without
+    /// alignment, and [`FileOptions::compression_method`] set to deflate.
+    pub fn set_deflate_dictionary(&mut self, dictionary: Option<&[u8]>) {
+        self.dictionary =
+            dictionary.map(|dictionary| crate::dictionary::window(dictionary).to_vec());
+    }
+
     /// Start a new file for with the requested options.
     fn start_entry<S>(
         &mut self,
         name: S,
         options: FileOptions,
         raw_values: Option<ZipRawValues>,
+        extra_field: Vec<u8>,
     ) -> ZipResult<()>
     where
         S: Into<String>,
     {
         self.finish_file()?;
 
-        let raw_values = raw_values.unwrap_or_else(|| ZipRawValues {
+        let raw_values = raw_values.unwrap_or(ZipRawValues {
             crc32: 0,
             compressed_size: 0,
             uncompressed_size: 0,
@@ -314,27 +936,49 @@ impl<W: Write + io::Seek> ZipWriter<W> {
             let writer = self.inner.get_plain();
             let header_start = writer.seek(io::SeekFrom::Current(0))?;
 
-            let permissions = options.permissions.unwrap_or(0o100644);
+            let mut permissions = options.permissions.unwrap_or(0o100644);
+            let mut last_modified_time = options.last_modified_time;
+            if self.deterministic {
+                // Keep the file type, and whether the file is executable at all
+                let mode = if permissions & 0o111 != 0 {
+                    0o755
+                } else {
+                    0o644
+                };
+                permissions = permissions & 0o170000 | mode;
+                last_modified_time = DateTime::default();
+            }
             let mut file = ZipFileData {
-                system: System::Unix,
+                system: options.system,
                 version_made_by: DEFAULT_VERSION,
+                version_to_extract: 0,
                 encrypted: false,
                 using_data_descriptor: false,
                 compression_method: options.compression_method,
-                last_modified_time: options.last_modified_time,
+                last_modified_time,
                 crc32: raw_values.crc32,
                 compressed_size: raw_values.compressed_size,
                 uncompressed_size: raw_values.uncompressed_size,
                 file_name: name.into(),
                 file_name_raw: Vec::new(), // Never used for saving
-                extra_field: Vec::new(),
+                extra_field,
                 file_comment: String::new(),
                 header_start,
+                disk_number: 0,
                 data_start: 0,
                 central_header_start: 0,
-                external_attributes: permissions << 16,
+                internal_attributes: options.text_file as u16,
+                external_attributes: external_attributes(
+                    options.system,
+                    permissions,
+                    options.dos_attributes,
+                ),
                 large_file: options.large_file,
+                recovered: false,
+                flags: 0,
             };
+            file.version_to_extract = file.version_needed();
+            file.flags = general_purpose_flags(&file);
             write_local_file_header(writer, &file)?;
 
             let header_end = writer.seek(io::SeekFrom::Current(0))?;
@@ -343,6 +987,7 @@ impl<W: Write + io::Seek> ZipWriter<W> {
 
             self.stats.bytes_written = 0;
             self.stats.hasher = Hasher::new();
+            self.stats.content_hasher = self.dedup.hasher();
 
             self.files.push(file);
         }
@@ -350,38 +995,287 @@ impl<W: Write + io::Seek> ZipWriter<W> {
         Ok(())
     }
 
-    fn finish_file(&mut self) -> ZipResult<()> {
+    /// Finish writing the current file, and return its sizes and checksum
+    ///
+    /// This is done automatically when the next file is started or the archive is finished.
+    /// Returns `None` if no file has been started yet.
+    pub fn finish_file(&mut self) -> ZipResult<Option<EntryStats>> {
         if self.writing_to_extra_field {
             // Implicitly calling [`ZipWriter::end_extra_data`] for empty files.
             self.end_extra_data()?;
         }
+        if let Some(buffered) = self.buffered.take() {
+            self.write_buffered(buffered)?;
+        }
         self.inner.switch_to(CompressionMethod::Stored)?;
         let writer = self.inner.get_plain();
 
-        if !self.writing_raw {
-            let file = match self.files.last_mut() {
-                None => return Ok(()),
-                Some(f) => f,
-            };
-            file.crc32 = self.stats.hasher.clone().finalize();
-            file.uncompressed_size = self.stats.bytes_written;
-
+        let file = match self.files.last_mut() {
+            None => return Ok(None),
+            Some(f) => f,
+        };
+        // Directories and files that were finished already have nothing left to update
+        let mut finished_end = None;
+        if self.writing_to_file {
             let file_end = writer.seek(io::SeekFrom::Current(0))?;
-            file.compressed_size = file_end - self.stats.start;
+            finished_end = Some((file_end, self.writing_raw));
+            let compressed_size = file_end - self.stats.start;
+            self.writing_to_file = false;
+            let reserved_zip64 = mem::replace(&mut self.reserved_zip64, false);
+            if self.writing_raw {
+                self.writing_raw = false;
+                let mut mismatch = None;
+                if compressed_size != file.compressed_size {
+                    mismatch = Some("Raw data doesn't match the compressed size");
+                }
+                if let Some(verifier) = self.raw_verifier.take() {
+                    let checksum = verifier.finish()?;
+                    if checksum.hasher.finalize() != file.crc32
+                        || checksum.size != file.uncompressed_size
+                    {
+                        mismatch = mismatch.or(Some("Raw data doesn't match the CRC32 and size"));
+                    }
+                }
+                if let Some(mismatch) = mismatch {
+                    // The entry would describe data that isn't there, so it is left out
+                    self.discard_last_file(file_end)?;
+                    return Err(ZipError::Io(io::Error::new(io::ErrorKind::Other, mismatch)));
+                }
+            } else {
+                file.crc32 = self.stats.hasher.clone().finalize();
+                file.uncompressed_size = self.stats.bytes_written;
+                file.compressed_size = compressed_size;
+
+                update_local_file_header(writer, file)?;
+                if reserved_zip64
+                    && file.compressed_size <= 0xFFFFFFFF
+                    && file.uncompressed_size <= 0xFFFFFFFF
+                {
+                    file.large_file = false;
+                    file.version_to_extract = file.version_needed();
+                    release_local_zip64_extra_field(writer, file)?;
+                }
+                writer.seek(io::SeekFrom::Start(file_end))?;
+            }
+        }
+        let stats = EntryStats {
+            uncompressed_size: file.uncompressed_size,
+            compressed_size: file.compressed_size,
+            crc32: file.crc32,
+        };
+        if let Some((file_end, raw)) = finished_end {
+            self.deduplicate(file_end, raw)?;
+        }
+        Ok(Some(stats))
+    }
 
-            update_local_file_header(writer, file)?;
-            writer.seek(io::SeekFrom::Start(file_end))?;
+    /// Remove the file that was just written, ending at `file_end`, so that it is left out of the
+    /// central directory and the next file is written over it
+    fn discard_last_file(&mut self, file_end: u64) -> ZipResult<()> {
+        let file = match self.files.pop() {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        self.stats.content_hasher = None;
+        // Earlier parts of split archives can't be written to anymore
+        if self.split_size.is_none() {
+            self.inner
+                .get_plain()
+                .seek(io::SeekFrom::Start(file.header_start))?;
+            self.dedup.overwritten_end = std::cmp::max(self.dedup.overwritten_end, file_end);
         }
+        Ok(())
+    }
 
-        self.writing_to_file = false;
-        self.writing_raw = false;
+    /// Write over the file that was just finished, ending at `file_end`, if it duplicates an
+    /// earlier one, see [`ZipWriter::set_deduplicate`]. `raw` is whether it was added as raw
+    /// data.
+    fn deduplicate(&mut self, file_end: u64, raw: bool) -> ZipResult<()> {
+        let mut hasher = match self.stats.content_hasher.take() {
+            Some(hasher) => hasher,
+            None => return Ok(()),
+        };
+        if self.split_size.is_some() {
+            return Ok(());
+        }
+        let original = match self.dedup.find(&self.files, hasher.finish(), raw) {
+            Some(original) => original,
+            None => return Ok(()),
+        };
+        let file = self.files.pop().unwrap();
+        self.inner
+            .get_plain()
+            .seek(io::SeekFrom::Start(file.header_start))?;
+        self.dedup.overwritten_end = std::cmp::max(self.dedup.overwritten_end, file_end);
+        if self.dedup.mode == Deduplicate::Share {
+            let mut shared = self.files[original].clone();
+            shared.system = file.system;
+            shared.version_made_by = file.version_made_by;
+            shared.last_modified_time = file.last_modified_time;
+            shared.file_name = file.file_name;
+            shared.file_name_raw = file.file_name_raw;
+            shared.file_comment = file.file_comment;
+            shared.internal_attributes = file.internal_attributes;
+            shared.external_attributes = file.external_attributes;
+            self.files.push(shared);
+        }
         Ok(())
     }
 
+    /// Get the number of files and their total sizes so far
+    ///
+    /// The sizes of the file that is being written are only counted once it is finished.
+    pub fn stats(&self) -> ArchiveStats {
+        let mut stats = ArchiveStats {
+            files: self.files.len(),
+            central_directory_offset: self.central_directory_start,
+            ..ArchiveStats::default()
+        };
+        for file in self.files.iter() {
+            stats.uncompressed_size += file.uncompressed_size;
+            stats.compressed_size += file.compressed_size;
+        }
+        stats
+    }
+
     /// Create a file in the archive and start writing its' contents.
     ///
     /// The data should be written using the [`io::Write`] implementation on this [`ZipWriter`]
     pub fn start_file<S>(&mut self, name: S, mut options: FileOptions) -> ZipResult<()>
+    where
+        S: Into<String>,
+    {
+        if options.alignment > 1 {
+            self.start_file_aligned(name, options, options.alignment)?;
+            return Ok(());
+        }
+        if options.permissions.is_none() {
+            options.permissions = Some(0o644);
+        }
+        // Only the modes of repacked files already have a file type, like symbolic links
+        if options.permissions.unwrap() & 0o170000 == 0 {
+            *options.permissions.as_mut().unwrap() |= 0o100000;
+        }
+        let dictionary = match self.dictionary {
+            Some(ref dictionary) if options.compression_method == CompressionMethod::DEFLATE => {
+                Some(dictionary.clone())
+            }
+            _ => None,
+        };
+        let extra_field = dictionary
+            .as_ref()
+            .map(|dictionary| crate::dictionary::extra_field(dictionary))
+            .unwrap_or_default();
+        self.start_entry(name, options, None, extra_field)?;
+        if options.store_incompressible && options.compression_method != CompressionMethod::Stored {
+            let mut compressed = GenericZipWriter::Storer(io::Cursor::new(Vec::new()));
+            compressed.switch_to(options.compression_method)?;
+            if let Some(dictionary) = dictionary {
+                compressed.prime(&dictionary)?;
+            }
+            self.buffered = Some(BufferedFile {
+                data: Vec::new(),
+                compressed,
+            });
+        } else {
+            self.inner.switch_to(options.compression_method)?;
+            if let Some(dictionary) = dictionary {
+                self.inner.prime(&dictionary)?;
+            }
+        }
+        self.writing_to_file = true;
+        Ok(())
+    }
+
+    /// Write the compressed data of a buffered file, or its data if that isn't larger
+    fn write_buffered(&mut self, mut buffered: BufferedFile) -> ZipResult<()> {
+        buffered.compressed.switch_to(CompressionMethod::Stored)?;
+        let compressed = buffered.compressed.get_plain().get_ref();
+        let writer = self.inner.get_plain();
+        if compressed.len() < buffered.data.len() {
+            writer.write_all(compressed)?;
+        } else {
+            let file = self.files.last_mut().unwrap();
+            file.compression_method = CompressionMethod::Stored;
+            update_local_compression_method(writer, file)?;
+            writer.write_all(&buffered.data)?;
+        }
+        Ok(())
+    }
+
+    /// Create the first file of the archive, stored without compression or extra fields.
+    ///
+    /// EPUB and OpenDocument files have to start with a `mimetype` file like that, so that it can
+    /// be read at a fixed offset. The compression method, alignment and `large_file` of `options`
+    /// are ignored. Returns an error if anything was written to the archive before.
+    pub fn start_file_stored_first<S>(&mut self, name: S, mut options: FileOptions) -> ZipResult<()>
+    where
+        S: Into<String>,
+    {
+        if !self.files.is_empty() || self.inner.get_plain().seek(io::SeekFrom::Current(0))? != 0 {
+            return Err(ZipError::Io(io::Error::new(
+                io::ErrorKind::Other,
+                "Not at the start of the archive",
+            )));
+        }
+        options.compression_method = CompressionMethod::Stored;
+        options.alignment = 1;
+        options.large_file = false;
+        self.start_file(name, options)
+    }
+
+    /// Create a file in the archive and copy the contents of `reader` into it.
+    ///
+    /// Returns the number of (uncompressed) bytes written. The size of `reader` isn't known
+    /// before the local header is written, so unless [`FileOptions::large_file`] is set, room
+    /// for a ZIP64 extra field is reserved in it. The field is used if the file turns out to
+    /// exceed 4 GiB, and is left as padding otherwise.
+    pub fn add_file_from_reader<S, R>(
+        &mut self,
+        name: S,
+        options: FileOptions,
+        reader: &mut R,
+    ) -> ZipResult<u64>
+    where
+        S: Into<String>,
+        R: Read + ?Sized,
+    {
+        let reserved_zip64 = !options.large_file;
+        self.start_file(name, options.large_file(true))?;
+        self.reserved_zip64 = reserved_zip64;
+        Ok(io::copy(reader, self)?)
+    }
+
+    /// Create a file in the archive from data that is already compressed.
+    ///
+    /// Exactly `compressed_size` bytes of data, compressed with the compression method of
+    /// `options`, should be written afterwards. They are written as they are, so the size and
+    /// CRC32 of the uncompressed data are trusted, unless [`ZipWriter::set_verify_raw_data`] is
+    /// enabled. Finishing the file fails if a different number of bytes was written. The
+    /// alignment of `options` is ignored, and `large_file` is set if either size needs it.
+    ///
+    /// ```no_run
+    /// # fn main() -> zip::result::ZipResult<()> {
+    /// use std::io::Write;
+    /// use zip::write::FileOptions;
+    ///
+    /// # let (method, compressed, size, crc32) = (zip::CompressionMethod::Stored, Vec::new(), 0, 0);
+    /// let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    /// let options = FileOptions::default().compression_method(method);
+    /// zip.start_file_raw("blob.bin", options, compressed.len() as u64, size, crc32)?;
+    /// zip.write_all(&compressed)?;
+    /// zip.finish()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn start_file_raw<S>(
+        &mut self,
+        name: S,
+        mut options: FileOptions,
+        compressed_size: u64,
+        uncompressed_size: u64,
+        crc32: u32,
+    ) -> ZipResult<()>
     where
         S: Into<String>,
     {
@@ -389,9 +1283,17 @@ impl<W: Write + io::Seek> ZipWriter<W> {
             options.permissions = Some(0o644);
         }
         *options.permissions.as_mut().unwrap() |= 0o100000;
-        self.start_entry(name, options, None)?;
-        self.inner.switch_to(options.compression_method)?;
+        options.large_file |= compressed_size > 0xFFFFFFFF || uncompressed_size > 0xFFFFFFFF;
+        let raw_values = ZipRawValues {
+            crc32,
+            compressed_size,
+            uncompressed_size,
+        };
+        let verifier = self.raw_verifier(options.compression_method)?;
+        self.start_entry(name, options, Some(raw_values), Vec::new())?;
+        self.raw_verifier = verifier;
         self.writing_to_file = true;
+        self.writing_raw = true;
         Ok(())
     }
 
@@ -515,7 +1417,7 @@ impl<W: Write + io::Seek> ZipWriter<W> {
             options.permissions = Some(0o644);
         }
         *options.permissions.as_mut().unwrap() |= 0o100000;
-        self.start_entry(name, options, None)?;
+        self.start_entry(name, options, None, Vec::new())?;
         self.writing_to_file = true;
         self.writing_to_extra_field = true;
         Ok(self.files.last().unwrap().data_start)
@@ -545,7 +1447,7 @@ impl<W: Write + io::Seek> ZipWriter<W> {
         }
         let file = self.files.last_mut().unwrap();
 
-        validate_extra_data(&file)?;
+        validate_extra_data(file)?;
 
         if !self.writing_to_central_extra_field_only {
             let writer = self.inner.get_plain();
@@ -575,8 +1477,9 @@ impl<W: Write + io::Seek> ZipWriter<W> {
 
     /// Add a new file using the already compressed data from a ZIP file being read and renames it, this
     /// allows faster copies of the `ZipFile` since there is no need to decompress and compress it again.
-    /// Any `ZipFile` metadata is copied and not checked, for example the file CRC.
-
+    /// Any `ZipFile` metadata is copied and not checked, for example the file CRC, unless
+    /// [`ZipWriter::set_verify_raw_data`] is enabled.
+    ///
     /// ```no_run
     /// use std::fs::File;
     /// use std::io::{Read, Seek, Write};
@@ -603,12 +1506,7 @@ impl<W: Write + io::Seek> ZipWriter<W> {
     where
         S: Into<String>,
     {
-        let options = FileOptions::default()
-            .last_modified_time(file.last_modified())
-            .compression_method(file.compression());
-        if let Some(perms) = file.unix_mode() {
-            options.unix_permissions(perms);
-        }
+        let options = file.options();
 
         let raw_values = ZipRawValues {
             crc32: file.crc32(),
@@ -616,7 +1514,12 @@ impl<W: Write + io::Seek> ZipWriter<W> {
             uncompressed_size: file.size(),
         };
 
-        self.start_entry(name, options, Some(raw_values))?;
+        let verifier = self.raw_verifier(file.compression())?;
+        let extra_field = copied_extra_fields(file.extra_data());
+        self.start_entry(name, options, Some(raw_values), extra_field)?;
+        // Only the central header has a comment
+        self.files.last_mut().unwrap().file_comment = file.comment().to_string();
+        self.raw_verifier = verifier;
         self.writing_to_file = true;
         self.writing_raw = true;
 
@@ -673,7 +1576,76 @@ impl<W: Write + io::Seek> ZipWriter<W> {
             _ => name_as_string + "/",
         };
 
-        self.start_entry(name_with_slash, options, None)?;
+        self.start_entry(name_with_slash, options, None, Vec::new())?;
+        self.writing_to_file = false;
+        Ok(())
+    }
+
+    /// Add a hard link to the file named `target`, which must have been added before.
+    ///
+    /// The link is a regular file without contents, which names its target in a PKWARE Unix
+    /// extra field. [`ZipArchive::extract`](crate::ZipArchive::extract) gives it a copy of
+    /// the target, or links it with [`ExtractOptions::hard_links`]. Tools that don't know about
+    /// hard links extract an empty file. The permissions in `options` should match those of
+    /// the target, as they share them once linked.
+    ///
+    /// [`ExtractOptions::hard_links`]: crate::read::ExtractOptions::hard_links
+    pub fn add_hard_link<N, T>(
+        &mut self,
+        name: N,
+        target: T,
+        mut options: FileOptions,
+    ) -> ZipResult<()>
+    where
+        N: Into<String>,
+        T: Into<String>,
+    {
+        let mut target = target.into();
+        let linked = self
+            .files
+            .iter()
+            .rev()
+            .find(|file| file.file_name == target)
+            .filter(|file| !file.is_dir())
+            .ok_or_else(|| {
+                ZipError::Io(io::Error::new(
+                    io::ErrorKind::Other,
+                    "The target of a hard link must be a file that was added before it",
+                ))
+            })?;
+        // Link to the file that a link links to, so that no chains are needed
+        if let Some(target_of_target) = crate::types::find_link_target(&linked.extra_field) {
+            target = String::from_utf8_lossy(target_of_target).into_owned();
+        }
+        if target.len() > 0xFFFF - 4 - 12 {
+            return Err(ZipError::Io(io::Error::new(
+                io::ErrorKind::Other,
+                "The target of a hard link is too long",
+            )));
+        }
+
+        if options.permissions.is_none() {
+            options.permissions = Some(0o644);
+        }
+        *options.permissions.as_mut().unwrap() |= 0o100000;
+        options.compression_method = CompressionMethod::Stored;
+
+        let time = if self.deterministic {
+            DateTime::default()
+        } else {
+            options.last_modified_time
+        };
+        let time = time.to_time_t() as u32;
+        let mut extra_field = Vec::with_capacity(16 + target.len());
+        extra_field.write_u16::<LittleEndian>(crate::types::PKWARE_UNIX_EXTRA_FIELD_ID)?;
+        extra_field.write_u16::<LittleEndian>(12 + target.len() as u16)?;
+        extra_field.write_u32::<LittleEndian>(time)?;
+        extra_field.write_u32::<LittleEndian>(time)?;
+        extra_field.write_u16::<LittleEndian>(0)?;
+        extra_field.write_u16::<LittleEndian>(0)?;
+        extra_field.write_all(target.as_bytes())?;
+
+        self.start_entry(name, options, None, extra_field)?;
         self.writing_to_file = false;
         Ok(())
     }
@@ -697,6 +1669,7 @@ impl<W: Write + io::Seek> ZipWriter<W> {
     /// Finish the last file and write all other zip-structures
     ///
     /// This will return the writer, but one should normally not append any data to the end of the file.
+    /// The writer is flushed, and [`ZipWriter::stats`] describes the finished archive afterwards.
     /// Note that the zipfile will also be finished on drop.
     pub fn finish(&mut self) -> ZipResult<W> {
         self.finalize()?;
@@ -706,67 +1679,349 @@ impl<W: Write + io::Seek> ZipWriter<W> {
 
     fn finalize(&mut self) -> ZipResult<()> {
         self.finish_file()?;
+        if let Some(block) = self.apk_signing_block.take() {
+            self.inner.get_plain().write_all(&block)?;
+        }
+        if let Some(segment_size) = self.split_size {
+            self.finalize_split(segment_size)?;
+            self.inner.get_plain().flush()?;
+            return Ok(());
+        }
 
-        {
-            let writer = self.inner.get_plain();
+        let writer = self.inner.get_plain();
+        let mut central_start = write_central_directory(writer, &self.files, &self.comment)?;
+        // The archive has to end after data that was written over, which nothing refers to
+        let end = writer.seek(io::SeekFrom::Current(0))?;
+        if end < self.dedup.overwritten_end {
+            writer.seek(io::SeekFrom::Start(
+                self.dedup.overwritten_end - (end - central_start),
+            ))?;
+            central_start = write_central_directory(writer, &self.files, &self.comment)?;
+        }
+        self.central_directory_start = Some(central_start);
+        writer.flush()?;
 
-            let central_start = writer.seek(io::SeekFrom::Current(0))?;
-            for file in self.files.iter() {
-                write_central_directory_header(writer, file)?;
-            }
-            let central_size = writer.seek(io::SeekFrom::Current(0))? - central_start;
-
-            if self.files.len() > 0xFFFF || central_size > 0xFFFFFFFF || central_start > 0xFFFFFFFF
-            {
-                let zip64_footer = spec::Zip64CentralDirectoryEnd {
-                    version_made_by: DEFAULT_VERSION as u16,
-                    version_needed_to_extract: DEFAULT_VERSION as u16,
-                    disk_number: 0,
-                    disk_with_central_directory: 0,
-                    number_of_files_on_this_disk: self.files.len() as u64,
-                    number_of_files: self.files.len() as u64,
-                    central_directory_size: central_size,
-                    central_directory_offset: central_start,
-                };
+        Ok(())
+    }
 
-                zip64_footer.write(writer)?;
+    /// Write the central directory of a split archive, where offsets are relative to the start
+    /// of the segment they point into.
+    fn finalize_split(&mut self, segment_size: u64) -> ZipResult<()> {
+        let mut central_directory = Vec::new();
+        let mut header_offsets = Vec::new();
+        for file in self.files.iter() {
+            let mut file = file.clone();
+            file.disk_number = (file.header_start / segment_size) as u32;
+            file.header_start %= segment_size;
+            header_offsets.push(central_directory.len() as u64);
+            write_central_directory_header(&mut central_directory, &file)?;
+        }
+        if self.files.len() >= 0xFFFF || central_directory.len() as u64 >= 0xFFFFFFFF {
+            return Err(ZipError::UnsupportedArchive(
+                "Split ZIP64 archives are not supported",
+            ));
+        }
+        let footer_size = 22 + self.comment.len() as u64;
+        if footer_size > segment_size {
+            return Err(ZipError::UnsupportedArchive(
+                "The archive comment doesn't fit in a segment",
+            ));
+        }
 
-                let zip64_footer = spec::Zip64CentralDirectoryEndLocator {
-                    disk_with_central_directory: 0,
-                    end_of_central_directory_offset: central_start + central_size,
-                    number_of_disks: 1,
-                };
+        let writer = self.inner.get_plain();
+        let data_end = writer.seek(io::SeekFrom::Current(0))?;
+
+        // The end of central directory record may not be split over two segments, so start the
+        // central directory later if it would be. Nothing refers to the gap.
+        let footer_start = data_end + central_directory.len() as u64;
+        let footer_offset = footer_start % segment_size;
+        let padding = if footer_offset + footer_size > segment_size {
+            segment_size - footer_offset
+        } else {
+            0
+        };
+        writer.write_all(&vec![0; padding as usize])?;
+        let central_start = data_end + padding;
+        self.central_directory_start = Some(central_start);
+        writer.write_all(&central_directory)?;
+
+        let footer_start = footer_start + padding;
+        let disk_number = footer_start / segment_size;
+        if disk_number >= 0xFFFF {
+            return Err(ZipError::UnsupportedArchive(
+                "Split ZIP64 archives are not supported",
+            ));
+        }
+        let number_of_files_on_this_disk = header_offsets
+            .iter()
+            .filter(|&&offset| (central_start + offset) / segment_size == disk_number)
+            .count();
+        let footer = format::CentralDirectoryEnd {
+            disk_number: disk_number as u16,
+            disk_with_central_directory: (central_start / segment_size) as u16,
+            number_of_files_on_this_disk: number_of_files_on_this_disk as u16,
+            number_of_files: self.files.len() as u16,
+            central_directory_size: central_directory.len() as u32,
+            central_directory_offset: (central_start % segment_size) as u32,
+            zip_file_comment: self.comment.clone(),
+        };
+        footer.write_to(writer)?;
+
+        if disk_number == 0 {
+            // The archive fits in a single segment after all, which is marked differently
+            let end = writer.seek(io::SeekFrom::Current(0))?;
+            writer.seek(io::SeekFrom::Start(0))?;
+            writer.write_u32::<LittleEndian>(spec::SINGLE_SEGMENT_SPLIT_ARCHIVE_SIGNATURE)?;
+            writer.seek(io::SeekFrom::Start(end))?;
+        }
+        Ok(())
+    }
+}
+
+impl<F, S> ZipWriter<SplitArchiveWriter<F, S>>
+where
+    F: FnMut(u32) -> io::Result<S>,
+    S: Write + io::Seek,
+{
+    /// Initializes an archive that is split over several segments of at most `max_segment_size`
+    /// bytes each, like the `.z01`, `.z02`, ..., `.zip` files made by `zip -s`.
+    ///
+    /// `sink_factory` is called with the number of each segment, starting at 0, when it is
+    /// needed. The last segment holds the central directory, and is conventionally given the
+    /// `.zip` extension; since it is only known to be last when the archive is finished, it
+    /// may have to be renamed afterwards:
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use std::io::Write;
+    /// use zip::write::{FileOptions, ZipWriter};
+    ///
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let segment_name = |disk: u32| format!("archive.z{:02}", disk + 1);
+    /// let mut zip = ZipWriter::new_split(|disk| File::create(segment_name(disk)), 1 << 20)?;
+    /// zip.start_file("hello_world.txt", FileOptions::default())?;
+    /// zip.write_all(b"Hello, World!")?;
+    /// let segments = zip.finish()?.into_inner();
+    /// std::fs::rename(segment_name(segments.len() as u32 - 1), "archive.zip")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Split archives that need ZIP64 are not supported. Fails if the signature at the start of
+    /// the first segment can't be written.
+    ///
+    /// # Panics
+    ///
+    /// If `max_segment_size` is smaller than 64 KiB, the minimum size of a segment.
+    pub fn new_split(
+        sink_factory: F,
+        max_segment_size: u64,
+    ) -> ZipResult<ZipWriter<SplitArchiveWriter<F, S>>> {
+        assert!(
+            max_segment_size >= 64 * 1024,
+            "Segments of a split archive must be at least 64 KiB"
+        );
+        let mut writer = SplitArchiveWriter {
+            factory: sink_factory,
+            segments: Vec::new(),
+            segment_size: max_segment_size,
+            position: 0,
+        };
+        writer.write_u32::<LittleEndian>(spec::SPLIT_ARCHIVE_SIGNATURE)?;
+        let mut zip = ZipWriter::new(writer);
+        zip.split_size = Some(max_segment_size);
+        Ok(zip)
+    }
+}
+
+/// Writer spreading a split archive over segments of a fixed size, see [`ZipWriter::new_split`]
+pub struct SplitArchiveWriter<F, S> {
+    factory: F,
+    segments: Vec<S>,
+    segment_size: u64,
+    position: u64,
+}
+
+impl<F, S> SplitArchiveWriter<F, S> {
+    /// Unwrap and return the segments, from the first disk to the last
+    pub fn into_inner(self) -> Vec<S> {
+        self.segments
+    }
+}
+
+impl<F, S> Write for SplitArchiveWriter<F, S>
+where
+    F: FnMut(u32) -> io::Result<S>,
+    S: Write + io::Seek,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let index = (self.position / self.segment_size) as usize;
+        let offset = self.position % self.segment_size;
+        while self.segments.len() <= index {
+            let segment = (self.factory)(self.segments.len() as u32)?;
+            self.segments.push(segment);
+        }
+        let len = std::cmp::min(buf.len() as u64, self.segment_size - offset) as usize;
+
+        let segment = &mut self.segments[index];
+        segment.seek(io::SeekFrom::Start(offset))?;
+        let count = segment.write(&buf[..len])?;
+        self.position += count as u64;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for segment in self.segments.iter_mut() {
+            segment.flush()?;
+        }
+        Ok(())
+    }
+}
 
-                zip64_footer.write(writer)?;
+impl<F, S> io::Seek for SplitArchiveWriter<F, S>
+where
+    S: io::Seek,
+{
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let len = match self.segments.last_mut() {
+            Some(last) => {
+                let last_len = last.seek(io::SeekFrom::End(0))?;
+                (self.segments.len() as u64 - 1) * self.segment_size + last_len
             }
+            None => 0,
+        };
+        let (base, offset) = match pos {
+            io::SeekFrom::Start(offset) => {
+                self.position = offset;
+                return Ok(offset);
+            }
+            io::SeekFrom::End(offset) => (len, offset),
+            io::SeekFrom::Current(offset) => (self.position, offset),
+        };
+        let position = base as i128 + offset as i128;
+        if position < 0 || position > ::std::u64::MAX as i128 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            ));
+        }
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
 
-            let number_of_files = if self.files.len() > 0xFFFF {
-                0xFFFF
-            } else {
-                self.files.len() as u16
-            };
-            let footer = spec::CentralDirectoryEnd {
-                disk_number: 0,
-                disk_with_central_directory: 0,
-                zip_file_comment: self.comment.clone(),
-                number_of_files_on_this_disk: number_of_files,
-                number_of_files,
-                central_directory_size: if central_size > 0xFFFFFFFF {
-                    0xFFFFFFFF
-                } else {
-                    central_size as u32
-                },
-                central_directory_offset: if central_start > 0xFFFFFFFF {
-                    0xFFFFFFFF
-                } else {
-                    central_start as u32
-                },
-            };
+/// Writer for output that can't be seeked, like stdout or a network stream
+///
+/// [`ZipWriter`] goes back to fill in the sizes and checksum of a file once it is finished. This
+/// keeps the file that is being written in memory, together with its local header, so that an
+/// exact local header is written before the data and no data descriptors are needed. It uses
+/// about as much memory as the largest compressed file.
+///
+/// Like [`io::BufWriter`], what is still buffered is written when it is dropped, ignoring
+/// errors. Call [`SpoolingWriter::into_inner`] to see them.
+///
+/// ```no_run
+/// use std::io::Write;
+/// use zip::write::{FileOptions, SpoolingWriter, ZipWriter};
+///
+/// let mut zip = ZipWriter::new(SpoolingWriter::new(std::io::stdout()));
+/// zip.start_file("hello.txt", FileOptions::default())?;
+/// zip.write_all(b"Hello, World!")?;
+/// zip.finish()?.into_inner()?;
+/// # Ok::<(), zip::result::ZipError>(())
+/// ```
+pub struct SpoolingWriter<W: Write> {
+    inner: Option<W>,
+    /// Number of bytes that were passed on to `inner`
+    written: u64,
+    buffer: Vec<u8>,
+    position: u64,
+}
+
+impl<W: Write> SpoolingWriter<W> {
+    /// Write to `inner`, starting at offset 0
+    pub fn new(inner: W) -> SpoolingWriter<W> {
+        SpoolingWriter {
+            inner: Some(inner),
+            written: 0,
+            buffer: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Write what is still buffered, and return the inner writer
+    pub fn into_inner(mut self) -> io::Result<W> {
+        let end = self.written + self.buffer.len() as u64;
+        self.write_buffer(end)?;
+        Ok(self.inner.take().unwrap())
+    }
+
+    /// Pass the buffered data before `end` on to the inner writer
+    fn write_buffer(&mut self, end: u64) -> io::Result<()> {
+        let len = (end - self.written) as usize;
+        self.inner
+            .as_mut()
+            .unwrap()
+            .write_all(&self.buffer[..len])?;
+        self.buffer.drain(..len);
+        self.written = end;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for SpoolingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let start = (self.position - self.written) as usize;
+        if self.buffer.len() < start + buf.len() {
+            self.buffer.resize(start + buf.len(), 0);
+        }
+        self.buffer[start..start + buf.len()].copy_from_slice(buf);
+        self.position += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    /// Flushes the inner writer, without writing the buffered data that may still change
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.as_mut().unwrap().flush()
+    }
+}
 
-            footer.write(writer)?;
+impl<W: Write> io::Seek for SpoolingWriter<W> {
+    /// Seeks within the buffered data
+    ///
+    /// Everything before the position of a backwards seek is written, since the [`ZipWriter`]
+    /// only goes back to the header of the current file.
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            io::SeekFrom::Start(offset) => (offset, 0),
+            io::SeekFrom::End(offset) => (self.written + self.buffer.len() as u64, offset),
+            io::SeekFrom::Current(offset) => (self.position, offset),
+        };
+        let position = base as i128 + offset as i128;
+        if position < self.written as i128 || position > ::std::u64::MAX as i128 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't seek to data that was already written",
+            ));
+        }
+        let position = position as u64;
+        if position < self.position {
+            self.write_buffer(position)?;
         }
+        self.position = position;
+        Ok(position)
+    }
+}
 
-        Ok(())
+impl<W: Write> Drop for SpoolingWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let end = self.written + self.buffer.len() as u64;
+            let _ = self.write_buffer(end);
+        }
     }
 }
 
@@ -801,7 +2056,7 @@ impl<W: Write + io::Seek> GenericZipWriter<W> {
                 feature = "deflate-miniz",
                 feature = "deflate-zlib"
             ))]
-            GenericZipWriter::Deflater(w) => w.finish()?,
+            GenericZipWriter::Deflater(w) => w.finish()?.inner,
             #[cfg(feature = "bzip2")]
             GenericZipWriter::Bzip2(w) => w.finish()?,
             GenericZipWriter::Closed => {
@@ -823,15 +2078,18 @@ impl<W: Write + io::Seek> GenericZipWriter<W> {
                     feature = "deflate-zlib"
                 ))]
                 CompressionMethod::Deflated => GenericZipWriter::Deflater(DeflateEncoder::new(
-                    bare,
+                    Priming {
+                        inner: bare,
+                        discard: false,
+                    },
                     flate2::Compression::default(),
                 )),
                 #[cfg(feature = "bzip2")]
                 CompressionMethod::Bzip2 => {
                     GenericZipWriter::Bzip2(BzEncoder::new(bare, bzip2::Compression::default()))
                 }
-                CompressionMethod::Unsupported(..) => {
-                    return Err(ZipError::UnsupportedArchive("Unsupported compression"))
+                CompressionMethod::Unsupported(id) => {
+                    return Err(ZipError::UnsupportedCompression(id))
                 }
             }
         };
@@ -839,6 +2097,33 @@ impl<W: Write + io::Seek> GenericZipWriter<W> {
         Ok(())
     }
 
+    /// Compress `dictionary` and throw the result away, so that the data written next can refer
+    /// back to it. The output is flushed to a byte boundary, so the data starts a new block.
+    #[cfg_attr(
+        not(any(
+            feature = "deflate",
+            feature = "deflate-miniz",
+            feature = "deflate-zlib"
+        )),
+        allow(unused_variables)
+    )]
+    fn prime(&mut self, dictionary: &[u8]) -> io::Result<()> {
+        #[cfg(any(
+            feature = "deflate",
+            feature = "deflate-miniz",
+            feature = "deflate-zlib"
+        ))]
+        {
+            if let GenericZipWriter::Deflater(ref mut w) = *self {
+                w.get_mut().discard = true;
+                let result = w.write_all(dictionary).and_then(|_| w.flush());
+                w.get_mut().discard = false;
+                result?;
+            }
+        }
+        Ok(())
+    }
+
     fn ref_mut(&mut self) -> Option<&mut dyn Write> {
         match *self {
             GenericZipWriter::Storer(ref mut w) => Some(w as &mut dyn Write),
@@ -897,12 +2182,7 @@ fn write_local_file_header<T: Write>(writer: &mut T, file: &ZipFileData) -> ZipR
     // version needed to extract
     writer.write_u16::<LittleEndian>(file.version_needed())?;
     // general purpose bit flag
-    let flag = if !file.file_name.is_ascii() {
-        1u16 << 11
-    } else {
-        0
-    };
-    writer.write_u16::<LittleEndian>(flag)?;
+    writer.write_u16::<LittleEndian>(general_purpose_flags(file))?;
     // Compression method
     #[allow(deprecated)]
     writer.write_u16::<LittleEndian>(file.compression_method.to_u16())?;
@@ -924,7 +2204,7 @@ fn write_local_file_header<T: Write>(writer: &mut T, file: &ZipFileData) -> ZipR
         file.uncompressed_size as u32
     })?;
     // file name length
-    writer.write_u16::<LittleEndian>(file.file_name.as_bytes().len() as u16)?;
+    writer.write_u16::<LittleEndian>(file.file_name.len() as u16)?;
     // extra field length
     let extra_field_length = if file.large_file { 20 } else { 0 } + file.extra_field.len() as u16;
     writer.write_u16::<LittleEndian>(extra_field_length)?;
@@ -932,9 +2212,26 @@ fn write_local_file_header<T: Write>(writer: &mut T, file: &ZipFileData) -> ZipR
     writer.write_all(file.file_name.as_bytes())?;
     // zip64 extra field
     if file.large_file {
-        write_local_zip64_extra_field(writer, &file)?;
+        write_local_zip64_extra_field(writer, file)?;
     }
+    // extra field
+    writer.write_all(&file.extra_field)?;
+
+    Ok(())
+}
 
+fn update_local_compression_method<T: Write + io::Seek>(
+    writer: &mut T,
+    file: &ZipFileData,
+) -> ZipResult<()> {
+    const COMPRESSION_METHOD_OFFSET: u64 = 8;
+    let end = writer.seek(io::SeekFrom::Current(0))?;
+    writer.seek(io::SeekFrom::Start(
+        file.header_start + COMPRESSION_METHOD_OFFSET,
+    ))?;
+    #[allow(deprecated)]
+    writer.write_u16::<LittleEndian>(file.compression_method.to_u16())?;
+    writer.seek(io::SeekFrom::Start(end))?;
     Ok(())
 }
 
@@ -970,6 +2267,101 @@ fn update_local_file_header<T: Write + io::Seek>(
     Ok(())
 }
 
+fn external_attributes(system: System, permissions: u32, dos_attributes: u32) -> u32 {
+    match system {
+        System::Dos => {
+            let mut attributes = dos_attributes;
+            if permissions & 0o222 == 0 {
+                // Read-only
+                attributes |= 0x01;
+            }
+            if permissions & 0o170000 == 0o040000 {
+                // Directory
+                attributes |= 0x10;
+            } else {
+                // Archive, set by Windows on every file that is written
+                attributes |= 0x20;
+            }
+            attributes
+        }
+        _ => permissions << 16 | dos_attributes,
+    }
+}
+
+/// The general purpose bit flags written for `file`: the ones it was read with, and the mark
+/// for UTF-8 names if its name is stored as UTF-8
+fn general_purpose_flags(file: &ZipFileData) -> u16 {
+    if file.file_name_raw.is_empty() && !file.file_name.is_ascii() {
+        file.flags | 1u16 << 11
+    } else {
+        file.flags & !(1u16 << 11)
+    }
+}
+
+/// Write the central directory and the end of central directory record for `files` at the
+/// current position, returning where the central directory starts
+fn write_central_directory<T: Write + io::Seek>(
+    writer: &mut T,
+    files: &[ZipFileData],
+    comment: &[u8],
+) -> ZipResult<u64> {
+    let central_start = writer.seek(io::SeekFrom::Current(0))?;
+    for file in files.iter() {
+        write_central_directory_header(writer, file)?;
+    }
+    let central_size = writer.seek(io::SeekFrom::Current(0))? - central_start;
+
+    if files.len() > 0xFFFF || central_size > 0xFFFFFFFF || central_start > 0xFFFFFFFF {
+        let zip64_footer = format::Zip64CentralDirectoryEnd {
+            version_made_by: DEFAULT_VERSION as u16,
+            version_needed_to_extract: 45,
+            disk_number: 0,
+            disk_with_central_directory: 0,
+            number_of_files_on_this_disk: files.len() as u64,
+            number_of_files: files.len() as u64,
+            central_directory_size: central_size,
+            central_directory_offset: central_start,
+            extensible_data: Vec::new(),
+        };
+
+        zip64_footer.write_to(writer)?;
+
+        let zip64_footer = format::Zip64CentralDirectoryEndLocator {
+            disk_with_central_directory: 0,
+            end_of_central_directory_offset: central_start + central_size,
+            number_of_disks: 1,
+        };
+
+        zip64_footer.write_to(writer)?;
+    }
+
+    let number_of_files = if files.len() > 0xFFFF {
+        0xFFFF
+    } else {
+        files.len() as u16
+    };
+    let footer = format::CentralDirectoryEnd {
+        disk_number: 0,
+        disk_with_central_directory: 0,
+        zip_file_comment: comment.to_vec(),
+        number_of_files_on_this_disk: number_of_files,
+        number_of_files,
+        central_directory_size: if central_size > 0xFFFFFFFF {
+            0xFFFFFFFF
+        } else {
+            central_size as u32
+        },
+        central_directory_offset: if central_start > 0xFFFFFFFF {
+            0xFFFFFFFF
+        } else {
+            central_start as u32
+        },
+    };
+
+    footer.write_to(writer)?;
+    Ok(central_start)
+}
+
 fn write_central_directory_header<T: Write>(writer: &mut T, file: &ZipFileData) -> ZipResult<()> {
     // buffer zip64 extra field to determine its variable length
     let mut zip64_extra_field = [0; 28];
@@ -984,12 +2376,7 @@ fn write_central_directory_header<T: Write>(writer: &mut T, file: &ZipFileData)
     // version needed to extract
     writer.write_u16::<LittleEndian>(file.version_needed())?;
     // general puprose bit flag
-    let flag = if !file.file_name.is_ascii() {
-        1u16 << 11
-    } else {
-        0
-    };
-    writer.write_u16::<LittleEndian>(flag)?;
+    writer.write_u16::<LittleEndian>(general_purpose_flags(file))?;
     // compression method
     #[allow(deprecated)]
     writer.write_u16::<LittleEndian>(file.compression_method.to_u16())?;
@@ -1011,15 +2398,15 @@ fn write_central_directory_header<T: Write>(writer: &mut T, file: &ZipFileData)
         file.uncompressed_size as u32
     })?;
     // file name length
-    writer.write_u16::<LittleEndian>(file.file_name.as_bytes().len() as u16)?;
+    writer.write_u16::<LittleEndian>(file.name_raw().len() as u16)?;
     // extra field length
     writer.write_u16::<LittleEndian>(zip64_extra_field_length + file.extra_field.len() as u16)?;
     // file comment length
-    writer.write_u16::<LittleEndian>(0)?;
+    writer.write_u16::<LittleEndian>(file.file_comment.len() as u16)?;
     // disk number start
-    writer.write_u16::<LittleEndian>(0)?;
+    writer.write_u16::<LittleEndian>(file.disk_number as u16)?;
     // internal file attribytes
-    writer.write_u16::<LittleEndian>(0)?;
+    writer.write_u16::<LittleEndian>(file.internal_attributes)?;
     // external file attributes
     writer.write_u32::<LittleEndian>(file.external_attributes)?;
     // relative offset of local header
@@ -1029,13 +2416,13 @@ fn write_central_directory_header<T: Write>(writer: &mut T, file: &ZipFileData)
         file.header_start as u32
     })?;
     // file name
-    writer.write_all(file.file_name.as_bytes())?;
+    writer.write_all(file.name_raw())?;
     // zip64 extra field
     writer.write_all(&zip64_extra_field[..zip64_extra_field_length as usize])?;
     // extra field
     writer.write_all(&file.extra_field)?;
     // file comment
-    // <none>
+    writer.write_all(file.file_comment.as_bytes())?;
 
     Ok(())
 }
@@ -1050,7 +2437,7 @@ fn validate_extra_data(file: &ZipFileData) -> ZipResult<()> {
         )));
     }
 
-    while data.len() > 0 {
+    while !data.is_empty() {
         let left = data.len();
         if left < 4 {
             return Err(ZipError::Io(io::Error::new(
@@ -1071,7 +2458,7 @@ fn validate_extra_data(file: &ZipFileData) -> ZipResult<()> {
 
         #[cfg(not(feature = "unreserved"))]
         {
-            if kind <= 31 || EXTRA_FIELD_MAPPING.iter().any(|&mapped| mapped == kind) {
+            if kind <= 31 || EXTRA_FIELD_MAPPING.contains(&kind) {
                 return Err(ZipError::Io(io::Error::new(
                     io::ErrorKind::Other,
                     format!(
@@ -1111,7 +2498,7 @@ fn update_local_zip64_extra_field<T: Write + io::Seek>(
     writer: &mut T,
     file: &ZipFileData,
 ) -> ZipResult<()> {
-    let zip64_extra_field = file.header_start + 30 + file.file_name.as_bytes().len() as u64;
+    let zip64_extra_field = file.header_start + 30 + file.file_name.len() as u64;
     writer.seek(io::SeekFrom::Start(zip64_extra_field + 4))?;
     writer.write_u64::<LittleEndian>(file.uncompressed_size)?;
     writer.write_u64::<LittleEndian>(file.compressed_size)?;
@@ -1120,6 +2507,26 @@ fn update_local_zip64_extra_field<T: Write + io::Seek>(
     Ok(())
 }
 
+/// Turn the ZIP64 extra field reserved in the local header of `file` into padding, for files
+/// that turned out not to need it, see [`ZipWriter::add_file_from_reader`]
+fn release_local_zip64_extra_field<T: Write + io::Seek>(
+    writer: &mut T,
+    file: &ZipFileData,
+) -> ZipResult<()> {
+    const VERSION_NEEDED_OFFSET: u64 = 4;
+    writer.seek(io::SeekFrom::Start(
+        file.header_start + VERSION_NEEDED_OFFSET,
+    ))?;
+    writer.write_u16::<LittleEndian>(file.version_to_extract)?;
+    let zip64_extra_field = file.header_start + 30 + file.file_name.len() as u64;
+    writer.seek(io::SeekFrom::Start(zip64_extra_field))?;
+    // The same padding as in `ZipWriter::start_file_aligned`
+    writer.write_all(b"za")?;
+    writer.write_u16::<LittleEndian>(16)?;
+    writer.write_all(&[0; 16])?;
+    Ok(())
+}
+
 fn write_central_zip64_extra_field<T: Write>(writer: &mut T, file: &ZipFileData) -> ZipResult<u16> {
     // The order of the fields in the zip64 extended
     // information record is fixed, but the fields MUST
@@ -1165,17 +2572,26 @@ fn path_to_string(path: &std::path::Path) -> String {
             if !path_str.is_empty() {
                 path_str.push('/');
             }
-            path_str.push_str(&*os_str.to_string_lossy());
+            path_str.push_str(&os_str.to_string_lossy());
         }
     }
     path_str
 }
 
+#[cfg(not(feature = "unreserved"))]
+const EXTRA_FIELD_MAPPING: [u16; 49] = [
+    0x0001, 0x0007, 0x0008, 0x0009, 0x000a, 0x000c, 0x000d, 0x000e, 0x000f, 0x0014, 0x0015, 0x0016,
+    0x0017, 0x0018, 0x0019, 0x0020, 0x0021, 0x0022, 0x0023, 0x0065, 0x0066, 0x4690, 0x07c8, 0x2605,
+    0x2705, 0x2805, 0x334d, 0x4341, 0x4453, 0x4704, 0x470f, 0x4b46, 0x4c41, 0x4d49, 0x4f4c, 0x5356,
+    0x5455, 0x554e, 0x5855, 0x6375, 0x6542, 0x7075, 0x756e, 0x7855, 0xa11e, 0xa220, 0xfd4a, 0x9901,
+    0x9902,
+];
+
 #[cfg(test)]
 mod test {
     use super::{FileOptions, ZipWriter};
     use crate::compression::CompressionMethod;
-    use crate::types::DateTime;
+    use crate::types::{DateTime, System};
     use std::io;
     use std::io::Write;
 
@@ -1191,6 +2607,77 @@ mod test {
         );
     }
 
+    #[test]
+    fn write_split_zip() {
+        use crate::read::{SplitArchiveReader, ZipArchive};
+        use std::io::Read;
+
+        let contents: Vec<Vec<u8>> = (0..3u8).map(|i| vec![i; 50_000]).collect();
+        let mut writer =
+            ZipWriter::new_split(|_| Ok(io::Cursor::new(Vec::new())), 64 * 1024).unwrap();
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        for (i, data) in contents.iter().enumerate() {
+            writer.start_file(format!("file{}", i), options).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        let segments = writer.finish().unwrap().into_inner();
+
+        assert_eq!(segments.len(), 3);
+        assert_eq!(&segments[0].get_ref()[..4], b"PK\x07\x08");
+        assert!(segments.iter().all(|s| s.get_ref().len() <= 64 * 1024));
+
+        let reader = SplitArchiveReader::new(segments).unwrap();
+        let mut zip = ZipArchive::new_split(reader).unwrap();
+        assert_eq!(zip.len(), 3);
+        for (i, data) in contents.iter().enumerate() {
+            let mut read = Vec::new();
+            zip.by_index(i).unwrap().read_to_end(&mut read).unwrap();
+            assert_eq!(&read, data);
+        }
+    }
+
+    #[test]
+    fn write_split_zip_single_segment() {
+        use crate::read::ZipArchive;
+
+        let mut writer =
+            ZipWriter::new_split(|_| Ok(io::Cursor::new(Vec::new())), 64 * 1024).unwrap();
+        writer.start_file("file", FileOptions::default()).unwrap();
+        writer.write_all(b"contents").unwrap();
+        let mut segments = writer.finish().unwrap().into_inner();
+
+        assert_eq!(segments.len(), 1);
+        let segment = segments.pop().unwrap();
+        assert_eq!(&segment.get_ref()[..4], b"PK00");
+        let mut zip = ZipArchive::new(segment).unwrap();
+        assert_eq!(zip.by_index(0).unwrap().name(), "file");
+
+        let failing = |_| -> io::Result<io::Cursor<Vec<u8>>> {
+            Err(io::Error::new(io::ErrorKind::Other, "no space"))
+        };
+        assert!(ZipWriter::new_split(failing, 64 * 1024).is_err());
+    }
+
+    #[test]
+    fn write_zip_with_prepended_data() {
+        use crate::read::ZipArchive;
+        use std::io::Read;
+
+        let stub = b"#!/bin/sh\nexit 0\n".to_vec();
+        let mut writer = ZipWriter::new_with_prepended_data(io::Cursor::new(stub.clone())).unwrap();
+        writer.start_file("file", FileOptions::default()).unwrap();
+        writer.write_all(b"contents").unwrap();
+        let result = writer.finish().unwrap().into_inner();
+        assert!(result.starts_with(&stub));
+
+        let mut zip = ZipArchive::new(io::Cursor::new(result)).unwrap();
+        let mut file = zip.by_index(0).unwrap();
+        assert_eq!(file.header_start(), stub.len() as u64);
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "contents");
+    }
+
     #[test]
     fn write_zip_dir() {
         let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
@@ -1227,10 +2714,15 @@ mod test {
             last_modified_time: DateTime::default(),
             permissions: Some(33188),
             large_file: false,
+            alignment: 1,
+            text_file: false,
+            system: System::Unix,
+            dos_attributes: 0,
+            store_incompressible: false,
         };
         writer.start_file("mimetype", options).unwrap();
         writer
-            .write(b"application/vnd.oasis.opendocument.text")
+            .write_all(b"application/vnd.oasis.opendocument.text")
             .unwrap();
         let result = writer.finish().unwrap();
 
@@ -1240,6 +2732,570 @@ mod test {
         assert_eq!(result.get_ref(), &v);
     }
 
+    #[test]
+    fn write_stored_first() {
+        use std::io::Seek;
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().large_file(true).with_alignment(4);
+        writer.start_file_stored_first("mimetype", options).unwrap();
+        writer.write_all(b"application/epub+zip").unwrap();
+        assert!(writer.start_file_stored_first("mimetype", options).is_err());
+        let result = writer.finish().unwrap().into_inner();
+        assert_eq!(&result[8..10], &[0, 0][..]);
+        assert_eq!(&result[28..30], &[0, 0][..]);
+        assert_eq!(&result[30..38], b"mimetype");
+        assert_eq!(&result[38..58], b"application/epub+zip");
+
+        let mut prepended = io::Cursor::new(b"#!/bin/sh\n".to_vec());
+        prepended.seek(io::SeekFrom::End(0)).unwrap();
+        let mut writer = ZipWriter::new(prepended);
+        assert!(writer.start_file_stored_first("mimetype", options).is_err());
+    }
+
+    #[test]
+    fn write_aligned_zip() {
+        use crate::read::ZipArchive;
+        use std::io::Read;
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for (name, alignment) in &[("a.txt", 4), ("lib/libfoo.so", 4096), ("b.txt", 4)] {
+            let options = FileOptions::default()
+                .compression_method(CompressionMethod::Stored)
+                .with_alignment(*alignment);
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(name.as_bytes()).unwrap();
+        }
+        let result = writer.finish().unwrap();
+
+        let mut archive = ZipArchive::new(result).unwrap();
+        for (name, alignment) in &[("a.txt", 4), ("lib/libfoo.so", 4096), ("b.txt", 4)] {
+            let mut file = archive.by_name(name).unwrap();
+            assert_eq!(file.data_start() % alignment, 0);
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, *name);
+        }
+    }
+
+    #[test]
+    fn write_deterministic_zip() {
+        fn write(time: DateTime, mode: u32) -> Vec<u8> {
+            let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+            writer.set_deterministic(true);
+            let options = FileOptions::default().last_modified_time(time);
+            writer
+                .add_directory("bin", options.unix_permissions(0o700))
+                .unwrap();
+            writer
+                .start_file("bin/run", options.unix_permissions(mode | 0o100))
+                .unwrap();
+            writer.write_all(b"#!/bin/sh").unwrap();
+            writer
+                .start_file("README", options.unix_permissions(mode))
+                .unwrap();
+            writer.write_all(b"Read me").unwrap();
+            writer.finish().unwrap().into_inner()
+        }
+
+        let first = write(
+            DateTime::from_date_and_time(2020, 1, 2, 3, 4, 6).unwrap(),
+            0o600,
+        );
+        let second = write(
+            DateTime::from_date_and_time(2021, 6, 7, 8, 9, 10).unwrap(),
+            0o664,
+        );
+        assert_eq!(first, second);
+
+        let mut archive = crate::read::ZipArchive::new(io::Cursor::new(first)).unwrap();
+        let modes: Vec<_> = (0..archive.len())
+            .map(|i| archive.by_index(i).unwrap().unix_mode().unwrap())
+            .collect();
+        assert_eq!(modes, vec![0o40755, 0o100755, 0o100644]);
+        let time = archive.by_index(0).unwrap().last_modified();
+        assert_eq!((time.year(), time.month(), time.day()), (1980, 1, 1));
+    }
+
+    #[test]
+    #[cfg(feature = "digest")]
+    fn write_deduplicated() {
+        use super::Deduplicate;
+        use std::io::Read;
+
+        fn write(mode: Deduplicate, files: &[(&str, &[u8])]) -> (Vec<u8>, Vec<(String, String)>) {
+            let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+            writer.set_deduplicate::<sha2::Sha256>(mode);
+            let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+            for &(name, data) in files.iter() {
+                writer.start_file(name, options).unwrap();
+                writer.write_all(data).unwrap();
+            }
+            writer.finish_file().unwrap();
+            let duplicates = writer.duplicates().to_vec();
+            (writer.finish().unwrap().into_inner(), duplicates)
+        }
+
+        let (big, other) = (vec![7; 10_000], vec![8; 10_000]);
+        let files: &[(&str, &[u8])] = &[
+            ("a", b"same"),
+            ("b", b"other"),
+            ("big", &big),
+            ("big copy", &big),
+            ("c", b"same"),
+            ("empty", b""),
+            ("empty too", b""),
+            ("other", &other),
+        ];
+        let (plain, duplicates) = write(Deduplicate::Off, files);
+        assert!(duplicates.is_empty());
+        let (shared, duplicates) = write(Deduplicate::Share, files);
+        let expected = vec![
+            ("big copy".to_string(), "big".to_string()),
+            ("c".to_string(), "a".to_string()),
+        ];
+        assert_eq!(duplicates, expected);
+        let (skipped, duplicates) = write(Deduplicate::Skip, files);
+        assert_eq!(duplicates, expected);
+        assert!(skipped.len() < shared.len() && shared.len() < plain.len() - big.len());
+
+        let mut archive = crate::read::ZipArchive::new(io::Cursor::new(shared.clone())).unwrap();
+        assert_eq!(archive.len(), 8);
+        assert!(archive.validate().is_empty());
+        let config = crate::read::Config::default().check_local_headers(true);
+        let mut checked =
+            crate::read::ZipArchive::new_with_config(io::Cursor::new(shared), config).unwrap();
+        assert_eq!(checked.by_name("c").unwrap().size(), 4);
+        let mut contents = Vec::new();
+        archive
+            .by_name("big copy")
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, big);
+        let file = archive.by_name("c").unwrap();
+        assert_eq!((file.name(), file.size()), ("c", 4));
+
+        let archive = crate::read::ZipArchive::new(io::Cursor::new(skipped)).unwrap();
+        let names: Vec<_> = archive.file_names().collect();
+        assert_eq!(names.len(), 6);
+        assert!(!names.contains(&"c") && !names.contains(&"big copy"));
+
+        // The data of a duplicate at the end stays in the archive, before the central directory
+        let (shared, _) = write(Deduplicate::Share, &[("big", &big), ("big copy", &big)]);
+        let mut archive = crate::read::ZipArchive::new(io::Cursor::new(shared)).unwrap();
+        let mut contents = Vec::new();
+        archive
+            .by_name("big copy")
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, big);
+
+        // Raw data is compared by its bytes, not by the CRC32 and size it is claimed to have
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.set_deduplicate::<sha2::Sha256>(Deduplicate::Share);
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        for &(name, data) in [("a", b"same"), ("b", b"fake"), ("c", b"same")].iter() {
+            writer.start_file_raw(name, options, 4, 4, 1234).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish_file().unwrap();
+        let duplicate = ("c".to_string(), "a".to_string());
+        assert_eq!(writer.duplicates(), &[duplicate]);
+    }
+
+    #[test]
+    fn write_version_needed() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        writer.start_file("small.txt", options).unwrap();
+        writer.write_all(b"small").unwrap();
+        writer
+            .start_file("large.txt", options.large_file(true))
+            .unwrap();
+        writer.write_all(b"large").unwrap();
+        let result = writer.finish().unwrap();
+
+        let mut archive = crate::read::ZipArchive::new(result).unwrap();
+        let small = archive.by_name("small.txt").unwrap();
+        assert_eq!(small.version_needed_to_extract(), (2, 0));
+        drop(small);
+        let large = archive.by_name("large.txt").unwrap();
+        assert_eq!(large.version_needed_to_extract(), (4, 5));
+    }
+
+    #[test]
+    fn write_text_flag() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default();
+        writer
+            .start_file("text.txt", options.text_file(true))
+            .unwrap();
+        writer.write_all(b"text\n").unwrap();
+        writer.start_file("binary.bin", options).unwrap();
+        writer.write_all(&[0, 1, 2]).unwrap();
+        let result = writer.finish().unwrap();
+
+        let mut archive = crate::read::ZipArchive::new(result).unwrap();
+        assert!(archive.by_name("text.txt").unwrap().is_text());
+        assert!(!archive.by_name("binary.bin").unwrap().is_text());
+    }
+
+    #[test]
+    fn write_dos_attributes() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().creator_system(System::Dos);
+        writer.add_directory("dir", options).unwrap();
+        writer
+            .start_file("dir/read-only.txt", options.unix_permissions(0o444))
+            .unwrap();
+        writer.start_file("dir/file.txt", options).unwrap();
+        let result = writer.finish().unwrap();
+
+        let mut archive = crate::read::ZipArchive::new(result).unwrap();
+        let modes: Vec<_> = (0..archive.len())
+            .map(|i| archive.by_index(i).unwrap().unix_mode().unwrap())
+            .collect();
+        assert_eq!(modes, vec![0o40775, 0o100444, 0o100664]);
+    }
+
+    #[test]
+    fn options_round_trip() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .last_modified_time(DateTime::from_date_and_time(2020, 2, 29, 12, 30, 0).unwrap())
+            .unix_permissions(0o640)
+            .text_file(true)
+            .hidden(true);
+        writer.start_file("original.txt", options).unwrap();
+        let mut archive = crate::read::ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = archive.by_index(0).unwrap().options();
+        writer.start_file("copy.txt", options).unwrap();
+        let mut copy = crate::read::ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let original = archive.by_index(0).unwrap();
+        let copy = copy.by_index(0).unwrap();
+        assert_eq!(copy.compression(), CompressionMethod::Stored);
+        assert_eq!(copy.last_modified(), original.last_modified());
+        assert_eq!(copy.unix_mode(), Some(0o100640));
+        assert!(copy.is_text() && copy.is_hidden());
+    }
+
+    #[test]
+    fn raw_copy_keeps_comment_and_extra_fields() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        writer.start_file_with_extra_data("file", options).unwrap();
+        writer.write_all(b"\xfe\xca\x03\0abc").unwrap();
+        writer.end_extra_data().unwrap();
+        writer.write_all(b"contents").unwrap();
+        writer.files.last_mut().unwrap().file_comment = "comment".to_string();
+        let mut archive = crate::read::ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.raw_copy_file(archive.by_index(0).unwrap()).unwrap();
+        let mut copy = crate::read::ZipArchive::new(writer.finish().unwrap()).unwrap();
+        let mut copy = copy.by_index(0).unwrap();
+        assert_eq!(copy.comment(), "comment");
+        assert_eq!(copy.extra_data(), b"\xfe\xca\x03\0abc");
+        let mut contents = String::new();
+        io::Read::read_to_string(&mut copy, &mut contents).unwrap();
+        assert_eq!(contents, "contents");
+    }
+
+    #[test]
+    fn copied_extra_fields() {
+        let mut extra_field = b"\x01\0\x08\0\0\0\0\0\0\0\0\x01".to_vec();
+        extra_field.extend_from_slice(b"\xfe\xca\x03\0abc");
+        extra_field.extend_from_slice(b"za\x02\0\0\0");
+        let dictionary = crate::dictionary::extra_field(b"dictionary");
+        extra_field.extend_from_slice(&dictionary);
+        // Cut off
+        extra_field.extend_from_slice(b"\xef\xbe\x10\0");
+        let mut copied = b"\xfe\xca\x03\0abc".to_vec();
+        copied.extend_from_slice(&dictionary);
+        assert_eq!(super::copied_extra_fields(&extra_field), copied);
+    }
+
+    #[test]
+    fn write_hidden_and_system_files() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for &system in &[System::Dos, System::Unix] {
+            let options = FileOptions::default().creator_system(system);
+            writer
+                .start_file(format!("{:?}/hidden", system), options.hidden(true))
+                .unwrap();
+            let options = options.hidden(true).system_file(true).hidden(false);
+            writer
+                .start_file(format!("{:?}/system", system), options)
+                .unwrap();
+        }
+        let result = writer.finish().unwrap();
+
+        let mut archive = crate::read::ZipArchive::new(result).unwrap();
+        for system in &["Dos", "Unix"] {
+            let file = archive.by_name(&format!("{}/hidden", system)).unwrap();
+            assert!(file.is_hidden() && !file.is_system_file());
+            drop(file);
+            let file = archive.by_name(&format!("{}/system", system)).unwrap();
+            assert!(!file.is_hidden() && file.is_system_file());
+        }
+    }
+
+    #[test]
+    fn write_from_reader() {
+        use std::io::Read;
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let data = vec![b'z'; 100_000];
+        let written = writer
+            .add_file_from_reader("data.bin", FileOptions::default(), &mut &data[..])
+            .unwrap();
+        assert_eq!(written, data.len() as u64);
+        let result = writer.finish().unwrap();
+
+        let mut archive = crate::read::ZipArchive::new(result).unwrap();
+        let mut file = archive.by_name("data.bin").unwrap();
+        assert_eq!(file.size(), data.len() as u64);
+        // The ZIP64 extra field that was reserved in case the file got large isn't used
+        assert_eq!(file.version_needed_to_extract(), (2, 0));
+        assert_eq!(&file.local_extra_data()[..4], b"za\x10\0");
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, data);
+    }
+
+    #[test]
+    fn write_raw() {
+        use std::io::Read;
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer
+            .start_file("data.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(&[b'a'; 1000]).unwrap();
+        let mut source = crate::read::ZipArchive::new(writer.finish().unwrap()).unwrap();
+        let mut file = source.by_index_raw(0).unwrap();
+        let (compression, size, crc32) = (file.compression(), file.size(), file.crc32());
+        let mut compressed = Vec::new();
+        file.read_to_end(&mut compressed).unwrap();
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(compression);
+        let length = compressed.len() as u64;
+        writer
+            .start_file_raw("copy.txt", options, length, size, crc32)
+            .unwrap();
+        writer.write_all(&compressed).unwrap();
+        let stats = writer.finish_file().unwrap().unwrap();
+        assert_eq!(stats.crc32(), crc32);
+        // Finishing twice doesn't touch the raw values
+        assert_eq!(writer.finish_file().unwrap(), Some(stats));
+
+        writer
+            .start_file_raw("short.txt", options, length, size, crc32)
+            .unwrap();
+        writer.write_all(&compressed[1..]).unwrap();
+        assert!(writer.finish_file().is_err());
+
+        // The file that failed is left out
+        let mut archive = crate::read::ZipArchive::new(writer.finish().unwrap()).unwrap();
+        assert_eq!(archive.file_names().collect::<Vec<_>>(), vec!["copy.txt"]);
+        let mut contents = Vec::new();
+        archive
+            .by_name("copy.txt")
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, vec![b'a'; 1000]);
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.set_verify_raw_data(true);
+        writer
+            .start_file_raw("copy.txt", options, length, size, crc32)
+            .unwrap();
+        writer.write_all(&compressed).unwrap();
+        writer.finish_file().unwrap();
+        writer
+            .start_file_raw("wrong.txt", options, length, size, crc32 ^ 1)
+            .unwrap();
+        writer.write_all(&compressed).unwrap();
+        assert!(writer.finish_file().is_err());
+        writer
+            .start_file("next.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"next").unwrap();
+        let mut archive = crate::read::ZipArchive::new(writer.finish().unwrap()).unwrap();
+        let mut names: Vec<_> = archive.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["copy.txt", "next.txt"]);
+        let mut contents = String::new();
+        archive
+            .by_name("next.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "next");
+    }
+
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    ))]
+    #[test]
+    fn write_deflate_dictionary() {
+        use crate::result::ZipError;
+        use std::io::{Read, Seek};
+
+        let dictionary = br#"{"name": "", "kind": "item", "description": "", "value": 0}"#;
+        let contents = br#"{"name": "sword", "kind": "item", "description": "sharp", "value": 10}"#;
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.start_file("plain.json", options).unwrap();
+        writer.write_all(contents).unwrap();
+        let plain = writer.finish_file().unwrap().unwrap();
+        writer.set_deflate_dictionary(Some(&dictionary[..]));
+        writer.start_file("primed.json", options).unwrap();
+        writer.write_all(contents).unwrap();
+        let primed = writer.finish_file().unwrap().unwrap();
+        assert!(primed.compressed_size() < plain.compressed_size());
+        let mut archive = crate::read::ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let error = archive
+            .by_name("primed.json")
+            .unwrap()
+            .read(&mut [0; 16])
+            .unwrap_err();
+        match error.get_ref().and_then(|e| e.downcast_ref::<ZipError>()) {
+            Some(ZipError::UnsupportedArchive(message)) => {
+                assert_eq!(*message, ZipError::DICTIONARY_REQUIRED)
+            }
+            _ => panic!("unexpected error {:?}", error),
+        }
+
+        archive.add_deflate_dictionary(&dictionary[..]);
+        for name in &["plain.json", "primed.json"] {
+            let mut file = archive.by_name(name).unwrap();
+            let mut read = Vec::new();
+            file.read_to_end(&mut read).unwrap();
+            assert_eq!(read, &contents[..]);
+            file.seek(io::SeekFrom::Start(10)).unwrap();
+            read.clear();
+            file.read_to_end(&mut read).unwrap();
+            assert_eq!(read, &contents[10..]);
+        }
+
+        // A raw copy keeps the dictionary id, as its data is still primed with the dictionary
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer
+            .raw_copy_file(archive.by_name("primed.json").unwrap())
+            .unwrap();
+        let mut copy = crate::read::ZipArchive::new(writer.finish().unwrap()).unwrap();
+        copy.add_deflate_dictionary(&dictionary[..]);
+        let mut read = Vec::new();
+        copy.by_index(0).unwrap().read_to_end(&mut read).unwrap();
+        assert_eq!(read, &contents[..]);
+    }
+
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    ))]
+    #[test]
+    fn write_store_incompressible() {
+        use std::io::Read;
+
+        let mut noise = Vec::new();
+        let mut state = 1u32;
+        for _ in 0..1000 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            noise.push((state >> 16) as u8);
+        }
+        let text = b"hello hello hello hello hello hello".to_vec();
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .store_incompressible(true);
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for (name, contents) in &[("noise.bin", &noise), ("text.txt", &text)] {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        let mut archive = crate::read::ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        for (name, contents, method) in &[
+            ("noise.bin", &noise, CompressionMethod::Stored),
+            ("text.txt", &text, CompressionMethod::Deflated),
+        ] {
+            let mut file = archive.by_name(name).unwrap();
+            assert_eq!(file.compression(), *method);
+            assert!(file.compressed_size() <= file.size());
+            let mut read = Vec::new();
+            file.read_to_end(&mut read).unwrap();
+            assert_eq!(&read, *contents);
+        }
+    }
+
+    #[test]
+    fn write_stats() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        assert_eq!(writer.finish_file().unwrap(), None);
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        writer.start_file("stored.txt", options).unwrap();
+        writer.write_all(b"hello").unwrap();
+        let stats = writer.finish_file().unwrap().unwrap();
+        assert_eq!(stats.size(), 5);
+        assert_eq!(stats.compressed_size(), 5);
+        assert_eq!(stats.crc32(), 0x3610a686);
+        assert_eq!(stats.compression_ratio(), 1.0);
+
+        writer.add_directory("dir", options).unwrap();
+        writer.start_file("more.txt", options).unwrap();
+        writer.write_all(b"world!").unwrap();
+        assert_eq!(writer.stats().size(), 5);
+        assert_eq!(writer.stats().central_directory_offset(), None);
+        let result = writer.finish().unwrap();
+        let stats = writer.stats();
+        assert_eq!(stats.files(), 3);
+        assert_eq!(stats.size(), 11);
+        assert_eq!(stats.compressed_size(), 11);
+
+        let archive = crate::read::ZipArchive::new(result).unwrap();
+        assert_eq!(
+            stats.central_directory_offset(),
+            Some(archive.central_directory_start())
+        );
+    }
+
+    #[test]
+    fn write_spooled() {
+        use super::SpoolingWriter;
+        use std::io::Read;
+
+        let mut writer = ZipWriter::new(SpoolingWriter::new(Vec::new()));
+        for name in ["first.txt", "second.txt"].iter() {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(name.as_bytes()).unwrap();
+        }
+        let result = writer.finish().unwrap().into_inner().unwrap();
+
+        // The uncompressed size is filled in in the local header
+        assert_eq!(&result[22..26], &[9, 0, 0, 0][..]);
+        let mut archive = crate::read::ZipArchive::new(io::Cursor::new(result)).unwrap();
+        let mut contents = String::new();
+        archive
+            .by_name("second.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "second.txt");
+    }
+
     #[test]
     fn path_to_string() {
         let mut path = std::path::PathBuf::new();
@@ -1255,12 +3311,3 @@ mod test {
         assert_eq!(path_str, "windows/system32");
     }
 }
-
-#[cfg(not(feature = "unreserved"))]
-const EXTRA_FIELD_MAPPING: [u16; 49] = [
-    0x0001, 0x0007, 0x0008, 0x0009, 0x000a, 0x000c, 0x000d, 0x000e, 0x000f, 0x0014, 0x0015, 0x0016,
-    0x0017, 0x0018, 0x0019, 0x0020, 0x0021, 0x0022, 0x0023, 0x0065, 0x0066, 0x4690, 0x07c8, 0x2605,
-    0x2705, 0x2805, 0x334d, 0x4341, 0x4453, 0x4704, 0x470f, 0x4b46, 0x4c41, 0x4d49, 0x4f4c, 0x5356,
-    0x5455, 0x554e, 0x5855, 0x6375, 0x6542, 0x7075, 0x756e, 0x7855, 0xa11e, 0xa220, 0xfd4a, 0x9901,
-    0x9902,
-];
@@ -0,0 +1,449 @@
+//! Creating a whole archive at once
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use super::{path_to_string, FileOptions, ZipWriter};
+use crate::compression::CompressionMethod;
+use crate::result::ZipResult;
+
+/// Builder for an archive of files on disk and data in memory, written in one go
+///
+/// Entries are written in the order they were added, with the contents of a directory sorted by
+/// name, except for those moved to the front with [`ZipBuilder::first`]. Directory entries are
+/// created for the parents of every file that is added, unless disabled with
+/// [`ZipBuilder::directory_entries`].
+///
+/// ```no_run
+/// use zip::write::{WalkOptions, ZipBuilder};
+/// use zip::CompressionMethod;
+///
+/// let file = std::fs::File::create("release.zip")?;
+/// ZipBuilder::new()
+///     .compression_for_extension("png", CompressionMethod::Stored)
+///     .add_path("src/", WalkOptions::default())
+///     .add_bytes("VERSION", b"1.0")
+///     .write_to(file)?;
+/// # Ok::<(), zip::result::ZipError>(())
+/// ```
+///
+/// Some JVM tools expect the manifest at the start of a JAR, and don't need directory entries:
+///
+/// ```no_run
+/// use zip::write::{WalkOptions, ZipBuilder};
+///
+/// let file = std::fs::File::create("app.jar")?;
+/// ZipBuilder::new()
+///     .first(vec!["META-INF/", "META-INF/MANIFEST.MF"])
+///     .directory_entries(false)
+///     .add_path("classes/", WalkOptions::default().archive_name(""))
+///     .add_bytes("META-INF/MANIFEST.MF", b"Manifest-Version: 1.0\r\n")
+///     .write_to(file)?;
+/// # Ok::<(), zip::result::ZipError>(())
+/// ```
+pub struct ZipBuilder {
+    options: FileOptions,
+    methods: HashMap<String, CompressionMethod>,
+    sources: Vec<Source>,
+    first: Vec<String>,
+    directory_entries: bool,
+}
+
+impl Default for ZipBuilder {
+    fn default() -> ZipBuilder {
+        ZipBuilder {
+            options: FileOptions::default(),
+            methods: HashMap::new(),
+            sources: Vec::new(),
+            first: Vec::new(),
+            directory_entries: true,
+        }
+    }
+}
+
+enum Source {
+    Path(PathBuf, WalkOptions),
+    Bytes(String, Vec<u8>),
+}
+
+/// An entry of the archive, collected before anything is written so the entries can be reordered
+enum Entry {
+    Directory(String, FileOptions),
+    File(String, FileOptions, PathBuf),
+    Bytes(String, FileOptions, Vec<u8>),
+}
+
+impl Entry {
+    fn name(&self) -> &str {
+        match self {
+            Entry::Directory(name, _) | Entry::File(name, _, _) | Entry::Bytes(name, _, _) => name,
+        }
+    }
+}
+
+/// How a path on disk is added by [`ZipBuilder::add_path`]
+#[derive(Clone, Default)]
+pub struct WalkOptions {
+    options: Option<FileOptions>,
+    name: Option<String>,
+}
+
+impl WalkOptions {
+    /// Set the options for the files and directories under this path
+    ///
+    /// The default is to use the options of the [`ZipBuilder`]. Unless permissions are set, the
+    /// unix permissions of every file are taken from the file system.
+    pub fn file_options(mut self, options: FileOptions) -> WalkOptions {
+        self.options = Some(options);
+        self
+    }
+
+    /// Set the name of the path inside the archive
+    ///
+    /// The default is the last component of the path. With an empty name, the contents of a
+    /// directory are added at the top of the archive.
+    pub fn archive_name<S: Into<String>>(mut self, name: S) -> WalkOptions {
+        self.name = Some(name.into());
+        self
+    }
+}
+
+impl ZipBuilder {
+    /// Start an empty archive
+    pub fn new() -> ZipBuilder {
+        ZipBuilder::default()
+    }
+
+    /// Set the options for every entry that doesn't have options of its own
+    pub fn file_options(mut self, options: FileOptions) -> ZipBuilder {
+        self.options = options;
+        self
+    }
+
+    /// Compress files whose name ends in `.extension` with `method`, whatever their options say
+    ///
+    /// Extensions are matched without regard to case, so `"jpg"` also matches `photo.JPG`. This
+    /// is meant for files that are compressed already, like images and videos, which are
+    /// quicker to store than to compress again.
+    pub fn compression_for_extension<S: AsRef<str>>(
+        mut self,
+        extension: S,
+        method: CompressionMethod,
+    ) -> ZipBuilder {
+        let extension = extension.as_ref().trim_start_matches('.').to_lowercase();
+        self.methods.insert(extension, method);
+        self
+    }
+
+    /// Write the entries with these names before all others, in the given order
+    ///
+    /// Names of directories end in a slash. Names that aren't in the archive are ignored.
+    pub fn first<I, S>(mut self, names: I) -> ZipBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.first = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set whether directories get entries of their own. The default is `true`.
+    ///
+    /// Without directory entries, empty directories are left out, and directories can't have
+    /// permissions or modification times of their own.
+    pub fn directory_entries(mut self, directory_entries: bool) -> ZipBuilder {
+        self.directory_entries = directory_entries;
+        self
+    }
+
+    /// Add a file, or a directory with everything below it
+    ///
+    /// Symbolic links to files are followed, symbolic links to directories inside a directory
+    /// are skipped. Nothing is read until [`ZipBuilder::write_to`] is called.
+    pub fn add_path<P: AsRef<Path>>(mut self, path: P, options: WalkOptions) -> ZipBuilder {
+        self.sources
+            .push(Source::Path(path.as_ref().to_path_buf(), options));
+        self
+    }
+
+    /// Add a file with the given contents
+    pub fn add_bytes<S, B>(mut self, name: S, data: B) -> ZipBuilder
+    where
+        S: Into<String>,
+        B: AsRef<[u8]>,
+    {
+        self.sources
+            .push(Source::Bytes(name.into(), data.as_ref().to_vec()));
+        self
+    }
+
+    /// Write the archive to `writer`, and return the writer afterwards
+    pub fn write_to<W: Write + Seek>(self, writer: W) -> ZipResult<W> {
+        let mut builder = Builder {
+            entries: Vec::new(),
+            directories: HashSet::new(),
+            methods: self.methods,
+            directory_entries: self.directory_entries,
+        };
+        for source in self.sources {
+            match source {
+                Source::Path(path, walk) => {
+                    let name = match walk.name {
+                        Some(name) => path_to_string(Path::new(&name)),
+                        None => path
+                            .file_name()
+                            .map(|name| name.to_string_lossy().into_owned())
+                            .unwrap_or_default(),
+                    };
+                    builder.add_path(&path, name, walk.options.unwrap_or(self.options))?;
+                }
+                Source::Bytes(name, data) => {
+                    builder.add_parents(&name, self.options);
+                    let options = builder.file_options(&name, self.options);
+                    builder.entries.push(Entry::Bytes(name, options, data));
+                }
+            }
+        }
+
+        let mut entries = builder.entries;
+        if !self.first.is_empty() {
+            let mut first = Vec::new();
+            for name in self.first.iter() {
+                if let Some(index) = entries.iter().position(|entry| entry.name() == name) {
+                    first.push(entries.remove(index));
+                }
+            }
+            first.extend(entries);
+            entries = first;
+        }
+
+        let mut zip = ZipWriter::new(writer);
+        for entry in entries {
+            match entry {
+                Entry::Directory(name, options) => zip.add_directory(name, options)?,
+                Entry::File(name, options, path) => {
+                    // The size is known, so no room for a ZIP64 extra field has to be reserved
+                    let mut file = fs::File::open(path)?;
+                    let large = options.large_file || file.metadata()?.len() > 0xFFFFFFFF;
+                    zip.start_file(name, options.large_file(large))?;
+                    io::copy(&mut file, &mut zip)?;
+                }
+                Entry::Bytes(name, options, data) => {
+                    zip.start_file(name, options)?;
+                    zip.write_all(&data)?;
+                }
+            }
+        }
+        zip.finish()
+    }
+}
+
+struct Builder {
+    entries: Vec<Entry>,
+    directories: HashSet<String>,
+    methods: HashMap<String, CompressionMethod>,
+    directory_entries: bool,
+}
+
+impl Builder {
+    fn add_path(&mut self, path: &Path, name: String, options: FileOptions) -> ZipResult<()> {
+        let metadata = fs::metadata(path)?;
+        let entry_options = match (options.permissions, unix_permissions(&metadata)) {
+            (None, Some(mode)) => options.unix_permissions(mode),
+            _ => options,
+        };
+
+        if !metadata.is_dir() {
+            self.add_parents(&name, options);
+            let entry_options = self.file_options(&name, entry_options);
+            self.entries
+                .push(Entry::File(name, entry_options, path.to_path_buf()));
+            return Ok(());
+        }
+
+        if !name.is_empty() {
+            self.add_directory(&name, entry_options);
+        }
+        let mut children = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        children.sort();
+        for child in children {
+            if fs::symlink_metadata(&child)?.file_type().is_symlink() && child.is_dir() {
+                continue;
+            }
+            let child_name = match child.file_name() {
+                Some(child_name) => child_name.to_string_lossy().into_owned(),
+                None => continue,
+            };
+            let child_name = if name.is_empty() {
+                child_name
+            } else {
+                format!("{}/{}", name, child_name)
+            };
+            self.add_path(&child, child_name, options)?;
+        }
+        Ok(())
+    }
+
+    /// Options for the file `name`, with the compression method for its extension if there is one
+    fn file_options(&self, name: &str, options: FileOptions) -> FileOptions {
+        let extension = match Path::new(name).extension() {
+            Some(extension) => extension.to_string_lossy().to_lowercase(),
+            None => return options,
+        };
+        match self.methods.get(&extension) {
+            Some(&method) => options.compression_method(method),
+            None => options,
+        }
+    }
+
+    fn add_directory(&mut self, name: &str, options: FileOptions) {
+        if !self.directory_entries {
+            return;
+        }
+        self.add_parents(name, options);
+        let name = format!("{}/", name.trim_end_matches('/'));
+        if self.directories.insert(name.clone()) {
+            self.entries.push(Entry::Directory(name, options));
+        }
+    }
+
+    /// Add the directories that contain `name`, if they weren't added yet
+    fn add_parents(&mut self, name: &str, mut options: FileOptions) {
+        if !self.directory_entries {
+            return;
+        }
+        options.permissions = None;
+        for (i, _) in name.trim_end_matches('/').match_indices('/') {
+            let directory = &name[..=i];
+            if i > 0 && self.directories.insert(directory.to_string()) {
+                self.entries
+                    .push(Entry::Directory(directory.to_string(), options));
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn unix_permissions(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn unix_permissions(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ZipArchive;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn build_from_directory() {
+        let directory = std::env::temp_dir().join(format!("zip-builder-{}", std::process::id()));
+        fs::create_dir_all(directory.join("src").join("nested")).unwrap();
+        fs::write(directory.join("src").join("lib.rs"), "lib").unwrap();
+        fs::write(directory.join("src").join("nested").join("mod.rs"), "mod").unwrap();
+
+        let result = ZipBuilder::new()
+            .add_path(directory.join("src"), WalkOptions::default())
+            .add_bytes("meta/VERSION", b"1.0")
+            .write_to(Cursor::new(Vec::new()))
+            .unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let mut archive = ZipArchive::new(result).unwrap();
+        let names: Vec<_> = (0..archive.len())
+            .map(|i| archive.by_index(i).unwrap().name().to_string())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "src/",
+                "src/lib.rs",
+                "src/nested/",
+                "src/nested/mod.rs",
+                "meta/",
+                "meta/VERSION"
+            ]
+        );
+        assert!(archive
+            .by_name("src/lib.rs")
+            .unwrap()
+            .local_extra_data()
+            .is_empty());
+        let mut contents = String::new();
+        archive
+            .by_name("src/nested/mod.rs")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "mod");
+    }
+
+    #[test]
+    fn jar_layout() {
+        let result = ZipBuilder::new()
+            .first(vec!["META-INF/", "META-INF/MANIFEST.MF", "missing"])
+            .add_bytes("com/example/Main.class", b"class")
+            .add_bytes("META-INF/MANIFEST.MF", b"Manifest-Version: 1.0\r\n")
+            .write_to(Cursor::new(Vec::new()))
+            .unwrap();
+        assert_eq!(
+            names(result),
+            [
+                "META-INF/",
+                "META-INF/MANIFEST.MF",
+                "com/",
+                "com/example/",
+                "com/example/Main.class"
+            ]
+        );
+
+        let result = ZipBuilder::new()
+            .directory_entries(false)
+            .add_bytes("com/example/Main.class", b"class")
+            .write_to(Cursor::new(Vec::new()))
+            .unwrap();
+        assert_eq!(names(result), ["com/example/Main.class"]);
+    }
+
+    fn names(archive: Cursor<Vec<u8>>) -> Vec<String> {
+        let mut archive = ZipArchive::new(archive).unwrap();
+        (0..archive.len())
+            .map(|i| archive.by_index(i).unwrap().name().to_string())
+            .collect()
+    }
+
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate-miniz",
+        feature = "deflate-zlib"
+    ))]
+    #[test]
+    fn compression_for_extension() {
+        let result = ZipBuilder::new()
+            .file_options(FileOptions::default().compression_method(CompressionMethod::Deflated))
+            .compression_for_extension(".png", CompressionMethod::Stored)
+            .add_bytes("images/logo.PNG", b"not really a png")
+            .add_bytes("images/README", b"readme")
+            .add_bytes("notes.txt", b"notes")
+            .write_to(Cursor::new(Vec::new()))
+            .unwrap();
+
+        let mut archive = ZipArchive::new(result).unwrap();
+        for (name, method) in &[
+            ("images/logo.PNG", CompressionMethod::Stored),
+            ("images/README", CompressionMethod::Deflated),
+            ("notes.txt", CompressionMethod::Deflated),
+        ] {
+            assert_eq!(archive.by_name(name).unwrap().compression(), *method);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Storing the contents of identical files only once
+
+use std::collections::HashMap;
+
+use crate::compression::CompressionMethod;
+use crate::types::ZipFileData;
+
+/// What a [`ZipWriter`](super::ZipWriter) does with a file whose contents were added before, see
+/// `ZipWriter::set_deduplicate` of the `digest` feature
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Deduplicate {
+    /// Add it as usual
+    Off,
+    /// Only add it to the central directory, pointing at the local header and data of the file
+    /// it duplicates
+    ///
+    /// Its name and metadata are kept in the central directory, but its local header is the one
+    /// of the other file. Most tools only look at the central directory, but some, like Python's
+    /// `zipfile`, reject files whose local header has another name. This crate's checks of local
+    /// headers accept it, as the name is the one of the file whose local header it is.
+    Share,
+    /// Leave it out of the archive, and only list it in
+    /// [`ZipWriter::duplicates`](super::ZipWriter::duplicates)
+    Skip,
+}
+
+impl Default for Deduplicate {
+    fn default() -> Deduplicate {
+        Deduplicate::Off
+    }
+}
+
+/// Digest of the contents of files, see
+/// [`ZipWriter::set_deduplicate`](super::ZipWriter::set_deduplicate)
+pub(super) trait ContentHasher {
+    fn update(&mut self, data: &[u8]);
+
+    fn finish(&mut self) -> Vec<u8>;
+}
+
+#[cfg(feature = "digest")]
+impl<D: digest::DynDigest> ContentHasher for D {
+    fn update(&mut self, data: &[u8]) {
+        digest::DynDigest::input(self, data)
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        self.result_reset().into_vec()
+    }
+}
+
+/// What is compared of the contents of files
+#[derive(Hash, PartialEq, Eq)]
+struct Contents {
+    crc32: u32,
+    size: u64,
+    // The compression method of files added as raw data, whose compressed data is hashed
+    raw: Option<CompressionMethod>,
+    digest: Vec<u8>,
+}
+
+/// The contents of the files added so far, and the duplicates that were found among them
+#[derive(Default)]
+pub(super) struct Deduplicator {
+    pub mode: Deduplicate,
+    pub new_hasher: Option<fn() -> Box<dyn ContentHasher>>,
+    // Index of the first file with the contents
+    seen: HashMap<Contents, usize>,
+    duplicates: Vec<(String, String)>,
+    /// Where the data of duplicates that were written over ended, so that the archive can be
+    /// made to end after it
+    pub overwritten_end: u64,
+}
+
+impl Deduplicator {
+    /// Get a hasher for the contents of the next file, if duplicates are looked for
+    pub fn hasher(&self) -> Option<Box<dyn ContentHasher>> {
+        match self.mode {
+            Deduplicate::Off => None,
+            _ => self.new_hasher.map(|new_hasher| new_hasher()),
+        }
+    }
+
+    /// Check whether the last of `files`, with the given digest of its contents, or of its
+    /// compressed data if it was added `raw`, duplicates an earlier one. Returns the index of
+    /// the earlier file if so, after listing the duplicate.
+    pub fn find(&mut self, files: &[ZipFileData], digest: Vec<u8>, raw: bool) -> Option<usize> {
+        let index = files.len().checked_sub(1)?;
+        let file = &files[index];
+        if self.mode == Deduplicate::Off || file.uncompressed_size == 0 {
+            return None;
+        }
+        let contents = Contents {
+            crc32: file.crc32,
+            size: file.uncompressed_size,
+            raw: if raw {
+                Some(file.compression_method)
+            } else {
+                None
+            },
+            digest,
+        };
+        let original = *self.seen.entry(contents).or_insert(index);
+        if original == index {
+            return None;
+        }
+        self.duplicates
+            .push((file.file_name.clone(), files[original].file_name.clone()));
+        Some(original)
+    }
+
+    pub fn duplicates(&self) -> &[(String, String)] {
+        &self.duplicates
+    }
+}
@@ -0,0 +1,407 @@
+//! Changing the metadata of an archive without rewriting it
+
+use std::io::{self, Read, Seek, Write};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::{general_purpose_flags, write_central_directory};
+use crate::read::ffi;
+use crate::result::{InvalidArchiveKind, ZipError, ZipResult};
+use crate::spec;
+use crate::types::{DateTime, System, ZipFileData, DOS_HIDDEN, DOS_SYSTEM};
+
+/// Editor for the metadata of the files in an archive, see
+/// [`ZipArchive::into_editor`](crate::read::ZipArchive::into_editor)
+///
+/// Changes are kept in memory until [`ZipEditor::finish`] writes them. Only the central
+/// directory is rewritten, along with the name and modification time in the local headers of
+/// the files that changed, so this is fast even for huge archives. The exception is renaming
+/// a file to a name of a different length, which doesn't fit in its local header: the file is
+/// then copied to the end of the archive, and the space it took is left unused until the
+/// archive is [`repack`](super::repack)ed.
+///
+/// ```
+/// use std::io::{Cursor, Write};
+/// use zip::write::FileOptions;
+/// use zip::{ZipArchive, ZipWriter};
+///
+/// # fn main() -> zip::result::ZipResult<()> {
+/// let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+/// writer.start_file("reprot.txt", FileOptions::default())?;
+/// writer.write_all(b"Everything is fine")?;
+/// let archive = ZipArchive::new(writer.finish()?)?;
+///
+/// let mut editor = archive.into_editor()?;
+/// let index = editor.index_for_name("reprot.txt").unwrap();
+/// editor.rename(index, "report.txt")?;
+/// editor.set_unix_permissions(index, 0o600)?;
+/// let mut archive = ZipArchive::new(editor.finish()?)?;
+/// assert_eq!(archive.by_index(0)?.name(), "report.txt");
+/// # Ok(())
+/// # }
+/// ```
+pub struct ZipEditor<R> {
+    inner: R,
+    files: Vec<ZipFileData>,
+    // Whether the name or modification time in the local header of each file is outdated
+    changed: Vec<bool>,
+    comment: Vec<u8>,
+    // Where the files end, and where files that are moved go
+    files_end: u64,
+    apk_signing_block: Option<Vec<u8>>,
+}
+
+impl<R: Read + Write + Seek> ZipEditor<R> {
+    pub(crate) fn new(
+        inner: R,
+        mut files: Vec<ZipFileData>,
+        comment: Vec<u8>,
+        files_end: u64,
+        apk_signing_block: Option<Vec<u8>>,
+    ) -> ZipEditor<R> {
+        for file in files.iter_mut() {
+            strip_zip64_extra_field(file);
+        }
+        ZipEditor {
+            inner,
+            changed: vec![false; files.len()],
+            files,
+            comment,
+            files_end,
+            apk_signing_block,
+        }
+    }
+
+    /// Number of files in the archive
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Whether the archive contains no files
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Get the index of the last file with this name, like
+    /// [`ZipArchive::by_name`](crate::read::ZipArchive::by_name) finds
+    pub fn index_for_name(&self, name: &str) -> Option<usize> {
+        self.files.iter().rposition(|file| file.file_name == name)
+    }
+
+    /// Get the name of a file, including any change made to it
+    pub fn name(&self, index: usize) -> ZipResult<&str> {
+        Ok(&self.file(index)?.file_name)
+    }
+
+    /// Rename a file
+    pub fn rename<S: Into<String>>(&mut self, index: usize, name: S) -> ZipResult<()> {
+        let name = name.into();
+        if name.len() > 0xFFFF {
+            return Err(ZipError::Io(io::Error::new(
+                io::ErrorKind::Other,
+                "File name is too long",
+            )));
+        }
+        let file = self.file_mut(index)?;
+        file.file_name = name;
+        file.file_name_raw = Vec::new();
+        self.changed[index] = true;
+        Ok(())
+    }
+
+    /// Set the comment of a file
+    pub fn set_file_comment<S: Into<String>>(&mut self, index: usize, comment: S) -> ZipResult<()> {
+        let comment = comment.into();
+        if comment.len() > 0xFFFF {
+            return Err(ZipError::Io(io::Error::new(
+                io::ErrorKind::Other,
+                "File comment is too long",
+            )));
+        }
+        self.file_mut(index)?.file_comment = comment;
+        Ok(())
+    }
+
+    /// Set the modification time of a file
+    ///
+    /// Fails for files encrypted with ZipCrypto that have a data descriptor, as their password
+    /// is checked against the modification time.
+    pub fn set_last_modified_time(&mut self, index: usize, time: DateTime) -> ZipResult<()> {
+        let file = self.file_mut(index)?;
+        if file.encrypted && file.using_data_descriptor {
+            return Err(ZipError::UnsupportedArchive(
+                "The modification time of this encrypted file can't be changed",
+            ));
+        }
+        file.last_modified_time = time;
+        self.changed[index] = true;
+        Ok(())
+    }
+
+    /// Set the unix permissions of a file, keeping its file type
+    ///
+    /// This makes the file a [`System::Unix`] file. The hidden and system attributes are kept,
+    /// other DOS attributes are replaced by the permissions.
+    pub fn set_unix_permissions(&mut self, index: usize, mode: u32) -> ZipResult<()> {
+        let file = self.file_mut(index)?;
+        let file_type = match file.unix_mode() {
+            Some(mode) if mode & 0o170000 != 0 => mode & 0o170000,
+            _ if file.is_dir() => ffi::S_IFDIR,
+            _ => ffi::S_IFREG,
+        };
+        let dos_attributes = file.external_attributes & (DOS_HIDDEN | DOS_SYSTEM);
+        file.system = System::Unix;
+        file.external_attributes = (file_type | mode & 0o777) << 16 | dos_attributes;
+        Ok(())
+    }
+
+    /// Set the archive comment
+    pub fn set_comment<S: Into<String>>(&mut self, comment: S) {
+        self.set_raw_comment(comment.into().into())
+    }
+
+    /// Set the archive comment from raw bytes
+    pub fn set_raw_comment(&mut self, comment: Vec<u8>) {
+        self.comment = comment;
+    }
+
+    /// Write the changes and return the inner reader, positioned at the end of the archive.
+    ///
+    /// The archive can end up shorter than before, for example when the names get shorter, in
+    /// which case the rest of the old archive is overwritten with zeros, so that readers can't
+    /// find the old end of central directory record. Truncate a file at the position of the
+    /// reader, with [`File::set_len`](std::fs::File::set_len), to get rid of them.
+    pub fn finish(mut self) -> ZipResult<R> {
+        let old_end = self.inner.seek(io::SeekFrom::End(0))?;
+        let mut end = self.files_end;
+        for index in 0..self.files.len() {
+            if self.changed[index] {
+                end = update_local_header(&mut self.inner, &mut self.files[index], end)?;
+            }
+        }
+        self.inner.seek(io::SeekFrom::Start(end))?;
+        if let Some(ref block) = self.apk_signing_block {
+            self.inner.write_all(block)?;
+        }
+        write_central_directory(&mut self.inner, &self.files, &self.comment)?;
+        let new_end = self.inner.seek(io::SeekFrom::Current(0))?;
+        if new_end < old_end {
+            io::copy(&mut io::repeat(0).take(old_end - new_end), &mut self.inner)?;
+            self.inner.seek(io::SeekFrom::Start(new_end))?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn file(&self, index: usize) -> ZipResult<&ZipFileData> {
+        self.files.get(index).ok_or(ZipError::FileNotFound)
+    }
+
+    fn file_mut(&mut self, index: usize) -> ZipResult<&mut ZipFileData> {
+        self.files.get_mut(index).ok_or(ZipError::FileNotFound)
+    }
+}
+
+/// Remove the ZIP64 extra field read from the central directory, which is written anew with it
+pub(super) fn strip_zip64_extra_field(file: &mut ZipFileData) {
+    file.extra_field = extra_blocks(&file.extra_field)
+        .into_iter()
+        .filter(|&(kind, _)| kind != 0x0001)
+        .flat_map(|(_, block)| block.iter().cloned())
+        .collect();
+}
+
+/// Write the name and modification time of a file to its local header, or copy the file to
+/// `end` if its name doesn't fit. Returns where the files end afterwards.
+pub(super) fn update_local_header<T: Read + Write + Seek>(
+    inner: &mut T,
+    file: &mut ZipFileData,
+    end: u64,
+) -> ZipResult<u64> {
+    inner.seek(io::SeekFrom::Start(file.header_start))?;
+    let mut header = [0; 30];
+    inner.read_exact(&mut header)?;
+    if LittleEndian::read_u32(&header) != spec::LOCAL_FILE_HEADER_SIGNATURE {
+        return Err(
+            ZipError::invalid(InvalidArchiveKind::InvalidLocalFileHeader)
+                .at_offset(file.header_start)
+                .in_entry(&file.file_name),
+        );
+    }
+    let flags = LittleEndian::read_u16(&header[6..]);
+    let utf8 = 1u16 << 11;
+    LittleEndian::write_u16(
+        &mut header[6..],
+        flags & !utf8 | general_purpose_flags(file) & utf8,
+    );
+    LittleEndian::write_u16(&mut header[10..], file.last_modified_time.timepart());
+    LittleEndian::write_u16(&mut header[12..], file.last_modified_time.datepart());
+    let name_length = LittleEndian::read_u16(&header[26..]) as usize;
+    let extra_length = LittleEndian::read_u16(&header[28..]) as usize;
+
+    let name = file.name_raw();
+    if name.len() == name_length {
+        inner.seek(io::SeekFrom::Start(file.header_start))?;
+        inner.write_all(&header)?;
+        inner.write_all(name)?;
+        return Ok(end);
+    }
+
+    inner.seek(io::SeekFrom::Current(name_length as i64))?;
+    let mut extra_field = vec![0; extra_length];
+    inner.read_exact(&mut extra_field)?;
+    let data_start = inner.seek(io::SeekFrom::Current(0))?;
+    let mut data_length = file.compressed_size;
+    if flags & (1 << 3) != 0 {
+        inner.seek(io::SeekFrom::Start(data_start + data_length))?;
+        let mut signature = [0; 4];
+        inner.read_exact(&mut signature)?;
+        // Sizes in a data descriptor take 8 bytes when the local header has a ZIP64 field
+        let zip64 = extra_blocks(&extra_field)
+            .iter()
+            .any(|&(kind, _)| kind == 0x0001);
+        data_length += if zip64 { 20 } else { 12 };
+        if LittleEndian::read_u32(&signature) == spec::DATA_DESCRIPTOR_SIGNATURE {
+            data_length += 4;
+        }
+    }
+
+    LittleEndian::write_u16(&mut header[26..], name.len() as u16);
+    inner.seek(io::SeekFrom::Start(end))?;
+    inner.write_all(&header)?;
+    inner.write_all(name)?;
+    inner.write_all(&extra_field)?;
+    let new_data_start = inner.seek(io::SeekFrom::Current(0))?;
+    copy_forward(inner, data_start, new_data_start, data_length)?;
+    file.header_start = end;
+    Ok(new_data_start + data_length)
+}
+
+/// Split an extra field into its blocks, each with its header id and including its header
+fn extra_blocks(mut extra_field: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut blocks = Vec::new();
+    while extra_field.len() >= 4 {
+        let kind = LittleEndian::read_u16(&extra_field[0..]);
+        let len = (4 + LittleEndian::read_u16(&extra_field[2..]) as usize).min(extra_field.len());
+        blocks.push((kind, &extra_field[..len]));
+        extra_field = &extra_field[len..];
+    }
+    blocks
+}
+
+/// Copy `length` bytes from `from` to `to` within `inner`, where `to` comes after `from`
+fn copy_forward<T: Read + Write + Seek>(
+    inner: &mut T,
+    from: u64,
+    to: u64,
+    length: u64,
+) -> io::Result<()> {
+    let mut buffer = vec![0; 64 * 1024];
+    let mut copied = 0;
+    while copied < length {
+        let chunk = (length - copied).min(buffer.len() as u64) as usize;
+        inner.seek(io::SeekFrom::Start(from + copied))?;
+        inner.read_exact(&mut buffer[..chunk])?;
+        inner.seek(io::SeekFrom::Start(to + copied))?;
+        inner.write_all(&buffer[..chunk])?;
+        copied += chunk as u64;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::read::ZipArchive;
+    use crate::write::{FileOptions, ZipWriter};
+    use std::io::Cursor;
+
+    fn archive() -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.set_comment("old");
+        for &(name, contents) in
+            [("a.txt", "first"), ("b.txt", "second"), ("c.txt", "third")].iter()
+        {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    fn contents(archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut contents = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    #[test]
+    fn edit_in_place() {
+        let archive = archive();
+        let length = archive.clone().into_inner().into_inner().len();
+        let mut editor = archive.into_editor().unwrap();
+        let time = DateTime::from_date_and_time(2020, 2, 29, 12, 30, 0).unwrap();
+        editor.rename(1, "ß.tx").unwrap();
+        editor.set_last_modified_time(1, time).unwrap();
+        editor.set_file_comment(2, "a comment").unwrap();
+        editor.set_unix_permissions(2, 0o600).unwrap();
+        editor.set_comment("new");
+        let inner = editor.finish().unwrap();
+        let end = inner.position() as usize;
+        assert_eq!(end, length + "a comment".len());
+
+        let mut archive = ZipArchive::new(inner).unwrap();
+        assert!(archive.validate().is_empty());
+        assert_eq!(archive.comment(), b"new");
+        assert_eq!(contents(&mut archive, "ß.tx"), "second");
+        assert_eq!(
+            archive.by_name("ß.tx").unwrap().last_modified().datepart(),
+            time.datepart()
+        );
+        let file = archive.by_index(2).unwrap();
+        assert_eq!(file.comment(), "a comment");
+        assert_eq!(file.unix_mode(), Some(0o100600));
+    }
+
+    #[test]
+    fn rename_moves_file() {
+        let archive = archive();
+        let mut editor = archive.into_editor().unwrap();
+        editor.rename(0, "a longer name.txt").unwrap();
+        let mut inner = editor.finish().unwrap();
+        let end = inner.position() as usize;
+        inner.get_mut().truncate(end);
+
+        let mut archive = ZipArchive::new(inner).unwrap();
+        assert!(archive.validate().is_empty());
+        assert_eq!(archive.by_index(0).unwrap().name(), "a longer name.txt");
+        assert_eq!(contents(&mut archive, "a longer name.txt"), "first");
+        assert_eq!(contents(&mut archive, "b.txt"), "second");
+        assert_eq!(contents(&mut archive, "c.txt"), "third");
+    }
+
+    #[test]
+    fn shrink_in_place() {
+        let mut editor = archive().into_editor().unwrap();
+        editor.set_file_comment(0, "x".repeat(100)).unwrap();
+        let archive = ZipArchive::new(editor.finish().unwrap()).unwrap();
+        let length = archive.clone().into_inner().into_inner().len();
+
+        // The old records are overwritten, so the archive can be read without truncating it
+        let mut editor = archive.into_editor().unwrap();
+        editor.set_file_comment(0, "").unwrap();
+        editor.set_comment("new");
+        let inner = editor.finish().unwrap();
+        let end = inner.position() as usize;
+        assert_eq!(end, length - 100);
+        assert!(inner.get_ref()[end..].iter().all(|&byte| byte == 0));
+
+        let mut archive = ZipArchive::new(inner).unwrap();
+        assert_eq!(archive.comment(), b"new");
+        assert_eq!(archive.by_index(0).unwrap().comment(), "");
+        assert_eq!(contents(&mut archive, "a.txt"), "first");
+    }
+}
@@ -0,0 +1,204 @@
+//! Rewriting an archive from scratch
+
+use std::collections::HashSet;
+use std::io::{self, Read, Seek, Write};
+
+use super::{FileOptions, ZipWriter};
+use crate::compression::CompressionMethod;
+use crate::read::ZipArchive;
+use crate::result::{ZipError, ZipResult};
+
+/// How [`repack`] rewrites an archive
+#[derive(Clone, Debug, Default)]
+pub struct RepackOptions {
+    compression_method: Option<CompressionMethod>,
+    sort_by_name: bool,
+    first: Vec<String>,
+}
+
+impl RepackOptions {
+    /// Recompress every file with `method`
+    ///
+    /// By default, the compressed data is copied as it is.
+    pub fn compression_method(mut self, method: CompressionMethod) -> RepackOptions {
+        self.compression_method = Some(method);
+        self
+    }
+
+    /// Set whether files are sorted by name, instead of keeping the order of the central
+    /// directory. The default is `false`.
+    pub fn sort_by_name(mut self, sort: bool) -> RepackOptions {
+        self.sort_by_name = sort;
+        self
+    }
+
+    /// Put the files with these names at the start of the archive, in the given order
+    ///
+    /// This is meant for the files that are read first, or most often, so that reading them
+    /// from a slow medium touches as little of the archive as possible. Names that aren't in the
+    /// archive are ignored. The other files follow in their usual order.
+    pub fn first<I, S>(mut self, names: I) -> RepackOptions
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.first = names.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+/// Write the files of `src` to a new archive in `dst`, and return `dst` afterwards.
+///
+/// Only the files in the central directory are kept, so data that isn't referred to anymore,
+/// like files replaced by appending a new version, is left out. Of several files with the same
+/// name, only the last one is kept, like [`ZipArchive::by_name`] finds. The archive comment and
+/// the APK Signing Block are copied. Encrypted files can't be repacked, and result in an error.
+pub fn repack<R, W>(src: &mut ZipArchive<R>, dst: W, options: RepackOptions) -> ZipResult<W>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let superseded: HashSet<usize> = src
+        .duplicate_names()
+        .flat_map(|(_, indices)| indices[..indices.len() - 1].iter().cloned())
+        .collect();
+    let mut indices: Vec<usize> = (0..src.len())
+        .filter(|index| !superseded.contains(index))
+        .collect();
+    if options.sort_by_name {
+        let mut names = Vec::with_capacity(indices.len());
+        for &index in indices.iter() {
+            names.push((src.by_index_raw(index)?.name().to_string(), index));
+        }
+        names.sort();
+        indices = names.into_iter().map(|(_, index)| index).collect();
+    }
+    if !options.first.is_empty() {
+        let mut first = Vec::new();
+        for name in options.first.iter() {
+            if let Some(&index) = src.indices_for_name(name).last() {
+                if !first.contains(&index) {
+                    first.push(index);
+                }
+            }
+        }
+        indices.retain(|index| !first.contains(index));
+        first.extend(indices);
+        indices = first;
+    }
+
+    let mut writer = ZipWriter::new(dst);
+    writer.set_raw_comment(src.comment().to_vec());
+    for index in indices {
+        if src.is_encrypted(index)? {
+            return Err(ZipError::UnsupportedArchive(
+                "Encrypted files can't be repacked",
+            ));
+        }
+        let method = match options.compression_method {
+            Some(method) => method,
+            None => {
+                writer.raw_copy_file(src.by_index_raw(index)?)?;
+                continue;
+            }
+        };
+        let mut file = src.by_index(index)?;
+        let mut file_options = FileOptions::default()
+            .compression_method(method)
+            .last_modified_time(file.last_modified())
+            .large_file(file.size() > 0xFFFFFFFF)
+            .text_file(file.is_text());
+        // The whole mode, so that the file type is kept
+        file_options.permissions = file.unix_mode();
+        if file.is_dir() {
+            writer.add_directory(file.name(), file_options)?;
+        } else {
+            writer.start_file(file.name(), file_options)?;
+            io::copy(&mut file, &mut writer)?;
+        }
+    }
+    if let Some(block) = src.read_apk_signing_block()? {
+        writer.set_apk_signing_block(block)?;
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn repack_archive() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.set_comment("comment");
+        let options = FileOptions::default().unix_permissions(0o600);
+        writer.start_file("z.txt", options).unwrap();
+        writer.start_file("b.txt", options).unwrap();
+        writer.write_all(b"old").unwrap();
+        writer.add_directory("a", FileOptions::default()).unwrap();
+        writer.start_file("a/c.txt", options).unwrap();
+        writer.write_all(b"c").unwrap();
+        writer.start_file("b.txt", options).unwrap();
+        writer.write_all(b"new").unwrap();
+        let mut src = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let stored = RepackOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .sort_by_name(true);
+        for options in [RepackOptions::default(), stored].iter() {
+            let result = repack(&mut src, Cursor::new(Vec::new()), options.clone()).unwrap();
+            let mut archive = ZipArchive::new(result).unwrap();
+            assert_eq!(archive.comment(), b"comment");
+            assert_eq!(archive.duplicate_names().count(), 0);
+            let names: Vec<_> = (0..archive.len())
+                .map(|i| archive.by_index(i).unwrap().name().to_string())
+                .collect();
+            if options.sort_by_name {
+                assert_eq!(names, vec!["a/", "a/c.txt", "b.txt", "z.txt"]);
+            } else {
+                assert_eq!(names, vec!["z.txt", "a/", "a/c.txt", "b.txt"]);
+            }
+            assert_eq!(archive.read_entry_to_string("b.txt").unwrap(), "new");
+            let file = archive.by_name("a/c.txt").unwrap();
+            assert_eq!(file.unix_mode(), Some(0o100600));
+        }
+    }
+    #[test]
+    fn repack_first() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for name in ["a.txt", "b.txt", "c.txt", "d.txt"].iter() {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+        }
+        let mut src = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let options = RepackOptions::default().first(vec!["c.txt", "missing.txt", "b.txt"]);
+        let result = repack(&mut src, Cursor::new(Vec::new()), options).unwrap();
+        let mut archive = ZipArchive::new(result).unwrap();
+        let names: Vec<_> = (0..archive.len())
+            .map(|i| archive.by_index(i).unwrap().name().to_string())
+            .collect();
+        assert_eq!(names, vec!["c.txt", "b.txt", "a.txt", "d.txt"]);
+    }
+
+    #[test]
+    fn repack_file_types() {
+        use byteorder::{ByteOrder, LittleEndian};
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("link", FileOptions::default()).unwrap();
+        writer.write_all(b"target").unwrap();
+        let mut bytes = writer.finish().unwrap().into_inner();
+        // Make the file a symbolic link
+        let central = bytes.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        LittleEndian::write_u32(&mut bytes[central + 38..], 0o120777 << 16);
+        let mut src = ZipArchive::new(Cursor::new(bytes)).unwrap();
+
+        let options = RepackOptions::default().compression_method(CompressionMethod::Stored);
+        let result = repack(&mut src, Cursor::new(Vec::new()), options).unwrap();
+        let mut archive = ZipArchive::new(result).unwrap();
+        let file = archive.by_name("link").unwrap();
+        assert_eq!(file.compression(), CompressionMethod::Stored);
+        assert_eq!(file.unix_mode(), Some(0o120777));
+    }
+}
@@ -0,0 +1,149 @@
+//! Creating archives from something other than the file system
+
+use std::io::{Read, Seek, Write};
+
+use super::{FileOptions, ZipWriter};
+use crate::result::ZipResult;
+
+/// Source of the entries for [`ZipWriter::add_tree`], such as an in-memory file system or blobs
+/// in a database
+///
+/// ```
+/// use std::io::Cursor;
+/// use zip::result::ZipResult;
+/// use zip::write::{FileOptions, SourceTree, TreeEntry};
+///
+/// struct Numbers(u32);
+///
+/// impl SourceTree for Numbers {
+///     type Reader = Cursor<String>;
+///
+///     fn next_entry(&mut self) -> ZipResult<Option<TreeEntry<Cursor<String>>>> {
+///         self.0 += 1;
+///         Ok(Some(match self.0 {
+///             1 => TreeEntry::Directory {
+///                 name: "numbers".to_string(),
+///                 options: FileOptions::default(),
+///             },
+///             n if n <= 10 => TreeEntry::File {
+///                 name: format!("numbers/{}.txt", n),
+///                 options: FileOptions::default(),
+///                 contents: Cursor::new(n.to_string()),
+///             },
+///             _ => return Ok(None),
+///         }))
+///     }
+/// }
+///
+/// # fn main() -> ZipResult<()> {
+/// let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+/// zip.add_tree(&mut Numbers(0))?;
+/// zip.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait SourceTree {
+    /// Reader for the contents of a file
+    type Reader: Read;
+
+    /// Get the next entry, or `None` once there are no entries left
+    ///
+    /// Entries are added to the archive in the order they are returned in. The contents of a
+    /// file are read before the next entry is asked for.
+    fn next_entry(&mut self) -> ZipResult<Option<TreeEntry<Self::Reader>>>;
+}
+
+/// An entry of a [`SourceTree`]
+pub enum TreeEntry<R> {
+    /// A directory, see [`ZipWriter::add_directory`]
+    Directory {
+        /// Name of the directory in the archive
+        name: String,
+        /// Options of the directory, with its permissions and modification time
+        options: FileOptions,
+    },
+    /// A file, see [`ZipWriter::start_file`]
+    File {
+        /// Name of the file in the archive
+        name: String,
+        /// Options of the file, with its permissions, modification time and compression
+        options: FileOptions,
+        /// The contents of the file
+        contents: R,
+    },
+}
+
+impl<W: Write + Seek> ZipWriter<W> {
+    /// Add every entry of `tree` to the archive, without touching the file system.
+    ///
+    /// Returns the number of entries that were added.
+    pub fn add_tree<T: SourceTree + ?Sized>(&mut self, tree: &mut T) -> ZipResult<usize> {
+        let mut count = 0;
+        while let Some(entry) = tree.next_entry()? {
+            match entry {
+                TreeEntry::Directory { name, options } => self.add_directory(name, options)?,
+                TreeEntry::File {
+                    name,
+                    options,
+                    mut contents,
+                } => {
+                    self.add_file_from_reader(name, options, &mut contents)?;
+                }
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ZipArchive;
+    use std::io::Cursor;
+
+    struct Blobs(Vec<(&'static str, Option<&'static [u8]>)>);
+
+    impl SourceTree for Blobs {
+        type Reader = &'static [u8];
+
+        fn next_entry(&mut self) -> ZipResult<Option<TreeEntry<&'static [u8]>>> {
+            if self.0.is_empty() {
+                return Ok(None);
+            }
+            let options = FileOptions::default();
+            Ok(Some(match self.0.remove(0) {
+                (name, None) => TreeEntry::Directory {
+                    name: name.to_string(),
+                    options,
+                },
+                (name, Some(contents)) => TreeEntry::File {
+                    name: name.to_string(),
+                    options,
+                    contents,
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn add_tree() {
+        let mut tree = Blobs(vec![
+            ("blobs", None),
+            ("blobs/a", Some(b"first")),
+            ("blobs/b", Some(b"second")),
+        ]);
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        assert_eq!(writer.add_tree(&mut tree).unwrap(), 3);
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        assert!(archive.by_name("blobs/").unwrap().is_dir());
+        let mut contents = String::new();
+        archive
+            .by_name("blobs/b")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "second");
+    }
+}
@@ -1,8 +1,9 @@
 use byteorder::{LittleEndian, WriteBytesExt};
 use std::collections::HashSet;
 use std::io::prelude::*;
-use std::io::{Cursor, Seek};
+use std::io::{self, Cursor, Seek};
 use std::iter::FromIterator;
+use zip::result::ZipError;
 use zip::write::FileOptions;
 use zip::CompressionMethod;
 
@@ -66,6 +67,34 @@ fn append() {
     check_zip_file_contents(&mut zip, COPY_ENTRY_NAME);
 }
 
+// This test asserts that files renamed while appending keep their contents, whether the new name
+// fits in the local header or not.
+#[test]
+fn append_rename() {
+    let mut file = &mut Cursor::new(Vec::new());
+    write_to_zip(file).expect("file written");
+
+    {
+        let mut zip = zip::ZipWriter::new_append(&mut file).unwrap();
+        zip.rename(ENTRY_NAME, COPY_ENTRY_NAME).unwrap();
+        zip.rename("test/☃.txt", "test/☂.txt").unwrap();
+        match zip.rename("test/☂.txt", COPY_ENTRY_NAME) {
+            Err(ZipError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::AlreadyExists),
+            result => panic!("unexpected result {:?}", result),
+        }
+        zip.finish().unwrap();
+    }
+
+    let mut zip = zip::ZipArchive::new(&mut file).unwrap();
+    assert!(zip.validate().is_empty());
+    assert!(zip.by_name(ENTRY_NAME).is_err());
+    check_zip_file_contents(&mut zip, COPY_ENTRY_NAME);
+    assert_eq!(
+        read_zip_file(&mut zip, "test/☂.txt").unwrap(),
+        "Hello, World!\n"
+    );
+}
+
 fn write_to_zip(file: &mut Cursor<Vec<u8>>) -> zip::result::ZipResult<()> {
     let mut zip = zip::ZipWriter::new(file);
 
@@ -100,7 +129,7 @@ fn read_zip<R: Read + Seek>(zip_file: R) -> zip::result::ZipResult<zip::ZipArchi
         "test_with_extra_data/🐢.txt",
         ENTRY_NAME,
     ];
-    let expected_file_names = HashSet::from_iter(expected_file_names.iter().map(|&v| v));
+    let expected_file_names = HashSet::from_iter(expected_file_names.iter().copied());
     let file_names = archive.file_names().collect::<HashSet<_>>();
     assert_eq!(file_names, expected_file_names);
 
@@ -137,14 +166,14 @@ fn check_zip_file_contents<R: Read + Seek>(archive: &mut zip::ZipArchive<R>, nam
     assert!(file_contents.as_bytes() == LOREM_IPSUM);
 }
 
-const LOREM_IPSUM : &'static [u8] = b"Lorem ipsum dolor sit amet, consectetur adipiscing elit. In tellus elit, tristique vitae mattis egestas, ultricies vitae risus. Quisque sit amet quam ut urna aliquet
+const LOREM_IPSUM : &[u8] = b"Lorem ipsum dolor sit amet, consectetur adipiscing elit. In tellus elit, tristique vitae mattis egestas, ultricies vitae risus. Quisque sit amet quam ut urna aliquet
 molestie. Proin blandit ornare dui, a tempor nisl accumsan in. Praesent a consequat felis. Morbi metus diam, auctor in auctor vel, feugiat id odio. Curabitur ex ex,
 dictum quis auctor quis, suscipit id lorem. Aliquam vestibulum dolor nec enim vehicula, porta tristique augue tincidunt. Vivamus ut gravida est. Sed pellentesque, dolor
 vitae tristique consectetur, neque lectus pulvinar dui, sed feugiat purus diam id lectus. Class aptent taciti sociosqu ad litora torquent per conubia nostra, per
 inceptos himenaeos. Maecenas feugiat velit in ex ultrices scelerisque id id neque.
 ";
 
-const EXTRA_DATA: &'static [u8] = b"Extra Data";
+const EXTRA_DATA: &[u8] = b"Extra Data";
 
 const ENTRY_NAME: &str = "test/lorem_ipsum.txt";
 
@@ -20,9 +20,8 @@
 use std::io::Cursor;
 use std::io::Read;
 
-#[test]
-fn encrypted_file() {
-    let zip_file_bytes = &mut Cursor::new(vec![
+fn encrypted_archive() -> Cursor<Vec<u8>> {
+    Cursor::new(vec![
         0x50, 0x4b, 0x03, 0x04, 0x14, 0x00, 0x01, 0x00, 0x00, 0x00, 0x54, 0xbd, 0xb5, 0x50, 0x2f,
         0x20, 0x79, 0x55, 0x2f, 0x00, 0x00, 0x00, 0x23, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00,
         0x74, 0x65, 0x73, 0x74, 0x2e, 0x74, 0x78, 0x74, 0xca, 0x2d, 0x1d, 0x27, 0x19, 0x19, 0x63,
@@ -37,11 +36,17 @@ fn encrypted_file() {
         0xd6, 0x01, 0xa8, 0xc4, 0x45, 0xbd, 0xb8, 0x2f, 0xd6, 0x01, 0x50, 0x4b, 0x05, 0x06, 0x00,
         0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x5a, 0x00, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00,
         0x00, 0x00,
-    ]);
+    ])
+}
 
-    let mut archive = zip::ZipArchive::new(zip_file_bytes).unwrap();
+#[test]
+fn encrypted_file() {
+    let mut archive = zip::ZipArchive::new(encrypted_archive()).unwrap();
 
     assert_eq!(archive.len(), 1); //Only one file inside archive: `test.txt`
+    assert!(archive.has_encrypted_entries());
+    assert!(archive.is_encrypted(0).unwrap());
+    assert!(archive.by_index_raw(0).unwrap().is_encrypted());
 
     {
         // No password
@@ -57,6 +62,13 @@ fn encrypted_file() {
         }
     }
 
+    {
+        // Checking the password only
+        assert!(!archive.verify_password(0, b"wrong password").unwrap());
+        assert!(archive.verify_password(0, b"test").unwrap());
+        assert!(archive.verify_password(1, b"test").is_err());
+    }
+
     {
         // Wrong password
         let file = archive.by_index_decrypt(0, b"wrong password");
@@ -83,3 +95,33 @@ fn encrypted_file() {
         assert_eq!(data, "abcdefghijklmnopqrstuvwxyz123456789".as_bytes());
     }
 }
+
+#[test]
+fn default_password() {
+    let mut archive = zip::ZipArchive::new(encrypted_archive()).unwrap();
+
+    archive.set_default_password(b"wrong password");
+    match archive.by_index(0) {
+        Err(zip::result::ZipError::UnsupportedArchive(zip::result::ZipError::INVALID_PASSWORD)) => {
+        }
+        Err(_) => panic!("Expected InvalidPassword error when the default password is wrong"),
+        Ok(_) => panic!("Error: Successfully opened encrypted file with wrong password?!"),
+    }
+
+    archive.set_default_password(b"test");
+    let debug = format!("{:?}", archive);
+    assert!(debug.contains("password: Some(\"<redacted>\")"));
+    let mut data = Vec::new();
+    archive
+        .by_name("test.txt")
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    assert_eq!(data, "abcdefghijklmnopqrstuvwxyz123456789".as_bytes());
+
+    let directory = std::env::temp_dir().join(format!("zip-crypto-{}", std::process::id()));
+    archive.extract(&directory).unwrap();
+    let contents = std::fs::read_to_string(directory.join("test.txt")).unwrap();
+    assert_eq!(contents, "abcdefghijklmnopqrstuvwxyz123456789");
+    std::fs::remove_dir_all(&directory).unwrap();
+}